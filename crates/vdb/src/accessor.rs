@@ -1,13 +1,13 @@
 use glam::UVec3;
 
-use crate::{tree::TreeMeta, Node, NodeConst, Tree};
+use crate::{tree::TreeMeta, Node, NodeConst, Tree, TreeCoords};
 
 pub struct Accessor<'a, ROOT: Node>
 where
     [(); ROOT::LEVEL as usize]: Sized,
 {
     tree: &'a Tree<ROOT>,
-    ptrs: [u32; ROOT::LEVEL],
+    pub(crate) ptrs: [u32; ROOT::LEVEL],
     last_coords: UVec3,
 }
 
@@ -29,21 +29,33 @@ fn lowest_common_ancestor_level(a: UVec3, b: UVec3, mask: UVec3, root_level: u32
     root_level + 1 - parent_index
 }
 
+/// Lookups that terminated early mark the levels below them as invalid in the cached path.
+/// Returns the lowest level starting from `level` that still holds a valid node pointer.
+#[inline]
+pub(crate) fn first_valid_level(ptrs: &[u32], mut level: u32) -> u32 {
+    while (level as usize) < ptrs.len() && ptrs[level as usize] == u32::MAX {
+        level += 1;
+    }
+    level
+}
+
 impl<'a, ROOT: Node> Accessor<'a, ROOT>
 where
     [(); ROOT::LEVEL as usize + 1]: Sized,
 {
     #[inline]
-    pub fn get(&mut self, coords: UVec3) -> Option<ROOT::Voxel>
+    pub fn get(&mut self, coords: impl TreeCoords<ROOT>) -> Option<ROOT::Voxel>
     where
         ROOT: ~const NodeConst,
     {
+        let coords = coords.to_tree_coords();
         let lca_level = lowest_common_ancestor_level(
             self.last_coords,
             coords,
             <Tree<ROOT> as TreeMeta<ROOT>>::META_MASK,
             ROOT::LEVEL as u32,
         );
+        let lca_level = first_valid_level(&self.ptrs, lca_level);
         self.last_coords = coords;
         let result = if lca_level >= ROOT::LEVEL as u32 {
             self.tree.root.get(&self.tree.pool, coords, &mut self.ptrs)
@@ -55,6 +67,32 @@ where
         };
         return result;
     }
+
+    /// Returns the value of the voxel at `coords` without moving the cached path. The lookup
+    /// starts from the lowest node shared with the path of the last call to [`Accessor::get`],
+    /// so voxels within the same leaf node are read from the cached leaf node directly.
+    #[inline]
+    pub(crate) fn get_nearby(&self, coords: UVec3) -> Option<ROOT::Voxel>
+    where
+        ROOT: ~const NodeConst,
+    {
+        let lca_level = lowest_common_ancestor_level(
+            self.last_coords,
+            coords,
+            <Tree<ROOT> as TreeMeta<ROOT>>::META_MASK,
+            ROOT::LEVEL as u32,
+        );
+        let lca_level = first_valid_level(&self.ptrs, lca_level);
+        // The nodes visited below the shared node are written to a scratch copy of the path.
+        let mut ptrs = self.ptrs;
+        if lca_level >= ROOT::LEVEL as u32 {
+            self.tree.root.get(&self.tree.pool, coords, &mut ptrs)
+        } else {
+            let meta = &<Tree<ROOT> as TreeMeta<ROOT>>::METAS[lca_level as usize];
+            let ptr = self.ptrs[lca_level as usize];
+            (meta.getter)(&self.tree.pool, coords & meta.extent_mask, ptr, &mut ptrs)
+        }
+    }
 }
 
 pub struct AccessorMut<'a, ROOT: Node>
@@ -71,16 +109,18 @@ where
     [(); ROOT::LEVEL as usize + 1]: Sized,
 {
     #[inline]
-    pub fn get(&mut self, coords: UVec3) -> Option<ROOT::Voxel>
+    pub fn get(&mut self, coords: impl TreeCoords<ROOT>) -> Option<ROOT::Voxel>
     where
         ROOT: ~const NodeConst,
     {
+        let coords = coords.to_tree_coords();
         let lca_level = lowest_common_ancestor_level(
             self.last_coords,
             coords,
             <Tree<ROOT> as TreeMeta<ROOT>>::META_MASK,
             ROOT::LEVEL as u32,
         );
+        let lca_level = first_valid_level(&self.ptrs, lca_level);
         self.last_coords = coords;
         let result = if lca_level >= ROOT::LEVEL as u32 {
             self.tree.root.get(&self.tree.pool, coords, &mut self.ptrs)
@@ -93,17 +133,31 @@ where
         return result;
     }
 
+    /// Set the value of a voxel. Setting a voxel to `None` removes it from the tree,
+    /// and nodes left empty by the removal are released back to their pools.
     #[inline]
-    pub fn set(&mut self, coords: UVec3, value: Option<ROOT::Voxel>)
+    pub fn set(&mut self, coords: impl TreeCoords<ROOT>, value: Option<ROOT::Voxel>)
     where
         ROOT: ~const NodeConst,
     {
+        let coords = coords.to_tree_coords();
+        self.tree.mark_dirty_voxel(coords);
+        if value.is_none() {
+            // Removals always start from the root so that the parents get a chance
+            // to release the nodes emptied by the removal.
+            self.last_coords = coords;
+            self.tree
+                .root
+                .set(&mut self.tree.pool, coords, value, &mut self.ptrs);
+            return;
+        }
         let lca_level = lowest_common_ancestor_level(
             self.last_coords,
             coords,
             <Tree<ROOT> as TreeMeta<ROOT>>::META_MASK,
             ROOT::LEVEL as u32,
         );
+        let lca_level = first_valid_level(&self.ptrs, lca_level);
         self.last_coords = coords;
         if lca_level >= ROOT::LEVEL as u32 {
             self.tree
@@ -125,14 +179,14 @@ where
     pub fn accessor(&self) -> Accessor<ROOT> {
         Accessor {
             tree: self,
-            ptrs: [0; ROOT::LEVEL],
+            ptrs: [u32::MAX; ROOT::LEVEL],
             last_coords: UVec3::new(u32::MAX, u32::MAX, u32::MAX),
         }
     }
     pub fn accessor_mut(&mut self) -> AccessorMut<ROOT> {
         AccessorMut {
             tree: self,
-            ptrs: [0; ROOT::LEVEL],
+            ptrs: [u32::MAX; ROOT::LEVEL],
             last_coords: UVec3::new(u32::MAX, u32::MAX, u32::MAX),
         }
     }
@@ -193,4 +247,52 @@ mod tests {
             assert_eq!(result, Some(true));
         }
     }
+
+    #[test]
+    fn test_accessor_mut_remove() {
+        use rand::prelude::*;
+        use std::collections::HashSet;
+        let mut rng = rand::thread_rng();
+
+        type MyTree = Tree<hierarchy!(2, 4, 2)>;
+        let mut tree = MyTree::new();
+
+        let mut set_locations: HashSet<UVec3> = HashSet::new();
+        let mut accessor = tree.accessor_mut();
+        for _i in 0..2000 {
+            let location = UVec3::new(
+                rng.gen_range(0..64),
+                rng.gen_range(0..64),
+                rng.gen_range(0..64),
+            );
+            if rng.gen_bool(0.4) {
+                accessor.set(location, None);
+                set_locations.remove(&location);
+            } else {
+                accessor.set(location, Some(true));
+                set_locations.insert(location);
+            }
+            assert_eq!(
+                accessor.get(location).is_some(),
+                set_locations.contains(&location)
+            );
+        }
+        for location in set_locations.iter() {
+            accessor.set(*location, None);
+        }
+        assert!(tree.iter_leaf().next().is_none());
+        assert!(tree.pool.iter().all(|pool| pool.count() == 0));
+    }
+
+    #[test]
+    fn test_accessor_first_lookup() {
+        use glam::IVec3;
+        let mut tree = Tree::<hierarchy!(#, 4, 2; u8)>::new();
+        // Shares its offsets within the nodes with the coordinates looked up below, and is held
+        // by the first node of each pool.
+        tree.set_value(IVec3::new(62, 62, 62), Some(1));
+        let coords = IVec3::splat(i32::MAX - 1);
+        assert_eq!(tree.accessor().get(coords), None);
+        assert_eq!(tree.accessor_mut().get(coords), None);
+    }
 }
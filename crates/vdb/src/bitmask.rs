@@ -73,6 +73,10 @@ where
         self.data.iter().all(|&a| a == 0)
     }
 
+    pub fn is_full(&self) -> bool {
+        self.data.iter().all(|&a| a == usize::MAX)
+    }
+
     pub fn count_ones(&self) -> usize {
         self.data.iter().map(|a| a.count_ones() as usize).sum()
     }
@@ -0,0 +1,532 @@
+use std::{any::Any, marker::PhantomData};
+
+use fxhash::{FxHashMap, FxHashSet};
+use glam::UVec3;
+
+use crate::{
+    journal::iter_set_bits, parallel::leaf_local_coords, IsLeaf, Node, NodeConst, Tree, TreeCoords,
+};
+
+/// Identifies a channel of a [`ChannelGrid`] holding values of type `T`.
+pub struct ChannelId<T> {
+    index: usize,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Clone for ChannelId<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for ChannelId<T> {}
+
+impl<T> PartialEq for ChannelId<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.index == other.index
+    }
+}
+
+impl<T> Eq for ChannelId<T> {}
+
+impl<T> std::fmt::Debug for ChannelId<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("ChannelId").field(&self.index).finish()
+    }
+}
+
+/// Values of one channel, stored per leaf-sized region and indexed like the occupancy mask of
+/// leaf nodes. Regions without stored values hold the default value everywhere.
+struct Channel<T> {
+    leaves: FxHashMap<UVec3, Box<[T]>>,
+    /// Origins of the regions modified since the last call to [`ChannelGrid::drain_dirty`].
+    dirty: FxHashSet<UVec3>,
+}
+
+/// Operations on channels regardless of their value type, applied by edits of the topology.
+trait AnyChannel: Send + Sync {
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+    /// Reset the voxels set in `mask` within the region at `origin` to the default value.
+    /// `mask` is indexed like the occupancy mask of leaf nodes.
+    fn reset(&mut self, origin: UVec3, mask: &[u64]);
+    /// Drop the values of the region at `origin`, which no longer has a leaf node.
+    fn remove_leaf(&mut self, origin: UVec3);
+}
+
+impl<T: Copy + Default + PartialEq + Send + Sync + 'static> AnyChannel for Channel<T> {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+    fn reset(&mut self, origin: UVec3, mask: &[u64]) {
+        let Some(values) = self.leaves.get_mut(&origin) else {
+            return;
+        };
+        let mut changed = false;
+        for index in iter_set_bits(mask) {
+            changed |= values[index] != T::default();
+            values[index] = T::default();
+        }
+        if changed {
+            self.dirty.insert(origin);
+        }
+    }
+    fn remove_leaf(&mut self, origin: UVec3) {
+        if self.leaves.remove(&origin).is_some() {
+            self.dirty.insert(origin);
+        }
+    }
+}
+
+/// A tree with any number of attribute channels sharing its topology.
+///
+/// The tree holds the voxels and their main value, while each channel holds one more value of
+/// its own type for every voxel of the tree. Channels are added and removed at runtime. Removing
+/// a voxel from the tree resets its values in all channels, and voxels added to the tree start
+/// with the default value in all channels.
+///
+/// Voxels are always kept in leaf nodes, so that each voxel has its own values in the channels.
+/// The values of each channel can be packed in the order of the leaf nodes of the tree with
+/// [`ChannelGrid::pack_channel`] to be uploaded independently of other channels, and regions
+/// modified since the last upload are returned by [`ChannelGrid::drain_dirty`].
+/// ```
+/// #![feature(generic_const_exprs)]
+/// use dust_vdb::{ChannelGrid, hierarchy};
+/// use glam::IVec3;
+/// let mut grid = ChannelGrid::<hierarchy!(#, 4, 2; u8)>::new();
+/// let emissive = grid.add_channel::<f32>("emissive");
+/// let damage = grid.add_channel::<u16>("damage");
+///
+/// grid.set_value(IVec3::new(1, 2, 3), Some(7));
+/// grid.set(emissive, IVec3::new(1, 2, 3), 0.5);
+/// grid.set(damage, IVec3::new(1, 2, 3), 20);
+/// assert_eq!(grid.get(emissive, IVec3::new(1, 2, 3)), Some(0.5));
+/// // Channel values only exist where the tree has voxels.
+/// assert!(!grid.set(damage, IVec3::new(0, 0, 0), 5));
+///
+/// grid.set_value(IVec3::new(1, 2, 3), None);
+/// grid.set_value(IVec3::new(1, 2, 3), Some(8));
+/// assert_eq!(grid.get(damage, IVec3::new(1, 2, 3)), Some(0));
+/// ```
+pub struct ChannelGrid<ROOT: Node>
+where
+    [(); ROOT::LEVEL as usize]: Sized,
+{
+    tree: Tree<ROOT>,
+    /// Channels by index, with their name. Removed channels leave an empty slot behind,
+    /// so that the ids of removed channels are never reused.
+    channels: Vec<Option<(String, Box<dyn AnyChannel>)>>,
+}
+
+#[inline]
+fn leaf_index<ROOT: Node>(local: UVec3) -> usize {
+    let log2 = ROOT::LeafType::EXTENT_LOG2;
+    ((local.x as usize) << (log2.y + log2.z)) | ((local.y as usize) << log2.z) | (local.z as usize)
+}
+
+impl<ROOT: Node> ChannelGrid<ROOT>
+where
+    [(); ROOT::LEVEL as usize + 1]: Sized,
+{
+    /// Number of words in the masks of each region.
+    const MASK_WORDS: usize = (ROOT::LeafType::SIZE + 63) / 64;
+
+    pub fn new() -> Self
+    where
+        ROOT: ~const NodeConst,
+    {
+        Self {
+            tree: Tree::new(),
+            channels: Vec::new(),
+        }
+    }
+
+    /// The tree holding the topology shared by all channels.
+    pub fn tree(&self) -> &Tree<ROOT> {
+        &self.tree
+    }
+
+    /// Add a channel named `name`, holding the default value for all voxels.
+    /// Panics if a channel with the same name already exists.
+    pub fn add_channel<T: Copy + Default + PartialEq + Send + Sync + 'static>(
+        &mut self,
+        name: &str,
+    ) -> ChannelId<T> {
+        assert!(
+            self.channel_names().all(|other| other != name),
+            "A channel named {} already exists",
+            name
+        );
+        let channel = Channel::<T> {
+            leaves: FxHashMap::default(),
+            dirty: FxHashSet::default(),
+        };
+        self.channels
+            .push(Some((name.to_string(), Box::new(channel))));
+        ChannelId {
+            index: self.channels.len() - 1,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Remove a channel and all of its values.
+    pub fn remove_channel<T: 'static>(&mut self, id: ChannelId<T>) {
+        self.channels[id.index] = None;
+    }
+
+    /// Returns the id of the channel named `name`, or None if there is no such channel
+    /// or if it does not hold values of type `T`.
+    pub fn channel_id<T: 'static>(&self, name: &str) -> Option<ChannelId<T>> {
+        self.channels.iter().enumerate().find_map(|(index, slot)| {
+            let (channel_name, channel) = slot.as_ref()?;
+            (channel_name == name && channel.as_any().is::<Channel<T>>()).then_some(ChannelId {
+                index,
+                _marker: PhantomData,
+            })
+        })
+    }
+
+    pub fn channel_names(&self) -> impl Iterator<Item = &str> {
+        self.channels
+            .iter()
+            .flatten()
+            .map(|(name, _)| name.as_str())
+    }
+
+    fn channel<T: 'static>(&self, id: ChannelId<T>) -> &Channel<T> {
+        self.channels[id.index]
+            .as_ref()
+            .expect("The channel was removed")
+            .1
+            .as_any()
+            .downcast_ref()
+            .unwrap()
+    }
+
+    fn channel_mut<T: 'static>(&mut self, id: ChannelId<T>) -> &mut Channel<T> {
+        self.channels[id.index]
+            .as_mut()
+            .expect("The channel was removed")
+            .1
+            .as_any_mut()
+            .downcast_mut()
+            .unwrap()
+    }
+
+    pub fn get_value(&self, coords: impl TreeCoords<ROOT>) -> Option<ROOT::Voxel> {
+        self.tree.get_value(coords)
+    }
+
+    /// Set the value of a voxel in the tree. Adding or removing the voxel resets its values in
+    /// all channels, and channel values of leaf nodes released by the removal are dropped.
+    pub fn set_value(&mut self, coords: impl TreeCoords<ROOT>, value: Option<ROOT::Voxel>) {
+        let coords = coords.to_tree_coords();
+        let previous = self.tree.get_value(coords);
+        self.tree.set_value(coords, value);
+        if previous.is_some() != value.is_some() {
+            let origin = coords & !ROOT::LeafType::EXTENT_MASK;
+            let index = leaf_index::<ROOT>(coords - origin);
+            let mut mask = vec![0; Self::MASK_WORDS];
+            mask[index / 64] |= 1 << (index % 64);
+            self.reset_channels(origin, &mask);
+        }
+    }
+
+    /// Set the value of all voxels within `min` and `max` (inclusive) in the tree. Each leaf-sized
+    /// region intersecting the box is written as a whole leaf node, so that the voxels are kept in
+    /// leaf nodes, and its values in the channels are updated along with it.
+    /// See [`ChannelGrid::set_value`].
+    pub fn fill<C: TreeCoords<ROOT>>(&mut self, min: C, max: C, value: Option<ROOT::Voxel>)
+    where
+        ROOT: ~const NodeConst,
+        ROOT::LeafType: IsLeaf + Node<Voxel = ROOT::Voxel>,
+    {
+        let min = min.to_tree_coords();
+        let max = max.to_tree_coords().min(ROOT::EXTENT_MASK);
+        if min.cmpgt(max).any() {
+            return;
+        }
+        let track = self.channels.iter().any(Option::is_some);
+        let leaf_log2 = ROOT::LeafType::EXTENT_LOG2;
+        let (cell_min, cell_max) = (min >> leaf_log2, max >> leaf_log2);
+        for cell_x in cell_min.x..=cell_max.x {
+            for cell_y in cell_min.y..=cell_max.y {
+                for cell_z in cell_min.z..=cell_max.z {
+                    let origin = UVec3::new(cell_x, cell_y, cell_z) << leaf_log2;
+                    let local_min = min.max(origin) - origin;
+                    let local_max = max.min(origin + ROOT::LeafType::EXTENT_MASK) - origin;
+                    let whole =
+                        local_min == UVec3::ZERO && local_max == ROOT::LeafType::EXTENT_MASK;
+                    if whole && value.is_none() {
+                        self.tree
+                            .fill(origin, origin + ROOT::LeafType::EXTENT_MASK, None);
+                        for (_, channel) in self.channels.iter_mut().flatten() {
+                            channel.remove_leaf(origin);
+                        }
+                        continue;
+                    }
+                    self.fill_leaf(origin, local_min, local_max, value, whole, track);
+                }
+            }
+        }
+    }
+
+    /// Write `value` into the voxels within `local_min` and `local_max` (inclusive) of the
+    /// leaf-sized region at `origin`, rewriting its leaf node as a whole. When `track` is set,
+    /// the voxels added or removed have their values reset in all channels.
+    fn fill_leaf(
+        &mut self,
+        origin: UVec3,
+        local_min: UVec3,
+        local_max: UVec3,
+        value: Option<ROOT::Voxel>,
+        whole: bool,
+        track: bool,
+    ) where
+        ROOT: ~const NodeConst,
+        ROOT::LeafType: IsLeaf + Node<Voxel = ROOT::Voxel>,
+    {
+        self.tree.mark_dirty_voxel(origin);
+        // Safety: the leaf node is only used until the next edit of the tree.
+        let leaf = unsafe { &mut *self.tree.ensure_leaf(origin) };
+        let mut changed = track.then(|| {
+            let mut mask = vec![0; Self::MASK_WORDS];
+            leaf.get_occupancy(&mut mask);
+            mask
+        });
+        if whole {
+            leaf.set_voxels(&vec![value; ROOT::LeafType::SIZE]);
+        } else {
+            let mut values: Vec<_> = (0..ROOT::LeafType::SIZE)
+                .map(|index| leaf.get(&[], leaf_local_coords::<ROOT>(index), &mut []))
+                .collect();
+            for x in local_min.x..=local_max.x {
+                for y in local_min.y..=local_max.y {
+                    for z in local_min.z..=local_max.z {
+                        values[leaf_index::<ROOT>(UVec3 { x, y, z })] = value;
+                    }
+                }
+            }
+            leaf.set_voxels(&values);
+        }
+        if let Some(changed) = changed.as_mut() {
+            let mut occupancy = vec![0; Self::MASK_WORDS];
+            leaf.get_occupancy(&mut occupancy);
+            for (changed, occupancy) in changed.iter_mut().zip(occupancy.iter()) {
+                *changed ^= *occupancy;
+            }
+        }
+        let released = leaf.is_empty();
+        if released {
+            // Release the leaf node, along with the nodes above it left empty.
+            self.tree
+                .fill(origin, origin + ROOT::LeafType::EXTENT_MASK, None);
+        }
+        let Some(changed) = changed else { return };
+        for (_, channel) in self.channels.iter_mut().flatten() {
+            if released {
+                channel.remove_leaf(origin);
+            } else if changed.iter().any(|word| *word != 0) {
+                channel.reset(origin, &changed);
+            }
+        }
+    }
+
+    /// Reset the values of the voxels set in `mask` within the region at `origin` in all
+    /// channels, or drop the values of the region if its leaf node was released.
+    fn reset_channels(&mut self, origin: UVec3, mask: &[u64]) {
+        let has_leaf = self.tree.iter_leaf_in_aabb(origin, origin).next().is_some();
+        for (_, channel) in self.channels.iter_mut().flatten() {
+            if has_leaf {
+                channel.reset(origin, mask);
+            } else {
+                channel.remove_leaf(origin);
+            }
+        }
+    }
+
+    /// Returns the value of a voxel in a channel, or None if the tree has no voxel there.
+    pub fn get<T: Copy + Default + 'static>(
+        &self,
+        id: ChannelId<T>,
+        coords: impl TreeCoords<ROOT>,
+    ) -> Option<T> {
+        let coords = coords.to_tree_coords();
+        self.tree.get_value(coords)?;
+        let origin = coords & !ROOT::LeafType::EXTENT_MASK;
+        let value = match self.channel(id).leaves.get(&origin) {
+            Some(values) => values[leaf_index::<ROOT>(coords - origin)],
+            None => T::default(),
+        };
+        Some(value)
+    }
+
+    /// Set the value of a voxel in a channel. Returns false without writing anything if the tree
+    /// has no voxel there.
+    pub fn set<T: Copy + Default + 'static>(
+        &mut self,
+        id: ChannelId<T>,
+        coords: impl TreeCoords<ROOT>,
+        value: T,
+    ) -> bool {
+        let coords = coords.to_tree_coords();
+        if self.tree.get_value(coords).is_none() {
+            return false;
+        }
+        let origin = coords & !ROOT::LeafType::EXTENT_MASK;
+        let channel = self.channel_mut(id);
+        let values = channel
+            .leaves
+            .entry(origin)
+            .or_insert_with(|| vec![T::default(); ROOT::LeafType::SIZE].into_boxed_slice());
+        values[leaf_index::<ROOT>(coords - origin)] = value;
+        channel.dirty.insert(origin);
+        true
+    }
+
+    /// Returns the values of a channel for the leaf node at `origin`, indexed like its
+    /// occupancy mask, or None if the region holds the default value everywhere.
+    pub fn channel_leaf<T: 'static>(&self, id: ChannelId<T>, origin: UVec3) -> Option<&[T]> {
+        self.channel(id).leaves.get(&origin).map(|values| &**values)
+    }
+
+    /// Returns the values of a channel for the leaf node at `origin` for editing, indexed like
+    /// its occupancy mask, or None if the tree has no leaf node there. Values of voxels absent
+    /// from the leaf node are ignored, and reset when the voxels are added.
+    pub fn channel_leaf_mut<T: Copy + Default + 'static>(
+        &mut self,
+        id: ChannelId<T>,
+        origin: UVec3,
+    ) -> Option<&mut [T]> {
+        self.tree.iter_leaf_in_aabb(origin, origin).next()?;
+        let channel = self.channel_mut(id);
+        channel.dirty.insert(origin);
+        let values = channel
+            .leaves
+            .entry(origin)
+            .or_insert_with(|| vec![T::default(); ROOT::LeafType::SIZE].into_boxed_slice());
+        Some(&mut **values)
+    }
+
+    /// Returns the values of a channel for all leaf nodes, one after the other in the order of
+    /// [`Tree::iter_leaf`], along with the origin of each leaf node. Channels packed between the
+    /// same topology edits share the same layout, so each of them can be uploaded on its own.
+    pub fn pack_channel<T: Copy + Default + 'static>(
+        &self,
+        id: ChannelId<T>,
+    ) -> (Vec<UVec3>, Vec<T>) {
+        let channel = self.channel(id);
+        let mut origins = Vec::new();
+        let mut values = Vec::new();
+        for (origin, _) in self.tree.iter_leaf() {
+            origins.push(origin);
+            match channel.leaves.get(&origin) {
+                Some(leaf_values) => values.extend_from_slice(leaf_values),
+                None => values.resize(values.len() + ROOT::LeafType::SIZE, T::default()),
+            }
+        }
+        (origins, values)
+    }
+
+    /// Returns the origins of the leaf-sized regions whose values in the channel changed since
+    /// the last call, and clears them. Regions of released leaf nodes are included.
+    pub fn drain_dirty<T: 'static>(&mut self, id: ChannelId<T>) -> impl Iterator<Item = UVec3> {
+        std::mem::take(&mut self.channel_mut(id).dirty).into_iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use glam::{IVec3, UVec3};
+
+    use super::ChannelGrid;
+    use crate::{hierarchy, TreeCoords};
+
+    type MyRoot = hierarchy!(#, 3, 2; u8);
+
+    #[test]
+    fn test_channels_follow_topology() {
+        use rand::prelude::*;
+        let mut rng = StdRng::seed_from_u64(9);
+        let mut grid = ChannelGrid::<MyRoot>::new();
+        let palette = grid.add_channel::<u16>("palette");
+        let mut damage = grid.add_channel::<f32>("damage");
+        assert_eq!(grid.channel_id::<u16>("palette"), Some(palette));
+        assert_eq!(grid.channel_id::<u32>("palette"), None);
+
+        let mut expected: HashMap<IVec3, (u16, f32)> = HashMap::new();
+        for i in 0..4000 {
+            let coords = IVec3::new(
+                rng.gen_range(-20..20),
+                rng.gen_range(-20..20),
+                rng.gen_range(-20..20),
+            );
+            match rng.gen_range(0..4) {
+                0 => {
+                    grid.set_value(coords, None);
+                    expected.remove(&coords);
+                }
+                1 => {
+                    if grid.get_value(coords).is_none() {
+                        expected.insert(coords, (0, 0.0));
+                    }
+                    grid.set_value(coords, Some(1));
+                }
+                _ => {
+                    let written = grid.set(palette, coords, i as u16);
+                    assert_eq!(grid.set(damage, coords, i as f32), written);
+                    if let Some(values) = expected.get_mut(&coords) {
+                        *values = (i as u16, i as f32);
+                    }
+                    assert_eq!(written, expected.contains_key(&coords));
+                }
+            }
+        }
+        grid.fill(IVec3::new(-20, -20, -20), IVec3::new(-10, 20, 20), None);
+        expected.retain(|coords, _| coords.x > -10);
+        // Voxels added by a fill start with default values, while the others keep theirs.
+        let (fill_min, fill_max) = (IVec3::new(-12, -3, -5), IVec3::new(-5, 6, 2));
+        grid.fill(fill_min, fill_max, Some(2));
+        for x in fill_min.x..=fill_max.x {
+            for y in fill_min.y..=fill_max.y {
+                for z in fill_min.z..=fill_max.z {
+                    expected.entry(IVec3::new(x, y, z)).or_insert((0, 0.0));
+                }
+            }
+        }
+        for (coords, (palette_value, damage_value)) in expected.iter() {
+            assert_eq!(grid.get(palette, *coords), Some(*palette_value));
+            assert_eq!(grid.get(damage, *coords), Some(*damage_value));
+        }
+        assert_eq!(grid.tree().count_active(), expected.len() as u64);
+
+        // Packed channels follow the leaf nodes of the tree.
+        let (origins, values) = grid.pack_channel(palette);
+        assert_eq!(origins.len(), grid.tree().iter_leaf().count());
+        assert_eq!(values.len(), origins.len() * 64);
+        let (coords, (palette_value, _)) = expected.iter().next().unwrap();
+        let coords: UVec3 = TreeCoords::<MyRoot>::to_tree_coords(*coords);
+        let origin = coords & !3;
+        let leaf = origins.iter().position(|o| *o == origin).unwrap();
+        let local = coords - origin;
+        assert_eq!(
+            values[leaf * 64 + (local.x * 16 + local.y * 4 + local.z) as usize],
+            *palette_value
+        );
+        assert!(grid.drain_dirty(palette).count() > 0);
+        assert_eq!(grid.drain_dirty(palette).count(), 0);
+
+        // Channels are removed and added at runtime.
+        grid.remove_channel(damage);
+        assert_eq!(grid.channel_names().collect::<Vec<_>>(), vec!["palette"]);
+        damage = grid.add_channel::<f32>("damage");
+        for coords in expected.keys() {
+            assert_eq!(grid.get(damage, *coords), Some(0.0));
+        }
+    }
+}
@@ -0,0 +1,736 @@
+use fxhash::{FxHashMap, FxHashSet};
+use glam::{IVec3, UVec3};
+
+use crate::{
+    Accessor, IsLeaf, Node, NodeConst, Tree, ALL_NEIGHBORS, EDGE_NEIGHBORS, FACE_NEIGHBORS,
+};
+
+/// Which neighbors of a voxel are connected to it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Connectivity {
+    /// 6 neighbors sharing a face.
+    Face,
+    /// 18 neighbors sharing a face or an edge.
+    Edge,
+    /// 26 neighbors sharing a face, an edge or a corner.
+    Vertex,
+}
+
+impl Connectivity {
+    pub fn offsets(self) -> &'static [IVec3] {
+        match self {
+            Connectivity::Face => &FACE_NEIGHBORS,
+            Connectivity::Edge => &EDGE_NEIGHBORS,
+            Connectivity::Vertex => &ALL_NEIGHBORS,
+        }
+    }
+}
+
+/// A group of connected voxels found by [`Tree::connected_components`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Component {
+    /// The voxel the component was found from, either within a leaf node or at the min corner
+    /// of a tile. Pass it to [`Tree::connected_component`] to extract the voxels of the component.
+    pub seed: UVec3,
+    /// Min corner of the bounding box of the component.
+    pub min: UVec3,
+    /// Max corner (inclusive) of the bounding box of the component.
+    pub max: UVec3,
+    pub voxel_count: u64,
+}
+
+/// Voxels visited together by [`OccupancyCache::flood`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Unit {
+    Voxel(UVec3),
+    /// A leaf-sized block where all voxels have the same occupancy, given by its origin.
+    Block(UVec3),
+    /// A tile, given by its min and max corners (inclusive).
+    Tile(UVec3, UVec3),
+}
+
+impl Unit {
+    /// Min and max corners (inclusive) of the voxels of the unit.
+    #[inline]
+    fn bounds<ROOT: Node>(self) -> (UVec3, UVec3) {
+        match self {
+            Unit::Voxel(coords) => (coords, coords),
+            Unit::Block(origin) => (origin, origin | ROOT::LeafType::EXTENT_MASK),
+            Unit::Tile(min, max) => (min, max),
+        }
+    }
+
+    #[inline]
+    fn voxel_count<ROOT: Node>(self) -> u64 {
+        let (min, max) = self.bounds::<ROOT>();
+        let size = (max - min).as_u64vec3() + 1;
+        size.x * size.y * size.z
+    }
+}
+
+/// One bit per voxel, stored as a bitmask for each leaf-sized block with the same layout as
+/// the occupancy of the leaf nodes.
+struct BlockBits<ROOT: Node> {
+    blocks: FxHashMap<UVec3, Vec<u64>>,
+    _marker: std::marker::PhantomData<ROOT>,
+}
+
+impl<ROOT: Node> BlockBits<ROOT> {
+    const WORDS: usize = (ROOT::LeafType::SIZE + 63) / 64;
+
+    fn new() -> Self {
+        Self {
+            blocks: FxHashMap::default(),
+            _marker: std::marker::PhantomData,
+        }
+    }
+
+    #[inline]
+    fn index(coords: UVec3) -> (UVec3, usize) {
+        let log2 = ROOT::LeafType::EXTENT_LOG2;
+        let local = coords & ROOT::LeafType::EXTENT_MASK;
+        let index = ((local.x as usize) << (log2.y + log2.z))
+            | ((local.y as usize) << log2.z)
+            | (local.z as usize);
+        (coords & !ROOT::LeafType::EXTENT_MASK, index)
+    }
+
+    /// Inverse of [`BlockBits::index`].
+    #[inline]
+    fn coords(origin: UVec3, index: usize) -> UVec3 {
+        let log2 = ROOT::LeafType::EXTENT_LOG2;
+        let local = UVec3::new(
+            (index >> (log2.y + log2.z)) as u32,
+            (index >> log2.z) as u32,
+            index as u32,
+        );
+        origin | (local & ROOT::LeafType::EXTENT_MASK)
+    }
+
+    #[inline]
+    fn contains(&self, coords: UVec3) -> bool {
+        let (origin, index) = Self::index(coords);
+        self.blocks
+            .get(&origin)
+            .map_or(false, |words| words[index / 64] & (1 << (index % 64)) != 0)
+    }
+
+    /// Set the bit of the voxel at `coords`. Returns false if it was already set.
+    #[inline]
+    fn insert(&mut self, coords: UVec3) -> bool {
+        let (origin, index) = Self::index(coords);
+        let words = self
+            .blocks
+            .entry(origin)
+            .or_insert_with(|| vec![0; Self::WORDS]);
+        let bit = 1 << (index % 64);
+        let inserted = words[index / 64] & bit == 0;
+        words[index / 64] |= bit;
+        inserted
+    }
+
+    /// Set the bits of all voxels in the block at `origin`.
+    #[inline]
+    fn insert_block(&mut self, origin: UVec3) {
+        self.blocks.insert(origin, vec![u64::MAX; Self::WORDS]);
+    }
+}
+
+/// Units already visited by [`OccupancyCache::flood`].
+struct Visited<ROOT: Node> {
+    voxels: BlockBits<ROOT>,
+    /// Min corners of the visited tiles.
+    tiles: FxHashSet<UVec3>,
+}
+
+impl<ROOT: Node> Visited<ROOT> {
+    fn new() -> Self {
+        Self {
+            voxels: BlockBits::new(),
+            tiles: FxHashSet::default(),
+        }
+    }
+
+    /// Whether the voxel at `coords`, or the tile with its min corner at `coords`, was visited.
+    #[inline]
+    fn contains(&self, coords: UVec3) -> bool {
+        self.voxels.contains(coords) || self.tiles.contains(&coords)
+    }
+
+    /// Mark the unit as visited. Returns false if it was already visited.
+    #[inline]
+    fn insert(&mut self, unit: Unit) -> bool {
+        match unit {
+            Unit::Voxel(coords) => self.voxels.insert(coords),
+            Unit::Block(origin) => {
+                if self.voxels.contains(origin) {
+                    return false;
+                }
+                self.voxels.insert_block(origin);
+                true
+            }
+            Unit::Tile(min, _) => self.tiles.insert(min),
+        }
+    }
+}
+
+/// Occupancy of the tree, read from the bitmasks of the leaf nodes and cached one leaf-sized
+/// block at a time.
+struct OccupancyCache<'a, ROOT: Node>
+where
+    [(); ROOT::LEVEL as usize]: Sized,
+{
+    leaves: FxHashMap<UVec3, &'a ROOT::LeafType>,
+    /// Max corner (inclusive) of each tile, keyed by its min corner.
+    tiles: FxHashMap<UVec3, UVec3>,
+    /// Distinct extents of the tiles in the tree, used to find the tile containing a voxel.
+    tile_extents: Vec<UVec3>,
+    accessor: Accessor<'a, ROOT>,
+    occupancy: BlockBits<ROOT>,
+}
+
+impl<'a, ROOT: Node> OccupancyCache<'a, ROOT>
+where
+    [(); ROOT::LEVEL as usize + 1]: Sized,
+{
+    fn new(tree: &'a Tree<ROOT>) -> Self {
+        let mut tiles = FxHashMap::default();
+        let mut tile_extents = Vec::new();
+        tree.for_each_tile_in_aabb(UVec3::ZERO, ROOT::EXTENT_MASK, |min, max, _| {
+            tiles.insert(min, max);
+            let extent = max - min + 1;
+            if !tile_extents.contains(&extent) {
+                tile_extents.push(extent);
+            }
+        });
+        Self {
+            leaves: tree.iter_leaf().collect(),
+            tiles,
+            tile_extents,
+            accessor: tree.accessor(),
+            occupancy: BlockBits::new(),
+        }
+    }
+
+    /// Occupancy bits of the leaf-sized block at `origin`.
+    #[inline]
+    fn block(&mut self, origin: UVec3) -> &[u64]
+    where
+        ROOT: ~const NodeConst,
+    {
+        if !self.occupancy.blocks.contains_key(&origin) {
+            let mut words = vec![0; BlockBits::<ROOT>::WORDS];
+            if let Some(leaf) = self.leaves.get(&origin) {
+                leaf.get_occupancy(&mut words);
+            } else if self.accessor.get(origin).is_some() {
+                // Blocks without a leaf node are either empty or within a tile.
+                words.fill(u64::MAX);
+            }
+            self.occupancy.blocks.insert(origin, words);
+        }
+        &self.occupancy.blocks[&origin]
+    }
+
+    #[inline]
+    fn is_occupied(&mut self, coords: UVec3) -> bool
+    where
+        ROOT: ~const NodeConst,
+    {
+        let (origin, index) = BlockBits::<ROOT>::index(coords);
+        self.block(origin)[index / 64] & (1 << (index % 64)) != 0
+    }
+
+    /// The largest unit containing `coords` with uniform occupancy: the tile or the block
+    /// of the voxel if it has one, or the voxel itself.
+    fn unit_at(&mut self, coords: UVec3) -> Unit
+    where
+        ROOT: ~const NodeConst,
+    {
+        let origin = coords & !ROOT::LeafType::EXTENT_MASK;
+        let count: usize = self
+            .block(origin)
+            .iter()
+            .map(|word| word.count_ones() as usize)
+            .sum();
+        if count == ROOT::LeafType::SIZE && !self.leaves.contains_key(&origin) {
+            for extent in self.tile_extents.iter() {
+                let min = coords & !(*extent - 1);
+                if let Some(max) = self.tiles.get(&min) {
+                    return Unit::Tile(min, *max);
+                }
+            }
+        }
+        if count == 0 || count == ROOT::LeafType::SIZE {
+            return Unit::Block(origin);
+        }
+        Unit::Voxel(coords)
+    }
+
+    /// Visit the units connected to `seed` with the given occupancy, and not visited before.
+    /// Only units within `min` and `max` (inclusive) are visited. Returns true and stops early
+    /// if an unoccupied region continues beyond the box or beyond the boundary of the tree.
+    ///
+    /// Tiles and leaf-sized blocks with uniform occupancy are visited at once, looking up only
+    /// the voxels around their boundary.
+    fn flood(
+        &mut self,
+        visited: &mut Visited<ROOT>,
+        seed: UVec3,
+        connectivity: Connectivity,
+        (min, max): (UVec3, UVec3),
+        occupied: bool,
+        mut visit: impl FnMut(Unit),
+    ) -> bool
+    where
+        ROOT: ~const NodeConst,
+    {
+        let outside = |unit: Unit| {
+            let (unit_min, unit_max) = unit.bounds::<ROOT>();
+            unit_min.cmplt(min).any() || unit_max.cmpgt(max).any()
+        };
+        let seed = self.unit_at(seed);
+        if outside(seed) {
+            return true;
+        }
+        let mut stack = vec![seed];
+        visited.insert(seed);
+        let log2 = ROOT::LeafType::EXTENT_LOG2;
+        let mut neighbors = Vec::new();
+        while let Some(unit) = stack.pop() {
+            visit(unit);
+            let (unit_min, unit_max) = unit.bounds::<ROOT>();
+            for offset in connectivity.offsets() {
+                // The voxels next to the unit in the direction of the offset.
+                let Some((near_min, near_max)) =
+                    neighbor_region::<ROOT>(unit_min, unit_max, *offset)
+                else {
+                    if occupied {
+                        continue;
+                    }
+                    return true;
+                };
+                let (first, last) = (near_min >> log2, near_max >> log2);
+                for x in first.x..=last.x {
+                    for y in first.y..=last.y {
+                        for z in first.z..=last.z {
+                            let origin = UVec3::new(x, y, z) << log2;
+                            let block_min = near_min.max(origin);
+                            let block_max = near_max.min(origin | ROOT::LeafType::EXTENT_MASK);
+                            neighbors.clear();
+                            match self.unit_at(block_min) {
+                                Unit::Voxel(_) => {
+                                    for x in block_min.x..=block_max.x {
+                                        for y in block_min.y..=block_max.y {
+                                            for z in block_min.z..=block_max.z {
+                                                neighbors.push(Unit::Voxel(UVec3::new(x, y, z)));
+                                            }
+                                        }
+                                    }
+                                }
+                                neighbor => neighbors.push(neighbor),
+                            }
+                            for neighbor in neighbors.iter().copied() {
+                                let (neighbor_min, _) = neighbor.bounds::<ROOT>();
+                                if self.is_occupied(neighbor_min) != occupied {
+                                    continue;
+                                }
+                                if outside(neighbor) {
+                                    return true;
+                                }
+                                if visited.insert(neighbor) {
+                                    stack.push(neighbor);
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        false
+    }
+}
+
+/// Box (inclusive) of the voxels next to the box between `min` and `max` in the direction
+/// of `offset`, or None if they are beyond the boundary of the tree.
+#[inline]
+fn neighbor_region<ROOT: Node>(min: UVec3, max: UVec3, offset: IVec3) -> Option<(UVec3, UVec3)> {
+    let mut near_min = min;
+    let mut near_max = max;
+    for axis in 0..3 {
+        match offset[axis] {
+            -1 => {
+                near_min[axis] = min[axis].checked_sub(1)?;
+                near_max[axis] = near_min[axis];
+            }
+            1 => {
+                near_max[axis] = max[axis].checked_add(1)?;
+                near_min[axis] = near_max[axis];
+            }
+            _ => {}
+        }
+    }
+    if near_max.cmpgt(ROOT::EXTENT_MASK).any() {
+        return None;
+    }
+    Some((near_min, near_max))
+}
+
+impl<ROOT: Node> Tree<ROOT>
+where
+    [(); ROOT::LEVEL as usize + 1]: Sized,
+{
+    /// Returns a tree with the voxels connected to `seed`, or None if `seed` is unoccupied.
+    /// ```
+    /// #![feature(generic_const_exprs)]
+    /// use dust_vdb::{Tree, hierarchy, Connectivity};
+    /// use glam::UVec3;
+    /// let mut tree = Tree::<hierarchy!(4, 2; u8)>::new();
+    /// tree.fill(UVec3::new(0, 0, 0), UVec3::new(3, 3, 3), Some(1));
+    /// tree.set_value(UVec3::new(4, 4, 4), Some(2));
+    /// // Only touches the cube by a corner.
+    /// let component = tree.connected_component(UVec3::new(4, 4, 4), Connectivity::Face).unwrap();
+    /// assert_eq!(component.count_active(), 1);
+    /// let component = tree.connected_component(UVec3::new(4, 4, 4), Connectivity::Vertex).unwrap();
+    /// assert_eq!(component.count_active(), 65);
+    /// ```
+    pub fn connected_component(&self, seed: UVec3, connectivity: Connectivity) -> Option<Self>
+    where
+        ROOT: ~const NodeConst,
+    {
+        self.get_value(seed)?;
+        let mut units: Vec<Unit> = Vec::new();
+        let mut cache = OccupancyCache::new(self);
+        cache.flood(
+            &mut Visited::new(),
+            seed,
+            connectivity,
+            (UVec3::ZERO, ROOT::EXTENT_MASK),
+            true,
+            |unit| units.push(unit),
+        );
+        Some(self.copy_units(&units))
+    }
+
+    /// Label the groups of connected voxels in the tree, returning their bounding boxes and
+    /// voxel counts. Components are found going through the leaf nodes and then the tiles of
+    /// the tree, in the order of their seeds.
+    /// ```
+    /// #![feature(generic_const_exprs)]
+    /// use dust_vdb::{Tree, hierarchy, Connectivity};
+    /// use glam::UVec3;
+    /// let mut tree = Tree::<hierarchy!(4, 2)>::new();
+    /// tree.fill(UVec3::new(0, 0, 0), UVec3::new(9, 0, 9), Some(true));
+    /// // A column cut off from the floor.
+    /// tree.fill(UVec3::new(5, 2, 5), UVec3::new(5, 6, 5), Some(true));
+    /// let components = tree.connected_components(Connectivity::Face);
+    /// assert_eq!(components.len(), 2);
+    /// assert_eq!(components[0].voxel_count, 100);
+    /// assert_eq!(components[1].seed, UVec3::new(5, 2, 5));
+    /// assert_eq!(components[1].max, UVec3::new(5, 6, 5));
+    /// ```
+    pub fn connected_components(&self, connectivity: Connectivity) -> Vec<Component>
+    where
+        ROOT: ~const NodeConst,
+    {
+        let mut components = Vec::new();
+        let mut cache = OccupancyCache::new(self);
+        let mut visited = Visited::new();
+        for seed in self.component_seeds(&cache) {
+            if visited.contains(seed) {
+                continue;
+            }
+            let mut component = Component {
+                seed,
+                min: seed,
+                max: seed,
+                voxel_count: 0,
+            };
+            cache.flood(
+                &mut visited,
+                seed,
+                connectivity,
+                (UVec3::ZERO, ROOT::EXTENT_MASK),
+                true,
+                |unit| {
+                    let (min, max) = unit.bounds::<ROOT>();
+                    component.min = component.min.min(min);
+                    component.max = component.max.max(max);
+                    component.voxel_count += unit.voxel_count::<ROOT>();
+                },
+            );
+            components.push(component);
+        }
+        components
+    }
+
+    /// Split the tree into one tree for each group of connected voxels,
+    /// in the same order as [`Tree::connected_components`].
+    pub fn split_components(&self, connectivity: Connectivity) -> Vec<Self>
+    where
+        ROOT: ~const NodeConst,
+    {
+        let mut trees = Vec::new();
+        let mut cache = OccupancyCache::new(self);
+        let mut visited = Visited::new();
+        let mut units: Vec<Unit> = Vec::new();
+        for seed in self.component_seeds(&cache) {
+            if visited.contains(seed) {
+                continue;
+            }
+            units.clear();
+            cache.flood(
+                &mut visited,
+                seed,
+                connectivity,
+                (UVec3::ZERO, ROOT::EXTENT_MASK),
+                true,
+                |unit| units.push(unit),
+            );
+            trees.push(self.copy_units(&units));
+        }
+        trees
+    }
+
+    /// Fill the unoccupied region connected to `seed` with `value`, without leaving the box
+    /// between `min` and `max` (inclusive). Returns the number of voxels filled.
+    ///
+    /// Nothing is filled and None is returned if the region is not enclosed, which is when it
+    /// continues beyond the box or reaches the boundary of the tree. This is also the case when
+    /// `seed` is occupied or outside of the box.
+    /// ```
+    /// #![feature(generic_const_exprs)]
+    /// use dust_vdb::{Tree, hierarchy, Connectivity};
+    /// use glam::UVec3;
+    /// let mut tree = Tree::<hierarchy!(4, 2; u8)>::new();
+    /// // A hollow room.
+    /// tree.fill(UVec3::new(10, 10, 10), UVec3::new(14, 14, 14), Some(1));
+    /// tree.fill(UVec3::new(11, 11, 11), UVec3::new(13, 13, 13), None);
+    /// let (min, max) = (UVec3::new(8, 8, 8), UVec3::new(16, 16, 16));
+    /// let filled = tree.flood_fill(UVec3::new(12, 12, 12), min, max, Connectivity::Face, 2);
+    /// assert_eq!(filled, Some(27));
+    /// assert_eq!(tree.get_value(UVec3::new(11, 13, 12)), Some(2));
+    ///
+    /// // Break the wall, and the outside is not enclosed within the box.
+    /// tree.set_value(UVec3::new(10, 12, 12), None);
+    /// let filled = tree.flood_fill(UVec3::new(10, 12, 12), min, max, Connectivity::Face, 3);
+    /// assert_eq!(filled, None);
+    /// assert_eq!(tree.get_value(UVec3::new(9, 12, 12)), None);
+    /// ```
+    pub fn flood_fill(
+        &mut self,
+        seed: UVec3,
+        min: UVec3,
+        max: UVec3,
+        connectivity: Connectivity,
+        value: ROOT::Voxel,
+    ) -> Option<u64>
+    where
+        ROOT: ~const NodeConst,
+    {
+        if seed.cmplt(min).any() || seed.cmpgt(max).any() || self.get_value(seed).is_some() {
+            return None;
+        }
+        let mut units: Vec<Unit> = Vec::new();
+        let mut cache = OccupancyCache::new(self);
+        let escaped = cache.flood(
+            &mut Visited::new(),
+            seed,
+            connectivity,
+            (min, max),
+            false,
+            |unit| units.push(unit),
+        );
+        if escaped {
+            return None;
+        }
+        let mut count = 0;
+        {
+            let mut accessor = self.accessor_mut();
+            for unit in units.iter() {
+                if let Unit::Voxel(coords) = unit {
+                    accessor.set(*coords, Some(value));
+                }
+            }
+        }
+        for unit in units {
+            if !matches!(unit, Unit::Voxel(_)) {
+                let (unit_min, unit_max) = unit.bounds::<ROOT>();
+                self.fill(unit_min, unit_max, Some(value));
+            }
+            count += unit.voxel_count::<ROOT>();
+        }
+        Some(count)
+    }
+
+    /// Seeds to find the components of the tree from: the occupied voxels of the leaf nodes,
+    /// then the min corners of the tiles.
+    fn component_seeds(&self, cache: &OccupancyCache<ROOT>) -> Vec<UVec3> {
+        let mut seeds = Vec::new();
+        let mut words = vec![0; BlockBits::<ROOT>::WORDS];
+        for (origin, leaf) in self.iter_leaf() {
+            leaf.get_occupancy(&mut words);
+            for (i, word) in words.iter().enumerate() {
+                let mut word = *word;
+                while word != 0 {
+                    let index = i * 64 + word.trailing_zeros() as usize;
+                    seeds.push(BlockBits::<ROOT>::coords(origin, index));
+                    word &= word - 1;
+                }
+            }
+        }
+        let mut tiles: Vec<UVec3> = cache.tiles.keys().copied().collect();
+        tiles.sort_unstable_by_key(|min| min.to_array());
+        seeds.extend(tiles);
+        seeds
+    }
+
+    /// Returns a new tree with the values of the voxels within `units`.
+    fn copy_units(&self, units: &[Unit]) -> Self
+    where
+        ROOT: ~const NodeConst,
+    {
+        let mut tree = Self::new();
+        for unit in units.iter() {
+            if let Unit::Tile(min, max) = unit {
+                tree.fill(*min, *max, self.get_value(*min));
+            }
+        }
+        let mut src = self.accessor();
+        let mut dst = tree.accessor_mut();
+        for unit in units.iter() {
+            let (min, max) = match *unit {
+                Unit::Tile(..) => continue,
+                unit => unit.bounds::<ROOT>(),
+            };
+            for x in min.x..=max.x {
+                for y in min.y..=max.y {
+                    for z in min.z..=max.z {
+                        let coords = UVec3::new(x, y, z);
+                        dst.set(coords, src.get(coords));
+                    }
+                }
+            }
+        }
+        tree
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashSet, VecDeque};
+
+    use glam::UVec3;
+
+    use super::Connectivity;
+    use crate::{hierarchy, stencil::offset_coords, Tree};
+
+    type MyRoot = hierarchy!(3, 2, 2; u8);
+
+    /// Breadth-first search over the voxels of the tree with `get_value`.
+    fn reference_component(
+        tree: &Tree<MyRoot>,
+        seed: UVec3,
+        connectivity: Connectivity,
+    ) -> HashSet<UVec3> {
+        let mut visited = HashSet::from([seed]);
+        let mut queue = VecDeque::from([seed]);
+        while let Some(coords) = queue.pop_front() {
+            for offset in connectivity.offsets() {
+                let Some(neighbor) = offset_coords::<MyRoot>(coords, *offset) else {
+                    continue;
+                };
+                if tree.get_value(neighbor).is_some() && visited.insert(neighbor) {
+                    queue.push_back(neighbor);
+                }
+            }
+        }
+        visited
+    }
+
+    #[test]
+    fn test_connected_components() {
+        use rand::prelude::*;
+        let mut rng = rand::thread_rng();
+        let mut tree = Tree::<MyRoot>::new();
+        for _i in 0..3000 {
+            let location = UVec3::new(rng.gen(), rng.gen(), rng.gen()) >> 27;
+            tree.set_value(location, Some(rng.gen()));
+        }
+        // Tiles, and full leaf nodes next to them.
+        tree.fill(UVec3::new(32, 0, 0), UVec3::new(63, 31, 31), Some(7));
+        tree.fill(UVec3::new(64, 4, 8), UVec3::new(71, 7, 11), Some(8));
+        tree.fill(UVec3::new(96, 96, 96), UVec3::new(111, 111, 111), Some(9));
+
+        for connectivity in [Connectivity::Face, Connectivity::Edge, Connectivity::Vertex] {
+            let components = tree.connected_components(connectivity);
+            let trees = tree.split_components(connectivity);
+            assert_eq!(components.len(), trees.len());
+            let mut seen: HashSet<UVec3> = HashSet::new();
+            for (component, split) in components.iter().zip(trees.iter()) {
+                let expected = reference_component(&tree, component.seed, connectivity);
+                let voxels: HashSet<UVec3> = split.iter().collect();
+                assert_eq!(voxels, expected);
+                assert_eq!(component.voxel_count, expected.len() as u64);
+                assert_eq!(split.bounding_box(), Some((component.min, component.max)));
+                for coords in voxels.iter() {
+                    assert_eq!(split.get_value(*coords), tree.get_value(*coords));
+                    assert!(seen.insert(*coords));
+                }
+            }
+            assert_eq!(seen.len() as u64, tree.count_active());
+        }
+    }
+
+    #[test]
+    fn test_flood_fill() {
+        // A hollow room spanning empty leaf-sized blocks and nodes, with a pillar inside.
+        let mut tree = Tree::<MyRoot>::new();
+        tree.fill(UVec3::new(4, 4, 4), UVec3::new(60, 60, 60), Some(1));
+        tree.fill(UVec3::new(5, 5, 5), UVec3::new(59, 59, 59), None);
+        tree.fill(UVec3::new(16, 5, 16), UVec3::new(31, 59, 31), Some(2));
+        let expected = 55 * 55 * 55 - 16 * 55 * 16;
+
+        let mut escaped = tree.clone();
+        escaped.set_value(UVec3::new(60, 30, 30), None);
+        let filled = escaped.flood_fill(
+            UVec3::new(8, 8, 8),
+            UVec3::ZERO,
+            UVec3::splat(64),
+            Connectivity::Face,
+            3,
+        );
+        assert_eq!(filled, None);
+        assert_eq!(escaped.get_value(UVec3::new(8, 8, 8)), None);
+
+        let filled = tree.flood_fill(
+            UVec3::new(8, 8, 8),
+            UVec3::new(4, 4, 4),
+            UVec3::new(60, 60, 60),
+            Connectivity::Face,
+            3,
+        );
+        assert_eq!(filled, Some(expected));
+        for x in 0..64 {
+            for y in 0..64 {
+                for z in 0..64 {
+                    let coords = UVec3::new(x, y, z);
+                    let outside =
+                        coords.cmplt(UVec3::splat(4)).any() || coords.cmpgt(UVec3::splat(60)).any();
+                    let wall =
+                        coords.cmpeq(UVec3::splat(4)).any() || coords.cmpeq(UVec3::splat(60)).any();
+                    let pillar = (16..32).contains(&x) && (16..32).contains(&z);
+                    let value = if outside {
+                        None
+                    } else if wall {
+                        Some(1)
+                    } else if pillar {
+                        Some(2)
+                    } else {
+                        Some(3)
+                    };
+                    assert_eq!(tree.get_value(coords), value, "{:?}", coords);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,187 @@
+use glam::{IVec3, UVec3, Vec3};
+
+use crate::{Node, RootNode, Tree};
+
+/// Coordinates accepted by trees with `ROOT` as their root node.
+///
+/// All trees take [`UVec3`] coordinates, starting from zero. Trees with a [`RootNode`] at the root
+/// extend infinitely in both directions, so they additionally take [`IVec3`] coordinates.
+/// Signed coordinates are mapped onto the unsigned coordinates of the tree by flipping the sign bit,
+/// which keeps the ordering of coordinates and the alignment of nodes intact.
+/// As a result, `IVec3::ZERO` corresponds to `UVec3::splat(1 << 31)` in the same tree.
+/// ```
+/// #![feature(generic_const_exprs)]
+/// use dust_vdb::{Tree, hierarchy};
+/// use glam::{IVec3, UVec3};
+/// let mut tree = Tree::<hierarchy!(#, 4, 2; u8)>::new();
+/// tree.set_value(IVec3::new(-1, 20, -300), Some(1));
+/// tree.set_value(IVec3::ZERO, Some(2));
+/// assert_eq!(tree.get_value(IVec3::new(-1, 20, -300)), Some(1));
+/// assert_eq!(tree.get_value(UVec3::splat(1 << 31)), Some(2));
+/// assert_eq!(
+///     tree.bounding_box_signed(),
+///     Some((IVec3::new(-1, 0, -300), IVec3::new(0, 20, 0)))
+/// );
+/// ```
+pub trait TreeCoords<ROOT: Node>: Copy {
+    /// Tree coordinates of the zero coordinates of this type. Positions given in the space of
+    /// these coordinates, like the points passed to [`Tree::fill_sdf`], are measured from there.
+    const ORIGIN: UVec3;
+    fn to_tree_coords(self) -> UVec3;
+    fn from_tree_coords(coords: UVec3) -> Self;
+}
+
+impl<ROOT: Node> TreeCoords<ROOT> for UVec3 {
+    const ORIGIN: UVec3 = UVec3::ZERO;
+    #[inline]
+    fn to_tree_coords(self) -> UVec3 {
+        self
+    }
+    #[inline]
+    fn from_tree_coords(coords: UVec3) -> Self {
+        coords
+    }
+}
+
+impl<CHILD: Node> TreeCoords<RootNode<CHILD>> for IVec3 {
+    const ORIGIN: UVec3 = UVec3::splat(1 << 31);
+    #[inline]
+    fn to_tree_coords(self) -> UVec3 {
+        from_signed(self)
+    }
+    #[inline]
+    fn from_tree_coords(coords: UVec3) -> Self {
+        to_signed(coords)
+    }
+}
+
+/// Returns the position of the minimum corner of the voxel at `coords` (in tree coordinates)
+/// in the space of the coordinates `C`. The difference to the origin of `C` is computed exactly
+/// before rounding to `f32`, so positions close to that origin stay precise.
+#[inline]
+pub(crate) fn position<ROOT: Node, C: TreeCoords<ROOT>>(coords: UVec3) -> Vec3 {
+    Vec3::new(
+        (coords.x as i64 - C::ORIGIN.x as i64) as f32,
+        (coords.y as i64 - C::ORIGIN.y as i64) as f32,
+        (coords.z as i64 - C::ORIGIN.z as i64) as f32,
+    )
+}
+
+/// Maps unsigned tree coordinates to signed coordinates by flipping the sign bit.
+#[inline]
+pub(crate) fn to_signed(coords: UVec3) -> IVec3 {
+    (coords ^ (1 << 31)).as_ivec3()
+}
+
+/// Maps signed coordinates to unsigned tree coordinates by flipping the sign bit.
+#[inline]
+pub(crate) fn from_signed(coords: IVec3) -> UVec3 {
+    coords.as_uvec3() ^ (1 << 31)
+}
+
+/// Signed versions of the iterators and queries, for trees with a [`RootNode`] at the root.
+impl<ROOT: Node> Tree<ROOT>
+where
+    [(); ROOT::LEVEL as usize + 1]: Sized,
+    IVec3: TreeCoords<ROOT>,
+{
+    /// Iterate over all voxels in the tree in signed coordinates. See [`Tree::iter`].
+    pub fn iter_signed(&self) -> impl Iterator<Item = IVec3> + '_ {
+        self.iter().map(IVec3::from_tree_coords)
+    }
+
+    /// Iterate over all voxels within `min` and `max` (inclusive) in signed coordinates.
+    /// See [`Tree::iter_in_aabb`].
+    /// ```
+    /// #![feature(generic_const_exprs)]
+    /// use dust_vdb::{Tree, hierarchy};
+    /// use glam::IVec3;
+    /// let mut tree = Tree::<hierarchy!(#, 4, 2)>::new();
+    /// tree.fill(IVec3::new(-2, -2, -2), IVec3::new(1, 1, 1), Some(true));
+    /// let voxels: Vec<IVec3> = tree
+    ///     .iter_in_aabb_signed(IVec3::new(-10, -10, -10), IVec3::new(-2, -2, -1))
+    ///     .collect();
+    /// assert_eq!(voxels, vec![IVec3::new(-2, -2, -2), IVec3::new(-2, -2, -1)]);
+    /// ```
+    pub fn iter_in_aabb_signed(&self, min: IVec3, max: IVec3) -> impl Iterator<Item = IVec3> + '_ {
+        self.iter_in_aabb(min.to_tree_coords(), max.to_tree_coords())
+            .map(IVec3::from_tree_coords)
+    }
+
+    /// Iterate over all leaf nodes in the tree along with their origin in signed coordinates.
+    /// See [`Tree::iter_leaf`].
+    pub fn iter_leaf_signed(&self) -> impl Iterator<Item = (IVec3, &ROOT::LeafType)> {
+        self.iter_leaf()
+            .map(|(origin, leaf)| (IVec3::from_tree_coords(origin), leaf))
+    }
+
+    /// Returns the smallest box (inclusive) containing all voxels in the tree in signed coordinates,
+    /// or None if the tree is empty. See [`Tree::bounding_box`].
+    pub fn bounding_box_signed(&self) -> Option<(IVec3, IVec3)> {
+        self.bounding_box()
+            .map(|(min, max)| (IVec3::from_tree_coords(min), IVec3::from_tree_coords(max)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use glam::IVec3;
+
+    use crate::{hierarchy, Tree};
+
+    #[test]
+    fn test_signed_coords() {
+        use rand::prelude::*;
+        let mut rng = rand::thread_rng();
+        type MyTree = Tree<hierarchy!(#, 3, 2; u8)>;
+        let mut tree = MyTree::new();
+        let mut model: HashMap<IVec3, u8> = HashMap::new();
+        for _i in 0..2000 {
+            let location = IVec3::new(
+                rng.gen_range(-100..100),
+                rng.gen_range(-100..100),
+                rng.gen_range(-100..100),
+            );
+            let value: u8 = rng.gen();
+            tree.set_value(location, Some(value));
+            model.insert(location, value);
+        }
+        let removed: Vec<IVec3> = model.keys().step_by(3).copied().collect();
+        let mut accessor = tree.accessor_mut();
+        for location in removed {
+            accessor.set(location, None);
+            model.remove(&location);
+        }
+        assert_eq!(tree.count_active(), model.len() as u64);
+
+        let mut accessor = tree.accessor();
+        for (location, value) in model.iter() {
+            assert_eq!(accessor.get(*location), Some(*value));
+        }
+        for location in tree.iter_signed() {
+            assert!(model.contains_key(&location));
+        }
+        let min = model.keys().fold(IVec3::MAX, |a, b| a.min(*b));
+        let max = model.keys().fold(IVec3::MIN, |a, b| a.max(*b));
+        assert_eq!(tree.bounding_box_signed(), Some((min, max)));
+
+        let expected = model
+            .keys()
+            .filter(|k| k.cmpge(IVec3::splat(-10)).all() && k.cmple(IVec3::splat(10)).all())
+            .count();
+        assert_eq!(
+            tree.iter_in_aabb_signed(IVec3::splat(-10), IVec3::splat(10))
+                .count(),
+            expected
+        );
+
+        let mut data: Vec<u8> = Vec::new();
+        tree.write_to(&mut data).unwrap();
+        let tree = MyTree::read_from(&mut data.as_slice()).unwrap();
+        for (location, value) in model.iter() {
+            assert_eq!(tree.get_value(*location), Some(*value));
+        }
+    }
+}
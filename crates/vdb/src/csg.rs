@@ -0,0 +1,240 @@
+use glam::UVec3;
+
+use crate::{Node, NodeConst, Tree};
+
+/// Boolean operations between two trees of the same hierarchy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CsgOperation {
+    /// Keep the voxels present in either tree.
+    /// Where both trees have a voxel, the value from the other tree wins.
+    Union,
+    /// Keep the voxels present in both trees, with the values from this tree.
+    Intersection,
+    /// Keep the voxels present in this tree but not in the other tree.
+    Difference,
+}
+
+impl<ROOT: Node> Tree<ROOT>
+where
+    [(); ROOT::LEVEL as usize + 1]: Sized,
+{
+    /// Combine the voxels of `other` into this tree with `operation`.
+    /// The trees are combined node by node. Subtrees empty in the tree that decides the result
+    /// are skipped, tiles are combined without visiting their voxels, and leaf nodes are combined
+    /// one mask word at a time.
+    /// ```
+    /// #![feature(generic_const_exprs)]
+    /// use dust_vdb::{CsgOperation, Tree, hierarchy};
+    /// use glam::UVec3;
+    /// let mut terrain = Tree::<hierarchy!(4, 2; u8)>::new();
+    /// terrain.fill(UVec3::new(0, 0, 0), UVec3::new(63, 15, 63), Some(1));
+    ///
+    /// let mut tunnel = Tree::<hierarchy!(4, 2; u8)>::new();
+    /// tunnel.fill(UVec3::new(0, 4, 30), UVec3::new(63, 7, 33), Some(0));
+    /// terrain.csg_in_place(&tunnel, CsgOperation::Difference);
+    /// assert_eq!(terrain.get_value(UVec3::new(10, 5, 31)), None);
+    /// assert_eq!(terrain.get_value(UVec3::new(10, 8, 31)), Some(1));
+    /// assert_eq!(terrain.count_active(), 64 * 16 * 64 - 64 * 4 * 4);
+    /// ```
+    pub fn csg_in_place(&mut self, other: &Self, operation: CsgOperation) {
+        self.mark_dirty_csg(other, operation);
+        self.root
+            .csg(&mut self.pool, &other.root, &other.pool, operation)
+    }
+
+    /// Returns a new tree combining the voxels of this tree and `other` with `operation`.
+    /// See [`Tree::csg_in_place`].
+    pub fn csg(&self, other: &Self, operation: CsgOperation) -> Self
+    where
+        ROOT: ~const NodeConst,
+    {
+        let mut tree = Self::new();
+        tree.csg_in_place(self, CsgOperation::Union);
+        tree.csg_in_place(other, operation);
+        tree
+    }
+
+    /// Combine the voxels of `other` placed at `offset` into this tree with `operation`.
+    /// Voxels of `other` moved outside of the extent of the tree are discarded.
+    ///
+    /// `other` is first moved into a shifted tree, which is then combined node by node like
+    /// [`Tree::csg_in_place`]. Tiles are moved as boxes. When `offset` is a multiple of the leaf
+    /// node extent, leaf nodes are moved as a whole; otherwise their voxels are moved one by one.
+    /// ```
+    /// #![feature(generic_const_exprs)]
+    /// use dust_vdb::{CsgOperation, Tree, hierarchy};
+    /// use glam::UVec3;
+    /// let mut terrain = Tree::<hierarchy!(#, 4, 2; u8)>::new();
+    /// let mut prefab = Tree::<hierarchy!(#, 4, 2; u8)>::new();
+    /// prefab.set_value(UVec3::new(0, 0, 0), Some(3));
+    /// prefab.set_value(UVec3::new(1, 2, 3), Some(4));
+    /// terrain.csg_in_place_with_offset(&prefab, UVec3::new(100, 50, 7), CsgOperation::Union);
+    /// assert_eq!(terrain.get_value(UVec3::new(100, 50, 7)), Some(3));
+    /// assert_eq!(terrain.get_value(UVec3::new(101, 52, 10)), Some(4));
+    /// assert_eq!(terrain.count_active(), 2);
+    /// ```
+    pub fn csg_in_place_with_offset(&mut self, other: &Self, offset: UVec3, operation: CsgOperation)
+    where
+        ROOT: ~const NodeConst,
+    {
+        if offset == UVec3::ZERO {
+            self.csg_in_place(other, operation);
+            return;
+        }
+        let mut shifted = Self::new();
+        if offset.cmple(ROOT::EXTENT_MASK).all() {
+            let max_coords = ROOT::EXTENT_MASK - offset;
+            other.for_each_tile_in_aabb(UVec3::ZERO, max_coords, |min, max, value| {
+                shifted.fill(min + offset, max.min(max_coords) + offset, Some(value));
+            });
+            let leaf_aligned = offset & ROOT::LeafType::EXTENT_MASK == UVec3::ZERO;
+            let mut accessor = other.accessor();
+            for (origin, leaf) in other.iter_leaf_in_aabb(UVec3::ZERO, max_coords) {
+                if leaf_aligned {
+                    // Leaf nodes of `other` line up with the leaf nodes of the shifted tree.
+                    unsafe {
+                        *shifted.ensure_leaf(origin + offset) = leaf.clone();
+                    }
+                    continue;
+                }
+                let mut shifted_accessor = shifted.accessor_mut();
+                for coords in leaf.iter(&[], origin) {
+                    if coords.cmple(max_coords).all() {
+                        shifted_accessor.set(coords + offset, accessor.get(coords));
+                    }
+                }
+            }
+        }
+        self.csg_in_place(&shifted, operation);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use glam::UVec3;
+
+    use super::CsgOperation;
+    use crate::{hierarchy, IsLeaf, Node, NodeConst, Tree};
+
+    fn random_tree<ROOT: Node<Voxel = u8> + NodeConst>(
+        rng: &mut impl rand::Rng,
+        shift: u32,
+    ) -> (Tree<ROOT>, HashMap<UVec3, u8>)
+    where
+        [(); ROOT::LEVEL as usize + 1]: Sized,
+    {
+        let mut tree = Tree::<ROOT>::new();
+        let mut model = HashMap::new();
+        for _i in 0..500 {
+            let location = UVec3::new(rng.gen(), rng.gen(), rng.gen()) >> shift;
+            let value: u8 = rng.gen();
+            tree.set_value(location, Some(value));
+            model.insert(location, value);
+        }
+        // Add tiles at the root level and at the internal level.
+        let min = UVec3::new(rng.gen(), rng.gen(), rng.gen()) >> (shift + 1);
+        let max = min + UVec3::new(rng.gen_range(0..40), rng.gen_range(0..40), 16);
+        let value: u8 = rng.gen();
+        tree.fill(min, max, Some(value));
+        for x in min.x..=max.x {
+            for y in min.y..=max.y {
+                for z in min.z..=max.z {
+                    model.insert(UVec3::new(x, y, z), value);
+                }
+            }
+        }
+        (tree, model)
+    }
+
+    fn combine(
+        a: &HashMap<UVec3, u8>,
+        b: &HashMap<UVec3, u8>,
+        operation: CsgOperation,
+    ) -> HashMap<UVec3, u8> {
+        match operation {
+            CsgOperation::Union => {
+                let mut result = a.clone();
+                result.extend(b.iter());
+                result
+            }
+            CsgOperation::Intersection => a
+                .iter()
+                .filter(|(k, _)| b.contains_key(k))
+                .map(|(k, v)| (*k, *v))
+                .collect(),
+            CsgOperation::Difference => a
+                .iter()
+                .filter(|(k, _)| !b.contains_key(k))
+                .map(|(k, v)| (*k, *v))
+                .collect(),
+        }
+    }
+
+    fn assert_matches<ROOT: Node<Voxel = u8> + NodeConst>(
+        tree: &Tree<ROOT>,
+        model: &HashMap<UVec3, u8>,
+    ) where
+        [(); ROOT::LEVEL as usize + 1]: Sized,
+    {
+        assert_eq!(tree.count_active(), model.len() as u64);
+        for (location, value) in model.iter() {
+            assert_eq!(tree.get_value(*location), Some(*value));
+        }
+        // Emptied leaf nodes were released.
+        for (_, leaf) in tree.iter_leaf() {
+            let mut occupancy = [0_u64; 1];
+            leaf.get_occupancy(&mut occupancy);
+            assert_ne!(occupancy[0], 0);
+        }
+    }
+
+    #[test]
+    fn test_csg() {
+        let mut rng = rand::thread_rng();
+        for operation in [
+            CsgOperation::Union,
+            CsgOperation::Intersection,
+            CsgOperation::Difference,
+        ] {
+            let (a, model_a) = random_tree::<hierarchy!(#, 3, 2; u8)>(&mut rng, 26);
+            let (b, model_b) = random_tree::<hierarchy!(#, 3, 2; u8)>(&mut rng, 26);
+            let expected = combine(&model_a, &model_b, operation);
+            let result = a.csg(&b, operation);
+            assert_matches(&result, &expected);
+            // The source trees are left untouched.
+            assert_matches(&a, &model_a);
+
+            let (mut a, model_a) = random_tree::<hierarchy!(3, 2, 2; u8)>(&mut rng, 25);
+            let (b, model_b) = random_tree::<hierarchy!(3, 2, 2; u8)>(&mut rng, 25);
+            let expected = combine(&model_a, &model_b, operation);
+            a.csg_in_place(&b, operation);
+            assert_matches(&a, &expected);
+        }
+    }
+
+    #[test]
+    fn test_csg_with_offset() {
+        let mut rng = rand::thread_rng();
+        for operation in [
+            CsgOperation::Union,
+            CsgOperation::Intersection,
+            CsgOperation::Difference,
+        ] {
+            // Leaf nodes are moved voxel by voxel first, and as a whole with the aligned offset.
+            for offset in [UVec3::new(13, 70, 2), UVec3::new(16, 68, 4)] {
+                let (mut a, model_a) = random_tree::<hierarchy!(3, 2, 2; u8)>(&mut rng, 25);
+                let (b, model_b) = random_tree::<hierarchy!(3, 2, 2; u8)>(&mut rng, 25);
+                let model_b: HashMap<UVec3, u8> = model_b
+                    .into_iter()
+                    .map(|(k, v)| (k + offset, v))
+                    .filter(|(k, _)| k.cmplt(UVec3::splat(128)).all())
+                    .collect();
+                let expected = combine(&model_a, &model_b, operation);
+                a.csg_in_place_with_offset(&b, offset, operation);
+                assert_matches(&a, &expected);
+            }
+        }
+    }
+}
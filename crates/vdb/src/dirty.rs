@@ -0,0 +1,295 @@
+use fxhash::FxHashSet;
+use glam::UVec3;
+
+use crate::{CsgOperation, Node, Tree};
+
+/// Regions of a tree modified since the last call to [`Tree::drain_dirty`].
+#[derive(Debug, Clone, Default)]
+pub(crate) struct DirtySet {
+    /// Origins of the leaf-sized regions touched by single voxel edits.
+    leaves: FxHashSet<UVec3>,
+    /// Boxes (inclusive) touched by bulk edits.
+    boxes: Vec<(UVec3, UVec3)>,
+}
+
+/// A node within the regions modified in a tree, see [`Tree::drain_dirty_nodes`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DirtyNode {
+    /// Level of the node, starting from the leaf nodes at 0. This is the index of the pool holding
+    /// the node in [`Tree::memory_stats`].
+    pub level: usize,
+    /// Location of the node in the pool of its level.
+    pub ptr: u32,
+    /// Coordinates of the first voxel in the node.
+    pub origin: UVec3,
+}
+
+impl DirtySet {
+    #[inline]
+    pub(crate) fn mark_leaf(&mut self, origin: UVec3) {
+        self.leaves.insert(origin);
+    }
+}
+
+impl<ROOT: Node> Tree<ROOT>
+where
+    [(); ROOT::LEVEL as usize + 1]: Sized,
+{
+    /// Start or stop recording the regions modified in the tree. Tracking is disabled by default.
+    /// Disabling tracking discards the regions recorded so far.
+    pub fn track_dirty(&mut self, enabled: bool) {
+        if !enabled {
+            self.dirty = None;
+        } else if self.dirty.is_none() {
+            self.dirty = Some(DirtySet::default());
+        }
+    }
+
+    pub fn is_tracking_dirty(&self) -> bool {
+        self.dirty.is_some()
+    }
+
+    /// Returns the boxes (inclusive) modified since tracking was enabled or since the last call,
+    /// and clears them. Boxes may overlap, and are not guaranteed to contain any changed voxels.
+    ///
+    /// Single voxel edits through [`Tree::set_value`] and [`crate::AccessorMut::set`] record the
+    /// leaf-sized region containing the voxel. Bulk edits like [`Tree::fill`] and
+    /// [`Tree::csg_in_place`] record the box they may have modified as a whole.
+    /// All leaf nodes visited by [`Tree::iter_leaf_mut`] are recorded, since they may have been
+    /// edited in any way. Leaf nodes may be allocated, collapsed into tiles or released within
+    /// a box, so a box should be rebuilt entirely with [`Tree::iter_leaf_in_aabb`].
+    /// ```
+    /// #![feature(generic_const_exprs)]
+    /// use dust_vdb::{Tree, hierarchy};
+    /// use glam::UVec3;
+    /// let mut tree = Tree::<hierarchy!(4, 2; u8)>::new();
+    /// tree.set_value(UVec3::new(1, 2, 3), Some(1));
+    /// tree.track_dirty(true);
+    /// tree.set_value(UVec3::new(1, 2, 3), Some(2));
+    /// tree.set_value(UVec3::new(2, 2, 2), Some(2));
+    /// tree.fill(UVec3::new(20, 0, 0), UVec3::new(40, 3, 3), Some(3));
+    /// let dirty: Vec<(UVec3, UVec3)> = tree.drain_dirty().collect();
+    /// assert_eq!(
+    ///     dirty,
+    ///     vec![
+    ///         (UVec3::new(0, 0, 0), UVec3::new(3, 3, 3)),
+    ///         (UVec3::new(20, 0, 0), UVec3::new(40, 3, 3)),
+    ///     ]
+    /// );
+    /// assert_eq!(tree.drain_dirty().count(), 0);
+    /// ```
+    pub fn drain_dirty(&mut self) -> impl Iterator<Item = (UVec3, UVec3)> {
+        let dirty = self.dirty.as_mut().map(std::mem::take).unwrap_or_default();
+        dirty
+            .leaves
+            .into_iter()
+            .map(|origin| (origin, origin + ROOT::LeafType::EXTENT_MASK))
+            .chain(dirty.boxes)
+    }
+
+    /// Returns the nodes within the regions modified since tracking was enabled or since the
+    /// last call, and clears the regions. See [`Tree::drain_dirty`] for the regions recorded.
+    ///
+    /// Every leaf and internal node intersecting a modified region is reported once, parents
+    /// before their children. Pointers refer to the nodes as they are now, so a node released
+    /// since the edit isn't reported, while the parent that dropped it is. Newly allocated nodes
+    /// may reuse the pointer of a released node. The root node is owned by the tree and is not
+    /// reported. This is meant for incremental updates of copies of the node pools, such as
+    /// GPU buffers or acceleration structures built over the leaf nodes.
+    /// ```
+    /// #![feature(generic_const_exprs)]
+    /// use dust_vdb::{DirtyNode, Tree, hierarchy};
+    /// use glam::UVec3;
+    /// let mut tree = Tree::<hierarchy!(3, 2, 2; u8)>::new();
+    /// tree.set_value(UVec3::new(1, 2, 3), Some(1));
+    /// tree.set_value(UVec3::new(40, 2, 3), Some(1));
+    /// tree.track_dirty(true);
+    /// tree.set_value(UVec3::new(41, 2, 3), Some(2));
+    /// let nodes = tree.drain_dirty_nodes();
+    /// assert_eq!(
+    ///     nodes,
+    ///     vec![
+    ///         DirtyNode { level: 1, ptr: 1, origin: UVec3::new(32, 0, 0) },
+    ///         DirtyNode { level: 0, ptr: 1, origin: UVec3::new(40, 0, 0) },
+    ///     ]
+    /// );
+    /// ```
+    pub fn drain_dirty_nodes(&mut self) -> Vec<DirtyNode> {
+        let regions: Vec<(UVec3, UVec3)> = self.drain_dirty().collect();
+        let mut seen: FxHashSet<(usize, u32)> = FxHashSet::default();
+        let mut nodes = Vec::new();
+        for (min, max) in regions {
+            self.root.for_each_node_in_aabb(
+                &self.pool,
+                UVec3::ZERO,
+                min,
+                max.min(ROOT::EXTENT_MASK),
+                &mut |level, ptr, origin| {
+                    if seen.insert((level, ptr)) {
+                        nodes.push(DirtyNode { level, ptr, origin });
+                    }
+                },
+            );
+        }
+        nodes
+    }
+
+    #[inline]
+    pub(crate) fn mark_dirty_voxel(&mut self, coords: UVec3) {
+        if let Some(dirty) = self.dirty.as_mut() {
+            dirty.mark_leaf(coords & !ROOT::LeafType::EXTENT_MASK);
+        }
+    }
+
+    pub(crate) fn mark_dirty_box(&mut self, min: UVec3, max: UVec3) {
+        if let Some(dirty) = self.dirty.as_mut() {
+            dirty.boxes.push((min, max));
+        }
+    }
+
+    /// Record the region modified by combining `other` into this tree with `operation`.
+    pub(crate) fn mark_dirty_csg(&mut self, other: &Self, operation: CsgOperation) {
+        if self.dirty.is_none() {
+            return;
+        }
+        // Union and difference only change voxels where the other tree has voxels,
+        // while intersection may remove any voxel of this tree.
+        let bounds = match operation {
+            CsgOperation::Union | CsgOperation::Difference => other.bounding_box(),
+            CsgOperation::Intersection => self.bounding_box(),
+        };
+        if let Some((min, max)) = bounds {
+            self.mark_dirty_box(min, max);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use glam::UVec3;
+
+    use crate::{hierarchy, CsgOperation, Node, Tree};
+
+    #[test]
+    fn test_drain_dirty() {
+        use rand::prelude::*;
+        let mut rng = rand::thread_rng();
+        type MyTree = Tree<hierarchy!(3, 2, 2; u8)>;
+        let mut tree = MyTree::new();
+        for _i in 0..1000 {
+            let location = UVec3::new(rng.gen(), rng.gen(), rng.gen()) >> 25;
+            tree.set_value(location, Some(rng.gen()));
+        }
+        assert!(!tree.is_tracking_dirty());
+        assert_eq!(tree.drain_dirty().count(), 0);
+
+        tree.track_dirty(true);
+        let before: HashMap<UVec3, u8> = tree
+            .iter()
+            .map(|coords| (coords, tree.get_value(coords).unwrap()))
+            .collect();
+        for _i in 0..200 {
+            let location = UVec3::new(rng.gen(), rng.gen(), rng.gen()) >> 25;
+            let value: Option<u8> = if rng.gen() { Some(rng.gen()) } else { None };
+            tree.set_value(location, value);
+        }
+        let mut accessor = tree.accessor_mut();
+        for _i in 0..200 {
+            let location = UVec3::new(rng.gen(), rng.gen(), rng.gen()) >> 25;
+            let value: Option<u8> = if rng.gen() { Some(rng.gen()) } else { None };
+            accessor.set(location, value);
+        }
+        tree.fill(UVec3::new(40, 0, 70), UVec3::new(90, 30, 127), None);
+        let mut other = MyTree::new();
+        other.fill(UVec3::new(0, 100, 0), UVec3::new(20, 110, 10), Some(5));
+        tree.csg_in_place(&other, CsgOperation::Union);
+
+        let dirty: Vec<(UVec3, UVec3)> = tree.drain_dirty().collect();
+        let inside = |coords: &UVec3| {
+            dirty
+                .iter()
+                .any(|(min, max)| coords.cmpge(*min).all() && coords.cmple(*max).all())
+        };
+        let after: HashMap<UVec3, u8> = tree
+            .iter()
+            .map(|coords| (coords, tree.get_value(coords).unwrap()))
+            .collect();
+        for (coords, value) in before.iter() {
+            if after.get(coords) != Some(value) {
+                assert!(inside(coords), "{:?}", coords);
+            }
+        }
+        for (coords, value) in after.iter() {
+            if before.get(coords) != Some(value) {
+                assert!(inside(coords), "{:?}", coords);
+            }
+        }
+        assert_eq!(tree.drain_dirty().count(), 0);
+
+        // Editing leaf nodes directly marks all of them.
+        for (_, leaf) in tree.iter_leaf_mut() {
+            leaf.active.set(0, true);
+        }
+        let dirty: Vec<(UVec3, UVec3)> = tree.drain_dirty().collect();
+        assert_eq!(dirty.len(), tree.iter_leaf().count());
+
+        tree.track_dirty(false);
+        tree.set_value(UVec3::new(1, 2, 3), Some(1));
+        assert_eq!(tree.drain_dirty().count(), 0);
+    }
+
+    #[test]
+    fn test_drain_dirty_nodes() {
+        use rand::prelude::*;
+        let mut rng = rand::thread_rng();
+        type MyRoot = hierarchy!(#, 3, 2; u8);
+        type MyLeaf = <MyRoot as Node>::LeafType;
+        let mut tree = Tree::<MyRoot>::new();
+        for _i in 0..1000 {
+            let location = UVec3::new(rng.gen(), rng.gen(), rng.gen()) >> 25;
+            tree.set_value(location, Some(rng.gen()));
+        }
+        tree.track_dirty(true);
+        let mut changed = Vec::new();
+        for _i in 0..100 {
+            let location = UVec3::new(rng.gen(), rng.gen(), rng.gen()) >> 25;
+            let value: Option<u8> = if rng.gen() { Some(rng.gen()) } else { None };
+            tree.set_value(location, value);
+            changed.push(location);
+        }
+        tree.fill(UVec3::new(40, 0, 70), UVec3::new(50, 30, 127), Some(1));
+
+        let nodes = tree.drain_dirty_nodes();
+        let mut seen = std::collections::HashSet::new();
+        for node in nodes.iter() {
+            assert!(seen.insert((node.level, node.ptr)));
+        }
+        let reported = |coords: UVec3| {
+            let origin = coords & !MyLeaf::EXTENT_MASK;
+            let Some((_, leaf)) = tree.iter_leaf_in_aabb(origin, origin).next() else {
+                // Released, or collapsed into a tile.
+                return true;
+            };
+            nodes.iter().any(|node| {
+                node.level == 0
+                    && node.origin == origin
+                    && std::ptr::eq(unsafe { tree.get_node::<MyLeaf>(node.ptr) }, leaf)
+            })
+        };
+        for coords in changed {
+            assert!(reported(coords), "{:?}", coords);
+        }
+        for (origin, _) in tree.iter_leaf_in_aabb(UVec3::new(40, 0, 70), UVec3::new(50, 30, 127)) {
+            assert!(reported(origin), "{:?}", origin);
+        }
+        // Internal nodes above the edited leaf nodes are reported as well.
+        for node in nodes.iter().filter(|node| node.level == 0) {
+            assert!(nodes
+                .iter()
+                .any(|parent| parent.level == 1 && parent.origin == node.origin & !0x1F));
+        }
+        assert!(tree.drain_dirty_nodes().is_empty());
+    }
+}
@@ -0,0 +1,705 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    marker::PhantomData,
+};
+
+use glam::UVec3;
+
+use crate::{
+    accessor::AccessorMut, parallel::leaf_local_coords, Node, NodeConst, Tree, TreeCoords,
+};
+
+/// Previous state of the voxels touched within a leaf-sized region.
+struct LeafDiff<V> {
+    /// One bit per touched voxel, indexed like the occupancy mask of leaf nodes.
+    touched: Box<[u64]>,
+    /// One bit per touched voxel which had a value.
+    occupied: Box<[u64]>,
+    /// While recording, the values of all voxels of the region, indexed like the masks.
+    /// Once committed, only the values of the occupied voxels, in the order of their index.
+    values: Vec<V>,
+}
+
+#[inline]
+fn get_bit(words: &[u64], index: usize) -> bool {
+    words[index / 64] & (1 << (index % 64)) != 0
+}
+
+#[inline]
+fn set_bit(words: &mut [u64], index: usize) {
+    words[index / 64] |= 1 << (index % 64);
+}
+
+pub(crate) fn iter_set_bits(words: &[u64]) -> impl Iterator<Item = usize> + '_ {
+    words.iter().enumerate().flat_map(|(word_index, word)| {
+        let mut word = *word;
+        std::iter::from_fn(move || {
+            if word == 0 {
+                return None;
+            }
+            let bit = word.trailing_zeros() as usize;
+            word &= word - 1;
+            Some(word_index * 64 + bit)
+        })
+    })
+}
+
+/// Previous state of a box overwritten as a whole, kept as tiles where the tree had tiles.
+struct BoxState<V> {
+    min: UVec3,
+    max: UVec3,
+    /// Tiles intersecting the box, clipped to it.
+    tiles: Vec<(UVec3, UVec3, V)>,
+    /// Origin of each leaf node intersecting the box, a mask of its voxels within the box which
+    /// had a value, and their values in the order of their index.
+    leaves: Vec<(UVec3, Box<[u64]>, Vec<V>)>,
+}
+
+/// Previous state of part of the tree, restored when undoing.
+enum Edit<V> {
+    /// Voxels touched one by one within the leaf-sized region at the given origin.
+    Voxels(UVec3, LeafDiff<V>),
+    /// A box overwritten as a whole.
+    Box(BoxState<V>),
+}
+
+/// The edits of a transaction, in the order they were made.
+struct Transaction<V> {
+    edits: Vec<Edit<V>>,
+}
+
+/// Transaction being recorded.
+struct Recording<V> {
+    transaction: Transaction<V>,
+    /// Index of the diff of each region in the transaction, since the last box edit.
+    index: HashMap<UVec3, usize>,
+    /// Origin and index of the region touched last, as edits tend to stay in the same region.
+    last: Option<(UVec3, usize)>,
+    /// Number of nested calls to [`EditJournal::begin`] not committed yet.
+    depth: u32,
+}
+
+/// Undo and redo history of the edits made to a tree.
+///
+/// Edits made through the journal record the previous value of each voxel the first time it is
+/// touched within a transaction. Previous values are kept per leaf-sized region, as a mask of the
+/// touched voxels, a mask of those which had a value, and the values themselves, so that
+/// regions touched by a brush stroke cost little more than the values they held. Filling a box
+/// records the tiles and the leaf nodes it overwrites instead, so large fills stay cheap.
+///
+/// Edits are grouped into transactions with [`EditJournal::begin`] and [`EditJournal::commit`].
+/// Edits made outside of a transaction form a transaction of their own.
+/// The tree must not be edited other than through the journal while the journal is in use,
+/// as undoing would then overwrite those edits.
+/// ```
+/// #![feature(generic_const_exprs)]
+/// use dust_vdb::{EditJournal, Tree, hierarchy};
+/// use glam::IVec3;
+/// let mut tree = Tree::<hierarchy!(#, 4, 2; u8)>::new();
+/// let mut journal = EditJournal::new();
+/// journal.set_value(&mut tree, IVec3::new(0, 0, 0), Some(1));
+///
+/// journal.begin();
+/// journal.fill(&mut tree, IVec3::new(0, 0, 0), IVec3::new(3, 3, 3), Some(2));
+/// journal.set_value(&mut tree, IVec3::new(-1, 0, 0), Some(3));
+/// journal.commit();
+///
+/// journal.undo(&mut tree);
+/// assert_eq!(tree.get_value(IVec3::new(0, 0, 0)), Some(1));
+/// assert_eq!(tree.get_value(IVec3::new(-1, 0, 0)), None);
+/// journal.redo(&mut tree);
+/// assert_eq!(tree.get_value(IVec3::new(0, 0, 0)), Some(2));
+/// assert_eq!(tree.get_value(IVec3::new(-1, 0, 0)), Some(3));
+/// ```
+pub struct EditJournal<ROOT: Node> {
+    undo: VecDeque<Transaction<ROOT::Voxel>>,
+    redo: Vec<Transaction<ROOT::Voxel>>,
+    recording: Option<Recording<ROOT::Voxel>>,
+    /// Maximum number of transactions kept for undo.
+    limit: usize,
+    _marker: PhantomData<ROOT>,
+}
+
+impl<ROOT: Node> Default for EditJournal<ROOT>
+where
+    ROOT::Voxel: Default,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<ROOT: Node> EditJournal<ROOT>
+where
+    ROOT::Voxel: Default,
+{
+    /// Number of words in the masks of each region.
+    const MASK_WORDS: usize = (ROOT::LeafType::SIZE + 63) / 64;
+
+    pub fn new() -> Self {
+        Self::with_limit(usize::MAX)
+    }
+
+    /// Create a journal keeping at most `limit` transactions for undo,
+    /// forgetting the oldest ones first.
+    pub fn with_limit(limit: usize) -> Self {
+        Self {
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            recording: None,
+            limit,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    /// Forget all transactions.
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+    }
+
+    /// Start a transaction. All edits until the matching [`EditJournal::commit`] are undone and
+    /// redone together. Transactions may be nested, in which case the edits are grouped into the
+    /// outermost transaction.
+    pub fn begin(&mut self) {
+        match self.recording.as_mut() {
+            Some(recording) => recording.depth += 1,
+            None => {
+                self.recording = Some(Recording {
+                    transaction: Transaction { edits: Vec::new() },
+                    index: HashMap::new(),
+                    last: None,
+                    depth: 1,
+                })
+            }
+        }
+    }
+
+    /// End a transaction started with [`EditJournal::begin`]. Once the outermost transaction
+    /// is committed, it can be undone and the transactions previously undone can no longer
+    /// be redone. Transactions without any edit are dropped.
+    pub fn commit(&mut self) {
+        let recording = self.recording.as_mut().expect("No transaction to commit");
+        recording.depth -= 1;
+        if recording.depth > 0 {
+            return;
+        }
+        let mut transaction = self.recording.take().unwrap().transaction;
+        if transaction.edits.is_empty() {
+            return;
+        }
+        for edit in transaction.edits.iter_mut() {
+            let Edit::Voxels(_, diff) = edit else {
+                continue;
+            };
+            let values = std::mem::take(&mut diff.values);
+            diff.values = iter_set_bits(&diff.occupied)
+                .map(|index| values[index])
+                .collect();
+        }
+        self.redo.clear();
+        if self.limit > 0 {
+            if self.undo.len() >= self.limit {
+                self.undo.pop_front();
+            }
+            self.undo.push_back(transaction);
+        }
+    }
+
+    /// Record `previous` as the value of the voxel at `coords` before the current transaction,
+    /// unless the voxel was already touched.
+    #[inline]
+    fn record(&mut self, coords: UVec3, previous: Option<ROOT::Voxel>) {
+        let recording = self.recording.as_mut().expect("No transaction recording");
+        let origin = coords & !ROOT::LeafType::EXTENT_MASK;
+        let diff_index = match recording.last {
+            Some((last_origin, diff_index)) if last_origin == origin => diff_index,
+            _ => {
+                let edits = &mut recording.transaction.edits;
+                let diff_index = *recording.index.entry(origin).or_insert_with(|| {
+                    edits.push(Edit::Voxels(
+                        origin,
+                        LeafDiff {
+                            touched: vec![0; Self::MASK_WORDS].into_boxed_slice(),
+                            occupied: vec![0; Self::MASK_WORDS].into_boxed_slice(),
+                            values: vec![Default::default(); ROOT::LeafType::SIZE],
+                        },
+                    ));
+                    edits.len() - 1
+                });
+                recording.last = Some((origin, diff_index));
+                diff_index
+            }
+        };
+        let Edit::Voxels(_, diff) = &mut recording.transaction.edits[diff_index] else {
+            unreachable!()
+        };
+        let local = coords - origin;
+        let log2 = ROOT::LeafType::EXTENT_LOG2;
+        let index = ((local.x as usize) << (log2.y + log2.z))
+            | ((local.y as usize) << log2.z)
+            | (local.z as usize);
+        if get_bit(&diff.touched, index) {
+            return;
+        }
+        set_bit(&mut diff.touched, index);
+        if let Some(value) = previous {
+            set_bit(&mut diff.occupied, index);
+            diff.values[index] = value;
+        }
+    }
+
+    /// Returns an accessor recording all edits made through it into the current transaction.
+    /// Without a current transaction, the edits made through the accessor form a transaction
+    /// committed when the accessor is dropped.
+    /// ```
+    /// #![feature(generic_const_exprs)]
+    /// use dust_vdb::{EditJournal, Tree, hierarchy};
+    /// use glam::UVec3;
+    /// let mut tree = Tree::<hierarchy!(4, 2; u8)>::new();
+    /// let mut journal = EditJournal::new();
+    /// let mut accessor = journal.accessor_mut(&mut tree);
+    /// for x in 0..64 {
+    ///     accessor.set(UVec3::new(x, 1, 2), Some(1));
+    /// }
+    /// drop(accessor);
+    /// assert_eq!(tree.count_active(), 64);
+    /// journal.undo(&mut tree);
+    /// assert_eq!(tree.count_active(), 0);
+    /// ```
+    pub fn accessor_mut<'a>(&'a mut self, tree: &'a mut Tree<ROOT>) -> JournalAccessor<'a, ROOT>
+    where
+        [(); ROOT::LEVEL as usize + 1]: Sized,
+    {
+        let began = self.recording.is_none();
+        if began {
+            self.begin();
+        }
+        JournalAccessor {
+            accessor: tree.accessor_mut(),
+            journal: self,
+            began,
+        }
+    }
+
+    /// Set the value of a voxel, recording its previous value. See [`Tree::set_value`].
+    pub fn set_value(
+        &mut self,
+        tree: &mut Tree<ROOT>,
+        coords: impl TreeCoords<ROOT>,
+        value: Option<ROOT::Voxel>,
+    ) where
+        [(); ROOT::LEVEL as usize + 1]: Sized,
+        ROOT: ~const NodeConst,
+    {
+        self.accessor_mut(tree).set(coords, value);
+    }
+
+    /// Set all voxels within `min` and `max` (inclusive) to `value`, recording the tiles and the
+    /// voxels of the leaf nodes previously in the box. See [`Tree::fill`].
+    pub fn fill<C: TreeCoords<ROOT>>(
+        &mut self,
+        tree: &mut Tree<ROOT>,
+        min: C,
+        max: C,
+        value: Option<ROOT::Voxel>,
+    ) where
+        [(); ROOT::LEVEL as usize + 1]: Sized,
+        ROOT: ~const NodeConst,
+    {
+        let min = min.to_tree_coords();
+        let max = max.to_tree_coords().min(ROOT::EXTENT_MASK);
+        if min.cmpgt(max).any() {
+            return;
+        }
+        let previous = Self::capture(tree, min, max);
+        let size = (max - min + UVec3::ONE).as_u64vec3();
+        let covered: u64 = previous
+            .tiles
+            .iter()
+            .filter(|(_, _, tile)| Some(*tile) == value)
+            .map(|(tile_min, tile_max, _)| {
+                let size = (*tile_max - *tile_min + UVec3::ONE).as_u64vec3();
+                size.x * size.y * size.z
+            })
+            .sum();
+        let unchanged = match value {
+            Some(_) => covered == size.x * size.y * size.z,
+            None => previous.tiles.is_empty(),
+        };
+        if unchanged && previous.leaves.is_empty() {
+            return;
+        }
+
+        let began = self.recording.is_none();
+        if began {
+            self.begin();
+        }
+        let recording = self.recording.as_mut().unwrap();
+        recording.transaction.edits.push(Edit::Box(previous));
+        // Voxels touched after the box must be recorded again, as undoing restores the box first.
+        recording.index.clear();
+        recording.last = None;
+        tree.fill(min, max, value);
+        if began {
+            self.commit();
+        }
+    }
+
+    /// Returns the state of the box within `min` and `max` (inclusive).
+    fn capture(tree: &Tree<ROOT>, min: UVec3, max: UVec3) -> BoxState<ROOT::Voxel>
+    where
+        [(); ROOT::LEVEL as usize + 1]: Sized,
+        ROOT: ~const NodeConst,
+    {
+        let mut tiles = Vec::new();
+        tree.for_each_tile_in_aabb(min, max, |tile_min, tile_max, value| {
+            tiles.push((tile_min.max(min), tile_max.min(max), value));
+        });
+        let log2 = ROOT::LeafType::EXTENT_LOG2;
+        let mut accessor = tree.accessor();
+        let leaves = tree
+            .iter_leaf_in_aabb(min, max)
+            .map(|(origin, _)| {
+                let local_min = min.max(origin) - origin;
+                let local_max = max.min(origin + ROOT::LeafType::EXTENT_MASK) - origin;
+                let mut occupied = vec![0; Self::MASK_WORDS].into_boxed_slice();
+                let mut values = Vec::new();
+                for x in local_min.x..=local_max.x {
+                    for y in local_min.y..=local_max.y {
+                        for z in local_min.z..=local_max.z {
+                            let local = UVec3 { x, y, z };
+                            if let Some(value) = accessor.get(origin + local) {
+                                let index = ((x as usize) << (log2.y + log2.z))
+                                    | ((y as usize) << log2.z)
+                                    | (z as usize);
+                                set_bit(&mut occupied, index);
+                                values.push(value);
+                            }
+                        }
+                    }
+                }
+                (origin, occupied, values)
+            })
+            .collect();
+        BoxState {
+            min,
+            max,
+            tiles,
+            leaves,
+        }
+    }
+
+    /// Undo the last committed transaction. Returns false if there was nothing to undo.
+    /// Panics while a transaction is being recorded.
+    pub fn undo(&mut self, tree: &mut Tree<ROOT>) -> bool
+    where
+        [(); ROOT::LEVEL as usize + 1]: Sized,
+        ROOT: ~const NodeConst,
+    {
+        assert!(
+            self.recording.is_none(),
+            "Can not undo during a transaction"
+        );
+        let Some(transaction) = self.undo.pop_back() else {
+            return false;
+        };
+        self.redo.push(Self::apply(tree, &transaction));
+        true
+    }
+
+    /// Redo the last undone transaction. Returns false if there was nothing to redo.
+    /// Panics while a transaction is being recorded.
+    pub fn redo(&mut self, tree: &mut Tree<ROOT>) -> bool
+    where
+        [(); ROOT::LEVEL as usize + 1]: Sized,
+        ROOT: ~const NodeConst,
+    {
+        assert!(
+            self.recording.is_none(),
+            "Can not redo during a transaction"
+        );
+        let Some(transaction) = self.redo.pop() else {
+            return false;
+        };
+        self.undo.push_back(Self::apply(tree, &transaction));
+        true
+    }
+
+    /// Restore the state recorded by `transaction`, undoing its edits in reverse order, and
+    /// returns the transaction restoring the tree as it was before.
+    fn apply(
+        tree: &mut Tree<ROOT>,
+        transaction: &Transaction<ROOT::Voxel>,
+    ) -> Transaction<ROOT::Voxel>
+    where
+        [(); ROOT::LEVEL as usize + 1]: Sized,
+        ROOT: ~const NodeConst,
+    {
+        let edits = transaction
+            .edits
+            .iter()
+            .rev()
+            .map(|edit| match edit {
+                Edit::Voxels(origin, diff) => {
+                    Edit::Voxels(*origin, Self::apply_voxels(tree, *origin, diff))
+                }
+                Edit::Box(state) => {
+                    let previous = Self::capture(tree, state.min, state.max);
+                    tree.fill(state.min, state.max, None);
+                    for (min, max, value) in state.tiles.iter() {
+                        tree.fill(*min, *max, Some(*value));
+                    }
+                    let mut accessor = tree.accessor_mut();
+                    for (origin, occupied, values) in state.leaves.iter() {
+                        for (index, value) in iter_set_bits(occupied).zip(values) {
+                            accessor.set(*origin + leaf_local_coords::<ROOT>(index), Some(*value));
+                        }
+                    }
+                    Edit::Box(previous)
+                }
+            })
+            .collect();
+        Transaction { edits }
+    }
+
+    /// Restore the voxels recorded by `diff` in the region at `origin`, and returns the diff
+    /// restoring the voxels as they were before.
+    fn apply_voxels(
+        tree: &mut Tree<ROOT>,
+        origin: UVec3,
+        diff: &LeafDiff<ROOT::Voxel>,
+    ) -> LeafDiff<ROOT::Voxel>
+    where
+        [(); ROOT::LEVEL as usize + 1]: Sized,
+        ROOT: ~const NodeConst,
+    {
+        let mut accessor = tree.accessor_mut();
+        let mut occupied = vec![0; Self::MASK_WORDS].into_boxed_slice();
+        let mut values = Vec::new();
+        let mut recorded = diff.values.iter();
+        for index in iter_set_bits(&diff.touched) {
+            let coords = origin + leaf_local_coords::<ROOT>(index);
+            if let Some(value) = accessor.get(coords) {
+                set_bit(&mut occupied, index);
+                values.push(value);
+            }
+            let value = if get_bit(&diff.occupied, index) {
+                recorded.next().copied()
+            } else {
+                None
+            };
+            accessor.set(coords, value);
+        }
+        LeafDiff {
+            touched: diff.touched.clone(),
+            occupied,
+            values,
+        }
+    }
+}
+
+/// Accessor recording all edits into an [`EditJournal`]. See [`EditJournal::accessor_mut`].
+pub struct JournalAccessor<'a, ROOT: Node>
+where
+    [(); ROOT::LEVEL as usize]: Sized,
+    ROOT::Voxel: Default,
+{
+    accessor: AccessorMut<'a, ROOT>,
+    journal: &'a mut EditJournal<ROOT>,
+    /// Whether the accessor started the transaction, which it then commits when dropped.
+    began: bool,
+}
+
+impl<'a, ROOT: Node> JournalAccessor<'a, ROOT>
+where
+    [(); ROOT::LEVEL as usize + 1]: Sized,
+    ROOT::Voxel: Default,
+{
+    #[inline]
+    pub fn get(&mut self, coords: impl TreeCoords<ROOT>) -> Option<ROOT::Voxel>
+    where
+        ROOT: ~const NodeConst,
+    {
+        self.accessor.get(coords)
+    }
+
+    /// Set the value of a voxel, recording its previous value.
+    #[inline]
+    pub fn set(&mut self, coords: impl TreeCoords<ROOT>, value: Option<ROOT::Voxel>)
+    where
+        ROOT: ~const NodeConst,
+    {
+        let coords = coords.to_tree_coords();
+        let previous = self.accessor.get(coords);
+        if previous != value {
+            self.journal.record(coords, previous);
+            self.accessor.set(coords, value);
+        }
+    }
+}
+
+impl<'a, ROOT: Node> Drop for JournalAccessor<'a, ROOT>
+where
+    [(); ROOT::LEVEL as usize]: Sized,
+    ROOT::Voxel: Default,
+{
+    fn drop(&mut self) {
+        if self.began {
+            self.journal.commit();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::IVec3;
+
+    use super::{Edit, EditJournal};
+    use crate::{hierarchy, Tree};
+
+    type MyRoot = hierarchy!(#, 3, 2; u16);
+
+    fn voxels(tree: &Tree<MyRoot>) -> Vec<(IVec3, u16)> {
+        let mut voxels: Vec<(IVec3, u16)> = tree
+            .iter_signed()
+            .map(|coords| (coords, tree.get_value(coords).unwrap()))
+            .collect();
+        voxels.sort_unstable_by_key(|(coords, _)| coords.to_array());
+        voxels
+    }
+
+    #[test]
+    fn test_undo_redo() {
+        use rand::prelude::*;
+        let mut rng = StdRng::seed_from_u64(5);
+        let mut tree = Tree::<MyRoot>::new();
+        tree.fill(IVec3::new(-30, -30, -30), IVec3::new(30, 0, 30), Some(1));
+        let mut journal = EditJournal::new();
+        let mut states = vec![voxels(&tree)];
+        for stroke in 0..20 {
+            // Brush strokes made of spheres along a line.
+            let mut accessor = journal.accessor_mut(&mut tree);
+            let start = IVec3::new(
+                rng.gen_range(-40..40),
+                rng.gen_range(-20..20),
+                rng.gen_range(-40..40),
+            );
+            let value = if stroke % 3 == 0 { None } else { Some(stroke) };
+            for step in 0..10 {
+                let center = start + IVec3::new(step * 2, 0, step);
+                for x in -4..=4 {
+                    for y in -4..=4 {
+                        for z in -4..=4 {
+                            if x * x + y * y + z * z <= 16 {
+                                accessor.set(center + IVec3::new(x, y, z), value);
+                            }
+                        }
+                    }
+                }
+            }
+            drop(accessor);
+            // Strokes missing all voxels are dropped from the history.
+            if journal.undo.len() == states.len() {
+                states.push(voxels(&tree));
+            }
+            if stroke % 5 == 4 {
+                journal.begin();
+                journal.fill(&mut tree, start, start + 6, Some(100));
+                journal.set_value(&mut tree, start - 1, None);
+                journal.commit();
+                states.push(voxels(&tree));
+            }
+        }
+
+        for state in states.iter().rev().skip(1) {
+            assert!(journal.undo(&mut tree));
+            assert_eq!(voxels(&tree), *state);
+        }
+        assert!(!journal.undo(&mut tree));
+        for state in states.iter().skip(1).take(12) {
+            assert!(journal.redo(&mut tree));
+            assert_eq!(voxels(&tree), *state);
+        }
+
+        // A new edit discards the transactions left to redo.
+        journal.set_value(&mut tree, IVec3::new(0, 50, 0), Some(7));
+        assert!(!journal.can_redo());
+        assert!(journal.undo(&mut tree));
+        assert_eq!(voxels(&tree), states[12]);
+    }
+
+    #[test]
+    fn test_limit() {
+        let mut tree = Tree::<MyRoot>::new();
+        let mut journal = EditJournal::with_limit(2);
+        for value in 0..4 {
+            journal.set_value(&mut tree, IVec3::new(1, 2, 3), Some(value));
+        }
+        // Edits which do not change anything are not recorded.
+        journal.set_value(&mut tree, IVec3::new(1, 2, 3), Some(3));
+        assert!(journal.undo(&mut tree));
+        assert!(journal.undo(&mut tree));
+        assert!(!journal.undo(&mut tree));
+        assert_eq!(tree.get_value(IVec3::new(1, 2, 3)), Some(1));
+
+        let mut journal = EditJournal::with_limit(0);
+        journal.set_value(&mut tree, IVec3::new(1, 2, 3), Some(5));
+        assert!(!journal.undo(&mut tree));
+        assert_eq!(tree.get_value(IVec3::new(1, 2, 3)), Some(5));
+    }
+
+    #[test]
+    fn test_fill() {
+        let mut tree = Tree::<MyRoot>::new();
+        tree.fill(IVec3::new(-64, -64, -64), IVec3::new(63, -1, 63), Some(1));
+        tree.set_value(IVec3::new(3, -2, 5), Some(2));
+        let mut journal = EditJournal::new();
+        let before = voxels(&tree);
+
+        journal.begin();
+        journal.set_value(&mut tree, IVec3::new(0, 0, 0), Some(3));
+        journal.set_value(&mut tree, IVec3::new(1, 0, 0), Some(3));
+        journal.fill(
+            &mut tree,
+            IVec3::new(-40, -40, -40),
+            IVec3::new(40, 40, 40),
+            None,
+        );
+        // Touched again after the fill, which must not restore the value set before it.
+        journal.set_value(&mut tree, IVec3::new(0, 0, 0), Some(4));
+        journal.commit();
+        let after = voxels(&tree);
+
+        // The fill is recorded as the tiles and leaf nodes it overwrote, not voxel by voxel.
+        let Edit::Box(state) = &journal.undo[0].edits[1] else {
+            panic!("fill not recorded as a box");
+        };
+        assert!(!state.tiles.is_empty());
+        assert!(state.leaves.len() < 4);
+
+        assert!(journal.undo(&mut tree));
+        assert_eq!(voxels(&tree), before);
+        assert!(journal.redo(&mut tree));
+        assert_eq!(voxels(&tree), after);
+        assert!(journal.undo(&mut tree));
+        assert_eq!(voxels(&tree), before);
+
+        // Fills which do not change anything are not recorded.
+        journal.fill(
+            &mut tree,
+            IVec3::new(0, 10, 0),
+            IVec3::new(100, 20, 100),
+            None,
+        );
+        assert!(!journal.can_undo());
+    }
+}
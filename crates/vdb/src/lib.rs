@@ -15,12 +15,51 @@
 
 mod accessor;
 mod bitmask;
+mod channels;
+mod components;
+mod coords;
+mod csg;
+mod dirty;
+mod journal;
+mod lod;
+mod mesh;
+mod meshing;
+mod morphology;
 mod node;
+mod openvdb;
+mod paging;
+mod parallel;
 mod pool;
+mod raycast;
+mod sdf;
+mod serialize;
+mod sharded;
+mod stencil;
+mod terrain;
 mod tree;
 
 pub use bitmask::BitMask;
-pub use pool::Pool;
+pub use channels::{ChannelGrid, ChannelId};
+pub use components::{Component, Connectivity};
+pub use coords::TreeCoords;
+pub use csg::CsgOperation;
+pub use dirty::DirtyNode;
+pub use journal::{EditJournal, JournalAccessor};
+pub use lod::LodPyramid;
+pub use mesh::{ObjError, TriangleMesh};
+pub use meshing::{MeshingMethod, SurfaceMesh};
+pub use openvdb::{
+    NodeOpenVdb, OpenVdbError, VdbCompression, VdbFile, VdbGridInfo, VdbTransform, VdbValue,
+    VdbWriter,
+};
+pub use paging::{DirectoryStore, MemoryStore, PackFileStore, PagedTree, TileStore};
+pub use pool::{Pool, PoolStats};
+pub use raycast::RayHit;
+pub use sdf::{Half, SdfValue};
+pub use serialize::{NodeSerialize, SerializableValue, SerializeError};
+pub use sharded::ShardedTree;
+pub use stencil::{Stencil, ALL_NEIGHBORS, EDGE_NEIGHBORS, FACE_NEIGHBORS};
+pub use terrain::{DensitySource, Fbm, HeightSource, Heightmap, Ridged, Terrain};
 pub use tree::Tree;
 
 pub use accessor::Accessor;
@@ -0,0 +1,417 @@
+use fxhash::FxHashSet;
+use glam::UVec3;
+
+use crate::{
+    coords::{from_signed, to_signed},
+    Accessor, Node, NodeConst, Tree, TreeCoords,
+};
+
+impl<ROOT: Node> Tree<ROOT>
+where
+    [(); ROOT::LEVEL as usize + 1]: Sized,
+{
+    /// Returns a coarser version of the tree, where each block of `1 << block_log2` voxels
+    /// on each axis collapses into a single voxel at `coords >> block_log2`. Trees with a
+    /// [`crate::RootNode`] are shifted in signed coordinates, so that the blocks stay aligned to
+    /// `IVec3::ZERO` and the voxel at signed `coords` collapses into signed `coords >> block_log2`.
+    /// A block becomes occupied when at least `min_occupied` of its voxels are occupied, and takes
+    /// the most common value among them. Ties go to the value found first in the block.
+    ///
+    /// `block_log2 = 1` halves the resolution, while the extent of the leaf nodes collapses
+    /// whole leaf nodes into single voxels.
+    /// ```
+    /// #![feature(generic_const_exprs)]
+    /// use dust_vdb::{Tree, hierarchy};
+    /// use glam::UVec3;
+    /// let mut tree = Tree::<hierarchy!(4, 2; u8)>::new();
+    /// tree.set_value(UVec3::new(0, 0, 0), Some(1));
+    /// tree.set_value(UVec3::new(1, 0, 0), Some(2));
+    /// tree.set_value(UVec3::new(1, 1, 0), Some(2));
+    /// tree.set_value(UVec3::new(5, 0, 0), Some(3));
+    ///
+    /// let coarse = tree.downsample(1, 1);
+    /// assert_eq!(coarse.get_value(UVec3::new(0, 0, 0)), Some(2));
+    /// assert_eq!(coarse.get_value(UVec3::new(2, 0, 0)), Some(3));
+    /// assert_eq!(coarse.count_active(), 2);
+    ///
+    /// // Require half of the block to be occupied.
+    /// let coarse = tree.downsample(1, 4);
+    /// assert_eq!(coarse.count_active(), 0);
+    /// ```
+    pub fn downsample(&self, block_log2: u32, min_occupied: u32) -> Self
+    where
+        ROOT: ~const NodeConst,
+    {
+        let mut coarse = Self::new();
+        downsample_region(
+            self,
+            &mut coarse,
+            UVec3::ZERO,
+            ROOT::EXTENT_MASK,
+            block_log2,
+            min_occupied,
+        );
+        coarse
+    }
+}
+
+/// Recompute the voxels of `coarse` covering the box between `min` and `max` (inclusive)
+/// in the coordinates of `fine`. Only blocks with voxels in either tree are visited.
+fn downsample_region<ROOT: Node + NodeConst>(
+    fine: &Tree<ROOT>,
+    coarse: &mut Tree<ROOT>,
+    min: UVec3,
+    max: UVec3,
+    block_log2: u32,
+    min_occupied: u32,
+) where
+    [(); ROOT::LEVEL as usize + 1]: Sized,
+{
+    let coarse_min = coarsen::<ROOT>(min, block_log2);
+    let coarse_max = coarsen::<ROOT>(max, block_log2);
+    let mut blocks: FxHashSet<UVec3> = fine
+        .iter_in_aabb(min, max)
+        .map(|coords| coarsen::<ROOT>(coords, block_log2))
+        .collect();
+    blocks.extend(coarse.iter_in_aabb(coarse_min, coarse_max));
+
+    let mut accessor = fine.accessor();
+    let mut counts: Vec<(ROOT::Voxel, u32)> = Vec::new();
+    let mut coarse_accessor = coarse.accessor_mut();
+    for block in blocks {
+        let value = collapse_block(
+            &mut accessor,
+            refine::<ROOT>(block, block_log2),
+            block_log2,
+            min_occupied,
+            &mut counts,
+        );
+        coarse_accessor.set(block, value);
+    }
+}
+
+/// Returns the coordinates of the block of `1 << shift` voxels on each axis containing `coords`.
+/// Trees spanning the entire coordinate range may be addressed in signed coordinates, see
+/// [`TreeCoords`]. Their coordinates are shifted in signed space, which keeps `IVec3::ZERO` in place.
+fn coarsen<ROOT: Node>(coords: UVec3, shift: u32) -> UVec3 {
+    if ROOT::EXTENT_MASK == UVec3::MAX {
+        from_signed(to_signed(coords) >> shift)
+    } else {
+        coords >> shift
+    }
+}
+
+/// Returns the coordinates of the first voxel in the block at `block`. See [`coarsen`].
+fn refine<ROOT: Node>(block: UVec3, shift: u32) -> UVec3 {
+    if ROOT::EXTENT_MASK == UVec3::MAX {
+        from_signed(to_signed(block) << shift)
+    } else {
+        block << shift
+    }
+}
+
+/// Returns the value of the block of `1 << block_log2` voxels on each axis starting at `origin`.
+/// See [`Tree::downsample`].
+fn collapse_block<ROOT: Node + NodeConst>(
+    accessor: &mut Accessor<ROOT>,
+    origin: UVec3,
+    block_log2: u32,
+    min_occupied: u32,
+    counts: &mut Vec<(ROOT::Voxel, u32)>,
+) -> Option<ROOT::Voxel>
+where
+    [(); ROOT::LEVEL as usize + 1]: Sized,
+{
+    counts.clear();
+    let mut occupied = 0;
+    let block_max = origin + UVec3::splat((1 << block_log2) - 1);
+    for x in origin.x..=block_max.x {
+        for y in origin.y..=block_max.y {
+            for z in origin.z..=block_max.z {
+                let Some(value) = accessor.get(UVec3 { x, y, z }) else {
+                    continue;
+                };
+                occupied += 1;
+                match counts.iter_mut().find(|(v, _)| *v == value) {
+                    Some((_, count)) => *count += 1,
+                    None => counts.push((value, 1)),
+                }
+            }
+        }
+    }
+    if occupied < min_occupied.max(1) {
+        return None;
+    }
+    // `max_by_key` returns the last maximum, so search backwards to prefer the first one.
+    counts
+        .iter()
+        .rev()
+        .max_by_key(|(_, count)| *count)
+        .map(|(value, _)| *value)
+}
+
+/// A chain of progressively coarser versions of a tree, for rendering distant geometry.
+///
+/// LOD 0 is the source tree itself, which is not owned by the pyramid. Each level after it is
+/// built from the previous one with [`Tree::downsample`], so the voxel at `coords` in the source
+/// tree is covered by the voxel at `coords >> (lod * block_log2)` in LOD `lod`, in signed
+/// coordinates for trees with a [`crate::RootNode`]. See [`LodPyramid::lod_coords`].
+/// ```
+/// #![feature(generic_const_exprs)]
+/// use dust_vdb::{LodPyramid, Tree, hierarchy};
+/// use glam::UVec3;
+/// let mut tree = Tree::<hierarchy!(4, 2; u8)>::new();
+/// tree.fill(UVec3::new(0, 0, 0), UVec3::new(63, 7, 63), Some(1));
+/// tree.track_dirty(true);
+/// let mut pyramid = LodPyramid::new(&tree, 3, 1, 1);
+/// assert_eq!(pyramid.level(2).count_active(), 16 * 2 * 16);
+///
+/// tree.fill(UVec3::new(0, 8, 0), UVec3::new(15, 15, 15), Some(2));
+/// let dirty: Vec<(UVec3, UVec3)> = tree.drain_dirty().collect();
+/// pyramid.update(&tree, dirty);
+/// assert_eq!(pyramid.level(3).get_value(UVec3::new(0, 1, 0)), Some(2));
+///
+/// assert_eq!(pyramid.select_lod(10.0, 50.0), 0);
+/// assert_eq!(pyramid.select_lod(150.0, 50.0), 2);
+/// assert_eq!(pyramid.select_lod(1000.0, 50.0), 3);
+/// ```
+pub struct LodPyramid<ROOT: Node>
+where
+    [(); ROOT::LEVEL as usize]: Sized,
+{
+    block_log2: u32,
+    min_occupied: u32,
+    /// LOD 1 and above.
+    levels: Vec<Tree<ROOT>>,
+}
+
+impl<ROOT: Node + ~const NodeConst> LodPyramid<ROOT>
+where
+    [(); ROOT::LEVEL as usize + 1]: Sized,
+{
+    /// Build `num_levels` coarser versions of `tree`.
+    /// See [`Tree::downsample`] for the meaning of `block_log2` and `min_occupied`.
+    pub fn new(tree: &Tree<ROOT>, num_levels: usize, block_log2: u32, min_occupied: u32) -> Self {
+        let mut levels: Vec<Tree<ROOT>> = Vec::with_capacity(num_levels);
+        for lod in 0..num_levels {
+            let fine = if lod == 0 { tree } else { &levels[lod - 1] };
+            let coarse = fine.downsample(block_log2, min_occupied);
+            levels.push(coarse);
+        }
+        Self {
+            block_log2,
+            min_occupied,
+            levels,
+        }
+    }
+
+    /// Number of LODs in the pyramid, not counting the source tree.
+    pub fn num_levels(&self) -> usize {
+        self.levels.len()
+    }
+
+    /// Returns the tree of LOD `lod`, starting from 1 for the first coarser level.
+    /// LOD 0 is the source tree, which is not owned by the pyramid.
+    pub fn level(&self, lod: usize) -> &Tree<ROOT> {
+        assert!(lod > 0, "LOD 0 is the source tree");
+        &self.levels[lod - 1]
+    }
+
+    /// Mutable version of [`LodPyramid::level`], for example to track changes on a level with
+    /// [`Tree::track_dirty`]. Edits made directly on the levels are overwritten by
+    /// [`LodPyramid::update`].
+    pub fn level_mut(&mut self, lod: usize) -> &mut Tree<ROOT> {
+        assert!(lod > 0, "LOD 0 is the source tree");
+        &mut self.levels[lod - 1]
+    }
+
+    /// Returns the coordinates of the voxel covering `coords` of the source tree in LOD `lod`.
+    /// ```
+    /// #![feature(generic_const_exprs)]
+    /// use dust_vdb::{LodPyramid, Tree, hierarchy};
+    /// use glam::IVec3;
+    /// let mut tree = Tree::<hierarchy!(#, 3, 2; u8)>::new();
+    /// tree.set_value(IVec3::new(-3, 5, 0), Some(1));
+    /// let pyramid = LodPyramid::new(&tree, 2, 1, 1);
+    /// let coords = pyramid.lod_coords(IVec3::new(-3, 5, 0), 2);
+    /// assert_eq!(coords, IVec3::new(-1, 1, 0));
+    /// assert_eq!(pyramid.level(2).get_value(coords), Some(1));
+    /// ```
+    pub fn lod_coords<C: TreeCoords<ROOT>>(&self, coords: C, lod: usize) -> C {
+        C::from_tree_coords(coarsen::<ROOT>(
+            coords.to_tree_coords(),
+            lod as u32 * self.block_log2,
+        ))
+    }
+
+    /// Bring all levels up to date with `tree` after the boxes (inclusive) in `dirty` were
+    /// modified, as returned by [`Tree::drain_dirty`]. Only the blocks covering these boxes
+    /// are recomputed on each level.
+    pub fn update(&mut self, tree: &Tree<ROOT>, dirty: impl IntoIterator<Item = (UVec3, UVec3)>) {
+        let mut boxes: Vec<(UVec3, UVec3)> = dirty.into_iter().collect();
+        for lod in 0..self.levels.len() {
+            let (finer, coarser) = self.levels.split_at_mut(lod);
+            let fine = finer.last().unwrap_or(tree);
+            for (min, max) in boxes.iter_mut() {
+                downsample_region(
+                    fine,
+                    &mut coarser[0],
+                    *min,
+                    *max,
+                    self.block_log2,
+                    self.min_occupied,
+                );
+                *min = coarsen::<ROOT>(*min, self.block_log2);
+                *max = coarsen::<ROOT>(*max, self.block_log2);
+            }
+        }
+    }
+
+    /// Returns the LOD to render at `distance`, given that LOD 0 is used up to `lod0_distance`.
+    /// The range covered by each level grows with its voxel size, so LOD `n` is used up to
+    /// `lod0_distance * (1 << (n * block_log2))`. Distances beyond the coarsest level use
+    /// the coarsest level.
+    pub fn select_lod(&self, distance: f32, lod0_distance: f32) -> usize {
+        if distance <= lod0_distance {
+            return 0;
+        }
+        let lod = ((distance / lod0_distance).log2() / self.block_log2 as f32).ceil();
+        (lod as usize).min(self.levels.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use glam::{IVec3, UVec3};
+
+    use crate::{
+        coords::{from_signed, to_signed},
+        hierarchy, LodPyramid, Tree,
+    };
+
+    /// `block` returns the coordinates of the block containing a voxel.
+    fn expected_downsample(
+        fine: &HashMap<UVec3, u8>,
+        block: impl Fn(UVec3) -> UVec3,
+        min_occupied: u32,
+    ) -> HashMap<UVec3, u8> {
+        let mut blocks: HashMap<UVec3, Vec<(UVec3, u8)>> = HashMap::new();
+        for (coords, value) in fine.iter() {
+            blocks
+                .entry(block(*coords))
+                .or_default()
+                .push((*coords, *value));
+        }
+        blocks
+            .into_iter()
+            .filter(|(_, voxels)| voxels.len() as u32 >= min_occupied)
+            .map(|(block, mut voxels)| {
+                voxels.sort_by_key(|(coords, _)| coords.to_array());
+                let mut best = voxels[0].1;
+                let mut best_count = 0;
+                for (_, value) in voxels.iter() {
+                    let count = voxels.iter().filter(|(_, v)| v == value).count();
+                    if count > best_count {
+                        best = *value;
+                        best_count = count;
+                    }
+                }
+                (block, best)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_lod_pyramid() {
+        use rand::prelude::*;
+        let mut rng = rand::thread_rng();
+        type MyTree = Tree<hierarchy!(3, 2, 2; u8)>;
+        let mut tree = MyTree::new();
+        let mut model: HashMap<UVec3, u8> = HashMap::new();
+        for _i in 0..3000 {
+            let location = UVec3::new(rng.gen(), rng.gen(), rng.gen()) >> 25;
+            let value: u8 = rng.gen_range(0..3);
+            tree.set_value(location, Some(value));
+            model.insert(location, value);
+        }
+        tree.track_dirty(true);
+        let mut pyramid = LodPyramid::new(&tree, 3, 1, 2);
+
+        for _i in 0..500 {
+            let location = UVec3::new(rng.gen(), rng.gen(), rng.gen()) >> 25;
+            let value: Option<u8> = if rng.gen() {
+                Some(rng.gen_range(0..3))
+            } else {
+                None
+            };
+            tree.set_value(location, value);
+            match value {
+                Some(value) => model.insert(location, value),
+                None => model.remove(&location),
+            };
+        }
+        tree.fill(UVec3::new(8, 8, 8), UVec3::new(20, 20, 20), None);
+        model.retain(|coords, _| {
+            coords.cmplt(UVec3::splat(8)).any() || coords.cmpgt(UVec3::splat(20)).any()
+        });
+        let dirty: Vec<(UVec3, UVec3)> = tree.drain_dirty().collect();
+        pyramid.update(&tree, dirty);
+
+        let mut expected = model;
+        for lod in 1..=3 {
+            expected = expected_downsample(&expected, |coords| coords >> 1, 2);
+            let level = pyramid.level(lod);
+            assert_eq!(level.count_active(), expected.len() as u64);
+            for (coords, value) in expected.iter() {
+                assert_eq!(level.get_value(*coords), Some(*value));
+            }
+        }
+    }
+
+    #[test]
+    fn test_lod_pyramid_signed() {
+        use rand::prelude::*;
+        let mut rng = rand::thread_rng();
+        type MyTree = Tree<hierarchy!(#, 3, 2; u8)>;
+        let mut tree = MyTree::new();
+        let mut model: HashMap<UVec3, u8> = HashMap::new();
+        for _i in 0..3000 {
+            let location = IVec3::new(
+                rng.gen_range(-40..40),
+                rng.gen_range(-40..40),
+                rng.gen_range(-40..40),
+            );
+            let value: u8 = rng.gen_range(0..3);
+            tree.set_value(location, Some(value));
+            model.insert(from_signed(location), value);
+        }
+        tree.track_dirty(true);
+        let mut pyramid = LodPyramid::new(&tree, 3, 1, 2);
+
+        tree.fill(IVec3::new(-10, -10, -10), IVec3::new(5, 5, 5), None);
+        model.retain(|coords, _| {
+            let coords = to_signed(*coords);
+            coords.cmplt(IVec3::splat(-10)).any() || coords.cmpgt(IVec3::splat(5)).any()
+        });
+        let dirty: Vec<(UVec3, UVec3)> = tree.drain_dirty().collect();
+        pyramid.update(&tree, dirty);
+
+        let mut expected = model;
+        for lod in 1..=3 {
+            expected =
+                expected_downsample(&expected, |coords| from_signed(to_signed(coords) >> 1), 2);
+            let level = pyramid.level(lod);
+            assert_eq!(level.count_active(), expected.len() as u64);
+            for (coords, value) in expected.iter() {
+                assert_eq!(level.get_value(*coords), Some(*value));
+            }
+        }
+        // The blocks stay aligned to the signed origin.
+        assert_eq!(
+            pyramid.lod_coords(IVec3::new(-1, 0, 7), 3),
+            IVec3::new(-1, 0, 0)
+        );
+    }
+}
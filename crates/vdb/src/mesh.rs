@@ -0,0 +1,654 @@
+use fxhash::FxHashMap;
+use glam::{I64Vec3, UVec3, Vec2, Vec3};
+
+use crate::{Node, NodeConst, Tree, TreeCoords};
+
+/// A list of triangles with a value attached to each vertex.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TriangleMesh<T> {
+    pub positions: Vec<Vec3>,
+    /// Indices into `positions` of the three corners of each triangle.
+    pub triangles: Vec<[u32; 3]>,
+    /// Value of each vertex, like a color or a material index. Same length as `positions`.
+    pub values: Vec<T>,
+}
+
+impl<T> TriangleMesh<T> {
+    pub fn new(positions: Vec<Vec3>, triangles: Vec<[u32; 3]>, values: Vec<T>) -> Self {
+        assert_eq!(
+            positions.len(),
+            values.len(),
+            "Each vertex must have exactly one value"
+        );
+        Self {
+            positions,
+            triangles,
+            values,
+        }
+    }
+
+    /// Create a mesh where all vertices have the same value.
+    pub fn uniform(positions: Vec<Vec3>, triangles: Vec<[u32; 3]>, value: T) -> Self
+    where
+        T: Clone,
+    {
+        let values = vec![value; positions.len()];
+        Self::new(positions, triangles, values)
+    }
+
+    /// Returns the min and max corners of the box containing all vertices, or None if the mesh
+    /// has no vertices.
+    pub fn bounding_box(&self) -> Option<(Vec3, Vec3)> {
+        let first = *self.positions.first()?;
+        Some(
+            self.positions
+                .iter()
+                .fold((first, first), |(min, max), p| (min.min(*p), max.max(*p))),
+        )
+    }
+
+    /// Move all vertices by `offset`.
+    pub fn translate(&mut self, offset: Vec3) {
+        for position in self.positions.iter_mut() {
+            *position += offset;
+        }
+    }
+
+    #[inline]
+    fn corners(&self, triangle: [u32; 3]) -> [Vec3; 3] {
+        triangle.map(|i| self.positions[i as usize])
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ObjError {
+    /// A statement could not be parsed. Lines are numbered from 1.
+    InvalidLine { line: usize },
+    /// A face refers to a vertex that does not exist.
+    InvalidIndex { line: usize, index: i64 },
+}
+
+impl std::fmt::Display for ObjError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ObjError::InvalidLine { line } => write!(f, "invalid statement on line {}", line),
+            ObjError::InvalidIndex { line, index } => {
+                write!(f, "invalid vertex index {} on line {}", index, line)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ObjError {}
+
+impl TriangleMesh<u32> {
+    /// Parse the geometry of a Wavefront OBJ file. The value of each vertex is the index of the
+    /// material assigned to its face with `usemtl`, and the names of the materials are returned
+    /// in the same order. Faces before the first `usemtl` statement use material 0, named
+    /// `"default"`. Vertices shared by faces with different materials are duplicated.
+    ///
+    /// Faces with more than three corners are split into triangle fans. Texture coordinates,
+    /// normals, groups and all other statements are ignored.
+    /// ```
+    /// use dust_vdb::TriangleMesh;
+    /// let source = "
+    /// v 0 0 0
+    /// v 1 0 0
+    /// v 1 1 0
+    /// v 0 1 0
+    /// usemtl stone
+    /// f 1/1/1 2/2/1 3/3/1 4/4/1
+    /// ";
+    /// let (mesh, materials) = TriangleMesh::from_obj(source).unwrap();
+    /// assert_eq!(mesh.triangles, vec![[0, 1, 2], [0, 2, 3]]);
+    /// assert_eq!(materials, vec!["default".to_string(), "stone".to_string()]);
+    /// assert_eq!(mesh.values, vec![1, 1, 1, 1]);
+    /// ```
+    pub fn from_obj(source: &str) -> Result<(Self, Vec<String>), ObjError> {
+        let mut obj_positions: Vec<Vec3> = Vec::new();
+        let mut materials: Vec<String> = vec!["default".to_string()];
+        let mut material: u32 = 0;
+        // Vertices of the mesh for each pair of OBJ vertex and material.
+        let mut vertices: FxHashMap<(u32, u32), u32> = FxHashMap::default();
+        let mut mesh = TriangleMesh::new(Vec::new(), Vec::new(), Vec::new());
+        let mut face: Vec<u32> = Vec::new();
+
+        for (i, line) in source.lines().enumerate() {
+            let line_number = i + 1;
+            let invalid = || ObjError::InvalidLine { line: line_number };
+            let line = line.split('#').next().unwrap_or_default();
+            let mut tokens = line.split_whitespace();
+            match tokens.next() {
+                Some("v") => {
+                    let mut coords = [0.0_f32; 3];
+                    for c in coords.iter_mut() {
+                        *c = tokens
+                            .next()
+                            .and_then(|t| t.parse().ok())
+                            .ok_or_else(invalid)?;
+                    }
+                    obj_positions.push(Vec3::from_array(coords));
+                }
+                Some("usemtl") => {
+                    let name = tokens.next().ok_or_else(invalid)?;
+                    material = match materials.iter().position(|m| m == name) {
+                        Some(index) => index as u32,
+                        None => {
+                            materials.push(name.to_string());
+                            materials.len() as u32 - 1
+                        }
+                    };
+                }
+                Some("f") => {
+                    face.clear();
+                    for token in tokens {
+                        let index: i64 = token
+                            .split('/')
+                            .next()
+                            .and_then(|t| t.parse().ok())
+                            .ok_or_else(invalid)?;
+                        // Indices start at 1, and negative indices count back from the
+                        // last vertex defined so far.
+                        let resolved = if index < 0 {
+                            obj_positions.len() as i64 + index
+                        } else {
+                            index - 1
+                        };
+                        if resolved < 0 || resolved >= obj_positions.len() as i64 {
+                            return Err(ObjError::InvalidIndex {
+                                line: line_number,
+                                index,
+                            });
+                        }
+                        let vertex =
+                            *vertices
+                                .entry((resolved as u32, material))
+                                .or_insert_with(|| {
+                                    mesh.positions.push(obj_positions[resolved as usize]);
+                                    mesh.values.push(material);
+                                    mesh.positions.len() as u32 - 1
+                                });
+                        face.push(vertex);
+                    }
+                    if face.len() < 3 {
+                        return Err(invalid());
+                    }
+                    for j in 1..face.len() - 1 {
+                        mesh.triangles.push([face[0], face[j], face[j + 1]]);
+                    }
+                }
+                _ => (),
+            }
+        }
+        Ok((mesh, materials))
+    }
+}
+
+impl<ROOT: Node> Tree<ROOT>
+where
+    [(); ROOT::LEVEL as usize + 1]: Sized,
+{
+    /// Voxelize the triangles of `mesh` into the tree, with the origin of the mesh at the minimum
+    /// corner of the voxel at `origin`. Vertex positions are divided by `voxel_size`, so that the
+    /// voxel at `origin + (x, y, z)` covers the box from `(x, y, z) * voxel_size` to
+    /// `(x + 1, y + 1, z + 1) * voxel_size` in the space of the mesh. The mesh is rasterized
+    /// relative to `origin`, so it keeps its precision anywhere in the tree. `origin` may be given
+    /// in signed coordinates for trees with a [`crate::RootNode`], which makes voxelizing meshes
+    /// centered around their origin straightforward. Parts of the mesh outside of the tree are
+    /// clipped.
+    ///
+    /// The surface voxelization is conservative: every voxel overlapping or touching a triangle is
+    /// set, so the surface has no holes even where triangles are thinner than a voxel. Each
+    /// surface voxel takes the value of the vertex of the triangle closest to its center. Voxels
+    /// overlapped by several triangles take the value of the last one.
+    ///
+    /// When `fill` is given, voxels whose centers are enclosed by the mesh are set to it, with
+    /// the surface voxels written over them. The inside is found by counting the crossings of
+    /// the mesh along the z axis, so the mesh must be closed.
+    /// ```
+    /// #![feature(generic_const_exprs)]
+    /// use dust_vdb::{Tree, TriangleMesh, hierarchy};
+    /// use glam::{IVec3, UVec3, Vec3};
+    /// let mesh = TriangleMesh::new(
+    ///     vec![Vec3::new(0.0, 0.0, 2.0), Vec3::new(8.0, 0.0, 2.0), Vec3::new(0.0, 8.0, 2.0)],
+    ///     vec![[0, 1, 2]],
+    ///     vec![1_u8, 2, 3],
+    /// );
+    /// let mut tree = Tree::<hierarchy!(4, 2; u8)>::new();
+    /// tree.voxelize(&mesh, UVec3::ZERO, 0.5, None);
+    /// // The triangle lies on a boundary between voxels, so it touches voxels on both sides.
+    /// assert_eq!(tree.get_value(UVec3::new(0, 0, 3)), Some(1));
+    /// assert_eq!(tree.get_value(UVec3::new(0, 0, 4)), Some(1));
+    /// assert_eq!(tree.get_value(UVec3::new(15, 0, 4)), Some(2));
+    /// assert_eq!(tree.get_value(UVec3::new(0, 15, 4)), Some(3));
+    /// assert_eq!(tree.get_value(UVec3::new(15, 15, 4)), None);
+    ///
+    /// let mut tree = Tree::<hierarchy!(#, 4, 2; u8)>::new();
+    /// tree.voxelize(&mesh, IVec3::new(-8, -8, 0), 0.5, None);
+    /// assert_eq!(tree.get_value(IVec3::new(-8, -8, 4)), Some(1));
+    /// assert_eq!(tree.get_value(IVec3::new(7, -8, 4)), Some(2));
+    /// ```
+    pub fn voxelize<C: TreeCoords<ROOT>>(
+        &mut self,
+        mesh: &TriangleMesh<ROOT::Voxel>,
+        origin: C,
+        voxel_size: f32,
+        fill: Option<ROOT::Voxel>,
+    ) where
+        ROOT: ~const NodeConst,
+    {
+        let origin = origin.to_tree_coords().as_i64vec3();
+        // Voxels within the tree, relative to `origin`.
+        let bounds_min = -origin;
+        let bounds_max = ROOT::EXTENT_MASK.as_i64vec3() - origin;
+        let to_tree_coords = |local: I64Vec3| (origin + local).as_uvec3();
+        let scale = voxel_size.recip();
+        if let Some(fill) = fill {
+            self.voxelize_interior(mesh, scale, fill, bounds_min, bounds_max, to_tree_coords);
+        }
+        let mut accessor = self.accessor_mut();
+        for &triangle in mesh.triangles.iter() {
+            let corners = mesh.corners(triangle).map(|p| p * scale);
+            let min = corners[0].min(corners[1]).min(corners[2]);
+            let max = corners[0].max(corners[1]).max(corners[2]);
+            // Voxels touching the triangle from below are included as well.
+            let voxel_min = (min - 1.0).ceil().as_i64vec3().max(bounds_min);
+            let voxel_max = max.floor().as_i64vec3().min(bounds_max);
+            if voxel_min.cmpgt(voxel_max).any() {
+                continue;
+            }
+            // Iterate over columns of voxels along the axis closest to the normal,
+            // only testing the voxels around the plane of the triangle in each column.
+            let normal = (corners[1] - corners[0]).cross(corners[2] - corners[0]);
+            let n = normal.abs();
+            let axis = if n.x >= n.y && n.x >= n.z {
+                0
+            } else if n.y >= n.z {
+                1
+            } else {
+                2
+            };
+            let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+            for a in voxel_min[u]..=voxel_max[u] {
+                for b in voxel_min[v]..=voxel_max[v] {
+                    let (column_min, column_max) =
+                        plane_range(normal, corners[0], axis, u, v, a as f32, b as f32);
+                    let start =
+                        ((column_min.max(min[axis]) - 1.0).ceil() as i64).max(voxel_min[axis]);
+                    let end = (column_max.min(max[axis]).floor() as i64).min(voxel_max[axis]);
+                    for c in start..=end {
+                        let mut local = I64Vec3::ZERO;
+                        local[axis] = c;
+                        local[u] = a;
+                        local[v] = b;
+                        let center = local.as_vec3() + 0.5;
+                        if !triangle_box_overlap(center, corners) {
+                            continue;
+                        }
+                        let nearest = (0..3)
+                            .min_by(|&i, &j| {
+                                let di = corners[i].distance_squared(center);
+                                let dj = corners[j].distance_squared(center);
+                                di.total_cmp(&dj)
+                            })
+                            .unwrap();
+                        let value = mesh.values[triangle[nearest] as usize];
+                        accessor.set(to_tree_coords(local), Some(value));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Set the voxels with centers inside the closed mesh to `value`, using the parity of the
+    /// number of triangles crossed along each column of voxels in the z direction.
+    /// Voxels are located relative to the origin of the mesh, and clipped to `bounds_min` and
+    /// `bounds_max` (inclusive).
+    fn voxelize_interior(
+        &mut self,
+        mesh: &TriangleMesh<ROOT::Voxel>,
+        scale: f32,
+        value: ROOT::Voxel,
+        bounds_min: I64Vec3,
+        bounds_max: I64Vec3,
+        to_tree_coords: impl Fn(I64Vec3) -> UVec3,
+    ) where
+        ROOT: ~const NodeConst,
+    {
+        let mut crossings: FxHashMap<(i64, i64), Vec<f32>> = FxHashMap::default();
+        for &triangle in mesh.triangles.iter() {
+            let mut corners = mesh.corners(triangle).map(|p| p * scale);
+            let mut area = edge_function(
+                corners[0].truncate(),
+                corners[1].truncate(),
+                corners[2].truncate(),
+            );
+            if area == 0.0 {
+                // Parallel to the z axis, so the columns graze it.
+                continue;
+            }
+            if area < 0.0 {
+                corners.swap(1, 2);
+                area = -area;
+            }
+            let flat = corners.map(|p| p.truncate());
+            let min = flat[0].min(flat[1]).min(flat[2]);
+            let max = flat[0].max(flat[1]).max(flat[2]);
+            // Columns with centers within the triangle.
+            let column_min = (min - 0.5).ceil().as_i64vec2().max(bounds_min.truncate());
+            let column_max = (max - 0.5).floor().as_i64vec2().min(bounds_max.truncate());
+            for x in column_min.x..=column_max.x {
+                for y in column_min.y..=column_max.y {
+                    let center = Vec2::new(x as f32, y as f32) + 0.5;
+                    let mut weights = [0.0; 3];
+                    let mut inside = true;
+                    for i in 0..3 {
+                        let (a, b) = (flat[(i + 1) % 3], flat[(i + 2) % 3]);
+                        let w = edge_function(a, b, center);
+                        // Points on an edge shared by two triangles only belong to one of them,
+                        // so that the crossing is counted exactly once.
+                        if w < 0.0 || (w == 0.0 && !is_top_left(a, b)) {
+                            inside = false;
+                            break;
+                        }
+                        weights[i] = w / area;
+                    }
+                    if !inside {
+                        continue;
+                    }
+                    let z = weights[0] * corners[0].z
+                        + weights[1] * corners[1].z
+                        + weights[2] * corners[2].z;
+                    crossings.entry((x, y)).or_default().push(z);
+                }
+            }
+        }
+
+        for ((x, y), mut zs) in crossings.into_iter() {
+            zs.sort_unstable_by(f32::total_cmp);
+            for pair in zs.chunks_exact(2) {
+                // Voxels with centers between the two crossings.
+                let start = ((pair[0] - 0.5).ceil() as i64).max(bounds_min.z);
+                let end = ((pair[1] - 0.5).ceil() as i64 - 1).min(bounds_max.z);
+                if end < start {
+                    continue;
+                }
+                self.fill(
+                    to_tree_coords(I64Vec3::new(x, y, start)),
+                    to_tree_coords(I64Vec3::new(x, y, end)),
+                    Some(value),
+                );
+            }
+        }
+    }
+}
+
+/// Twice the signed area of the triangle `a`, `b`, `p`. Positive when counter-clockwise.
+#[inline]
+fn edge_function(a: Vec2, b: Vec2, p: Vec2) -> f32 {
+    (b - a).perp_dot(p - a)
+}
+
+/// Whether the edge from `a` to `b` of a counter-clockwise triangle is a top or a left edge.
+#[inline]
+fn is_top_left(a: Vec2, b: Vec2) -> bool {
+    (a.y == b.y && b.x < a.x) || b.y < a.y
+}
+
+/// Range of the coordinates along `axis` of the plane with `normal` through `point`
+/// within the column of voxels at `a`, `b` along the two other axes.
+fn plane_range(
+    normal: Vec3,
+    point: Vec3,
+    axis: usize,
+    u: usize,
+    v: usize,
+    a: f32,
+    b: f32,
+) -> (f32, f32) {
+    if normal[axis] == 0.0 {
+        return (f32::NEG_INFINITY, f32::INFINITY);
+    }
+    let d = normal.dot(point);
+    let mut min = f32::INFINITY;
+    let mut max = f32::NEG_INFINITY;
+    for (du, dv) in [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0), (1.0, 1.0)] {
+        let c = (d - normal[u] * (a + du) - normal[v] * (b + dv)) / normal[axis];
+        min = min.min(c);
+        max = max.max(c);
+    }
+    (min, max)
+}
+
+/// Separating axis test between a triangle and the unit box around `center`,
+/// counting boxes touching the triangle as overlapping.
+fn triangle_box_overlap(center: Vec3, corners: [Vec3; 3]) -> bool {
+    const HALF: Vec3 = Vec3::splat(0.5);
+    let v = corners.map(|p| p - center);
+    // Axes of the box.
+    let min = v[0].min(v[1]).min(v[2]);
+    let max = v[0].max(v[1]).max(v[2]);
+    if min.cmpgt(HALF).any() || max.cmplt(-HALF).any() {
+        return false;
+    }
+    let edges = [v[1] - v[0], v[2] - v[1], v[0] - v[2]];
+    // Normal of the triangle.
+    let normal = edges[0].cross(edges[1]);
+    if normal.dot(v[0]).abs() > HALF.dot(normal.abs()) {
+        return false;
+    }
+    // Cross products of the edges with the axes of the box.
+    for edge in edges.iter() {
+        for axis in [Vec3::X, Vec3::Y, Vec3::Z] {
+            let a = axis.cross(*edge);
+            let p = v.map(|p| a.dot(p));
+            let radius = HALF.dot(a.abs());
+            if p[0].min(p[1]).min(p[2]) > radius || p[0].max(p[1]).max(p[2]) < -radius {
+                return false;
+            }
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{IVec3, UVec3, Vec3};
+
+    use super::{triangle_box_overlap, TriangleMesh};
+    use crate::{hierarchy, Tree};
+
+    /// A closed box made of 12 triangles facing outwards.
+    fn cube(min: Vec3, max: Vec3) -> TriangleMesh<u8> {
+        let positions = (0..8)
+            .map(|i| {
+                Vec3::select(
+                    glam::BVec3::new(i & 1 != 0, i & 2 != 0, i & 4 != 0),
+                    max,
+                    min,
+                )
+            })
+            .collect();
+        let quads = [
+            [0, 4, 6, 2],
+            [1, 3, 7, 5],
+            [0, 1, 5, 4],
+            [2, 6, 7, 3],
+            [0, 2, 3, 1],
+            [4, 5, 7, 6],
+        ];
+        let triangles = quads
+            .iter()
+            .flat_map(|q| [[q[0], q[1], q[2]], [q[0], q[2], q[3]]])
+            .collect();
+        TriangleMesh::uniform(positions, triangles, 1)
+    }
+
+    #[test]
+    fn test_voxelize_cube() {
+        type MyTree = Tree<hierarchy!(3, 2, 2; u8)>;
+        let mesh = cube(Vec3::new(2.25, 3.25, 4.25), Vec3::new(9.75, 12.75, 20.75));
+
+        let mut shell = MyTree::new();
+        shell.voxelize(&mesh, UVec3::ZERO, 1.0, None);
+        let mut solid = MyTree::new();
+        solid.voxelize(&mesh, UVec3::ZERO, 1.0, Some(2));
+        for x in 0..24 {
+            for y in 0..24 {
+                for z in 0..24 {
+                    let coords = UVec3::new(x, y, z);
+                    let inside = coords.cmpge(UVec3::new(2, 3, 4)).all()
+                        && coords.cmple(UVec3::new(9, 12, 20)).all();
+                    let interior = coords.cmpgt(UVec3::new(2, 3, 4)).all()
+                        && coords.cmplt(UVec3::new(9, 12, 20)).all();
+                    let surface = inside && !interior;
+                    assert_eq!(shell.get_value(coords).is_some(), surface, "{:?}", coords);
+                    let expected = if surface {
+                        Some(1)
+                    } else if interior {
+                        Some(2)
+                    } else {
+                        None
+                    };
+                    assert_eq!(solid.get_value(coords), expected, "{:?}", coords);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_voxelize_placed() {
+        let mesh = cube(Vec3::new(-3.75, -4.75, -8.25), Vec3::new(3.75, 4.75, 8.25));
+        let expected = |local: IVec3| {
+            let inside =
+                local.cmpge(IVec3::new(-4, -5, -9)).all() && local.cmple(IVec3::new(3, 4, 8)).all();
+            let interior =
+                local.cmpgt(IVec3::new(-4, -5, -9)).all() && local.cmplt(IVec3::new(3, 4, 8)).all();
+            if interior {
+                Some(2)
+            } else if inside {
+                Some(1)
+            } else {
+                None
+            }
+        };
+
+        // Centered around the signed origin.
+        let mut tree = Tree::<hierarchy!(#, 3, 2; u8)>::new();
+        let origin = IVec3::new(-1, 2, 0);
+        tree.voxelize(&mesh, origin, 1.0, Some(2));
+        for x in -12..12 {
+            for y in -12..12 {
+                for z in -12..12 {
+                    let local = IVec3::new(x, y, z);
+                    assert_eq!(
+                        tree.get_value(origin + local),
+                        expected(local),
+                        "{:?}",
+                        local
+                    );
+                }
+            }
+        }
+
+        // Clipped by the boundary of the tree.
+        let mut tree = Tree::<hierarchy!(3, 2, 2; u8)>::new();
+        let origin = UVec3::new(2, 125, 9);
+        tree.voxelize(&mesh, origin, 1.0, Some(2));
+        let mut count = 0;
+        for x in -12..12 {
+            for y in -12..12 {
+                for z in -12..12 {
+                    let local = IVec3::new(x, y, z);
+                    let coords = origin.as_ivec3() + local;
+                    if coords.cmpge(IVec3::ZERO).all() && coords.cmplt(IVec3::splat(128)).all() {
+                        let value = tree.get_value(coords.as_uvec3());
+                        assert_eq!(value, expected(local), "{:?}", local);
+                        count += value.is_some() as u64;
+                    }
+                }
+            }
+        }
+        assert_eq!(tree.count_active(), count);
+    }
+
+    #[test]
+    fn test_voxelize_thin_triangles() {
+        use rand::prelude::*;
+        let mut rng = rand::thread_rng();
+        type MyTree = Tree<hierarchy!(3, 2; u8)>;
+        let mut tree = MyTree::new();
+        let mut mesh = TriangleMesh::uniform(Vec::new(), Vec::new(), 1);
+        for i in 0..50 {
+            let base = Vec3::new(rng.gen(), rng.gen(), rng.gen()) * 20.0 + 6.0;
+            let dir = Vec3::new(rng.gen(), rng.gen(), rng.gen()) - 0.5;
+            // Long slivers, much thinner than a voxel.
+            let side = dir.any_orthogonal_vector() * 0.01;
+            mesh.positions
+                .extend([base, base + dir * 10.0, base + side]);
+            mesh.values.extend([1, 1, 1]);
+            mesh.triangles.push([i * 3, i * 3 + 1, i * 3 + 2]);
+        }
+        tree.voxelize(&mesh, UVec3::ZERO, 1.0, None);
+        // Every point on the triangles lies in a voxel.
+        for triangle in mesh.triangles.iter() {
+            let corners = mesh.corners(*triangle);
+            for _i in 0..100 {
+                let (mut s, mut t): (f32, f32) = (rng.gen(), rng.gen());
+                if s + t > 1.0 {
+                    (s, t) = (1.0 - s, 1.0 - t);
+                }
+                let p = corners[0] + (corners[1] - corners[0]) * s + (corners[2] - corners[0]) * t;
+                let coords = p.floor().as_uvec3();
+                assert_eq!(tree.get_value(coords), Some(1), "{:?}", p);
+            }
+        }
+        // And every voxel overlaps a triangle.
+        for coords in tree.iter() {
+            assert!(mesh
+                .triangles
+                .iter()
+                .any(|t| triangle_box_overlap(coords.as_vec3() + 0.5, mesh.corners(*t))));
+        }
+    }
+
+    #[test]
+    fn test_from_obj() {
+        let source = "
+# A tetrahedron
+mtllib props.mtl
+v 0 0 0
+v 4 0 0
+v 0 4 0
+v 0 0 4
+vn 0 0 1
+usemtl wood
+f 1//1 3//1 2//1
+f 1 2 4
+usemtl stone
+f -4 4 3
+f 2 3 4
+";
+        let (mesh, materials) = TriangleMesh::from_obj(source).unwrap();
+        assert_eq!(materials, vec!["default", "wood", "stone"]);
+        assert_eq!(mesh.triangles.len(), 4);
+        // Each vertex is used by both materials.
+        assert_eq!(mesh.positions.len(), 8);
+        let mut tree = Tree::<hierarchy!(3, 2; u32)>::new();
+        let mut mesh = mesh;
+        mesh.translate(Vec3::splat(1.0));
+        tree.voxelize(&mesh, UVec3::ZERO, 0.5, Some(0));
+        assert_eq!(tree.get_value(UVec3::new(3, 3, 3)), Some(0));
+        // Touched by faces of both materials, and the last one is stone.
+        assert_eq!(tree.get_value(UVec3::new(2, 2, 9)), Some(2));
+
+        assert_eq!(
+            TriangleMesh::from_obj("v 0 0\n"),
+            Err(super::ObjError::InvalidLine { line: 1 })
+        );
+        assert_eq!(
+            TriangleMesh::from_obj("v 0 0 0\nf 1 2 3\n"),
+            Err(super::ObjError::InvalidIndex { line: 2, index: 2 })
+        );
+    }
+}
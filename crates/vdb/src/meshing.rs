@@ -0,0 +1,764 @@
+use std::sync::OnceLock;
+
+use fxhash::FxHashMap;
+use glam::{IVec3, UVec3, Vec3};
+
+use crate::{stencil::offset_coords, Accessor, Node, NodeConst, Tree, TreeCoords};
+
+/// Method used to extract the surface of a tree into a triangle mesh.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MeshingMethod {
+    /// Blocky surface along the faces of the voxels. Coplanar faces with the same value are
+    /// merged into larger quads.
+    Greedy,
+    /// Smooth surface with one vertex within each cell of 2x2x2 voxels crossing the surface.
+    SurfaceNets,
+    /// Smooth surface with the triangles of marching cubes in each cell of 2x2x2 voxels crossing
+    /// the surface. Trees only store occupancy, so vertices are placed on the midpoints of the
+    /// edges between occupied and unoccupied voxels rather than interpolated along them.
+    /// Ambiguous faces keep their occupied corners connected.
+    MarchingCubes,
+}
+
+/// Indexed triangle mesh extracted from a tree with [`Tree::extract_mesh`].
+/// Positions are in voxels relative to the origin passed to the extraction, where the voxel at
+/// `origin + (x, y, z)` covers the box from `(x, y, z)` to `(x + 1, y + 1, z + 1)`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SurfaceMesh<T> {
+    pub positions: Vec<Vec3>,
+    /// Unit normal of each vertex, pointing out of the occupied region.
+    pub normals: Vec<Vec3>,
+    /// Value of the voxel each vertex was extracted from.
+    pub values: Vec<T>,
+    /// Three indices per triangle, counter-clockwise when seen from outside.
+    pub indices: Vec<u32>,
+}
+
+impl<T> Default for SurfaceMesh<T> {
+    fn default() -> Self {
+        Self {
+            positions: Vec::new(),
+            normals: Vec::new(),
+            values: Vec::new(),
+            indices: Vec::new(),
+        }
+    }
+}
+
+impl<T> SurfaceMesh<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    pub fn num_triangles(&self) -> usize {
+        self.indices.len() / 3
+    }
+
+    /// Move the vertices and triangles of `other` into this mesh.
+    pub fn append(&mut self, other: Self) {
+        let offset = self.positions.len() as u32;
+        self.positions.extend(other.positions);
+        self.normals.extend(other.normals);
+        self.values.extend(other.values);
+        self.indices
+            .extend(other.indices.into_iter().map(|index| index + offset));
+    }
+
+    #[inline]
+    fn push_vertex(&mut self, position: Vec3, normal: Vec3, value: T) -> u32 {
+        self.positions.push(position);
+        self.normals.push(normal);
+        self.values.push(value);
+        self.positions.len() as u32 - 1
+    }
+
+    /// Add two triangles covering the quad between four vertices in counter-clockwise order.
+    #[inline]
+    fn push_quad(&mut self, vertices: [u32; 4]) {
+        self.indices.extend([
+            vertices[0],
+            vertices[1],
+            vertices[2],
+            vertices[0],
+            vertices[2],
+            vertices[3],
+        ]);
+    }
+}
+
+/// Values of the voxels in a leaf-sized block and of the layer of voxels around it,
+/// so that faces on the boundary of the block can be resolved without looking up the tree.
+struct PaddedBlock<T> {
+    /// Position of the min corner of the block relative to the origin of the mesh.
+    offset: Vec3,
+    /// Number of layers of voxels loaded around the block.
+    padding: i32,
+    size: IVec3,
+    values: Vec<Option<T>>,
+}
+
+impl<T: Copy> PaddedBlock<T> {
+    /// Value of the voxel at `local` from the origin of the block, within the padding around
+    /// the block on each axis.
+    #[inline]
+    fn get(&self, local: IVec3) -> Option<T> {
+        let p = local + self.padding;
+        self.values[((p.x * self.size.y + p.y) * self.size.z + p.z) as usize]
+    }
+}
+
+impl<ROOT: Node> Tree<ROOT>
+where
+    [(); ROOT::LEVEL as usize + 1]: Sized,
+{
+    /// Extract the surface between occupied and unoccupied voxels into a triangle mesh, with
+    /// positions relative to `origin`. Voxels outside of the tree are considered unoccupied,
+    /// so the mesh is closed.
+    ///
+    /// The tree is meshed one leaf-sized block at a time, and each block looks up the layer of
+    /// voxels around it to find the faces on its boundary. Only the blocks on the boundary of
+    /// tiles are meshed, as the surface can't pass through their interior.
+    /// Quads from [`MeshingMethod::Greedy`] never span multiple blocks, and vertices shared by
+    /// two blocks with [`MeshingMethod::SurfaceNets`] or [`MeshingMethod::MarchingCubes`] are
+    /// duplicated in both of them. With [`MeshingMethod::MarchingCubes`], the triangles of a cell
+    /// are meshed by the block holding its first occupied corner.
+    /// ```
+    /// #![feature(generic_const_exprs)]
+    /// use dust_vdb::{Tree, hierarchy, MeshingMethod};
+    /// use glam::UVec3;
+    /// let mut tree = Tree::<hierarchy!(4, 2; u8)>::new();
+    /// tree.fill(UVec3::new(0, 0, 0), UVec3::new(3, 3, 1), Some(1));
+    /// let mesh = tree.extract_mesh(UVec3::ZERO, MeshingMethod::Greedy);
+    /// // One quad for each side of the box.
+    /// assert_eq!(mesh.num_triangles(), 12);
+    /// assert_eq!(mesh.values, vec![1; 24]);
+    ///
+    /// // The box now spans two leaf nodes along x, so the sides along x are split in two.
+    /// tree.fill(UVec3::new(4, 0, 0), UVec3::new(5, 3, 1), Some(1));
+    /// let mesh = tree.extract_mesh(UVec3::ZERO, MeshingMethod::Greedy);
+    /// assert_eq!(mesh.num_triangles(), 20);
+    /// ```
+    pub fn extract_mesh<C: TreeCoords<ROOT>>(
+        &self,
+        origin: C,
+        method: MeshingMethod,
+    ) -> SurfaceMesh<ROOT::Voxel>
+    where
+        ROOT: ~const NodeConst,
+    {
+        let mut blocks: Vec<UVec3> = self.iter_leaf().map(|(origin, _)| origin).collect();
+        let leaf_extent = ROOT::LeafType::EXTENT;
+        self.for_each_tile_in_aabb(UVec3::ZERO, ROOT::EXTENT_MASK, |min, max, _| {
+            let last = (max - min) / leaf_extent;
+            for x in 0..=last.x {
+                for y in 0..=last.y {
+                    // Skip over the blocks in the interior of the tile.
+                    let step = if x == 0 || x == last.x || y == 0 || y == last.y {
+                        1
+                    } else {
+                        last.z.max(1)
+                    };
+                    for z in (0..=last.z).step_by(step as usize) {
+                        blocks.push(min + UVec3::new(x, y, z) * leaf_extent);
+                    }
+                }
+            }
+        });
+        blocks.sort_unstable_by_key(|origin| origin.to_array());
+
+        let origin = origin.to_tree_coords();
+        let mut mesh = SurfaceMesh::new();
+        let mut accessor = self.accessor();
+        for block_origin in blocks {
+            let block = self.load_padded_block(&mut accessor, block_origin, origin, method);
+            mesh_block::<ROOT>(&block, method, &mut mesh);
+        }
+        mesh
+    }
+
+    /// Extract the surface within the leaf-sized block containing `coords` into a triangle mesh,
+    /// with positions relative to `origin`. Meshes of adjacent blocks fit together into the mesh
+    /// returned by [`Tree::extract_mesh`], so only the blocks modified since the last extraction
+    /// need to be meshed again.
+    pub fn extract_leaf_mesh<C: TreeCoords<ROOT>>(
+        &self,
+        coords: C,
+        origin: C,
+        method: MeshingMethod,
+    ) -> SurfaceMesh<ROOT::Voxel>
+    where
+        ROOT: ~const NodeConst,
+    {
+        let mut mesh = SurfaceMesh::new();
+        let block = self.load_padded_block(
+            &mut self.accessor(),
+            coords.to_tree_coords() & !ROOT::LeafType::EXTENT_MASK,
+            origin.to_tree_coords(),
+            method,
+        );
+        mesh_block::<ROOT>(&block, method, &mut mesh);
+        mesh
+    }
+
+    fn load_padded_block(
+        &self,
+        accessor: &mut Accessor<ROOT>,
+        block_origin: UVec3,
+        mesh_origin: UVec3,
+        method: MeshingMethod,
+    ) -> PaddedBlock<ROOT::Voxel>
+    where
+        ROOT: ~const NodeConst,
+    {
+        // Normals of marching cubes look up the neighbors of the voxels around the block.
+        let padding = match method {
+            MeshingMethod::Greedy | MeshingMethod::SurfaceNets => 1,
+            MeshingMethod::MarchingCubes => 2,
+        };
+        let size = ROOT::LeafType::EXTENT.as_ivec3() + padding * 2;
+        let mut values = Vec::with_capacity((size.x * size.y * size.z) as usize);
+        for x in -padding..size.x - padding {
+            for y in -padding..size.y - padding {
+                for z in -padding..size.z - padding {
+                    let coords = offset_coords::<ROOT>(block_origin, IVec3::new(x, y, z));
+                    values.push(coords.and_then(|coords| accessor.get(coords)));
+                }
+            }
+        }
+        PaddedBlock {
+            offset: (block_origin.as_i64vec3() - mesh_origin.as_i64vec3()).as_vec3(),
+            padding,
+            size,
+            values,
+        }
+    }
+}
+
+fn mesh_block<ROOT: Node>(
+    block: &PaddedBlock<ROOT::Voxel>,
+    method: MeshingMethod,
+    mesh: &mut SurfaceMesh<ROOT::Voxel>,
+) {
+    match method {
+        MeshingMethod::Greedy => greedy_mesh_block::<ROOT>(block, mesh),
+        MeshingMethod::SurfaceNets => surface_nets_block::<ROOT>(block, mesh),
+        MeshingMethod::MarchingCubes => marching_cubes_block::<ROOT>(block, mesh),
+    }
+}
+
+/// Unit vector along `axis`, scaled by `sign`.
+#[inline]
+fn axis_step(axis: usize, sign: i32) -> IVec3 {
+    let mut step = IVec3::ZERO;
+    step[axis] = sign;
+    step
+}
+
+fn greedy_mesh_block<ROOT: Node>(
+    block: &PaddedBlock<ROOT::Voxel>,
+    mesh: &mut SurfaceMesh<ROOT::Voxel>,
+) {
+    let extent = ROOT::LeafType::EXTENT.as_ivec3();
+    for d in 0..3 {
+        let (u, v) = ((d + 1) % 3, (d + 2) % 3);
+        let mut mask: Vec<Option<ROOT::Voxel>> = vec![None; (extent[u] * extent[v]) as usize];
+        for sign in [-1, 1] {
+            let step = axis_step(d, sign);
+            let normal = step.as_vec3();
+            for k in 0..extent[d] {
+                // Faces in this slice that are not covered by a neighbor.
+                for a in 0..extent[u] {
+                    for b in 0..extent[v] {
+                        let mut p = IVec3::ZERO;
+                        p[d] = k;
+                        p[u] = a;
+                        p[v] = b;
+                        mask[(a * extent[v] + b) as usize] = match block.get(p) {
+                            Some(value) if block.get(p + step).is_none() => Some(value),
+                            _ => None,
+                        };
+                    }
+                }
+                // Grow each face along v, then along u as long as the whole row matches.
+                for a in 0..extent[u] {
+                    let mut b = 0;
+                    while b < extent[v] {
+                        let start = (a * extent[v] + b) as usize;
+                        let Some(value) = mask[start] else {
+                            b += 1;
+                            continue;
+                        };
+                        let mut width = 1;
+                        while b + width < extent[v] && mask[start + width as usize] == Some(value) {
+                            width += 1;
+                        }
+                        let mut height = 1;
+                        while a + height < extent[u] {
+                            let row = ((a + height) * extent[v] + b) as usize;
+                            if mask[row..row + width as usize]
+                                .iter()
+                                .any(|face| *face != Some(value))
+                            {
+                                break;
+                            }
+                            height += 1;
+                        }
+                        for h in 0..height {
+                            let row = ((a + h) * extent[v] + b) as usize;
+                            mask[row..row + width as usize].fill(None);
+                        }
+
+                        let mut corner = IVec3::ZERO;
+                        corner[d] = k + (sign > 0) as i32;
+                        corner[u] = a;
+                        corner[v] = b;
+                        let corner = block.offset + corner.as_vec3();
+                        let du = axis_step(u, height).as_vec3();
+                        let dv = axis_step(v, width).as_vec3();
+                        // u cross v is along +d, so the winding flips for faces facing -d.
+                        let corners = if sign > 0 {
+                            [corner, corner + du, corner + du + dv, corner + dv]
+                        } else {
+                            [corner, corner + dv, corner + du + dv, corner + du]
+                        };
+                        let vertices = corners.map(|p| mesh.push_vertex(p, normal, value));
+                        mesh.push_quad(vertices);
+                        b += width;
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn surface_nets_block<ROOT: Node>(
+    block: &PaddedBlock<ROOT::Voxel>,
+    mesh: &mut SurfaceMesh<ROOT::Voxel>,
+) {
+    let extent = ROOT::LeafType::EXTENT.as_ivec3();
+    // Vertex of each cell, keyed by the voxel at its min corner.
+    let mut vertices: FxHashMap<IVec3, u32> = FxHashMap::default();
+    for x in 0..extent.x {
+        for y in 0..extent.y {
+            for z in 0..extent.z {
+                let p = IVec3::new(x, y, z);
+                if block.get(p).is_none() {
+                    continue;
+                }
+                for d in 0..3 {
+                    let (u, v) = ((d + 1) % 3, (d + 2) % 3);
+                    for sign in [-1, 1] {
+                        let step = axis_step(d, sign);
+                        if block.get(p + step).is_some() {
+                            continue;
+                        }
+                        // The four cells around the edge between the voxel and its neighbor,
+                        // counter-clockwise around +d.
+                        let base = if sign > 0 { p } else { p + step };
+                        let (eu, ev) = (axis_step(u, 1), axis_step(v, 1));
+                        let cells = [base, base - eu, base - eu - ev, base - ev];
+                        let normal = step.as_vec3();
+                        let mut quad = cells.map(|cell| {
+                            *vertices.entry(cell).or_insert_with(|| {
+                                let (position, normal, value) = cell_vertex(block, cell, normal);
+                                mesh.push_vertex(position, normal, value)
+                            })
+                        });
+                        if sign < 0 {
+                            quad.swap(1, 3);
+                        }
+                        mesh.push_quad(quad);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Position, normal and value of the vertex of the cell with its min corner at `cell`.
+/// The vertex is placed at the average of the midpoints of the cell edges crossing the surface,
+/// and the normal follows the gradient of the occupancy of the corners, falling back to
+/// `fallback_normal` when the corners cancel out.
+fn cell_vertex<T: Copy>(
+    block: &PaddedBlock<T>,
+    cell: IVec3,
+    fallback_normal: Vec3,
+) -> (Vec3, Vec3, T) {
+    let offsets = CELL_CORNERS;
+    let corners = offsets.map(|offset| block.get(cell + offset));
+
+    let mut sum = Vec3::ZERO;
+    let mut count = 0;
+    let mut gradient = Vec3::ZERO;
+    for i in 0..8 {
+        let direction = offsets[i].as_vec3() * 2.0 - 1.0;
+        gradient += if corners[i].is_some() {
+            -direction
+        } else {
+            direction
+        };
+        for bit in [1, 2, 4] {
+            let j = i | bit;
+            if j != i && corners[i].is_some() != corners[j].is_some() {
+                sum += (offsets[i] + offsets[j]).as_vec3() * 0.5;
+                count += 1;
+            }
+        }
+    }
+    let position = block.offset + cell.as_vec3() + 0.5 + sum / count as f32;
+    let normal = match gradient.try_normalize() {
+        Some(normal) => normal,
+        None => fallback_normal,
+    };
+    let value = corners
+        .into_iter()
+        .flatten()
+        .next()
+        .expect("Cells on the surface have at least one occupied corner");
+    (position, normal, value)
+}
+
+/// Offset of each corner of a cell from its min corner, indexed by `x << 2 | y << 1 | z`.
+const CELL_CORNERS: [IVec3; 8] = {
+    let mut corners = [IVec3::ZERO; 8];
+    let mut i = 0;
+    while i < 8 {
+        corners[i] = IVec3::new((i >> 2) as i32 & 1, (i >> 1) as i32 & 1, i as i32 & 1);
+        i += 1;
+    }
+    corners
+};
+
+/// Triangles of marching cubes for each combination of occupied corners of a cell, as the
+/// corners at both ends of the edge holding each vertex.
+fn marching_cubes_table() -> &'static [Vec<[[u8; 2]; 3]>; 256] {
+    static TABLE: OnceLock<[Vec<[[u8; 2]; 3]>; 256]> = OnceLock::new();
+    TABLE.get_or_init(|| std::array::from_fn(|occupancy| marching_cubes_triangles(occupancy as u8)))
+}
+
+/// Build the triangles of a cell with the corners set in `occupancy` occupied.
+///
+/// The surface crosses each face of the cell along segments that cut off its unoccupied
+/// corners, from the edge leaving the occupied corners to the next edge entering them when
+/// walking around the face. Each edge crossing the surface starts a segment on one of its faces
+/// and ends one on the other, so the segments chain into loops that are split into triangle
+/// fans. Faces are resolved from their own corners only, so adjacent cells agree on them.
+fn marching_cubes_triangles(occupancy: u8) -> Vec<[[u8; 2]; 3]> {
+    let occupied = |corner: u8| occupancy >> corner & 1 != 0;
+    let edge = |a: u8, b: u8| a.min(b) as usize * 8 + a.max(b) as usize;
+    // Edge ending the segment started by each edge, indexed by `edge`.
+    let mut next = [None; 64];
+    for d in 0..3 {
+        let (u, v) = ((d + 1) % 3, (d + 2) % 3);
+        for side in 0..2 {
+            // Corners counter-clockwise when seen from outside of the cell.
+            let mut corners = [(0, 0), (1, 0), (1, 1), (0, 1)]
+                .map(|(a, b)| side << (2 - d) | a << (2 - u) | b << (2 - v));
+            if side == 0 {
+                corners.reverse();
+            }
+            for k in 0..4 {
+                if !occupied(corners[k]) || occupied(corners[(k + 1) % 4]) {
+                    continue;
+                }
+                let mut j = (k + 1) % 4;
+                while !occupied(corners[(j + 1) % 4]) {
+                    j = (j + 1) % 4;
+                }
+                next[edge(corners[k], corners[(k + 1) % 4])] =
+                    Some(edge(corners[j], corners[(j + 1) % 4]));
+            }
+        }
+    }
+
+    let mut triangles = Vec::new();
+    let mut visited = [false; 64];
+    for start in 0..64 {
+        if next[start].is_none() || visited[start] {
+            continue;
+        }
+        let mut polygon = Vec::new();
+        let mut current = start;
+        while !visited[current] {
+            visited[current] = true;
+            polygon.push([(current / 8) as u8, (current % 8) as u8]);
+            current = next[current].expect("Edges crossing the surface form loops");
+        }
+        // The loops run clockwise when seen from outside of the surface.
+        for i in 1..polygon.len() - 1 {
+            triangles.push([polygon[0], polygon[i + 1], polygon[i]]);
+        }
+    }
+    triangles
+}
+
+fn marching_cubes_block<ROOT: Node>(
+    block: &PaddedBlock<ROOT::Voxel>,
+    mesh: &mut SurfaceMesh<ROOT::Voxel>,
+) {
+    let extent = ROOT::LeafType::EXTENT.as_ivec3();
+    let table = marching_cubes_table();
+    // Vertex on each edge, keyed by the voxel at its min end and the axis of the edge.
+    let mut vertices: FxHashMap<(IVec3, usize), u32> = FxHashMap::default();
+    for x in -1..extent.x {
+        for y in -1..extent.y {
+            for z in -1..extent.z {
+                let cell = IVec3::new(x, y, z);
+                let corners = CELL_CORNERS.map(|offset| block.get(cell + offset));
+                let occupancy = (0..8).fold(0, |occupancy, i| {
+                    occupancy | (corners[i].is_some() as u8) << i
+                });
+                if occupancy == 0 || occupancy == u8::MAX {
+                    continue;
+                }
+                // Cells are meshed by the block holding their first occupied corner.
+                let owner = cell + CELL_CORNERS[occupancy.trailing_zeros() as usize];
+                if owner.cmplt(IVec3::ZERO).any() || owner.cmpge(extent).any() {
+                    continue;
+                }
+                for triangle in &table[occupancy as usize] {
+                    let triangle = triangle.map(|[a, b]| {
+                        let axis = 2 - (a ^ b).trailing_zeros() as usize;
+                        let min = cell + CELL_CORNERS[a as usize];
+                        *vertices.entry((min, axis)).or_insert_with(|| {
+                            let max = cell + CELL_CORNERS[b as usize];
+                            let (inside, outside, value) = match corners[a as usize] {
+                                Some(value) => (min, max, value),
+                                None => (max, min, corners[b as usize].unwrap()),
+                            };
+                            let normal = (occupancy_gradient(block, inside)
+                                + occupancy_gradient(block, outside))
+                            .try_normalize()
+                            .unwrap_or((outside - inside).as_vec3());
+                            let position = block.offset + (min + max).as_vec3() * 0.5 + 0.5;
+                            mesh.push_vertex(position, normal, value)
+                        })
+                    });
+                    mesh.indices.extend(triangle);
+                }
+            }
+        }
+    }
+}
+
+/// Gradient of the occupancy around the voxel at `local`, pointing towards unoccupied voxels.
+#[inline]
+fn occupancy_gradient<T: Copy>(block: &PaddedBlock<T>, local: IVec3) -> Vec3 {
+    let mut gradient = Vec3::ZERO;
+    for d in 0..3 {
+        let step = axis_step(d, 1);
+        gradient[d] = block.get(local - step).is_some() as i32 as f32
+            - block.get(local + step).is_some() as i32 as f32;
+    }
+    gradient
+}
+
+#[cfg(test)]
+mod tests {
+    use fxhash::FxHashMap;
+    use glam::{IVec3, UVec3, Vec3};
+
+    use super::{MeshingMethod, SurfaceMesh};
+    use crate::{hierarchy, stencil::offset_coords, Tree};
+
+    type MyRoot = hierarchy!(3, 2, 2; u8);
+    type MyTree = Tree<MyRoot>;
+
+    fn random_tree() -> MyTree {
+        use rand::prelude::*;
+        let mut rng = rand::thread_rng();
+        let mut tree = MyTree::new();
+        for _i in 0..40 {
+            let min = UVec3::new(rng.gen(), rng.gen(), rng.gen()) >> 26;
+            let size = UVec3::new(rng.gen(), rng.gen(), rng.gen()) >> 28;
+            tree.fill(min, min + size, Some(rng.gen_range(1..4)));
+        }
+        // A tile, and voxels on the boundary of the tree.
+        tree.fill(UVec3::new(64, 64, 64), UVec3::new(95, 95, 95), Some(5));
+        tree.fill(UVec3::new(120, 0, 0), UVec3::new(127, 3, 3), Some(6));
+        tree
+    }
+
+    #[test]
+    fn test_greedy_mesh() {
+        let tree = random_tree();
+        let mesh = tree.extract_mesh(UVec3::ZERO, MeshingMethod::Greedy);
+        let mut exposed_faces = 0;
+        for coords in tree.iter() {
+            for offset in crate::FACE_NEIGHBORS {
+                let neighbor = offset_coords::<MyRoot>(coords, offset);
+                if neighbor.and_then(|n| tree.get_value(n)).is_none() {
+                    exposed_faces += 1;
+                }
+            }
+        }
+        let mut area = 0.0;
+        for triangle in mesh.indices.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| mesh.positions[triangle[i] as usize]);
+            let normal = mesh.normals[triangle[0] as usize];
+            let cross = (b - a).cross(c - a);
+            assert!(cross.dot(normal) > 0.0);
+            area += cross.length() / 2.0;
+
+            // The voxel behind the face has the value of the face, and the one in front is empty.
+            let center = (a + b + c) / 3.0;
+            let behind = (center - normal * 0.5).floor().as_uvec3();
+            assert_eq!(
+                tree.get_value(behind),
+                Some(mesh.values[triangle[0] as usize])
+            );
+            let front = (center + normal * 0.5).floor();
+            if front.cmpge(Vec3::ZERO).all() && front.cmplt(Vec3::splat(128.0)).all() {
+                assert_eq!(tree.get_value(front.as_uvec3()), None);
+            }
+        }
+        assert_eq!(area, exposed_faces as f32);
+
+        // Meshing each block separately gives the same triangles.
+        let mut blocks: Vec<UVec3> = tree.iter().map(|coords| coords & !3).collect();
+        blocks.sort_unstable_by_key(|origin| origin.to_array());
+        blocks.dedup();
+        let mut combined = SurfaceMesh::new();
+        for origin in blocks {
+            combined.append(tree.extract_leaf_mesh(origin, UVec3::ZERO, MeshingMethod::Greedy));
+        }
+        assert_eq!(combined, mesh);
+    }
+
+    #[test]
+    fn test_surface_nets() {
+        let tree = random_tree();
+        let mesh = tree.extract_mesh(UVec3::ZERO, MeshingMethod::SurfaceNets);
+        assert!(!mesh.is_empty());
+        // Vertices are duplicated on the boundaries of blocks, so edges are matched by position.
+        // The surface is closed, so each edge is walked once in each direction.
+        let key = |index: u32| mesh.positions[index as usize].to_array().map(f32::to_bits);
+        let mut edges: FxHashMap<_, i32> = FxHashMap::default();
+        for triangle in mesh.indices.chunks_exact(3) {
+            for i in 0..3 {
+                let (a, b) = (key(triangle[i]), key(triangle[(i + 1) % 3]));
+                *edges.entry((a, b)).or_default() += 1;
+                *edges.entry((b, a)).or_default() -= 1;
+            }
+        }
+        assert!(edges.values().all(|count| *count == 0));
+
+        for (i, position) in mesh.positions.iter().enumerate() {
+            // Each vertex lies within its cell, next to a voxel with its value.
+            let cell = (*position - 0.5).floor();
+            let value = (0..8).find_map(|j| {
+                let offset = Vec3::new((j >> 2 & 1) as f32, (j >> 1 & 1) as f32, (j & 1) as f32);
+                let coords = cell + offset;
+                if coords.cmplt(Vec3::ZERO).any() {
+                    return None;
+                }
+                tree.get_value(coords.as_uvec3())
+            });
+            assert_eq!(value, Some(mesh.values[i]));
+            assert!((mesh.normals[i].length() - 1.0).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn test_marching_cubes() {
+        let tree = random_tree();
+        let mesh = tree.extract_mesh(UVec3::ZERO, MeshingMethod::MarchingCubes);
+        assert!(!mesh.is_empty());
+        // The surface is closed, so each edge is walked once in each direction, and encloses a
+        // positive volume.
+        let key = |index: u32| mesh.positions[index as usize].to_array().map(f32::to_bits);
+        let mut edges: FxHashMap<_, i32> = FxHashMap::default();
+        let mut volume = 0.0;
+        for triangle in mesh.indices.chunks_exact(3) {
+            for i in 0..3 {
+                let (a, b) = (key(triangle[i]), key(triangle[(i + 1) % 3]));
+                *edges.entry((a, b)).or_default() += 1;
+                *edges.entry((b, a)).or_default() -= 1;
+            }
+            let [a, b, c] = [0, 1, 2].map(|i| mesh.positions[triangle[i] as usize]);
+            volume += a.dot(b.cross(c)) / 6.0;
+        }
+        assert!(edges.values().all(|count| *count == 0));
+        assert!(volume > 0.0 && volume < tree.count_active() as f32);
+
+        for (i, position) in mesh.positions.iter().enumerate() {
+            // Each vertex lies halfway between the centers of an occupied voxel with its value
+            // and an unoccupied one.
+            let along = (*position - 0.5).fract().cmpne(Vec3::ZERO);
+            assert_eq!(along.bitmask().count_ones(), 1);
+            let min = (*position - 0.5).floor();
+            let max = min + Vec3::select(along, Vec3::ONE, Vec3::ZERO);
+            let value = |coords: Vec3| {
+                if coords.cmplt(Vec3::ZERO).any() || coords.cmpge(Vec3::splat(128.0)).any() {
+                    return None;
+                }
+                tree.get_value(coords.as_uvec3())
+            };
+            let (min, max) = (value(min), value(max));
+            assert!(min.is_some() != max.is_some());
+            assert_eq!(min.or(max), Some(mesh.values[i]));
+            assert!((mesh.normals[i].length() - 1.0).abs() < 1e-4);
+        }
+
+        // Triangles of a box face outwards, and meshing each block separately gives the same
+        // triangles.
+        let mut tree = MyTree::new();
+        tree.fill(UVec3::new(2, 3, 1), UVec3::new(9, 5, 6), Some(1));
+        let mesh = tree.extract_mesh(UVec3::ZERO, MeshingMethod::MarchingCubes);
+        for triangle in mesh.indices.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| mesh.positions[triangle[i] as usize]);
+            let outward = (a + b + c) / 3.0 - Vec3::new(6.0, 4.5, 4.0);
+            assert!((b - a).cross(c - a).dot(outward) > 0.0);
+        }
+        let mut combined = SurfaceMesh::new();
+        for origin in [0, 4, 8].map(|x| UVec3::new(x, 0, 0)) {
+            for y in [0, 4] {
+                for z in [0, 4] {
+                    let coords = origin + UVec3::new(0, y, z);
+                    let block =
+                        tree.extract_leaf_mesh(coords, UVec3::ZERO, MeshingMethod::MarchingCubes);
+                    combined.append(block);
+                }
+            }
+        }
+        assert_eq!(combined, mesh);
+    }
+
+    #[test]
+    fn test_extract_mesh_signed() {
+        // The same shapes around the signed origin, and shifted into an unsigned tree.
+        let boxes = [
+            (IVec3::new(-5, -3, -7), IVec3::new(2, 4, 1), 1),
+            (IVec3::new(-32, -32, -32), IVec3::new(-1, -1, -1), 2),
+            (IVec3::new(0, -40, 3), IVec3::new(0, 40, 3), 3),
+        ];
+        let shift = IVec3::splat(64);
+        let mut signed = Tree::<hierarchy!(#, 3, 2; u8)>::new();
+        let mut unsigned = Tree::<hierarchy!(3, 3, 2; u8)>::new();
+        for (min, max, value) in boxes {
+            signed.fill(min, max, Some(value));
+            unsigned.fill(
+                (min + shift).as_uvec3(),
+                (max + shift).as_uvec3(),
+                Some(value),
+            );
+        }
+        for method in [
+            MeshingMethod::Greedy,
+            MeshingMethod::SurfaceNets,
+            MeshingMethod::MarchingCubes,
+        ] {
+            let mesh = signed.extract_mesh(IVec3::ZERO, method);
+            assert!(!mesh.is_empty());
+            assert_eq!(mesh, unsigned.extract_mesh(shift.as_uvec3(), method));
+            assert_eq!(
+                signed.extract_leaf_mesh(IVec3::new(-1, -1, -1), IVec3::ZERO, method),
+                unsigned.extract_leaf_mesh(UVec3::splat(63), shift.as_uvec3(), method)
+            );
+        }
+    }
+}
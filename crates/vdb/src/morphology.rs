@@ -0,0 +1,589 @@
+use std::collections::{HashMap, HashSet};
+
+use glam::UVec3;
+
+use crate::{
+    journal::iter_set_bits,
+    parallel::leaf_local_coords,
+    stencil::{offset_coords, FACE_NEIGHBORS},
+    Accessor, IsLeaf, Node, NodeConst, Tree,
+};
+
+/// Returns the mask with bit `i` taken from bit `i + offset` of `src`, or zero where that bit is
+/// out of range.
+fn shifted(src: &[u64], offset: isize) -> Vec<u64> {
+    let words = src.len() as isize;
+    let word_shift = offset.div_euclid(64);
+    let bit_shift = offset.rem_euclid(64) as u32;
+    let word = |index: isize| {
+        if (0..words).contains(&index) {
+            src[index as usize]
+        } else {
+            0
+        }
+    };
+    (0..words)
+        .map(|index| {
+            let low = word(index + word_shift);
+            if bit_shift == 0 {
+                low
+            } else {
+                (low >> bit_shift) | (word(index + word_shift + 1) << (64 - bit_shift))
+            }
+        })
+        .collect()
+}
+
+/// Masks over the voxels of a leaf-sized region for each direction of [`FACE_NEIGHBORS`],
+/// indexed like the occupancy mask of leaf nodes.
+struct FaceMasks {
+    /// All voxels of a region.
+    full: Vec<u64>,
+    /// Voxels whose neighbor in the direction lies within the same region.
+    interior: [Vec<u64>; 6],
+    /// Voxels on the face of the region in the direction.
+    face: [Vec<u64>; 6],
+    /// Offset from the index of a voxel to the index of its neighbor in the direction, within
+    /// the same region.
+    step: [isize; 6],
+    /// Offset from the index of a voxel on the face to the index of its neighbor in the
+    /// direction, within the adjacent region.
+    wrap: [isize; 6],
+}
+
+impl FaceMasks {
+    fn new<L: Node>() -> Self {
+        let log2 = L::EXTENT_LOG2;
+        let words = (L::SIZE + 63) / 64;
+        let shifts = [log2.y + log2.z, log2.z, 0];
+        let mut full = vec![0; words];
+        for index in 0..L::SIZE {
+            full[index / 64] |= 1 << (index % 64);
+        }
+        let mut interior: [Vec<u64>; 6] = std::array::from_fn(|_| vec![0; words]);
+        let mut face: [Vec<u64>; 6] = std::array::from_fn(|_| vec![0; words]);
+        let mut step = [0; 6];
+        let mut wrap = [0; 6];
+        for (dir, offset) in FACE_NEIGHBORS.iter().enumerate() {
+            let axis = dir / 2;
+            let sign = offset[axis] as isize;
+            let edge = (1 << log2[axis]) - 1;
+            let stride = 1 << shifts[axis];
+            step[dir] = sign * stride;
+            wrap[dir] = -sign * edge as isize * stride;
+            for index in 0..L::SIZE {
+                let coord = (index >> shifts[axis]) & edge;
+                let on_face = if sign < 0 { coord == 0 } else { coord == edge };
+                let mask = if on_face {
+                    &mut face[dir]
+                } else {
+                    &mut interior[dir]
+                };
+                mask[index / 64] |= 1 << (index % 64);
+            }
+        }
+        Self {
+            full,
+            interior,
+            face,
+            step,
+            wrap,
+        }
+    }
+
+    /// Returns the voxels of a region whose neighbor in the direction `dir` is occupied,
+    /// given the occupancy of the region and of the adjacent region in that direction.
+    fn occupied(&self, dir: usize, own: &[u64], adjacent: &[u64]) -> Vec<u64> {
+        let within = shifted(own, self.step[dir]);
+        let across = shifted(adjacent, self.wrap[dir]);
+        (0..own.len())
+            .map(|i| (within[i] & self.interior[dir][i]) | (across[i] & self.face[dir][i]))
+            .collect()
+    }
+}
+
+/// Content of a leaf-sized region of the tree.
+enum Region<'a, ROOT: Node> {
+    Empty,
+    /// The region lies within a tile.
+    Tile(ROOT::Voxel),
+    Leaf(&'a ROOT::LeafType),
+}
+
+/// Reads leaf-sized regions of a tree and the regions next to them as occupancy masks.
+struct Regions<'a, ROOT: Node>
+where
+    [(); ROOT::LEVEL as usize]: Sized,
+{
+    accessor: Accessor<'a, ROOT>,
+    leaves: HashMap<UVec3, &'a ROOT::LeafType>,
+    masks: FaceMasks,
+}
+
+impl<'a, ROOT: Node> Regions<'a, ROOT>
+where
+    [(); ROOT::LEVEL as usize + 1]: Sized,
+    ROOT::LeafType: IsLeaf + Node<Voxel = ROOT::Voxel>,
+{
+    fn new(tree: &'a Tree<ROOT>) -> Self {
+        Self {
+            accessor: tree.accessor(),
+            leaves: tree.iter_leaf().collect(),
+            masks: FaceMasks::new::<ROOT::LeafType>(),
+        }
+    }
+
+    fn region(&mut self, origin: UVec3) -> Region<'a, ROOT>
+    where
+        ROOT: ~const NodeConst,
+    {
+        if let Some(leaf) = self.leaves.get(&origin) {
+            return Region::Leaf(*leaf);
+        }
+        // Tiles cover whole leaf-sized regions.
+        match self.accessor.get(origin) {
+            Some(value) => Region::Tile(value),
+            None => Region::Empty,
+        }
+    }
+
+    /// Returns the regions next to the region at `origin` in each direction of
+    /// [`FACE_NEIGHBORS`]. Regions outside of the tree are empty.
+    fn adjacent(&mut self, origin: UVec3) -> [Region<'a, ROOT>; 6]
+    where
+        ROOT: ~const NodeConst,
+    {
+        let extent = ROOT::LeafType::EXTENT.as_ivec3();
+        std::array::from_fn(|dir| {
+            match offset_coords::<ROOT>(origin, FACE_NEIGHBORS[dir] * extent) {
+                Some(neighbor) => self.region(neighbor),
+                None => Region::Empty,
+            }
+        })
+    }
+
+    fn occupancy(&self, region: &Region<ROOT>) -> Vec<u64> {
+        match region {
+            Region::Empty => vec![0; self.masks.full.len()],
+            Region::Tile(_) => self.masks.full.clone(),
+            Region::Leaf(leaf) => {
+                let mut occupancy = vec![0; self.masks.full.len()];
+                leaf.get_occupancy(&mut occupancy);
+                occupancy
+            }
+        }
+    }
+
+    /// Returns the voxels of a region with the occupancy `own` whose neighbor is occupied, for each
+    /// direction of [`FACE_NEIGHBORS`], given the regions `adjacent` to it.
+    fn occupied_neighbors(&self, own: &[u64], adjacent: &[Region<ROOT>; 6]) -> [Vec<u64>; 6] {
+        std::array::from_fn(|dir| {
+            self.masks
+                .occupied(dir, own, &self.occupancy(&adjacent[dir]))
+        })
+    }
+}
+
+fn region_value<ROOT: Node>(region: &Region<ROOT>, index: usize) -> Option<ROOT::Voxel>
+where
+    ROOT::LeafType: Node<Voxel = ROOT::Voxel>,
+{
+    match region {
+        Region::Empty => None,
+        Region::Tile(value) => Some(*value),
+        Region::Leaf(leaf) => leaf.get(&[], leaf_local_coords::<ROOT>(index), &mut []),
+    }
+}
+
+/// Inserts the origins of the leaf-sized regions touching the faces of the tile between `min` and
+/// `max` (inclusive) from the inside, or from the outside when `outside` is set.
+fn insert_tile_faces<ROOT: Node>(
+    regions: &mut HashSet<UVec3>,
+    min: UVec3,
+    max: UVec3,
+    outside: bool,
+) {
+    let log2 = ROOT::LeafType::EXTENT_LOG2;
+    for axis in 0..3 {
+        let layers = if outside {
+            [min[axis] as i64 - 1, max[axis] as i64 + 1]
+        } else {
+            [min[axis] as i64, max[axis] as i64]
+        };
+        for layer in layers {
+            if layer < 0 || layer > ROOT::EXTENT_MASK[axis] as i64 {
+                continue;
+            }
+            let mut cell_min = min >> log2;
+            let mut cell_max = max >> log2;
+            cell_min[axis] = layer as u32 >> log2[axis];
+            cell_max[axis] = cell_min[axis];
+            for x in cell_min.x..=cell_max.x {
+                for y in cell_min.y..=cell_max.y {
+                    for z in cell_min.z..=cell_max.z {
+                        regions.insert(UVec3 { x, y, z } << log2);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<ROOT: Node> Tree<ROOT>
+where
+    [(); ROOT::LEVEL as usize + 1]: Sized,
+{
+    /// Recompute [`crate::LeafNode::active`] for all leaf nodes from their occupancy.
+    /// A voxel is on the surface if it is occupied and at least one of its six face neighbors is not.
+    /// Neighbors in adjacent leaf nodes are read from the faces of those leaf nodes, and voxels
+    /// outside of the tree are considered unoccupied.
+    ///
+    /// Tiles have no active mask, so voxels within tiles are never marked as surface voxels.
+    /// Leaf voxels next to a tile see the tile as occupied.
+    /// ```
+    /// #![feature(generic_const_exprs)]
+    /// use dust_vdb::{Tree, hierarchy};
+    /// use glam::UVec3;
+    /// let mut tree = Tree::<hierarchy!(4, 2)>::new();
+    /// for x in 0..3 {
+    ///     for y in 0..3 {
+    ///         for z in 3..6 {
+    ///             tree.set_value(UVec3::new(x, y, z), Some(true));
+    ///         }
+    ///     }
+    /// }
+    /// tree.update_surface();
+    /// let active: u32 = tree
+    ///     .iter_leaf()
+    ///     .map(|(_, leaf)| leaf.active.count_ones() as u32)
+    ///     .sum();
+    /// // Everything but the voxel in the center of the cube.
+    /// assert_eq!(active, 26);
+    /// ```
+    pub fn update_surface(&mut self)
+    where
+        ROOT: ~const NodeConst,
+        ROOT::LeafType: IsLeaf + Node<Voxel = ROOT::Voxel>,
+    {
+        // Surfaces are computed for all leaf nodes before writing any of them,
+        // because the neighbor lookups need to borrow the tree.
+        let mut active: HashMap<UVec3, Vec<u64>> = HashMap::new();
+        let mut regions = Regions::new(self);
+        for (origin, leaf) in self.iter_leaf() {
+            let own = regions.occupancy(&Region::Leaf(leaf));
+            let adjacent = regions.adjacent(origin);
+            let occupied = regions.occupied_neighbors(&own, &adjacent);
+            let surface = (0..own.len())
+                .map(|i| own[i] & !occupied.iter().fold(!0, |all, mask| all & mask[i]))
+                .collect();
+            active.insert(origin, surface);
+        }
+        for (origin, leaf) in self.iter_leaf_mut() {
+            leaf.set_active(&active[&origin]);
+        }
+    }
+
+    /// Grow the voxels in the tree by `iterations` voxels.
+    /// Each iteration fills the unoccupied face neighbors of all occupied voxels. A filled voxel
+    /// takes the value of the first of its occupied neighbors in the order of [`FACE_NEIGHBORS`].
+    ///
+    /// Each iteration works on the occupancy masks of the leaf nodes, reading the faces of the
+    /// adjacent leaf nodes. Tiles are treated as boxes, so only the regions along their faces
+    /// are visited.
+    /// The active masks are not updated. Call [`Tree::update_surface`] afterwards if needed.
+    /// ```
+    /// #![feature(generic_const_exprs)]
+    /// use dust_vdb::{Tree, hierarchy};
+    /// use glam::UVec3;
+    /// let mut tree = Tree::<hierarchy!(4, 2; u8)>::new();
+    /// tree.set_value(UVec3::new(10, 10, 10), Some(3));
+    /// tree.dilate(2);
+    /// // Growing along the faces produces a diamond.
+    /// assert_eq!(tree.count_active(), 25);
+    /// assert_eq!(tree.get_value(UVec3::new(12, 10, 10)), Some(3));
+    /// assert_eq!(tree.get_value(UVec3::new(11, 11, 10)), Some(3));
+    /// assert_eq!(tree.get_value(UVec3::new(11, 11, 11)), None);
+    ///
+    /// // Between two voxels, the one in the negative x direction wins.
+    /// let mut tree = Tree::<hierarchy!(4, 2; u8)>::new();
+    /// tree.set_value(UVec3::new(10, 10, 10), Some(1));
+    /// tree.set_value(UVec3::new(12, 10, 10), Some(2));
+    /// tree.dilate(1);
+    /// assert_eq!(tree.get_value(UVec3::new(11, 10, 10)), Some(1));
+    /// ```
+    pub fn dilate(&mut self, iterations: u32)
+    where
+        ROOT: ~const NodeConst,
+        ROOT::LeafType: IsLeaf + Node<Voxel = ROOT::Voxel>,
+    {
+        for _ in 0..iterations {
+            let mut candidates: HashSet<UVec3> = HashSet::new();
+            let extent = ROOT::LeafType::EXTENT.as_ivec3();
+            for (origin, _) in self.iter_leaf() {
+                candidates.insert(origin);
+                candidates.extend(
+                    FACE_NEIGHBORS
+                        .iter()
+                        .filter_map(|dir| offset_coords::<ROOT>(origin, *dir * extent)),
+                );
+            }
+            self.for_each_tile_in_aabb(UVec3::ZERO, ROOT::EXTENT_MASK, |min, max, _| {
+                insert_tile_faces::<ROOT>(&mut candidates, min, max, true);
+            });
+
+            let mut grown = Vec::new();
+            let mut regions = Regions::new(self);
+            for origin in candidates {
+                let region = regions.region(origin);
+                if let Region::Tile(_) = region {
+                    continue;
+                }
+                let own = regions.occupancy(&region);
+                let adjacent = regions.adjacent(origin);
+                let occupied = regions.occupied_neighbors(&own, &adjacent);
+                let added: Vec<u64> = (0..own.len())
+                    .map(|i| !own[i] & occupied.iter().fold(0, |any, mask| any | mask[i]))
+                    .collect();
+                if added.iter().all(|word| *word == 0) {
+                    continue;
+                }
+                let values: Vec<_> = iter_set_bits(&added)
+                    .map(|index| {
+                        let masks = &regions.masks;
+                        let dir = (0..6)
+                            .find(|dir| occupied[*dir][index / 64] & (1 << (index % 64)) != 0)
+                            .unwrap();
+                        let value = if masks.interior[dir][index / 64] & (1 << (index % 64)) != 0 {
+                            region_value(&region, (index as isize + masks.step[dir]) as usize)
+                        } else {
+                            region_value(
+                                &adjacent[dir],
+                                (index as isize + masks.wrap[dir]) as usize,
+                            )
+                        };
+                        (index, value.unwrap())
+                    })
+                    .collect();
+                grown.push((origin, values));
+            }
+            if grown.is_empty() {
+                return;
+            }
+            for (origin, values) in grown {
+                self.mark_dirty_voxel(origin);
+                // Safety: the leaf node is only used until the next edit.
+                let leaf = unsafe { &mut *self.ensure_leaf(origin) };
+                for (index, value) in values {
+                    leaf.set(
+                        &mut [],
+                        leaf_local_coords::<ROOT>(index),
+                        Some(value),
+                        &mut [],
+                    );
+                }
+            }
+        }
+    }
+
+    /// Shrink the voxels in the tree by `iterations` voxels.
+    /// Each iteration removes all occupied voxels with at least one unoccupied face neighbor.
+    /// Voxels outside of the tree are considered unoccupied.
+    ///
+    /// Each iteration works on the occupancy masks of the leaf nodes, reading the faces of the
+    /// adjacent leaf nodes. Tiles are treated as boxes, so only the regions along their faces
+    /// are visited, and split into leaf nodes where their faces shrink.
+    /// The active masks are not updated. Call [`Tree::update_surface`] afterwards if needed.
+    /// ```
+    /// #![feature(generic_const_exprs)]
+    /// use dust_vdb::{Tree, hierarchy};
+    /// use glam::UVec3;
+    /// let mut tree = Tree::<hierarchy!(4, 2)>::new();
+    /// tree.fill(UVec3::new(10, 10, 10), UVec3::new(14, 14, 14), Some(true));
+    /// tree.erode(1);
+    /// assert_eq!(tree.count_active(), 27);
+    /// assert_eq!(tree.bounding_box(), Some((UVec3::splat(11), UVec3::splat(13))));
+    /// ```
+    pub fn erode(&mut self, iterations: u32)
+    where
+        ROOT: ~const NodeConst,
+        ROOT::LeafType: IsLeaf + Node<Voxel = ROOT::Voxel>,
+    {
+        for _ in 0..iterations {
+            let mut candidates: HashSet<UVec3> =
+                self.iter_leaf().map(|(origin, _)| origin).collect();
+            self.for_each_tile_in_aabb(UVec3::ZERO, ROOT::EXTENT_MASK, |min, max, _| {
+                insert_tile_faces::<ROOT>(&mut candidates, min, max, false);
+            });
+
+            let mut removed: Vec<(UVec3, Vec<u64>)> = Vec::new();
+            let mut regions = Regions::new(self);
+            for origin in candidates {
+                let region = regions.region(origin);
+                let own = regions.occupancy(&region);
+                let adjacent = regions.adjacent(origin);
+                let occupied = regions.occupied_neighbors(&own, &adjacent);
+                let exposed: Vec<u64> = (0..own.len())
+                    .map(|i| own[i] & !occupied.iter().fold(!0, |all, mask| all & mask[i]))
+                    .collect();
+                if exposed.iter().any(|word| *word != 0) {
+                    removed.push((origin, exposed));
+                }
+            }
+            if removed.is_empty() {
+                return;
+            }
+            for (origin, exposed) in removed {
+                self.mark_dirty_voxel(origin);
+                // Safety: the leaf node is only used until the next edit. Tiles are split so
+                // that the leaf node holds the voxels of the tile.
+                let leaf = unsafe { &mut *self.ensure_leaf(origin) };
+                for index in iter_set_bits(&exposed) {
+                    leaf.set(&mut [], leaf_local_coords::<ROOT>(index), None, &mut []);
+                }
+                if leaf.is_empty() {
+                    // Clearing a voxel releases the leaf node if it is empty, and so on up the tree.
+                    self.root.set(&mut self.pool, origin, None, &mut []);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use glam::{IVec3, UVec3};
+
+    use crate::{hierarchy, Node, NodeConst, Tree, FACE_NEIGHBORS};
+
+    fn random_tree<ROOT: Node<Voxel = bool> + NodeConst>(
+        rng: &mut impl rand::Rng,
+    ) -> (Tree<ROOT>, HashSet<UVec3>)
+    where
+        [(); ROOT::LEVEL as usize + 1]: Sized,
+    {
+        let mut tree = Tree::<ROOT>::new();
+        let mut model = HashSet::new();
+        for _i in 0..2000 {
+            let location = UVec3::new(rng.gen(), rng.gen(), rng.gen()) >> 27;
+            tree.set_value(location, Some(true));
+            model.insert(location);
+        }
+        tree.fill(UVec3::new(16, 16, 16), UVec3::new(31, 31, 31), Some(true));
+        for x in 16..32 {
+            for y in 16..32 {
+                for z in 16..32 {
+                    model.insert(UVec3::new(x, y, z));
+                }
+            }
+        }
+        (tree, model)
+    }
+
+    fn neighbors(coords: UVec3) -> impl Iterator<Item = Option<UVec3>> {
+        FACE_NEIGHBORS.iter().map(move |dir| {
+            let neighbor = coords.as_ivec3() + *dir;
+            if neighbor.cmpge(IVec3::ZERO).all() && neighbor.cmplt(IVec3::splat(128)).all() {
+                Some(neighbor.as_uvec3())
+            } else {
+                None
+            }
+        })
+    }
+
+    #[test]
+    fn test_update_surface() {
+        let mut rng = rand::thread_rng();
+        let (mut tree, model) = random_tree::<hierarchy!(3, 2, 2)>(&mut rng);
+        tree.update_surface();
+        for (origin, leaf) in tree.iter_leaf() {
+            for index in 0..64 {
+                let local = UVec3::new(index >> 4, (index >> 2) & 3, index & 3);
+                let coords = origin + local;
+                let expected = model.contains(&coords)
+                    && neighbors(coords).any(|n| n.map_or(true, |n| !model.contains(&n)));
+                assert_eq!(leaf.active.get(index as usize), expected, "{:?}", coords);
+            }
+        }
+
+        // Pruning keeps the uniform leaf nodes holding surface voxels.
+        let mut tree = Tree::<hierarchy!(3, 2, 2)>::new();
+        for index in 0..64 {
+            tree.set_value(
+                UVec3::new(index >> 4, (index >> 2) & 3, index & 3),
+                Some(true),
+            );
+        }
+        tree.update_surface();
+        tree.prune();
+        let (_, leaf) = tree.iter_leaf().next().unwrap();
+        assert_eq!(leaf.active.count_ones(), 56);
+    }
+
+    #[test]
+    fn test_dilate_erode() {
+        let mut rng = rand::thread_rng();
+        let (mut tree, mut model) = random_tree::<hierarchy!(3, 2, 2)>(&mut rng);
+        tree.dilate(2);
+        for _ in 0..2 {
+            let grown: Vec<UVec3> = model
+                .iter()
+                .flat_map(|coords| neighbors(*coords).flatten())
+                .collect();
+            model.extend(grown);
+        }
+        assert_eq!(tree.count_active(), model.len() as u64);
+        for coords in model.iter() {
+            assert_eq!(tree.get_value(*coords), Some(true));
+        }
+
+        tree.erode(3);
+        for _ in 0..3 {
+            let removed: Vec<UVec3> = model
+                .iter()
+                .filter(|coords| {
+                    neighbors(**coords).any(|n| n.map_or(true, |n| !model.contains(&n)))
+                })
+                .copied()
+                .collect();
+            for coords in removed {
+                model.remove(&coords);
+            }
+        }
+        assert_eq!(tree.count_active(), model.len() as u64);
+        for coords in model.iter() {
+            assert_eq!(tree.get_value(*coords), Some(true));
+        }
+    }
+
+    #[test]
+    fn test_dilate_erode_tiles() {
+        type MyRoot = hierarchy!(3, 2, 2; u8);
+        let mut tree = Tree::<MyRoot>::new();
+        tree.fill(UVec3::new(32, 32, 32), UVec3::new(63, 63, 63), Some(1));
+        tree.fill(UVec3::new(64, 32, 32), UVec3::new(95, 63, 63), Some(2));
+        tree.dilate(1);
+        assert_eq!(
+            tree.count_active(),
+            64 * 32 * 32 + 2 * 32 * 32 + 4 * 64 * 32
+        );
+        assert_eq!(tree.get_value(UVec3::new(31, 40, 40)), Some(1));
+        assert_eq!(tree.get_value(UVec3::new(96, 40, 40)), Some(2));
+        assert_eq!(tree.get_value(UVec3::new(70, 31, 40)), Some(2));
+        // Tiles not touching the grown faces are left as they were.
+        assert!(tree.iter_leaf().count() < 2 * 6 * 8 * 8 + 8 * 8);
+
+        tree.erode(2);
+        assert_eq!(tree.count_active(), 62 * 30 * 30);
+        assert_eq!(tree.get_value(UVec3::new(33, 33, 33)), Some(1));
+        assert_eq!(tree.get_value(UVec3::new(94, 62, 62)), Some(2));
+        assert_eq!(tree.get_value(UVec3::new(32, 40, 40)), None);
+
+        // Voxels outside of the tree are unoccupied.
+        let mut tree = Tree::<MyRoot>::new();
+        tree.fill(UVec3::ZERO, UVec3::splat(127), Some(1));
+        tree.erode(1);
+        assert_eq!(tree.count_active(), 126 * 126 * 126);
+    }
+}
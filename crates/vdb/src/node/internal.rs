@@ -1,33 +1,45 @@
-use super::{size_of_grid, NodeMeta};
-use crate::{bitmask::SetBitIterator, BitMask, ConstUVec3, Node, NodeConst, Pool};
+use super::{clip_aabb, invalidate_cached_path, size_of_grid, union_aabb, NodeMeta, TileIterator};
+use crate::{
+    bitmask::SetBitIterator,
+    openvdb::{read_active_values, write_active_values},
+    serialize::invalid_data,
+    BitMask, ConstUVec3, CsgOperation, Node, NodeConst, NodeOpenVdb, NodeSerialize, Pool,
+    SerializableValue, VdbValue,
+};
 use glam::UVec3;
 use std::{
     cell::UnsafeCell,
+    io::{Read, Write},
     marker::PhantomData,
     mem::{size_of, MaybeUninit},
 };
 
 #[derive(Clone, Copy)]
-pub union InternalNodeEntry {
+pub union InternalNodeEntry<V: Copy> {
     /// The corresponding bit on child_mask is set. Points to another node.
     pub occupied: u32,
     /// The corresponding bit on child_mask is not set.
-    /// Points to a value in the material array that describes all child nodes within the current node.
-    /// If this is u32::MAX, this is air.
-    pub free: u32,
+    /// If the corresponding bit on value_mask is set, this is the value of all voxels within the tile.
+    /// Otherwise, this tile is air and the value is meaningless.
+    pub free: V,
 }
 
 /// Internal nodes are always 4x4x4 so that the child mask contains exactly 64 voxels.
 /// Size: 3 - 66 u32
 #[repr(C)]
+#[derive(Clone)]
 pub struct InternalNode<CHILD: Node, const FANOUT_LOG2: ConstUVec3>
 where
     [(); size_of_grid(FANOUT_LOG2) / size_of::<usize>() / 8]: Sized,
 {
-    /// This is 0 if that tile is completely air, and 1 otherwise.
+    /// This is 1 if that tile is represented by a child node, and 0 otherwise.
     pub child_mask: BitMask<{ size_of_grid(FANOUT_LOG2) }>,
-    /// points to self.child_mask.count_ones() LeafNodes or InternalNodes
-    pub child_ptrs: [InternalNodeEntry; size_of_grid(FANOUT_LOG2)],
+    /// This is 1 if that tile is completely filled with a single value, and 0 otherwise.
+    /// Never set together with the corresponding bit on child_mask.
+    pub value_mask: BitMask<{ size_of_grid(FANOUT_LOG2) }>,
+    /// points to self.child_mask.count_ones() LeafNodes or InternalNodes,
+    /// and holds self.value_mask.count_ones() tile values.
+    pub child_ptrs: [InternalNodeEntry<CHILD::Voxel>; size_of_grid(FANOUT_LOG2)],
     _marker: PhantomData<CHILD>,
 }
 impl<CHILD: Node, const FANOUT_LOG2: ConstUVec3> Default for InternalNode<CHILD, FANOUT_LOG2>
@@ -37,7 +49,8 @@ where
     fn default() -> Self {
         Self {
             child_mask: Default::default(),
-            child_ptrs: [InternalNodeEntry { free: 0 }; size_of_grid(FANOUT_LOG2)],
+            value_mask: Default::default(),
+            child_ptrs: [InternalNodeEntry { occupied: 0 }; size_of_grid(FANOUT_LOG2)],
             _marker: Default::default(),
         }
     }
@@ -67,7 +80,8 @@ where
     fn new() -> Self {
         Self {
             child_mask: BitMask::new(),
-            child_ptrs: [InternalNodeEntry { free: 0 }; size_of_grid(FANOUT_LOG2)],
+            value_mask: BitMask::new(),
+            child_ptrs: [InternalNodeEntry { occupied: 0 }; size_of_grid(FANOUT_LOG2)],
             _marker: PhantomData,
         }
     }
@@ -81,7 +95,8 @@ where
             | (internal_offset.z as usize);
         let has_child = self.child_mask.get(index);
         if !has_child {
-            return None;
+            invalidate_cached_path(cached_path, CHILD::LEVEL);
+            return self.tile(index);
         }
         unsafe {
             let child_ptr = self.child_ptrs[index].occupied;
@@ -105,27 +120,27 @@ where
         let index = ((internal_offset.x as usize) << (FANOUT_LOG2.y + FANOUT_LOG2.z))
             | ((internal_offset.y as usize) << FANOUT_LOG2.z)
             | (internal_offset.z as usize);
-        if value.is_some() {
-            // set
-            let has_child = self.child_mask.get(index);
-            if !has_child {
-                // ensure have children
-                self.child_mask.set(index, true);
-                unsafe {
-                    // allocate a child node
-                    let allocated_ptr = pools[CHILD::LEVEL].alloc::<CHILD>();
-                    self.child_ptrs[index].occupied = allocated_ptr;
-                }
+        if !self.child_mask.get(index) {
+            if self.tile(index) == value {
+                // Nothing changes.
+                invalidate_cached_path(cached_path, CHILD::LEVEL);
+                return;
             }
-            // TODO: propagate when filled.
-        } else {
-            // clear
-            todo!() // TODO: clear recursively, propagate if completely cleared
+            self.ensure_child(pools, index);
         }
-        unsafe {
-            let new_coords = coords & CHILD::EXTENT_MASK;
-            let child_ptr = self.child_ptrs[index].occupied;
-            <CHILD as Node>::set_in_pools(pools, new_coords, child_ptr, value, cached_path)
+        let clear = value.is_none();
+        let new_coords = coords & CHILD::EXTENT_MASK;
+        let child_ptr = unsafe { self.child_ptrs[index].occupied };
+        <CHILD as Node>::set_in_pools(pools, new_coords, child_ptr, value, cached_path);
+        if clear {
+            // Release the child node if it was completely cleared.
+            let child_empty =
+                unsafe { pools[CHILD::LEVEL].get_item::<CHILD>(child_ptr).is_empty() };
+            if child_empty {
+                pools[CHILD::LEVEL].free(child_ptr);
+                self.child_mask.set(index, false);
+                invalidate_cached_path(cached_path, CHILD::LEVEL);
+            }
         }
     }
     #[inline]
@@ -162,34 +177,52 @@ where
 
     type Iterator<'a> = InternalNodeIterator<'a, CHILD, FANOUT_LOG2>;
     #[inline]
-    fn iter<'a>(&'a self, pools: &'a [Pool], offset: UVec3) -> Self::Iterator<'a> {
+    fn iter_in_aabb<'a>(
+        &'a self,
+        pools: &'a [Pool],
+        offset: UVec3,
+        min: UVec3,
+        max: UVec3,
+    ) -> Self::Iterator<'a> {
         InternalNodeIterator {
             pools,
             location_offset: offset,
+            min,
+            max,
             child_mask_iterator: self.child_mask.iter_set_bits(),
+            value_mask_iterator: self.value_mask.iter_set_bits(),
             child_ptrs: &self.child_ptrs,
             child_iterator: None,
+            tile_iterator: None,
         }
     }
     #[inline]
-    fn iter_in_pool<'a>(pools: &'a [Pool], ptr: u32, offset: UVec3) -> Self::Iterator<'a> {
+    fn iter_in_aabb_in_pool<'a>(
+        pools: &'a [Pool],
+        ptr: u32,
+        offset: UVec3,
+        min: UVec3,
+        max: UVec3,
+    ) -> Self::Iterator<'a> {
         let node = unsafe { pools[Self::LEVEL].get_item::<Self>(ptr) };
-        InternalNodeIterator {
-            pools,
-            location_offset: offset,
-            child_mask_iterator: node.child_mask.iter_set_bits(),
-            child_ptrs: &node.child_ptrs,
-            child_iterator: None,
-        }
+        node.iter_in_aabb(pools, offset, min, max)
     }
 
     type LeafIterator<'a> = InternalNodeLeafIterator<'a, CHILD, FANOUT_LOG2>;
 
     #[inline]
-    fn iter_leaf<'a>(&'a self, pools: &'a [Pool], offset: UVec3) -> Self::LeafIterator<'a> {
+    fn iter_leaf_in_aabb<'a>(
+        &'a self,
+        pools: &'a [Pool],
+        offset: UVec3,
+        min: UVec3,
+        max: UVec3,
+    ) -> Self::LeafIterator<'a> {
         InternalNodeLeafIterator {
             pools,
             location_offset: offset,
+            min,
+            max,
             child_mask_iterator: self.child_mask.iter_set_bits(),
             child_ptrs: &self.child_ptrs,
             child_iterator: None,
@@ -197,15 +230,419 @@ where
     }
 
     #[inline]
-    fn iter_leaf_in_pool<'a>(pools: &'a [Pool], ptr: u32, offset: UVec3) -> Self::LeafIterator<'a> {
+    fn iter_leaf_in_aabb_in_pool<'a>(
+        pools: &'a [Pool],
+        ptr: u32,
+        offset: UVec3,
+        min: UVec3,
+        max: UVec3,
+    ) -> Self::LeafIterator<'a> {
         let node = unsafe { pools[Self::LEVEL].get_item::<Self>(ptr) };
-        InternalNodeLeafIterator {
-            pools,
-            location_offset: offset,
-            child_mask_iterator: node.child_mask.iter_set_bits(),
-            child_ptrs: &node.child_ptrs,
-            child_iterator: None,
+        node.iter_leaf_in_aabb(pools, offset, min, max)
+    }
+
+    #[inline]
+    fn is_empty(&self) -> bool {
+        self.child_mask.is_zeroed() && self.value_mask.is_zeroed()
+    }
+
+    fn tile_value(&self) -> Option<Self::Voxel> {
+        if !self.child_mask.is_zeroed() || !self.value_mask.is_full() {
+            return None;
+        }
+        let value = unsafe { self.child_ptrs[0].free };
+        if self
+            .child_ptrs
+            .iter()
+            .all(|entry| unsafe { entry.free } == value)
+        {
+            Some(value)
+        } else {
+            None
+        }
+    }
+
+    fn prune(&mut self, pools: &mut [Pool]) {
+        let child_mask = self.child_mask.clone();
+        for index in child_mask.iter_set_bits() {
+            let child_ptr = unsafe { self.child_ptrs[index].occupied };
+            CHILD::prune_in_pools(pools, child_ptr);
+            let child = unsafe { pools[CHILD::LEVEL].get_item::<CHILD>(child_ptr) };
+            let child_empty = child.is_empty();
+            let tile_value = child.tile_value();
+            if child_empty || tile_value.is_some() {
+                // After pruning, uniform child nodes no longer have children of their own.
+                pools[CHILD::LEVEL].free(child_ptr);
+                self.child_mask.set(index, false);
+            }
+            if let Some(value) = tile_value {
+                self.value_mask.set(index, true);
+                self.child_ptrs[index].free = value;
+            }
+        }
+    }
+
+    fn prune_in_pools(pools: &mut [Pool], ptr: u32) {
+        // Safety: r was taken from pools[Self::LEVEL] and we know that self.prune only access pools[CHILD::LEVEL] and below.
+        unsafe {
+            let r = pools[Self::LEVEL].get_item_mut::<Self>(ptr) as *mut Self;
+            (*r).prune(pools)
+        }
+    }
+
+    fn free_in_pools(pools: &mut [Pool], ptr: u32) {
+        // Safety: node was taken from pools[Self::LEVEL] and we only free nodes in pools[CHILD::LEVEL] and below
+        // before releasing the node itself.
+        unsafe {
+            let node = pools[Self::LEVEL].get_item::<Self>(ptr) as *const Self;
+            for index in (*node).child_mask.iter_set_bits() {
+                CHILD::free_in_pools(pools, (*node).child_ptrs[index].occupied);
+            }
+        }
+        pools[Self::LEVEL].free(ptr);
+    }
+
+    fn bounding_box(&self, pools: &[Pool]) -> Option<(UVec3, UVec3)> {
+        let mut bounds = None;
+        for index in self.child_mask.iter_set_bits() {
+            let child_ptr = unsafe { self.child_ptrs[index].occupied };
+            if let Some((min, max)) = CHILD::bounding_box_in_pools(pools, child_ptr) {
+                let origin = Self::child_origin(index);
+                bounds = Some(union_aabb(bounds, (origin + min, origin + max)));
+            }
+        }
+        for index in self.value_mask.iter_set_bits() {
+            let origin = Self::child_origin(index);
+            bounds = Some(union_aabb(bounds, (origin, origin + CHILD::EXTENT_MASK)));
+        }
+        bounds
+    }
+
+    #[inline]
+    fn bounding_box_in_pools(pools: &[Pool], ptr: u32) -> Option<(UVec3, UVec3)> {
+        let node = unsafe { pools[Self::LEVEL].get_item::<Self>(ptr) };
+        node.bounding_box(pools)
+    }
+
+    fn count_active(&self, pools: &[Pool]) -> u64 {
+        let tile_size = CHILD::EXTENT.x as u64 * CHILD::EXTENT.y as u64 * CHILD::EXTENT.z as u64;
+        let mut count = self.value_mask.count_ones() as u64 * tile_size;
+        for index in self.child_mask.iter_set_bits() {
+            let child_ptr = unsafe { self.child_ptrs[index].occupied };
+            count += CHILD::count_active_in_pools(pools, child_ptr);
+        }
+        count
+    }
+
+    #[inline]
+    fn count_active_in_pools(pools: &[Pool], ptr: u32) -> u64 {
+        let node = unsafe { pools[Self::LEVEL].get_item::<Self>(ptr) };
+        node.count_active(pools)
+    }
+
+    fn fill(&mut self, pools: &mut [Pool], min: UVec3, max: UVec3, value: Option<Self::Voxel>) {
+        let internal_min = min >> CHILD::EXTENT_LOG2;
+        let internal_max = max >> CHILD::EXTENT_LOG2;
+        for x in internal_min.x..=internal_max.x {
+            for y in internal_min.y..=internal_max.y {
+                for z in internal_min.z..=internal_max.z {
+                    let internal_offset = UVec3 { x, y, z };
+                    let index = ((internal_offset.x as usize) << (FANOUT_LOG2.y + FANOUT_LOG2.z))
+                        | ((internal_offset.y as usize) << FANOUT_LOG2.z)
+                        | (internal_offset.z as usize);
+                    let child_origin = internal_offset << CHILD::EXTENT_LOG2;
+                    let child_min = min.max(child_origin) - child_origin;
+                    let child_max = max.min(child_origin + CHILD::EXTENT_MASK) - child_origin;
+                    if child_min == UVec3::ZERO && child_max == CHILD::EXTENT_MASK {
+                        // The child is entirely covered. Replace it with a tile.
+                        self.set_tile(pools, index, value);
+                        continue;
+                    }
+                    if !self.child_mask.get(index) {
+                        if self.tile(index) == value {
+                            continue;
+                        }
+                        self.ensure_child(pools, index);
+                    }
+                    let child_ptr = unsafe { self.child_ptrs[index].occupied };
+                    CHILD::fill_in_pools(pools, child_ptr, child_min, child_max, value);
+                    if value.is_none() {
+                        self.release_child_if_empty(pools, index);
+                    }
+                }
+            }
+        }
+    }
+
+    fn fill_in_pools(
+        pools: &mut [Pool],
+        ptr: u32,
+        min: UVec3,
+        max: UVec3,
+        value: Option<Self::Voxel>,
+    ) {
+        // Safety: r was taken from pools[Self::LEVEL] and we know that self.fill only access pools[CHILD::LEVEL] and below.
+        unsafe {
+            let r = pools[Self::LEVEL].get_item_mut::<Self>(ptr) as *mut Self;
+            (*r).fill(pools, min, max, value)
+        }
+    }
+
+    fn csg(
+        &mut self,
+        pools: &mut [Pool],
+        other: &Self,
+        other_pools: &[Pool],
+        operation: CsgOperation,
+    ) {
+        match operation {
+            CsgOperation::Union => {
+                for index in other.value_mask.iter_set_bits() {
+                    self.set_tile(pools, index, other.tile(index));
+                }
+                for index in other.child_mask.iter_set_bits() {
+                    let child_ptr = self.ensure_child(pools, index);
+                    let other_ptr = unsafe { other.child_ptrs[index].occupied };
+                    CHILD::csg_in_pools(pools, child_ptr, other_pools, other_ptr, operation);
+                }
+            }
+            CsgOperation::Intersection => {
+                for index in 0..Self::SIZE {
+                    if !self.child_mask.get(index) && !self.value_mask.get(index) {
+                        // Nothing to intersect with.
+                        continue;
+                    }
+                    if other.child_mask.get(index) {
+                        let child_ptr = self.ensure_child(pools, index);
+                        let other_ptr = unsafe { other.child_ptrs[index].occupied };
+                        CHILD::csg_in_pools(pools, child_ptr, other_pools, other_ptr, operation);
+                        self.release_child_if_empty(pools, index);
+                    } else if !other.value_mask.get(index) {
+                        self.set_tile(pools, index, None);
+                    }
+                }
+            }
+            CsgOperation::Difference => {
+                for index in other.value_mask.iter_set_bits() {
+                    self.set_tile(pools, index, None);
+                }
+                for index in other.child_mask.iter_set_bits() {
+                    if !self.child_mask.get(index) && !self.value_mask.get(index) {
+                        // Nothing to remove from.
+                        continue;
+                    }
+                    let child_ptr = self.ensure_child(pools, index);
+                    let other_ptr = unsafe { other.child_ptrs[index].occupied };
+                    CHILD::csg_in_pools(pools, child_ptr, other_pools, other_ptr, operation);
+                    self.release_child_if_empty(pools, index);
+                }
+            }
+        }
+    }
+
+    #[inline]
+    fn csg_in_pools(
+        pools: &mut [Pool],
+        ptr: u32,
+        other_pools: &[Pool],
+        other_ptr: u32,
+        operation: CsgOperation,
+    ) {
+        let other = unsafe { other_pools[Self::LEVEL].get_item::<Self>(other_ptr) };
+        // Safety: r was taken from pools[Self::LEVEL] and we know that self.csg only access pools[CHILD::LEVEL] and below.
+        unsafe {
+            let r = pools[Self::LEVEL].get_item_mut::<Self>(ptr) as *mut Self;
+            (*r).csg(pools, other, other_pools, operation)
+        }
+    }
+    fn remap_children(&mut self, pools: &mut [Pool], remaps: &[Vec<u32>]) {
+        for index in self.child_mask.iter_set_bits() {
+            let child_ptr =
+                remaps[CHILD::LEVEL][unsafe { self.child_ptrs[index].occupied } as usize];
+            self.child_ptrs[index].occupied = child_ptr;
+            CHILD::remap_children_in_pools(pools, child_ptr, remaps);
+        }
+    }
+
+    fn remap_children_in_pools(pools: &mut [Pool], ptr: u32, remaps: &[Vec<u32>]) {
+        // Safety: r was taken from pools[Self::LEVEL] and we know that self.remap_children only access pools[CHILD::LEVEL] and below.
+        unsafe {
+            let r = pools[Self::LEVEL].get_item_mut::<Self>(ptr) as *mut Self;
+            (*r).remap_children(pools, remaps)
+        }
+    }
+
+    fn for_each_tile_in_aabb(
+        &self,
+        pools: &[Pool],
+        offset: UVec3,
+        min: UVec3,
+        max: UVec3,
+        f: &mut dyn FnMut(UVec3, UVec3, Self::Voxel),
+    ) {
+        for index in self.value_mask.iter_set_bits() {
+            let origin = Self::child_origin(index);
+            if clip_aabb(min, max, origin, CHILD::EXTENT_MASK).is_some() {
+                let tile_min = offset + origin;
+                f(tile_min, tile_min + CHILD::EXTENT_MASK, unsafe {
+                    self.child_ptrs[index].free
+                });
+            }
+        }
+        for index in self.child_mask.iter_set_bits() {
+            let origin = Self::child_origin(index);
+            let Some((child_min, child_max)) = clip_aabb(min, max, origin, CHILD::EXTENT_MASK) else {
+                continue;
+            };
+            CHILD::for_each_tile_in_aabb_in_pool(
+                pools,
+                unsafe { self.child_ptrs[index].occupied },
+                offset + origin,
+                child_min - origin,
+                child_max - origin,
+                f,
+            );
+        }
+    }
+
+    #[inline]
+    fn for_each_tile_in_aabb_in_pool(
+        pools: &[Pool],
+        ptr: u32,
+        offset: UVec3,
+        min: UVec3,
+        max: UVec3,
+        f: &mut dyn FnMut(UVec3, UVec3, Self::Voxel),
+    ) {
+        let node = unsafe { pools[Self::LEVEL].get_item::<Self>(ptr) };
+        node.for_each_tile_in_aabb(pools, offset, min, max, f)
+    }
+
+    fn for_each_node_in_aabb(
+        &self,
+        pools: &[Pool],
+        offset: UVec3,
+        min: UVec3,
+        max: UVec3,
+        f: &mut dyn FnMut(usize, u32, UVec3),
+    ) {
+        for index in self.child_mask.iter_set_bits() {
+            let origin = Self::child_origin(index);
+            let Some((child_min, child_max)) = clip_aabb(min, max, origin, CHILD::EXTENT_MASK) else {
+                continue;
+            };
+            let child_ptr = unsafe { self.child_ptrs[index].occupied };
+            f(CHILD::LEVEL, child_ptr, offset + origin);
+            CHILD::for_each_node_in_aabb_in_pool(
+                pools,
+                child_ptr,
+                offset + origin,
+                child_min - origin,
+                child_max - origin,
+                f,
+            );
+        }
+    }
+
+    #[inline]
+    fn for_each_node_in_aabb_in_pool(
+        pools: &[Pool],
+        ptr: u32,
+        offset: UVec3,
+        min: UVec3,
+        max: UVec3,
+        f: &mut dyn FnMut(usize, u32, UVec3),
+    ) {
+        let node = unsafe { pools[Self::LEVEL].get_item::<Self>(ptr) };
+        node.for_each_node_in_aabb(pools, offset, min, max, f)
+    }
+
+    fn ensure_leaf(&mut self, pools: &mut [Pool], coords: UVec3) -> *mut Self::LeafType {
+        let internal_offset = coords >> CHILD::EXTENT_LOG2;
+        let index = ((internal_offset.x as usize) << (FANOUT_LOG2.y + FANOUT_LOG2.z))
+            | ((internal_offset.y as usize) << FANOUT_LOG2.z)
+            | (internal_offset.z as usize);
+        let child_ptr = self.ensure_child(pools, index);
+        CHILD::ensure_leaf_in_pools(pools, child_ptr, coords & CHILD::EXTENT_MASK)
+    }
+
+    fn ensure_leaf_in_pools(pools: &mut [Pool], ptr: u32, coords: UVec3) -> *mut Self::LeafType {
+        // Safety: r was taken from pools[Self::LEVEL] and we know that self.ensure_leaf only access pools[CHILD::LEVEL] and below.
+        unsafe {
+            let r = pools[Self::LEVEL].get_item_mut::<Self>(ptr) as *mut Self;
+            (*r).ensure_leaf(pools, coords)
+        }
+    }
+}
+
+impl<CHILD: Node, const FANOUT_LOG2: ConstUVec3> InternalNode<CHILD, FANOUT_LOG2>
+where
+    [(); size_of_grid(FANOUT_LOG2) / size_of::<usize>() / 8]: Sized,
+{
+    /// Returns the tile value at `index`, or None if the tile is air or represented by a child node.
+    #[inline]
+    fn tile(&self, index: usize) -> Option<CHILD::Voxel> {
+        if self.value_mask.get(index) {
+            Some(unsafe { self.child_ptrs[index].free })
+        } else {
+            None
+        }
+    }
+
+    /// Replace the content at `index` with a tile of `value`, releasing the child node if present.
+    /// The tile becomes air if `value` is None.
+    fn set_tile(&mut self, pools: &mut [Pool], index: usize, value: Option<CHILD::Voxel>) {
+        if self.child_mask.get(index) {
+            CHILD::free_in_pools(pools, unsafe { self.child_ptrs[index].occupied });
+            self.child_mask.set(index, false);
+        }
+        self.value_mask.set(index, value.is_some());
+        if let Some(value) = value {
+            self.child_ptrs[index].free = value;
+        }
+    }
+
+    /// Release the child node at `index` back to its pool if it no longer contains any voxels.
+    fn release_child_if_empty(&mut self, pools: &mut [Pool], index: usize) {
+        let child_ptr = unsafe { self.child_ptrs[index].occupied };
+        let child_empty = unsafe { pools[CHILD::LEVEL].get_item::<CHILD>(child_ptr).is_empty() };
+        if child_empty {
+            pools[CHILD::LEVEL].free(child_ptr);
+            self.child_mask.set(index, false);
+        }
+    }
+
+    /// Returns the origin of the child at `index` in the node space.
+    #[inline]
+    fn child_origin(index: usize) -> UVec3 {
+        let offset = UVec3 {
+            x: index as u32 >> (FANOUT_LOG2.z + FANOUT_LOG2.y),
+            y: (index as u32 >> FANOUT_LOG2.z) & ((1 << FANOUT_LOG2.y) - 1),
+            z: index as u32 & ((1 << FANOUT_LOG2.z) - 1),
+        };
+        offset * CHILD::EXTENT
+    }
+
+    /// Ensure that the tile at `index` is represented by a child node with the same content,
+    /// allocating a child node for air tiles and densifying uniform tiles as needed.
+    fn ensure_child(&mut self, pools: &mut [Pool], index: usize) -> u32 {
+        if self.child_mask.get(index) {
+            return unsafe { self.child_ptrs[index].occupied };
         }
+        let child_ptr = unsafe { pools[CHILD::LEVEL].alloc::<CHILD>() };
+        if let Some(value) = self.tile(index) {
+            CHILD::fill_in_pools(
+                pools,
+                child_ptr,
+                UVec3::ZERO,
+                CHILD::EXTENT_MASK,
+                Some(value),
+            );
+            self.value_mask.set(index, false);
+        }
+        self.child_mask.set(index, true);
+        self.child_ptrs[index].occupied = child_ptr;
+        child_ptr
     }
 }
 
@@ -227,6 +664,209 @@ where
     }
 }
 
+/// Internal nodes are written as the child mask and the value mask, followed by the tile values
+/// and then the child nodes in the order of their index.
+impl<CHILD: NodeSerialize, const FANOUT_LOG2: ConstUVec3> NodeSerialize
+    for InternalNode<CHILD, FANOUT_LOG2>
+where
+    CHILD::Voxel: SerializableValue,
+    [(); size_of_grid(FANOUT_LOG2) / size_of::<usize>() / 8]: Sized,
+{
+    fn write<W: Write>(&self, pools: &[Pool], writer: &mut W) -> std::io::Result<()> {
+        self.child_mask.write_to(writer)?;
+        self.value_mask.write_to(writer)?;
+        for index in self.value_mask.iter_set_bits() {
+            unsafe { self.child_ptrs[index].free }.write_to(writer)?;
+        }
+        for index in self.child_mask.iter_set_bits() {
+            CHILD::write_in_pools(pools, unsafe { self.child_ptrs[index].occupied }, writer)?;
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn write_in_pools<W: Write>(pools: &[Pool], ptr: u32, writer: &mut W) -> std::io::Result<()> {
+        let node = unsafe { pools[Self::LEVEL].get_item::<Self>(ptr) };
+        node.write(pools, writer)
+    }
+
+    fn read<R: Read>(&mut self, pools: &mut [Pool], reader: &mut R) -> std::io::Result<()> {
+        self.child_mask.read_from(reader)?;
+        self.value_mask.read_from(reader)?;
+        for index in self.value_mask.iter_set_bits() {
+            if self.child_mask.get(index) {
+                return Err(invalid_data("tile overlaps with child node"));
+            }
+            self.child_ptrs[index].free = CHILD::Voxel::read_from(reader)?;
+        }
+        for index in self.child_mask.iter_set_bits() {
+            let child_ptr = unsafe { pools[CHILD::LEVEL].alloc::<CHILD>() };
+            self.child_ptrs[index].occupied = child_ptr;
+            CHILD::read_in_pools(pools, child_ptr, reader)?;
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn read_in_pools<R: Read>(pools: &mut [Pool], ptr: u32, reader: &mut R) -> std::io::Result<()> {
+        // Safety: node was taken from pools[Self::LEVEL] and children are only allocated from
+        // pools[CHILD::LEVEL] and below.
+        unsafe {
+            let node = pools[Self::LEVEL].get_item_mut::<Self>(ptr) as *mut Self;
+            (*node).read(pools, reader)
+        }
+    }
+}
+
+/// Internal nodes are written as their child mask, their value mask and the values of their tiles
+/// in the topology, followed by the topology of their children.
+impl<CHILD: NodeOpenVdb, const FANOUT_LOG2: ConstUVec3> NodeOpenVdb
+    for InternalNode<CHILD, FANOUT_LOG2>
+where
+    CHILD::Voxel: VdbValue,
+    [(); size_of_grid(FANOUT_LOG2) / size_of::<usize>() / 8]: Sized,
+{
+    fn vdb_layout() -> Option<Vec<u32>> {
+        let cubic = FANOUT_LOG2.x == FANOUT_LOG2.y && FANOUT_LOG2.y == FANOUT_LOG2.z;
+        let mut layout = CHILD::vdb_layout().filter(|_| cubic && FANOUT_LOG2.x >= 2)?;
+        layout.insert(0, FANOUT_LOG2.x);
+        Some(layout)
+    }
+
+    fn write_vdb_topology<W: Write>(
+        &self,
+        pools: &[Pool],
+        writer: &mut W,
+        compression: u32,
+    ) -> std::io::Result<()> {
+        self.child_mask.write_to(writer)?;
+        self.value_mask.write_to(writer)?;
+        write_active_values(
+            writer,
+            self.value_mask
+                .iter_set_bits()
+                .map(|index| unsafe { self.child_ptrs[index].free }),
+            compression,
+        )?;
+        for index in self.child_mask.iter_set_bits() {
+            let child_ptr = unsafe { self.child_ptrs[index].occupied };
+            CHILD::write_vdb_topology_in_pools(pools, child_ptr, writer, compression)?;
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn write_vdb_topology_in_pools<W: Write>(
+        pools: &[Pool],
+        ptr: u32,
+        writer: &mut W,
+        compression: u32,
+    ) -> std::io::Result<()> {
+        let node = unsafe { pools[Self::LEVEL].get_item::<Self>(ptr) };
+        node.write_vdb_topology(pools, writer, compression)
+    }
+
+    fn write_vdb_buffers<W: Write>(
+        &self,
+        pools: &[Pool],
+        offset: UVec3,
+        writer: &mut W,
+        compression: u32,
+    ) -> std::io::Result<()> {
+        for index in self.child_mask.iter_set_bits() {
+            let child_ptr = unsafe { self.child_ptrs[index].occupied };
+            let child_offset = offset + Self::child_origin(index);
+            CHILD::write_vdb_buffers_in_pools(pools, child_ptr, child_offset, writer, compression)?;
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn write_vdb_buffers_in_pools<W: Write>(
+        pools: &[Pool],
+        ptr: u32,
+        offset: UVec3,
+        writer: &mut W,
+        compression: u32,
+    ) -> std::io::Result<()> {
+        let node = unsafe { pools[Self::LEVEL].get_item::<Self>(ptr) };
+        node.write_vdb_buffers(pools, offset, writer, compression)
+    }
+
+    fn read_vdb_topology<R: Read>(
+        &mut self,
+        pools: &mut [Pool],
+        reader: &mut R,
+        compression: u32,
+    ) -> std::io::Result<()> {
+        self.child_mask.read_from(reader)?;
+        self.value_mask.read_from(reader)?;
+        let values: Vec<CHILD::Voxel> = read_active_values(
+            reader,
+            &self.value_mask.data,
+            size_of_grid(FANOUT_LOG2),
+            compression,
+        )?;
+        for (index, value) in self.value_mask.iter_set_bits().zip(values) {
+            if self.child_mask.get(index) {
+                return Err(invalid_data("tile overlaps with child node"));
+            }
+            self.child_ptrs[index].free = value;
+        }
+        for index in self.child_mask.iter_set_bits() {
+            let child_ptr = unsafe { pools[CHILD::LEVEL].alloc::<CHILD>() };
+            self.child_ptrs[index].occupied = child_ptr;
+            CHILD::read_vdb_topology_in_pools(pools, child_ptr, reader, compression)?;
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn read_vdb_topology_in_pools<R: Read>(
+        pools: &mut [Pool],
+        ptr: u32,
+        reader: &mut R,
+        compression: u32,
+    ) -> std::io::Result<()> {
+        // Safety: node was taken from pools[Self::LEVEL] and children are only allocated from
+        // pools[CHILD::LEVEL] and below.
+        unsafe {
+            let node = pools[Self::LEVEL].get_item_mut::<Self>(ptr) as *mut Self;
+            (*node).read_vdb_topology(pools, reader, compression)
+        }
+    }
+
+    fn read_vdb_buffers<R: Read>(
+        &mut self,
+        pools: &mut [Pool],
+        offset: UVec3,
+        reader: &mut R,
+        compression: u32,
+    ) -> std::io::Result<()> {
+        for index in self.child_mask.iter_set_bits() {
+            let child_ptr = unsafe { self.child_ptrs[index].occupied };
+            let child_offset = offset + Self::child_origin(index);
+            CHILD::read_vdb_buffers_in_pools(pools, child_ptr, child_offset, reader, compression)?;
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn read_vdb_buffers_in_pools<R: Read>(
+        pools: &mut [Pool],
+        ptr: u32,
+        offset: UVec3,
+        reader: &mut R,
+        compression: u32,
+    ) -> std::io::Result<()> {
+        // Safety: see read_vdb_topology_in_pools.
+        unsafe {
+            let node = pools[Self::LEVEL].get_item_mut::<Self>(ptr) as *mut Self;
+            (*node).read_vdb_buffers(pools, offset, reader, compression)
+        }
+    }
+}
+
 /// When the alternate flag was specified, also print the child pointers.
 impl<CHILD: Node, const FANOUT_LOG2: ConstUVec3> std::fmt::Debug
     for InternalNode<CHILD, FANOUT_LOG2>
@@ -236,6 +876,10 @@ where
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.write_str("Internal Node\n")?;
         self.child_mask.fmt(f)?;
+        if !self.value_mask.is_zeroed() {
+            f.write_str("Tiles\n")?;
+            self.value_mask.fmt(f)?;
+        }
         Ok(())
     }
 }
@@ -246,9 +890,14 @@ where
 {
     pools: &'a [Pool],
     location_offset: UVec3,
+    /// Voxels outside of `min` and `max` (inclusive) in the node space are skipped.
+    min: UVec3,
+    max: UVec3,
     child_mask_iterator: SetBitIterator<'a, { size_of_grid(FANOUT_LOG2) }>,
+    value_mask_iterator: SetBitIterator<'a, { size_of_grid(FANOUT_LOG2) }>,
     child_iterator: Option<CHILD::Iterator<'a>>,
-    child_ptrs: &'a [InternalNodeEntry; size_of_grid(FANOUT_LOG2)],
+    tile_iterator: Option<TileIterator>,
+    child_ptrs: &'a [InternalNodeEntry<CHILD::Voxel>; size_of_grid(FANOUT_LOG2)],
 }
 impl<'a, CHILD: Node, const FANOUT_LOG2: ConstUVec3> Iterator
     for InternalNodeIterator<'a, CHILD, FANOUT_LOG2>
@@ -265,23 +914,38 @@ where
             }
             // self.child_iterator is None or ran out. Grab the next child.
             if let Some(next_child_index) = self.child_mask_iterator.next() {
-                let child_ptr = unsafe { self.child_ptrs[next_child_index].occupied };
-                let offset = UVec3 {
-                    x: next_child_index as u32 >> (FANOUT_LOG2.z + FANOUT_LOG2.y),
-                    y: (next_child_index as u32 >> FANOUT_LOG2.z) & ((1 << FANOUT_LOG2.y) - 1),
-                    z: next_child_index as u32 & ((1 << FANOUT_LOG2.z) - 1),
+                let offset = InternalNode::<CHILD, FANOUT_LOG2>::child_origin(next_child_index);
+                let Some((min, max)) = clip_aabb(self.min, self.max, offset, CHILD::EXTENT_MASK) else {
+                    continue;
                 };
-                let offset = offset * CHILD::EXTENT;
-                self.child_iterator = Some(CHILD::iter_in_pool(
+                let child_ptr = unsafe { self.child_ptrs[next_child_index].occupied };
+                self.child_iterator = Some(CHILD::iter_in_aabb_in_pool(
                     self.pools,
                     child_ptr,
                     self.location_offset + offset,
+                    min - offset,
+                    max - offset,
+                ));
+                continue;
+            }
+            self.child_iterator = None;
+            // Ran out of children. Visit the tiles.
+            if let Some(item) = self.tile_iterator.as_mut().and_then(|a| a.next()) {
+                return Some(item);
+            }
+            if let Some(next_tile_index) = self.value_mask_iterator.next() {
+                let offset = InternalNode::<CHILD, FANOUT_LOG2>::child_origin(next_tile_index);
+                let Some((min, max)) = clip_aabb(self.min, self.max, offset, CHILD::EXTENT_MASK) else {
+                    continue;
+                };
+                self.tile_iterator = Some(TileIterator::new(
+                    self.location_offset + min,
+                    max - min + UVec3::ONE,
                 ));
                 continue;
-            } else {
-                // Also ran out. We have nothing left.
-                return None;
             }
+            // Also ran out. We have nothing left.
+            return None;
         }
     }
 }
@@ -292,9 +956,12 @@ where
 {
     pools: &'a [Pool],
     location_offset: UVec3,
+    /// Leaf nodes outside of `min` and `max` (inclusive) in the node space are skipped.
+    min: UVec3,
+    max: UVec3,
     child_mask_iterator: SetBitIterator<'a, { size_of_grid(FANOUT_LOG2) }>,
     child_iterator: Option<CHILD::LeafIterator<'a>>,
-    child_ptrs: &'a [InternalNodeEntry; size_of_grid(FANOUT_LOG2)],
+    child_ptrs: &'a [InternalNodeEntry<CHILD::Voxel>; size_of_grid(FANOUT_LOG2)],
 }
 impl<'a, CHILD: Node, const FANOUT_LOG2: ConstUVec3> Iterator
     for InternalNodeLeafIterator<'a, CHILD, FANOUT_LOG2>
@@ -311,17 +978,17 @@ where
             }
             // self.child_iterator is None or ran out. Grab the next child.
             if let Some(next_child_index) = self.child_mask_iterator.next() {
-                let child_ptr = unsafe { self.child_ptrs[next_child_index].occupied };
-                let offset = UVec3 {
-                    x: next_child_index as u32 >> (FANOUT_LOG2.z + FANOUT_LOG2.y),
-                    y: (next_child_index as u32 >> FANOUT_LOG2.z) & ((1 << FANOUT_LOG2.y) - 1),
-                    z: next_child_index as u32 & ((1 << FANOUT_LOG2.z) - 1),
+                let offset = InternalNode::<CHILD, FANOUT_LOG2>::child_origin(next_child_index);
+                let Some((min, max)) = clip_aabb(self.min, self.max, offset, CHILD::EXTENT_MASK) else {
+                    continue;
                 };
-                let offset = offset * CHILD::EXTENT;
-                self.child_iterator = Some(CHILD::iter_leaf_in_pool(
+                let child_ptr = unsafe { self.child_ptrs[next_child_index].occupied };
+                self.child_iterator = Some(CHILD::iter_leaf_in_aabb_in_pool(
                     self.pools,
                     child_ptr,
                     self.location_offset + offset,
+                    min - offset,
+                    max - offset,
                 ));
                 continue;
             } else {
@@ -1,18 +1,21 @@
-use super::{size_of_grid, NodeMeta};
-use crate::{bitmask::SetBitIterator, BitMask, ConstUVec3, Node, NodeConst, Pool};
+use super::{size_of_grid, union_aabb, NodeMeta};
+use crate::{
+    bitmask::SetBitIterator, coords::to_signed, BitMask, ConstUVec3, CsgOperation, Node, NodeConst,
+    NodeOpenVdb, NodeSerialize, Pool, SerializableValue, VdbValue,
+};
 use glam::UVec3;
 use std::{
     cell::UnsafeCell,
-    iter::Once,
+    io::{Read, Write},
     mem::{size_of, MaybeUninit},
 };
 
 /// Nodes are always 4x4x4 so that each leaf node contains exactly 64 voxels,
 /// so that the occupancy mask happens to be exactly 64 bits.
-/// Size: 3 u32
+/// Size: 4 u32 + 64 values
 #[repr(C)]
-#[derive(Default, Clone)]
-pub struct LeafNode<const LOG2: ConstUVec3>
+#[derive(Clone)]
+pub struct LeafNode<T, const LOG2: ConstUVec3>
 where
     [(); size_of_grid(LOG2) / size_of::<usize>() / 8]: Sized,
 {
@@ -20,15 +23,32 @@ where
     pub occupancy: BitMask<{ size_of_grid(LOG2) }>,
     /// This is 1 for voxels located on the surface
     pub active: BitMask<{ size_of_grid(LOG2) }>,
-    /// A pointer to self.occupancy.count_ones() material values
-    pub material_ptr: u32,
+    /// Per-voxel values. Only meaningful for voxels with the corresponding bit set on `occupancy`.
+    /// Unoccupied voxels are kept at `T::default()`.
+    pub values: [T; size_of_grid(LOG2)],
+}
+
+impl<T: Copy + Default, const LOG2: ConstUVec3> Default for LeafNode<T, LOG2>
+where
+    [(); size_of_grid(LOG2) / size_of::<usize>() / 8]: Sized,
+{
+    fn default() -> Self {
+        Self {
+            occupancy: Default::default(),
+            active: Default::default(),
+            values: [T::default(); size_of_grid(LOG2)],
+        }
+    }
 }
 
 pub trait IsLeaf: Node {
     fn get_occupancy(&self, data: &mut [u64]);
+    fn set_active(&mut self, data: &[u64]);
+    /// Overwrite all voxels of the node, indexed like the occupancy mask.
+    fn set_voxels(&mut self, values: &[Option<Self::Voxel>]);
 }
 
-impl<const LOG2: ConstUVec3> IsLeaf for LeafNode<LOG2>
+impl<T: 'static + Copy + Default + PartialEq, const LOG2: ConstUVec3> IsLeaf for LeafNode<T, LOG2>
 where
     [(); size_of_grid(LOG2) / size_of::<usize>() / 8]: Sized,
 {
@@ -44,9 +64,29 @@ where
             );
         }
     }
+    fn set_active(&mut self, data: &[u64]) {
+        debug_assert_eq!(std::mem::size_of::<u64>(), std::mem::size_of::<usize>());
+        let len = self.active.data.len();
+        debug_assert!(data.len() >= len);
+        unsafe {
+            std::ptr::copy_nonoverlapping(
+                data.as_ptr(),
+                self.active.data.as_mut_ptr() as *mut u64,
+                len,
+            );
+        }
+    }
+    fn set_voxels(&mut self, values: &[Option<T>]) {
+        debug_assert_eq!(values.len(), Self::SIZE);
+        for (index, value) in values.iter().enumerate() {
+            self.occupancy.set(index, value.is_some());
+            self.active.set(index, false);
+            self.values[index] = value.unwrap_or_default();
+        }
+    }
 }
 
-impl<const LOG2: ConstUVec3> Node for LeafNode<LOG2>
+impl<T: 'static + Copy + Default + PartialEq, const LOG2: ConstUVec3> Node for LeafNode<T, LOG2>
 where
     [(); size_of_grid(LOG2) / size_of::<usize>() / 8]: Sized,
 {
@@ -70,11 +110,11 @@ where
         Self {
             occupancy: BitMask::new(),
             active: BitMask::new(),
-            material_ptr: 0,
+            values: [T::default(); size_of_grid(LOG2)],
         }
     }
 
-    type Voxel = bool;
+    type Voxel = T;
 
     #[inline]
     fn get(&self, _: &[Pool], coords: UVec3, _cached_path: &mut [u32]) -> Option<Self::Voxel> {
@@ -85,8 +125,7 @@ where
         if !occupied {
             return None;
         }
-        let active = self.active.get(index);
-        return Some(active);
+        return Some(self.values[index]);
     }
     #[inline]
     fn set(
@@ -101,9 +140,11 @@ where
             | (coords.z as usize);
         if let Some(voxel) = value {
             self.occupancy.set(index, true);
-            self.active.set(index, voxel);
+            self.values[index] = voxel;
         } else {
             self.occupancy.set(index, false);
+            self.active.set(index, false);
+            self.values[index] = T::default();
         }
     }
     #[inline]
@@ -136,35 +177,247 @@ where
     }
 
     type Iterator<'a> = LeafNodeIterator<'a, LOG2>;
-    fn iter<'a>(&'a self, _pool: &'a [Pool], offset: UVec3) -> Self::Iterator<'a> {
+    fn iter_in_aabb<'a>(
+        &'a self,
+        _pools: &'a [Pool],
+        offset: UVec3,
+        min: UVec3,
+        max: UVec3,
+    ) -> Self::Iterator<'a> {
         LeafNodeIterator {
             location_offset: offset,
             bits_iterator: self.occupancy.iter_set_bits(),
+            min,
+            max,
         }
     }
-    fn iter_in_pool<'a>(pools: &'a [Pool], ptr: u32, offset: UVec3) -> Self::Iterator<'a> {
+    fn iter_in_aabb_in_pool<'a>(
+        pools: &'a [Pool],
+        ptr: u32,
+        offset: UVec3,
+        min: UVec3,
+        max: UVec3,
+    ) -> Self::Iterator<'a> {
         let node = unsafe { pools[0].get_item::<Self>(ptr) };
-        LeafNodeIterator {
-            location_offset: offset,
-            bits_iterator: node.occupancy.iter_set_bits(),
-        }
+        node.iter_in_aabb(&[], offset, min, max)
     }
 
-    type LeafIterator<'a> = Once<(UVec3, &'a UnsafeCell<Self>)>;
+    type LeafIterator<'a> = std::option::IntoIter<(UVec3, &'a UnsafeCell<Self>)>;
 
     #[inline]
-    fn iter_leaf<'a>(&'a self, _pools: &'a [Pool], offset: UVec3) -> Self::LeafIterator<'a> {
-        std::iter::once((offset, unsafe { std::mem::transmute(self) }))
+    fn iter_leaf_in_aabb<'a>(
+        &'a self,
+        _pools: &'a [Pool],
+        offset: UVec3,
+        min: UVec3,
+        max: UVec3,
+    ) -> Self::LeafIterator<'a> {
+        if min.cmpgt(max).any() {
+            return None.into_iter();
+        }
+        Some((offset, unsafe { std::mem::transmute(self) })).into_iter()
     }
 
     #[inline]
-    fn iter_leaf_in_pool<'a>(pools: &'a [Pool], ptr: u32, offset: UVec3) -> Self::LeafIterator<'a> {
+    fn iter_leaf_in_aabb_in_pool<'a>(
+        pools: &'a [Pool],
+        ptr: u32,
+        offset: UVec3,
+        min: UVec3,
+        max: UVec3,
+    ) -> Self::LeafIterator<'a> {
         let node = unsafe { pools[0].get_item::<Self>(ptr) };
-        std::iter::once((offset, unsafe { std::mem::transmute(node) }))
+        node.iter_leaf_in_aabb(&[], offset, min, max)
+    }
+
+    #[inline]
+    fn is_empty(&self) -> bool {
+        self.occupancy.is_zeroed()
+    }
+
+    fn tile_value(&self) -> Option<Self::Voxel> {
+        // Tiles have no room for the surface voxels marked on `active`.
+        if !self.occupancy.is_full() || !self.active.is_zeroed() {
+            return None;
+        }
+        let value = self.values[0];
+        if self.values.iter().all(|v| *v == value) {
+            Some(value)
+        } else {
+            None
+        }
+    }
+
+    #[inline]
+    fn prune(&mut self, _pools: &mut [Pool]) {}
+
+    #[inline]
+    fn prune_in_pools(_pools: &mut [Pool], _ptr: u32) {}
+
+    #[inline]
+    fn free_in_pools(pools: &mut [Pool], ptr: u32) {
+        pools[Self::LEVEL].free(ptr);
+    }
+
+    fn bounding_box(&self, _pools: &[Pool]) -> Option<(UVec3, UVec3)> {
+        self.iter(&[], UVec3::ZERO).fold(None, |bounds, location| {
+            Some(union_aabb(bounds, (location, location)))
+        })
+    }
+
+    #[inline]
+    fn bounding_box_in_pools(pools: &[Pool], ptr: u32) -> Option<(UVec3, UVec3)> {
+        let leaf_node = unsafe { pools[Self::LEVEL].get_item::<Self>(ptr) };
+        leaf_node.bounding_box(&[])
+    }
+
+    #[inline]
+    fn count_active(&self, _pools: &[Pool]) -> u64 {
+        self.occupancy.count_ones() as u64
+    }
+
+    #[inline]
+    fn count_active_in_pools(pools: &[Pool], ptr: u32) -> u64 {
+        let leaf_node = unsafe { pools[Self::LEVEL].get_item::<Self>(ptr) };
+        leaf_node.count_active(&[])
+    }
+
+    fn fill(&mut self, _pools: &mut [Pool], min: UVec3, max: UVec3, value: Option<Self::Voxel>) {
+        for x in min.x..=max.x {
+            for y in min.y..=max.y {
+                for z in min.z..=max.z {
+                    self.set(&mut [], UVec3 { x, y, z }, value, &mut []);
+                }
+            }
+        }
+    }
+
+    #[inline]
+    fn fill_in_pools(
+        pools: &mut [Pool],
+        ptr: u32,
+        min: UVec3,
+        max: UVec3,
+        value: Option<Self::Voxel>,
+    ) {
+        let leaf_node = unsafe { pools[Self::LEVEL].get_item_mut::<Self>(ptr) };
+        leaf_node.fill(&mut [], min, max, value)
+    }
+
+    fn csg(
+        &mut self,
+        _pools: &mut [Pool],
+        other: &Self,
+        _other_pools: &[Pool],
+        operation: CsgOperation,
+    ) {
+        const NUM_BITS: usize = std::mem::size_of::<usize>() * 8;
+        if let CsgOperation::Union = operation {
+            for (i, word) in self.occupancy.data.iter_mut().enumerate() {
+                let other_occupancy = other.occupancy.data[i];
+                *word |= other_occupancy;
+                self.active.data[i] =
+                    (self.active.data[i] & !other_occupancy) | other.active.data[i];
+            }
+            for index in other.occupancy.iter_set_bits() {
+                self.values[index] = other.values[index];
+            }
+            return;
+        }
+        for (i, word) in self.occupancy.data.iter_mut().enumerate() {
+            let other_occupancy = other.occupancy.data[i];
+            let kept = match operation {
+                CsgOperation::Intersection => *word & other_occupancy,
+                _ => *word & !other_occupancy,
+            };
+            let mut removed = *word & !kept;
+            *word = kept;
+            self.active.data[i] &= kept;
+            // Unoccupied voxels are kept at the default value.
+            while removed != 0 {
+                let bit = removed.trailing_zeros() as usize;
+                self.values[i * NUM_BITS + bit] = T::default();
+                removed &= removed - 1;
+            }
+        }
+    }
+
+    #[inline]
+    fn csg_in_pools(
+        pools: &mut [Pool],
+        ptr: u32,
+        other_pools: &[Pool],
+        other_ptr: u32,
+        operation: CsgOperation,
+    ) {
+        let other = unsafe { other_pools[Self::LEVEL].get_item::<Self>(other_ptr) };
+        let leaf_node = unsafe { pools[Self::LEVEL].get_item_mut::<Self>(ptr) };
+        leaf_node.csg(&mut [], other, &[], operation)
+    }
+
+    #[inline]
+    fn remap_children(&mut self, _pools: &mut [Pool], _remaps: &[Vec<u32>]) {}
+
+    #[inline]
+    fn remap_children_in_pools(_pools: &mut [Pool], _ptr: u32, _remaps: &[Vec<u32>]) {}
+
+    #[inline]
+    fn for_each_tile_in_aabb(
+        &self,
+        _pools: &[Pool],
+        _offset: UVec3,
+        _min: UVec3,
+        _max: UVec3,
+        _f: &mut dyn FnMut(UVec3, UVec3, Self::Voxel),
+    ) {
+    }
+
+    #[inline]
+    fn for_each_tile_in_aabb_in_pool(
+        _pools: &[Pool],
+        _ptr: u32,
+        _offset: UVec3,
+        _min: UVec3,
+        _max: UVec3,
+        _f: &mut dyn FnMut(UVec3, UVec3, Self::Voxel),
+    ) {
+    }
+
+    #[inline]
+    fn for_each_node_in_aabb(
+        &self,
+        _pools: &[Pool],
+        _offset: UVec3,
+        _min: UVec3,
+        _max: UVec3,
+        _f: &mut dyn FnMut(usize, u32, UVec3),
+    ) {
+    }
+
+    #[inline]
+    fn for_each_node_in_aabb_in_pool(
+        _pools: &[Pool],
+        _ptr: u32,
+        _offset: UVec3,
+        _min: UVec3,
+        _max: UVec3,
+        _f: &mut dyn FnMut(usize, u32, UVec3),
+    ) {
+    }
+
+    #[inline]
+    fn ensure_leaf(&mut self, _pools: &mut [Pool], _coords: UVec3) -> *mut Self {
+        self
+    }
+
+    #[inline]
+    fn ensure_leaf_in_pools(pools: &mut [Pool], ptr: u32, _coords: UVec3) -> *mut Self {
+        unsafe { pools[Self::LEVEL].get_item_mut::<Self>(ptr) }
     }
 }
 
-impl<const LOG2: ConstUVec3> const NodeConst for LeafNode<LOG2>
+impl<T: 'static + Copy + Default + PartialEq, const LOG2: ConstUVec3> const NodeConst
+    for LeafNode<T, LOG2>
 where
     [(); size_of_grid(LOG2) / size_of::<usize>() / 8]: Sized,
 {
@@ -180,7 +433,157 @@ where
     }
 }
 
-impl<const LOG2: ConstUVec3> std::fmt::Debug for LeafNode<LOG2>
+/// Leaf nodes are written as the occupancy mask and the active mask,
+/// followed by the values of the occupied voxels.
+impl<T, const LOG2: ConstUVec3> NodeSerialize for LeafNode<T, LOG2>
+where
+    T: 'static + Copy + Default + PartialEq + SerializableValue,
+    [(); size_of_grid(LOG2) / size_of::<usize>() / 8]: Sized,
+{
+    fn write<W: Write>(&self, _pools: &[Pool], writer: &mut W) -> std::io::Result<()> {
+        self.occupancy.write_to(writer)?;
+        self.active.write_to(writer)?;
+        for index in self.occupancy.iter_set_bits() {
+            self.values[index].write_to(writer)?;
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn write_in_pools<W: Write>(pools: &[Pool], ptr: u32, writer: &mut W) -> std::io::Result<()> {
+        let leaf_node = unsafe { pools[Self::LEVEL].get_item::<Self>(ptr) };
+        leaf_node.write(&[], writer)
+    }
+
+    fn read<R: Read>(&mut self, _pools: &mut [Pool], reader: &mut R) -> std::io::Result<()> {
+        self.occupancy.read_from(reader)?;
+        self.active.read_from(reader)?;
+        for index in self.occupancy.iter_set_bits() {
+            self.values[index] = T::read_from(reader)?;
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn read_in_pools<R: Read>(pools: &mut [Pool], ptr: u32, reader: &mut R) -> std::io::Result<()> {
+        let leaf_node = unsafe { pools[Self::LEVEL].get_item_mut::<Self>(ptr) };
+        leaf_node.read(&mut [], reader)
+    }
+}
+
+/// Leaf nodes are written as their occupancy mask in the topology,
+/// and as their occupancy mask followed by their values in the buffers.
+impl<T, const LOG2: ConstUVec3> NodeOpenVdb for LeafNode<T, LOG2>
+where
+    T: VdbValue,
+    [(); size_of_grid(LOG2) / size_of::<usize>() / 8]: Sized,
+{
+    fn vdb_layout() -> Option<Vec<u32>> {
+        let cubic = LOG2.x == LOG2.y && LOG2.y == LOG2.z;
+        (cubic && LOG2.x >= 2).then(|| vec![LOG2.x])
+    }
+
+    fn write_vdb_topology<W: Write>(
+        &self,
+        _pools: &[Pool],
+        writer: &mut W,
+        _compression: u32,
+    ) -> std::io::Result<()> {
+        self.occupancy.write_to(writer)
+    }
+
+    #[inline]
+    fn write_vdb_topology_in_pools<W: Write>(
+        pools: &[Pool],
+        ptr: u32,
+        writer: &mut W,
+        compression: u32,
+    ) -> std::io::Result<()> {
+        let leaf_node = unsafe { pools[Self::LEVEL].get_item::<Self>(ptr) };
+        leaf_node.write_vdb_topology(&[], writer, compression)
+    }
+
+    fn write_vdb_buffers<W: Write>(
+        &self,
+        _pools: &[Pool],
+        offset: UVec3,
+        writer: &mut W,
+        compression: u32,
+    ) -> std::io::Result<()> {
+        self.occupancy.write_to(writer)?;
+        T::write_leaf_values(
+            &self.values,
+            &self.occupancy.data,
+            to_signed(offset),
+            writer,
+            compression,
+        )
+    }
+
+    #[inline]
+    fn write_vdb_buffers_in_pools<W: Write>(
+        pools: &[Pool],
+        ptr: u32,
+        offset: UVec3,
+        writer: &mut W,
+        compression: u32,
+    ) -> std::io::Result<()> {
+        let leaf_node = unsafe { pools[Self::LEVEL].get_item::<Self>(ptr) };
+        leaf_node.write_vdb_buffers(&[], offset, writer, compression)
+    }
+
+    fn read_vdb_topology<R: Read>(
+        &mut self,
+        _pools: &mut [Pool],
+        reader: &mut R,
+        _compression: u32,
+    ) -> std::io::Result<()> {
+        self.occupancy.read_from(reader)
+    }
+
+    #[inline]
+    fn read_vdb_topology_in_pools<R: Read>(
+        pools: &mut [Pool],
+        ptr: u32,
+        reader: &mut R,
+        compression: u32,
+    ) -> std::io::Result<()> {
+        let leaf_node = unsafe { pools[Self::LEVEL].get_item_mut::<Self>(ptr) };
+        leaf_node.read_vdb_topology(&mut [], reader, compression)
+    }
+
+    fn read_vdb_buffers<R: Read>(
+        &mut self,
+        _pools: &mut [Pool],
+        offset: UVec3,
+        reader: &mut R,
+        compression: u32,
+    ) -> std::io::Result<()> {
+        // The occupancy mask is repeated.
+        self.occupancy.read_from(reader)?;
+        T::read_leaf_values(
+            &mut self.values,
+            &self.occupancy.data,
+            to_signed(offset),
+            reader,
+            compression,
+        )
+    }
+
+    #[inline]
+    fn read_vdb_buffers_in_pools<R: Read>(
+        pools: &mut [Pool],
+        ptr: u32,
+        offset: UVec3,
+        reader: &mut R,
+        compression: u32,
+    ) -> std::io::Result<()> {
+        let leaf_node = unsafe { pools[Self::LEVEL].get_item_mut::<Self>(ptr) };
+        leaf_node.read_vdb_buffers(&mut [], offset, reader, compression)
+    }
+}
+
+impl<T, const LOG2: ConstUVec3> std::fmt::Debug for LeafNode<T, LOG2>
 where
     [(); size_of_grid(LOG2) / size_of::<usize>() / 8]: Sized,
 {
@@ -197,6 +600,9 @@ where
 {
     location_offset: UVec3,
     bits_iterator: SetBitIterator<'a, { size_of_grid(LOG2) }>,
+    /// Voxels outside of `min` and `max` (inclusive) are skipped.
+    min: UVec3,
+    max: UVec3,
 }
 impl<'a, const LOG2: ConstUVec3> Iterator for LeafNodeIterator<'a, LOG2>
 where
@@ -205,12 +611,17 @@ where
     type Item = UVec3;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let index = self.bits_iterator.next()?;
+        loop {
+            let index = self.bits_iterator.next()?;
 
-        let z = index & ((1 << LOG2.z) - 1);
-        let y = (index >> LOG2.z) & ((1 << LOG2.y) - 1);
-        let x = index >> (LOG2.z + LOG2.y);
-        let location = UVec3::new(x as u32, y as u32, z as u32);
-        Some(location + self.location_offset)
+            let z = index & ((1 << LOG2.z) - 1);
+            let y = (index >> LOG2.z) & ((1 << LOG2.y) - 1);
+            let x = index >> (LOG2.z + LOG2.y);
+            let location = UVec3::new(x as u32, y as u32, z as u32);
+            if location.cmplt(self.min).any() || location.cmpgt(self.max).any() {
+                continue;
+            }
+            return Some(location + self.location_offset);
+        }
     }
 }
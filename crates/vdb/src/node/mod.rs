@@ -12,7 +12,7 @@ pub use internal::*;
 pub use leaf::*;
 pub use root::*;
 
-use crate::{ConstUVec3, Pool};
+use crate::{ConstUVec3, CsgOperation, Pool};
 
 pub struct NodeMeta<V> {
     pub(crate) layout: Layout,
@@ -26,7 +26,7 @@ pub struct NodeMeta<V> {
     pub(crate) extent_mask: UVec3, // = (1 << extent_log2) - 1
 }
 
-pub trait Node: 'static + Default + Debug {
+pub trait Node: 'static + Default + Debug + Clone {
     /// span of the node.
     type LeafType: IsLeaf;
     const EXTENT_LOG2: UVec3;
@@ -39,7 +39,7 @@ pub trait Node: 'static + Default + Debug {
     const LEVEL: usize;
     fn new() -> Self;
 
-    type Voxel;
+    type Voxel: Copy + PartialEq;
 
     /// Get the value of a voxel at the specified coordinates within the node space.
     /// This is called when the node was owned.
@@ -78,15 +78,274 @@ pub trait Node: 'static + Default + Debug {
 
     type Iterator<'a>: Iterator<Item = UVec3>;
     /// This is called when the node was owned as the root node in the tree.
-    fn iter<'a>(&'a self, pools: &'a [Pool], offset: UVec3) -> Self::Iterator<'a>;
+    fn iter<'a>(&'a self, pools: &'a [Pool], offset: UVec3) -> Self::Iterator<'a> {
+        self.iter_in_aabb(pools, offset, UVec3::ZERO, Self::EXTENT_MASK)
+    }
     /// This is called when the node was located in a node pool.
-    fn iter_in_pool<'a>(pools: &'a [Pool], ptr: u32, offset: UVec3) -> Self::Iterator<'a>;
+    fn iter_in_pool<'a>(pools: &'a [Pool], ptr: u32, offset: UVec3) -> Self::Iterator<'a> {
+        Self::iter_in_aabb_in_pool(pools, ptr, offset, UVec3::ZERO, Self::EXTENT_MASK)
+    }
+    /// Iterate over the voxels within `min` and `max` (inclusive) in the node space.
+    /// Child nodes outside of the box are skipped entirely. Nothing is yielded if `min > max` on any axis.
+    /// This is called when the node was owned as the root node in the tree.
+    fn iter_in_aabb<'a>(
+        &'a self,
+        pools: &'a [Pool],
+        offset: UVec3,
+        min: UVec3,
+        max: UVec3,
+    ) -> Self::Iterator<'a>;
+    /// Iterate over the voxels within `min` and `max` (inclusive) in the node space.
+    /// Child nodes outside of the box are skipped entirely. Nothing is yielded if `min > max` on any axis.
+    /// This is called when the node was located in a node pool.
+    fn iter_in_aabb_in_pool<'a>(
+        pools: &'a [Pool],
+        ptr: u32,
+        offset: UVec3,
+        min: UVec3,
+        max: UVec3,
+    ) -> Self::Iterator<'a>;
 
     type LeafIterator<'a>: Iterator<Item = (UVec3, &'a UnsafeCell<Self::LeafType>)>;
     /// This is called when the node was owned as the root node in the tree.
-    fn iter_leaf<'a>(&'a self, pools: &'a [Pool], offset: UVec3) -> Self::LeafIterator<'a>;
+    fn iter_leaf<'a>(&'a self, pools: &'a [Pool], offset: UVec3) -> Self::LeafIterator<'a> {
+        self.iter_leaf_in_aabb(pools, offset, UVec3::ZERO, Self::EXTENT_MASK)
+    }
+    /// This is called when the node was located in a node pool.
+    fn iter_leaf_in_pool<'a>(pools: &'a [Pool], ptr: u32, offset: UVec3) -> Self::LeafIterator<'a> {
+        Self::iter_leaf_in_aabb_in_pool(pools, ptr, offset, UVec3::ZERO, Self::EXTENT_MASK)
+    }
+    /// Iterate over the leaf nodes intersecting the box between `min` and `max` (inclusive) in the node space.
+    /// This is called when the node was owned as the root node in the tree.
+    fn iter_leaf_in_aabb<'a>(
+        &'a self,
+        pools: &'a [Pool],
+        offset: UVec3,
+        min: UVec3,
+        max: UVec3,
+    ) -> Self::LeafIterator<'a>;
+    /// Iterate over the leaf nodes intersecting the box between `min` and `max` (inclusive) in the node space.
+    /// This is called when the node was located in a node pool.
+    fn iter_leaf_in_aabb_in_pool<'a>(
+        pools: &'a [Pool],
+        ptr: u32,
+        offset: UVec3,
+        min: UVec3,
+        max: UVec3,
+    ) -> Self::LeafIterator<'a>;
+
+    /// Returns true if the node contains no voxels.
+    fn is_empty(&self) -> bool;
+    /// Returns the value of all voxels if the node is completely filled with the same value,
+    /// in which case the parent may represent the node as a single tile value.
+    /// Leaf nodes with surface voxels marked on their `active` mask are never uniform.
+    fn tile_value(&self) -> Option<Self::Voxel>;
+    /// Release child nodes that no longer contain any voxels back to their pools,
+    /// and collapse child nodes completely filled with the same value into tiles.
+    /// This is called when the node was owned.
+    fn prune(&mut self, pools: &mut [Pool]);
+    /// Release child nodes that no longer contain any voxels back to their pools,
+    /// and collapse child nodes completely filled with the same value into tiles.
+    /// This is called when the node was located in a node pool.
+    fn prune_in_pools(pools: &mut [Pool], ptr: u32);
+    /// Release the node and all of its descendants back to their pools.
+    fn free_in_pools(pools: &mut [Pool], ptr: u32);
+
+    /// Returns the smallest box (inclusive, in the node space) containing all voxels within the node,
+    /// or None if the node is empty.
+    /// This is called when the node was owned.
+    fn bounding_box(&self, pools: &[Pool]) -> Option<(UVec3, UVec3)>;
+    /// Returns the smallest box (inclusive, in the node space) containing all voxels within the node,
+    /// or None if the node is empty.
+    /// This is called when the node was located in a node pool.
+    fn bounding_box_in_pools(pools: &[Pool], ptr: u32) -> Option<(UVec3, UVec3)>;
+    /// Returns the number of voxels with a value within the node, including those covered by tiles.
+    /// This is called when the node was owned.
+    fn count_active(&self, pools: &[Pool]) -> u64;
+    /// Returns the number of voxels with a value within the node, including those covered by tiles.
+    /// This is called when the node was located in a node pool.
+    fn count_active_in_pools(pools: &[Pool], ptr: u32) -> u64;
+
+    /// Set the value of all voxels within `min` and `max` (inclusive) in the node space.
+    /// Child regions entirely covered by the box are stored as tiles.
+    /// This is called when the node was owned.
+    fn fill(&mut self, pools: &mut [Pool], min: UVec3, max: UVec3, value: Option<Self::Voxel>);
+    /// Set the value of all voxels within `min` and `max` (inclusive) in the node space.
+    /// Child regions entirely covered by the box are stored as tiles.
+    /// This is called when the node was located in a node pool.
+    fn fill_in_pools(
+        pools: &mut [Pool],
+        ptr: u32,
+        min: UVec3,
+        max: UVec3,
+        value: Option<Self::Voxel>,
+    );
+
+    /// Combine the voxels of `other` into the node with `operation`.
+    /// `other` is the node at the same location in another tree, with its descendants located in `other_pools`.
+    /// This is called when the node was owned.
+    fn csg(
+        &mut self,
+        pools: &mut [Pool],
+        other: &Self,
+        other_pools: &[Pool],
+        operation: CsgOperation,
+    );
+    /// Combine the voxels of `other` into the node with `operation`.
+    /// `other` is the node at the same location in another tree, with its descendants located in `other_pools`.
+    /// This is called when the node was located in a node pool.
+    fn csg_in_pools(
+        pools: &mut [Pool],
+        ptr: u32,
+        other_pools: &[Pool],
+        other_ptr: u32,
+        operation: CsgOperation,
+    );
+
+    /// Rewrite the pointers to child nodes after the pools were compacted, and do the same for
+    /// all descendants. `remaps[level][ptr]` is the new location of the node at `ptr` in `pools[level]`.
+    /// This is called when the node was owned.
+    fn remap_children(&mut self, pools: &mut [Pool], remaps: &[Vec<u32>]);
+    /// Rewrite the pointers to child nodes after the pools were compacted, and do the same for
+    /// all descendants. `remaps[level][ptr]` is the new location of the node at `ptr` in `pools[level]`.
+    /// This is called when the node was located in a node pool, with `ptr` being its new location.
+    fn remap_children_in_pools(pools: &mut [Pool], ptr: u32, remaps: &[Vec<u32>]);
+
+    /// Call `f` with the box (inclusive, offset by `offset`) and the value of each tile intersecting
+    /// the box between `min` and `max` (inclusive) in the node space, including the tiles of descendants.
+    /// Tiles are reported in their entirety, so they may extend outside of the box.
+    /// This is called when the node was owned.
+    fn for_each_tile_in_aabb(
+        &self,
+        pools: &[Pool],
+        offset: UVec3,
+        min: UVec3,
+        max: UVec3,
+        f: &mut dyn FnMut(UVec3, UVec3, Self::Voxel),
+    );
+    /// Call `f` with the box (inclusive, offset by `offset`) and the value of each tile intersecting
+    /// the box between `min` and `max` (inclusive) in the node space, including the tiles of descendants.
     /// This is called when the node was located in a node pool.
-    fn iter_leaf_in_pool<'a>(pools: &'a [Pool], ptr: u32, offset: UVec3) -> Self::LeafIterator<'a>;
+    fn for_each_tile_in_aabb_in_pool(
+        pools: &[Pool],
+        ptr: u32,
+        offset: UVec3,
+        min: UVec3,
+        max: UVec3,
+        f: &mut dyn FnMut(UVec3, UVec3, Self::Voxel),
+    );
+
+    /// Call `f` with the level, the pointer and the origin (offset by `offset`) of each descendant
+    /// node intersecting the box between `min` and `max` (inclusive) in the node space.
+    /// Parents are reported before their children.
+    /// This is called when the node was owned.
+    fn for_each_node_in_aabb(
+        &self,
+        pools: &[Pool],
+        offset: UVec3,
+        min: UVec3,
+        max: UVec3,
+        f: &mut dyn FnMut(usize, u32, UVec3),
+    );
+    /// Call `f` with the level, the pointer and the origin (offset by `offset`) of each descendant
+    /// node intersecting the box between `min` and `max` (inclusive) in the node space.
+    /// This is called when the node was located in a node pool.
+    fn for_each_node_in_aabb_in_pool(
+        pools: &[Pool],
+        ptr: u32,
+        offset: UVec3,
+        min: UVec3,
+        max: UVec3,
+        f: &mut dyn FnMut(usize, u32, UVec3),
+    );
+
+    /// Returns the leaf node containing `coords` in the node space, allocating it and the nodes
+    /// above it as needed. Tiles on the way are densified, so the voxels are left unchanged.
+    /// Leaf nodes allocated this way are empty, and must be written to or released by the caller.
+    /// This is called when the node was owned.
+    fn ensure_leaf(&mut self, pools: &mut [Pool], coords: UVec3) -> *mut Self::LeafType;
+    /// Returns the leaf node containing `coords` in the node space, allocating it and the nodes
+    /// above it as needed. This is called when the node was located in a node pool.
+    fn ensure_leaf_in_pools(pools: &mut [Pool], ptr: u32, coords: UVec3) -> *mut Self::LeafType;
+}
+
+/// Marks all levels of the cached path up to and including `level` as invalid.
+/// This is called when a lookup terminated before reaching the leaf, or when the nodes
+/// on the path were released back to their pools.
+#[inline]
+pub(crate) fn invalidate_cached_path(cached_path: &mut [u32], level: usize) {
+    if !cached_path.is_empty() {
+        cached_path[..=level].fill(u32::MAX);
+    }
+}
+
+/// Returns the intersection of the box between `min` and `max` (inclusive) with the box
+/// between `origin` and `origin + extent_mask`, or None if they do not intersect.
+#[inline]
+pub(crate) fn clip_aabb(
+    min: UVec3,
+    max: UVec3,
+    origin: UVec3,
+    extent_mask: UVec3,
+) -> Option<(UVec3, UVec3)> {
+    let clipped_min = min.max(origin);
+    let clipped_max = max.min(origin + extent_mask);
+    if clipped_min.cmpgt(clipped_max).any() {
+        None
+    } else {
+        Some((clipped_min, clipped_max))
+    }
+}
+
+/// Returns the smallest box containing both boxes.
+#[inline]
+pub(crate) fn union_aabb(a: Option<(UVec3, UVec3)>, b: (UVec3, UVec3)) -> (UVec3, UVec3) {
+    match a {
+        Some((min, max)) => (min.min(b.0), max.max(b.1)),
+        None => b,
+    }
+}
+
+/// Iterator over the coordinates of all voxels within a tile.
+/// Coordinates are yielded in the same order as the voxels within a leaf node.
+pub struct TileIterator {
+    origin: UVec3,
+    extent: UVec3,
+    next: Option<UVec3>,
+}
+
+impl TileIterator {
+    pub fn new(origin: UVec3, extent: UVec3) -> Self {
+        Self {
+            origin,
+            extent,
+            next: Some(UVec3::ZERO),
+        }
+    }
+}
+
+impl Iterator for TileIterator {
+    type Item = UVec3;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let current = self.next?;
+        let mut next = current;
+        next.z += 1;
+        if next.z == self.extent.z {
+            next.z = 0;
+            next.y += 1;
+            if next.y == self.extent.y {
+                next.y = 0;
+                next.x += 1;
+            }
+        }
+        self.next = if next.x == self.extent.x {
+            None
+        } else {
+            Some(next)
+        };
+        Some(self.origin + current)
+    }
 }
 
 /// Trait that contains const methods for the node.
@@ -98,6 +357,8 @@ pub trait NodeConst: Node {
 }
 
 /// Macro that simplifies tree type construction.
+/// The type of the values stored in each voxel may be specified after a semicolon,
+/// and defaults to `bool`.
 /// ```
 /// use dust_vdb::{hierarchy, Node};
 /// // Create a 4x4x4 LeafNode
@@ -108,17 +369,28 @@ pub trait NodeConst: Node {
 /// let hierarchy = <hierarchy!(2, 2, 1)>::new();
 /// // Create a three-level tree with infinite size (implemented with a HashMap), 4x4x4 intermediate nodes and 2x2x2 leafs.
 /// let hierarchy = <hierarchy!(#, 2, 1)>::new();
+/// // Create a two-level tree storing a u16 material id in each voxel.
+/// let hierarchy = <hierarchy!(2, 2; u16)>::new();
 /// ```
 #[macro_export]
 macro_rules! hierarchy {
     ($e: tt) => {
-        $crate::LeafNode<{dust_vdb::ConstUVec3{x:$e,y:$e,z:$e}}>
+        hierarchy!($e; bool)
+    };
+    ($e: tt; $t: ty) => {
+        $crate::LeafNode<$t, {dust_vdb::ConstUVec3{x:$e,y:$e,z:$e}}>
     };
     (#, $($n:tt),+) => {
-        $crate::RootNode<hierarchy!($($n),*)>
+        hierarchy!(#, $($n),*; bool)
+    };
+    (#, $($n:tt),+; $t: ty) => {
+        $crate::RootNode<hierarchy!($($n),*; $t)>
     };
     ($e: tt, $($n:tt),+) => {
-        $crate::InternalNode::<hierarchy!($($n),*), {dust_vdb::ConstUVec3{x:$e,y:$e,z:$e}}>
+        hierarchy!($e, $($n),*; bool)
+    };
+    ($e: tt, $($n:tt),+; $t: ty) => {
+        $crate::InternalNode::<hierarchy!($($n),*; $t), {dust_vdb::ConstUVec3{x:$e,y:$e,z:$e}}>
     };
 }
 
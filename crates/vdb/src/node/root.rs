@@ -1,36 +1,75 @@
-use std::{cell::UnsafeCell, marker::PhantomData, mem::MaybeUninit};
+use std::{
+    cell::UnsafeCell,
+    io::{Read, Write},
+    marker::PhantomData,
+    mem::MaybeUninit,
+};
 
-use glam::UVec3;
+use glam::{IVec3, UVec3};
 
-use crate::{Node, NodeConst, Pool};
+use crate::{
+    coords::{from_signed, to_signed},
+    openvdb::{read_coords, write_coords},
+    serialize::invalid_data,
+    CsgOperation, Node, NodeConst, NodeOpenVdb, NodeSerialize, Pool, SerializableValue, VdbValue,
+};
 
-use super::NodeMeta;
+use super::{clip_aabb, invalidate_cached_path, union_aabb, NodeMeta, TileIterator};
 
-pub enum RootNodeEntry {
+#[derive(Clone, Copy)]
+pub enum RootNodeEntry<V> {
+    /// Points to a child node.
     Occupied(u32),
-    Free(u32),
+    /// All voxels within the tile have this value.
+    Free(V),
 }
 
+/// Signed index of a tile in the root node.
+/// This is the origin of the tile in signed coordinates, divided by the extent of the child nodes.
+/// See [`crate::TreeCoords`] for how signed coordinates relate to the coordinates of the tree.
 #[derive(PartialEq, Eq, Clone)]
-pub struct RootKey(UVec3);
+pub struct RootKey(IVec3);
 impl std::hash::Hash for RootKey {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
-        let root_hash = (self.0.x as u64).wrapping_mul(73856093_u64)
-            ^ (self.0.y as u64).wrapping_mul(19349663_u64)
-            ^ (self.0.z as u64).wrapping_mul(83492791_u64);
+        let root_hash = (self.0.x as i64 as u64).wrapping_mul(73856093_u64)
+            ^ (self.0.y as i64 as u64).wrapping_mul(19349663_u64)
+            ^ (self.0.z as i64 as u64).wrapping_mul(83492791_u64);
         state.write_u64(root_hash);
     }
 }
 
+impl RootKey {
+    /// Returns the key of the tile containing `coords`.
+    #[inline]
+    fn containing<CHILD: Node>(coords: UVec3) -> Self {
+        RootKey(to_signed(coords) >> CHILD::EXTENT_LOG2.as_ivec3())
+    }
+    /// Returns the origin of the tile in the coordinates of the tree.
+    #[inline]
+    fn origin<CHILD: Node>(&self) -> UVec3 {
+        from_signed(self.0 << CHILD::EXTENT_LOG2.as_ivec3())
+    }
+}
+
 /// The root node of the tree implemented with a [`std::collections::HashMap`].
 /// This enables trees of infinite size.
-#[derive(Default)]
+#[derive(Clone)]
 pub struct RootNode<CHILD: Node> {
     /// Map from [`RootKey`] to tiles.
-    map: std::collections::HashMap<RootKey, RootNodeEntry, nohash::BuildNoHashHasher<u64>>,
+    map: std::collections::HashMap<
+        RootKey,
+        RootNodeEntry<CHILD::Voxel>,
+        nohash::BuildNoHashHasher<u64>,
+    >,
     _marker: PhantomData<CHILD>,
 }
 
+impl<CHILD: Node> Default for RootNode<CHILD> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<CHILD: Node> Node for RootNode<CHILD> {
     type LeafType = CHILD::LeafType;
     const EXTENT_LOG2: UVec3 = UVec3 {
@@ -59,11 +98,13 @@ impl<CHILD: Node> Node for RootNode<CHILD> {
     type Voxel = CHILD::Voxel;
     #[inline]
     fn get(&self, pools: &[Pool], coords: UVec3, cached_path: &mut [u32]) -> Option<Self::Voxel> {
-        let root_offset = coords >> CHILD::EXTENT_LOG2;
-        let entry = self.map.get(&RootKey(root_offset));
+        let entry = self.map.get(&RootKey::containing::<CHILD>(coords));
         if let Some(entry) = entry {
             match entry {
-                RootNodeEntry::Free(_material_id) => None,
+                RootNodeEntry::Free(value) => {
+                    invalidate_cached_path(cached_path, CHILD::LEVEL);
+                    Some(*value)
+                }
                 RootNodeEntry::Occupied(ptr) => unsafe {
                     let _child_node = pools[CHILD::LEVEL].get_item::<CHILD>(*ptr);
                     let new_coords = UVec3 {
@@ -75,6 +116,7 @@ impl<CHILD: Node> Node for RootNode<CHILD> {
                 },
             }
         } else {
+            invalidate_cached_path(cached_path, CHILD::LEVEL);
             None
         }
     }
@@ -87,27 +129,37 @@ impl<CHILD: Node> Node for RootNode<CHILD> {
         cached_path: &mut [u32],
     ) {
         // ptr is meaningless and always 0 for root nodes.
-        let root_offset = coords >> CHILD::EXTENT_LOG2;
-        let key = RootKey(root_offset);
+        let key = RootKey::containing::<CHILD>(coords);
 
-        if value.is_some() {
-            // Ensure that the node contains stuff on ptr
-            if !self.map.contains_key(&key) {
-                let new_node_ptr = unsafe { pools[CHILD::LEVEL].alloc::<CHILD>() };
-                self.map
-                    .insert(key.clone(), RootNodeEntry::Occupied(new_node_ptr));
+        let new_coords = UVec3 {
+            x: coords.x & ((1_u32 << CHILD::EXTENT_LOG2.x) - 1),
+            y: coords.y & ((1_u32 << CHILD::EXTENT_LOG2.y) - 1),
+            z: coords.z & ((1_u32 << CHILD::EXTENT_LOG2.z) - 1),
+        };
+        let child_ptr = match self.map.get(&key) {
+            Some(RootNodeEntry::Occupied(ptr)) => *ptr,
+            Some(RootNodeEntry::Free(tile)) if Some(*tile) == value => {
+                // Nothing changes.
+                invalidate_cached_path(cached_path, CHILD::LEVEL);
+                return;
+            }
+            None if value.is_none() => {
+                // Nothing to clear.
+                invalidate_cached_path(cached_path, CHILD::LEVEL);
+                return;
+            }
+            _ => self.ensure_child(pools, key.clone()),
+        };
+        CHILD::set_in_pools(pools, new_coords, child_ptr, value, cached_path);
+        if value.is_none() {
+            // Drop the tile if it was completely cleared.
+            let child_empty =
+                unsafe { pools[CHILD::LEVEL].get_item::<CHILD>(child_ptr).is_empty() };
+            if child_empty {
+                pools[CHILD::LEVEL].free(child_ptr);
+                self.map.remove(&key);
+                invalidate_cached_path(cached_path, CHILD::LEVEL);
             }
-
-            let child_ptr = match self.map.get(&key).unwrap() {
-                RootNodeEntry::Occupied(ptr) => *ptr,
-                RootNodeEntry::Free(_) => todo!(),
-            };
-            let new_coords = UVec3 {
-                x: coords.x & ((1_u32 << CHILD::EXTENT_LOG2.x) - 1),
-                y: coords.y & ((1_u32 << CHILD::EXTENT_LOG2.y) - 1),
-                z: coords.z & ((1_u32 << CHILD::EXTENT_LOG2.z) - 1),
-            };
-            CHILD::set_in_pools(pools, new_coords, child_ptr, value, cached_path)
         }
     }
 
@@ -130,36 +182,530 @@ impl<CHILD: Node> Node for RootNode<CHILD> {
         unreachable!("Root Node is never kept in a pool!")
     }
     type Iterator<'a> = RootIterator<'a, CHILD>;
-    fn iter<'a>(&'a self, pools: &'a [Pool], offset: UVec3) -> Self::Iterator<'a> {
+    fn iter_in_aabb<'a>(
+        &'a self,
+        pools: &'a [Pool],
+        offset: UVec3,
+        min: UVec3,
+        max: UVec3,
+    ) -> Self::Iterator<'a> {
         RootIterator {
             pools,
             map_iterator: self.map.iter(),
             child_iterator: None,
+            tile_iterator: None,
             location_offset: offset,
+            min,
+            max,
         }
     }
-    fn iter_in_pool<'a>(_pools: &'a [Pool], _ptr: u32, _offset: UVec3) -> Self::Iterator<'a> {
+    fn iter_in_aabb_in_pool<'a>(
+        _pools: &'a [Pool],
+        _ptr: u32,
+        _offset: UVec3,
+        _min: UVec3,
+        _max: UVec3,
+    ) -> Self::Iterator<'a> {
         unreachable!("Root Node is never kept in a pool!")
     }
 
     type LeafIterator<'a> = RootLeafIterator<'a, CHILD>;
 
-    fn iter_leaf<'a>(&'a self, pools: &'a [Pool], offset: UVec3) -> Self::LeafIterator<'a> {
+    fn iter_leaf_in_aabb<'a>(
+        &'a self,
+        pools: &'a [Pool],
+        offset: UVec3,
+        min: UVec3,
+        max: UVec3,
+    ) -> Self::LeafIterator<'a> {
         RootLeafIterator {
             pools,
             map_iterator: self.map.iter(),
             child_iterator: None,
             location_offset: offset,
+            min,
+            max,
         }
     }
 
-    fn iter_leaf_in_pool<'a>(
+    fn iter_leaf_in_aabb_in_pool<'a>(
         _pools: &'a [Pool],
         _ptr: u32,
         _offset: UVec3,
+        _min: UVec3,
+        _max: UVec3,
     ) -> Self::LeafIterator<'a> {
         unreachable!("Root Node is never kept in a pool!")
     }
+
+    #[inline]
+    fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    fn tile_value(&self) -> Option<Self::Voxel> {
+        // The root node spans an infinite region and is never represented as a tile.
+        None
+    }
+
+    fn prune(&mut self, pools: &mut [Pool]) {
+        self.map.retain(|_, entry| {
+            let RootNodeEntry::Occupied(ptr) = *entry else {
+                return true;
+            };
+            CHILD::prune_in_pools(pools, ptr);
+            let child = unsafe { pools[CHILD::LEVEL].get_item::<CHILD>(ptr) };
+            let child_empty = child.is_empty();
+            let tile_value = child.tile_value();
+            if child_empty || tile_value.is_some() {
+                pools[CHILD::LEVEL].free(ptr);
+            }
+            if let Some(value) = tile_value {
+                *entry = RootNodeEntry::Free(value);
+            }
+            !child_empty
+        });
+    }
+
+    fn prune_in_pools(_pools: &mut [Pool], _ptr: u32) {
+        unreachable!("Root Node is never kept in a pool!")
+    }
+
+    fn free_in_pools(_pools: &mut [Pool], _ptr: u32) {
+        unreachable!("Root Node is never kept in a pool!")
+    }
+
+    fn bounding_box(&self, pools: &[Pool]) -> Option<(UVec3, UVec3)> {
+        let mut bounds = None;
+        for (key, entry) in self.map.iter() {
+            let origin = key.origin::<CHILD>();
+            let child_bounds = match entry {
+                RootNodeEntry::Occupied(ptr) => CHILD::bounding_box_in_pools(pools, *ptr),
+                RootNodeEntry::Free(_) => Some((UVec3::ZERO, CHILD::EXTENT_MASK)),
+            };
+            if let Some((min, max)) = child_bounds {
+                bounds = Some(union_aabb(bounds, (origin + min, origin + max)));
+            }
+        }
+        bounds
+    }
+
+    fn bounding_box_in_pools(_pools: &[Pool], _ptr: u32) -> Option<(UVec3, UVec3)> {
+        unreachable!("Root Node is never kept in a pool!")
+    }
+
+    fn count_active(&self, pools: &[Pool]) -> u64 {
+        let tile_size = CHILD::EXTENT.x as u64 * CHILD::EXTENT.y as u64 * CHILD::EXTENT.z as u64;
+        self.map
+            .values()
+            .map(|entry| match entry {
+                RootNodeEntry::Occupied(ptr) => CHILD::count_active_in_pools(pools, *ptr),
+                RootNodeEntry::Free(_) => tile_size,
+            })
+            .sum()
+    }
+
+    fn count_active_in_pools(_pools: &[Pool], _ptr: u32) -> u64 {
+        unreachable!("Root Node is never kept in a pool!")
+    }
+
+    fn fill(&mut self, pools: &mut [Pool], min: UVec3, max: UVec3, value: Option<Self::Voxel>) {
+        let root_min = min >> CHILD::EXTENT_LOG2;
+        let root_max = max >> CHILD::EXTENT_LOG2;
+        for x in root_min.x..=root_max.x {
+            for y in root_min.y..=root_max.y {
+                for z in root_min.z..=root_max.z {
+                    let root_offset = UVec3 { x, y, z };
+                    let child_origin = root_offset << CHILD::EXTENT_LOG2;
+                    let key = RootKey::containing::<CHILD>(child_origin);
+                    let child_min = min.max(child_origin) - child_origin;
+                    let child_max = max.min(child_origin + CHILD::EXTENT_MASK) - child_origin;
+                    if child_min == UVec3::ZERO && child_max == CHILD::EXTENT_MASK {
+                        // The child is entirely covered. Replace it with a tile.
+                        let entry = if let Some(value) = value {
+                            self.map.insert(key, RootNodeEntry::Free(value))
+                        } else {
+                            self.map.remove(&key)
+                        };
+                        if let Some(RootNodeEntry::Occupied(ptr)) = entry {
+                            CHILD::free_in_pools(pools, ptr);
+                        }
+                        continue;
+                    }
+                    let child_ptr = match self.map.get(&key) {
+                        Some(RootNodeEntry::Occupied(ptr)) => *ptr,
+                        Some(RootNodeEntry::Free(tile)) if Some(*tile) == value => continue,
+                        None if value.is_none() => continue,
+                        _ => self.ensure_child(pools, key.clone()),
+                    };
+                    CHILD::fill_in_pools(pools, child_ptr, child_min, child_max, value);
+                    if value.is_none() {
+                        let child_empty =
+                            unsafe { pools[CHILD::LEVEL].get_item::<CHILD>(child_ptr).is_empty() };
+                        if child_empty {
+                            pools[CHILD::LEVEL].free(child_ptr);
+                            self.map.remove(&key);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn fill_in_pools(
+        _pools: &mut [Pool],
+        _ptr: u32,
+        _min: UVec3,
+        _max: UVec3,
+        _value: Option<Self::Voxel>,
+    ) {
+        unreachable!("Root Node is never kept in a pool!")
+    }
+
+    fn csg(
+        &mut self,
+        pools: &mut [Pool],
+        other: &Self,
+        other_pools: &[Pool],
+        operation: CsgOperation,
+    ) {
+        match operation {
+            CsgOperation::Union => {
+                for (key, other_entry) in other.map.iter() {
+                    match other_entry {
+                        RootNodeEntry::Free(value) => {
+                            let entry = self.map.insert(key.clone(), RootNodeEntry::Free(*value));
+                            if let Some(RootNodeEntry::Occupied(ptr)) = entry {
+                                CHILD::free_in_pools(pools, ptr);
+                            }
+                        }
+                        RootNodeEntry::Occupied(other_ptr) => {
+                            let child_ptr = self.ensure_child(pools, key.clone());
+                            CHILD::csg_in_pools(
+                                pools,
+                                child_ptr,
+                                other_pools,
+                                *other_ptr,
+                                operation,
+                            );
+                        }
+                    }
+                }
+            }
+            CsgOperation::Intersection => {
+                let keys: Vec<RootKey> = self.map.keys().cloned().collect();
+                for key in keys {
+                    match other.map.get(&key) {
+                        None => {
+                            if let Some(RootNodeEntry::Occupied(ptr)) = self.map.remove(&key) {
+                                CHILD::free_in_pools(pools, ptr);
+                            }
+                        }
+                        Some(RootNodeEntry::Free(_)) => (),
+                        Some(RootNodeEntry::Occupied(other_ptr)) => {
+                            let child_ptr = self.ensure_child(pools, key.clone());
+                            CHILD::csg_in_pools(
+                                pools,
+                                child_ptr,
+                                other_pools,
+                                *other_ptr,
+                                operation,
+                            );
+                            self.release_child_if_empty(pools, key);
+                        }
+                    }
+                }
+            }
+            CsgOperation::Difference => {
+                for (key, other_entry) in other.map.iter() {
+                    match other_entry {
+                        RootNodeEntry::Free(_) => {
+                            if let Some(RootNodeEntry::Occupied(ptr)) = self.map.remove(key) {
+                                CHILD::free_in_pools(pools, ptr);
+                            }
+                        }
+                        RootNodeEntry::Occupied(other_ptr) => {
+                            if !self.map.contains_key(key) {
+                                // Nothing to remove from.
+                                continue;
+                            }
+                            let child_ptr = self.ensure_child(pools, key.clone());
+                            CHILD::csg_in_pools(
+                                pools,
+                                child_ptr,
+                                other_pools,
+                                *other_ptr,
+                                operation,
+                            );
+                            self.release_child_if_empty(pools, key.clone());
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn csg_in_pools(
+        _pools: &mut [Pool],
+        _ptr: u32,
+        _other_pools: &[Pool],
+        _other_ptr: u32,
+        _operation: CsgOperation,
+    ) {
+        unreachable!("Root Node is never kept in a pool!")
+    }
+    fn remap_children(&mut self, pools: &mut [Pool], remaps: &[Vec<u32>]) {
+        for entry in self.map.values_mut() {
+            if let RootNodeEntry::Occupied(ptr) = entry {
+                *ptr = remaps[CHILD::LEVEL][*ptr as usize];
+                CHILD::remap_children_in_pools(pools, *ptr, remaps);
+            }
+        }
+    }
+
+    fn remap_children_in_pools(_pools: &mut [Pool], _ptr: u32, _remaps: &[Vec<u32>]) {
+        unreachable!("Root Node is never kept in a pool!")
+    }
+
+    fn for_each_tile_in_aabb(
+        &self,
+        pools: &[Pool],
+        offset: UVec3,
+        min: UVec3,
+        max: UVec3,
+        f: &mut dyn FnMut(UVec3, UVec3, Self::Voxel),
+    ) {
+        for (key, entry) in self.map.iter() {
+            let origin = key.origin::<CHILD>();
+            let Some((child_min, child_max)) = clip_aabb(min, max, origin, CHILD::EXTENT_MASK) else {
+                continue;
+            };
+            match entry {
+                RootNodeEntry::Free(value) => {
+                    let tile_min = offset + origin;
+                    f(tile_min, tile_min + CHILD::EXTENT_MASK, *value);
+                }
+                RootNodeEntry::Occupied(ptr) => CHILD::for_each_tile_in_aabb_in_pool(
+                    pools,
+                    *ptr,
+                    offset + origin,
+                    child_min - origin,
+                    child_max - origin,
+                    f,
+                ),
+            }
+        }
+    }
+
+    fn for_each_tile_in_aabb_in_pool(
+        _pools: &[Pool],
+        _ptr: u32,
+        _offset: UVec3,
+        _min: UVec3,
+        _max: UVec3,
+        _f: &mut dyn FnMut(UVec3, UVec3, Self::Voxel),
+    ) {
+        unreachable!("Root Node is never kept in a pool!")
+    }
+
+    fn for_each_node_in_aabb(
+        &self,
+        pools: &[Pool],
+        offset: UVec3,
+        min: UVec3,
+        max: UVec3,
+        f: &mut dyn FnMut(usize, u32, UVec3),
+    ) {
+        for (key, entry) in self.map.iter() {
+            let RootNodeEntry::Occupied(ptr) = entry else {
+                continue;
+            };
+            let origin = key.origin::<CHILD>();
+            let Some((child_min, child_max)) = clip_aabb(min, max, origin, CHILD::EXTENT_MASK) else {
+                continue;
+            };
+            f(CHILD::LEVEL, *ptr, offset + origin);
+            CHILD::for_each_node_in_aabb_in_pool(
+                pools,
+                *ptr,
+                offset + origin,
+                child_min - origin,
+                child_max - origin,
+                f,
+            );
+        }
+    }
+
+    fn for_each_node_in_aabb_in_pool(
+        _pools: &[Pool],
+        _ptr: u32,
+        _offset: UVec3,
+        _min: UVec3,
+        _max: UVec3,
+        _f: &mut dyn FnMut(usize, u32, UVec3),
+    ) {
+        unreachable!("Root Node is never kept in a pool!")
+    }
+
+    fn ensure_leaf(&mut self, pools: &mut [Pool], coords: UVec3) -> *mut Self::LeafType {
+        let child_ptr = self.ensure_child(pools, RootKey::containing::<CHILD>(coords));
+        CHILD::ensure_leaf_in_pools(pools, child_ptr, coords & CHILD::EXTENT_MASK)
+    }
+
+    fn ensure_leaf_in_pools(_pools: &mut [Pool], _ptr: u32, _coords: UVec3) -> *mut Self::LeafType {
+        unreachable!("Root Node is never kept in a pool!")
+    }
+}
+
+impl<CHILD: Node> RootNode<CHILD> {
+    /// Ensure that the tile at `key` is represented by a child node with the same content,
+    /// allocating a child node for air tiles and densifying uniform tiles as needed.
+    fn ensure_child(&mut self, pools: &mut [Pool], key: RootKey) -> u32 {
+        let tile = match self.map.get(&key) {
+            Some(RootNodeEntry::Occupied(ptr)) => return *ptr,
+            Some(RootNodeEntry::Free(value)) => Some(*value),
+            None => None,
+        };
+        let child_ptr = unsafe { pools[CHILD::LEVEL].alloc::<CHILD>() };
+        if let Some(value) = tile {
+            CHILD::fill_in_pools(
+                pools,
+                child_ptr,
+                UVec3::ZERO,
+                CHILD::EXTENT_MASK,
+                Some(value),
+            );
+        }
+        self.map.insert(key, RootNodeEntry::Occupied(child_ptr));
+        child_ptr
+    }
+
+    /// Release the child node at `key` back to its pool if it no longer contains any voxels.
+    fn release_child_if_empty(&mut self, pools: &mut [Pool], key: RootKey) {
+        let Some(RootNodeEntry::Occupied(child_ptr)) = self.map.get(&key) else {
+            return;
+        };
+        let child_empty = unsafe { pools[CHILD::LEVEL].get_item::<CHILD>(*child_ptr).is_empty() };
+        if child_empty {
+            pools[CHILD::LEVEL].free(*child_ptr);
+            self.map.remove(&key);
+        }
+    }
+}
+
+/// Root nodes made of independent tiles, which may be populated separately and merged.
+/// See [`crate::ShardedTree`].
+pub trait IsRoot: Node {
+    /// Extent of each tile of the root node.
+    const TILE_EXTENT_LOG2: UVec3;
+    /// Move the tiles of `other` into this node. Child pointers are taken as they are,
+    /// so the child nodes of `other` must already be located in the pools of this node.
+    /// Panics if both nodes have a tile at the same location.
+    fn merge_tiles(&mut self, other: Self);
+}
+
+impl<CHILD: Node> IsRoot for RootNode<CHILD> {
+    const TILE_EXTENT_LOG2: UVec3 = CHILD::EXTENT_LOG2;
+    fn merge_tiles(&mut self, other: Self) {
+        self.map.reserve(other.map.len());
+        for (key, entry) in other.map.into_iter() {
+            let origin = key.origin::<CHILD>();
+            if self.map.insert(key, entry).is_some() {
+                panic!("Both root nodes have a tile at {:?}", origin);
+            }
+        }
+    }
+}
+
+/// Root nodes whose tiles can be written out and read back one at a time.
+/// See [`crate::PagedTree`].
+pub trait PagedRoot: IsRoot {
+    /// Write the tile containing `coords`, including its descendants.
+    fn write_tile<W: Write>(
+        &self,
+        pools: &[Pool],
+        coords: UVec3,
+        writer: &mut W,
+    ) -> std::io::Result<()>;
+    /// Replace the tile containing `coords` with one written by [`PagedRoot::write_tile`].
+    fn read_tile<R: Read>(
+        &mut self,
+        pools: &mut [Pool],
+        coords: UVec3,
+        reader: &mut R,
+    ) -> std::io::Result<()>;
+    /// Remove the tile containing `coords`, releasing its descendants back to their pools.
+    fn remove_tile(&mut self, pools: &mut [Pool], coords: UVec3);
+    /// Returns true if the tile containing `coords` does not hold any voxel.
+    fn is_tile_empty(&self, pools: &[Pool], coords: UVec3) -> bool;
+}
+
+/// Tiles are written as their kind, followed by the value of uniform tiles
+/// or the content of child nodes.
+impl<CHILD: NodeSerialize> PagedRoot for RootNode<CHILD>
+where
+    CHILD::Voxel: SerializableValue,
+{
+    fn write_tile<W: Write>(
+        &self,
+        pools: &[Pool],
+        coords: UVec3,
+        writer: &mut W,
+    ) -> std::io::Result<()> {
+        match self.map.get(&RootKey::containing::<CHILD>(coords)) {
+            None => 0_u8.write_to(writer),
+            Some(RootNodeEntry::Occupied(ptr)) => {
+                1_u8.write_to(writer)?;
+                CHILD::write_in_pools(pools, *ptr, writer)
+            }
+            Some(RootNodeEntry::Free(value)) => {
+                2_u8.write_to(writer)?;
+                value.write_to(writer)
+            }
+        }
+    }
+
+    fn read_tile<R: Read>(
+        &mut self,
+        pools: &mut [Pool],
+        coords: UVec3,
+        reader: &mut R,
+    ) -> std::io::Result<()> {
+        self.remove_tile(pools, coords);
+        let key = RootKey::containing::<CHILD>(coords);
+        match u8::read_from(reader)? {
+            0 => {}
+            1 => {
+                let child_ptr = unsafe { pools[CHILD::LEVEL].alloc::<CHILD>() };
+                self.map.insert(key, RootNodeEntry::Occupied(child_ptr));
+                CHILD::read_in_pools(pools, child_ptr, reader)?;
+            }
+            2 => {
+                let value = CHILD::Voxel::read_from(reader)?;
+                self.map.insert(key, RootNodeEntry::Free(value));
+            }
+            _ => return Err(invalid_data("invalid root node entry")),
+        }
+        Ok(())
+    }
+
+    fn remove_tile(&mut self, pools: &mut [Pool], coords: UVec3) {
+        if let Some(RootNodeEntry::Occupied(child_ptr)) =
+            self.map.remove(&RootKey::containing::<CHILD>(coords))
+        {
+            CHILD::free_in_pools(pools, child_ptr);
+        }
+    }
+
+    fn is_tile_empty(&self, pools: &[Pool], coords: UVec3) -> bool {
+        match self.map.get(&RootKey::containing::<CHILD>(coords)) {
+            None => true,
+            Some(RootNodeEntry::Occupied(ptr)) => unsafe {
+                pools[CHILD::LEVEL].get_item::<CHILD>(*ptr).is_empty()
+            },
+            Some(RootNodeEntry::Free(_)) => false,
+        }
+    }
 }
 
 impl<CHILD: ~const NodeConst> const NodeConst for RootNode<CHILD> {
@@ -176,6 +722,243 @@ impl<CHILD: ~const NodeConst> const NodeConst for RootNode<CHILD> {
     }
 }
 
+/// Root nodes are written as the number of entries, followed by the key, the kind
+/// and the content of each entry.
+impl<CHILD: NodeSerialize> NodeSerialize for RootNode<CHILD>
+where
+    CHILD::Voxel: SerializableValue,
+{
+    fn write<W: Write>(&self, pools: &[Pool], writer: &mut W) -> std::io::Result<()> {
+        (self.map.len() as u64).write_to(writer)?;
+        for (key, entry) in self.map.iter() {
+            key.0.x.write_to(writer)?;
+            key.0.y.write_to(writer)?;
+            key.0.z.write_to(writer)?;
+            match entry {
+                RootNodeEntry::Occupied(ptr) => {
+                    0_u8.write_to(writer)?;
+                    CHILD::write_in_pools(pools, *ptr, writer)?;
+                }
+                RootNodeEntry::Free(value) => {
+                    1_u8.write_to(writer)?;
+                    value.write_to(writer)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn write_in_pools<W: Write>(
+        _pools: &[Pool],
+        _ptr: u32,
+        _writer: &mut W,
+    ) -> std::io::Result<()> {
+        unreachable!("Root Node is never kept in a pool!")
+    }
+
+    fn read<R: Read>(&mut self, pools: &mut [Pool], reader: &mut R) -> std::io::Result<()> {
+        let len = u64::read_from(reader)?;
+        for _ in 0..len {
+            let key = RootKey(IVec3 {
+                x: i32::read_from(reader)?,
+                y: i32::read_from(reader)?,
+                z: i32::read_from(reader)?,
+            });
+            if self.map.contains_key(&key) {
+                return Err(invalid_data("duplicated root node entry"));
+            }
+            match u8::read_from(reader)? {
+                0 => {
+                    let child_ptr = unsafe { pools[CHILD::LEVEL].alloc::<CHILD>() };
+                    self.map.insert(key, RootNodeEntry::Occupied(child_ptr));
+                    CHILD::read_in_pools(pools, child_ptr, reader)?;
+                }
+                1 => {
+                    let value = CHILD::Voxel::read_from(reader)?;
+                    self.map.insert(key, RootNodeEntry::Free(value));
+                }
+                _ => return Err(invalid_data("invalid root node entry")),
+            }
+        }
+        Ok(())
+    }
+
+    fn read_in_pools<R: Read>(
+        _pools: &mut [Pool],
+        _ptr: u32,
+        _reader: &mut R,
+    ) -> std::io::Result<()> {
+        unreachable!("Root Node is never kept in a pool!")
+    }
+}
+
+impl<CHILD: Node> RootNode<CHILD> {
+    /// Entries of the map ordered by key, as in the root nodes of OpenVDB.
+    fn sorted_entries(&self) -> Vec<(IVec3, RootNodeEntry<CHILD::Voxel>)> {
+        let mut entries: Vec<_> = self
+            .map
+            .iter()
+            .map(|(key, entry)| (key.0, *entry))
+            .collect();
+        entries.sort_unstable_by_key(|(key, _)| key.to_array());
+        entries
+    }
+}
+
+/// Root nodes are written as the background value and the number of tiles and child nodes,
+/// followed by the origin and value of each tile, and the origin and topology of each child.
+/// Tiles are always active, so inactive tiles are skipped when reading.
+impl<CHILD: NodeOpenVdb> NodeOpenVdb for RootNode<CHILD>
+where
+    CHILD::Voxel: VdbValue,
+{
+    fn vdb_layout() -> Option<Vec<u32>> {
+        CHILD::vdb_layout()
+    }
+
+    fn write_vdb_topology<W: Write>(
+        &self,
+        pools: &[Pool],
+        writer: &mut W,
+        compression: u32,
+    ) -> std::io::Result<()> {
+        let entries = self.sorted_entries();
+        let num_tiles = entries
+            .iter()
+            .filter(|(_, entry)| matches!(entry, RootNodeEntry::Free(_)))
+            .count();
+        CHILD::Voxel::default().write_to(writer)?;
+        (num_tiles as u32).write_to(writer)?;
+        ((entries.len() - num_tiles) as u32).write_to(writer)?;
+        let log2 = CHILD::EXTENT_LOG2.as_ivec3();
+        for (key, entry) in entries.iter() {
+            if let RootNodeEntry::Free(value) = entry {
+                write_coords(writer, *key << log2)?;
+                value.write_to(writer)?;
+                true.write_to(writer)?;
+            }
+        }
+        for (key, entry) in entries.iter() {
+            if let RootNodeEntry::Occupied(ptr) = entry {
+                write_coords(writer, *key << log2)?;
+                CHILD::write_vdb_topology_in_pools(pools, *ptr, writer, compression)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn write_vdb_topology_in_pools<W: Write>(
+        _pools: &[Pool],
+        _ptr: u32,
+        _writer: &mut W,
+        _compression: u32,
+    ) -> std::io::Result<()> {
+        unreachable!("Root Node is never kept in a pool!")
+    }
+
+    fn write_vdb_buffers<W: Write>(
+        &self,
+        pools: &[Pool],
+        offset: UVec3,
+        writer: &mut W,
+        compression: u32,
+    ) -> std::io::Result<()> {
+        for (key, entry) in self.sorted_entries() {
+            if let RootNodeEntry::Occupied(ptr) = entry {
+                let child_offset = offset + RootKey(key).origin::<CHILD>();
+                CHILD::write_vdb_buffers_in_pools(pools, ptr, child_offset, writer, compression)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn write_vdb_buffers_in_pools<W: Write>(
+        _pools: &[Pool],
+        _ptr: u32,
+        _offset: UVec3,
+        _writer: &mut W,
+        _compression: u32,
+    ) -> std::io::Result<()> {
+        unreachable!("Root Node is never kept in a pool!")
+    }
+
+    fn read_vdb_topology<R: Read>(
+        &mut self,
+        pools: &mut [Pool],
+        reader: &mut R,
+        compression: u32,
+    ) -> std::io::Result<()> {
+        let _background = CHILD::Voxel::read_from(reader)?;
+        let num_tiles = u32::read_from(reader)?;
+        let num_children = u32::read_from(reader)?;
+        let read_key = |reader: &mut R| {
+            let origin = read_coords(reader)?;
+            if (origin.as_uvec3() & CHILD::EXTENT_MASK) != UVec3::ZERO {
+                return Err(invalid_data("misaligned root node entry"));
+            }
+            Ok(RootKey(origin >> CHILD::EXTENT_LOG2.as_ivec3()))
+        };
+        let duplicated = || invalid_data("duplicated root node entry");
+        for _ in 0..num_tiles {
+            let key = read_key(reader)?;
+            let value = CHILD::Voxel::read_from(reader)?;
+            if bool::read_from(reader)?
+                && self.map.insert(key, RootNodeEntry::Free(value)).is_some()
+            {
+                return Err(duplicated());
+            }
+        }
+        for _ in 0..num_children {
+            let key = read_key(reader)?;
+            let child_ptr = unsafe { pools[CHILD::LEVEL].alloc::<CHILD>() };
+            if self
+                .map
+                .insert(key, RootNodeEntry::Occupied(child_ptr))
+                .is_some()
+            {
+                return Err(duplicated());
+            }
+            CHILD::read_vdb_topology_in_pools(pools, child_ptr, reader, compression)?;
+        }
+        Ok(())
+    }
+
+    fn read_vdb_topology_in_pools<R: Read>(
+        _pools: &mut [Pool],
+        _ptr: u32,
+        _reader: &mut R,
+        _compression: u32,
+    ) -> std::io::Result<()> {
+        unreachable!("Root Node is never kept in a pool!")
+    }
+
+    fn read_vdb_buffers<R: Read>(
+        &mut self,
+        pools: &mut [Pool],
+        offset: UVec3,
+        reader: &mut R,
+        compression: u32,
+    ) -> std::io::Result<()> {
+        for (key, entry) in self.sorted_entries() {
+            if let RootNodeEntry::Occupied(ptr) = entry {
+                let child_offset = offset + RootKey(key).origin::<CHILD>();
+                CHILD::read_vdb_buffers_in_pools(pools, ptr, child_offset, reader, compression)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn read_vdb_buffers_in_pools<R: Read>(
+        _pools: &mut [Pool],
+        _ptr: u32,
+        _offset: UVec3,
+        _reader: &mut R,
+        _compression: u32,
+    ) -> std::io::Result<()> {
+        unreachable!("Root Node is never kept in a pool!")
+    }
+}
+
 impl<CHILD: Node> std::fmt::Debug for RootNode<CHILD> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.write_str("RootNode")
@@ -184,9 +967,13 @@ impl<CHILD: Node> std::fmt::Debug for RootNode<CHILD> {
 
 pub struct RootIterator<'a, CHILD: Node> {
     pools: &'a [Pool],
-    map_iterator: std::collections::hash_map::Iter<'a, RootKey, RootNodeEntry>,
+    map_iterator: std::collections::hash_map::Iter<'a, RootKey, RootNodeEntry<CHILD::Voxel>>,
     child_iterator: Option<CHILD::Iterator<'a>>,
+    tile_iterator: Option<TileIterator>,
     location_offset: UVec3,
+    /// Voxels outside of `min` and `max` (inclusive) are skipped.
+    min: UVec3,
+    max: UVec3,
 }
 
 impl<'a, CHILD: Node> Iterator for RootIterator<'a, CHILD> {
@@ -194,22 +981,39 @@ impl<'a, CHILD: Node> Iterator for RootIterator<'a, CHILD> {
 
     fn next(&mut self) -> Option<Self::Item> {
         loop {
-            // Try taking it out from the current child
+            // Try taking it out from the current child or tile
             if let Some(item) = self.child_iterator.as_mut().and_then(|a| a.next()) {
                 return Some(item);
             }
-            // self.child_iterator is None or ran out. Grab the next child.
-            if let Some((_, root_node)) = self.map_iterator.next() {
+            if let Some(item) = self.tile_iterator.as_mut().and_then(|a| a.next()) {
+                return Some(item);
+            }
+            self.child_iterator = None;
+            self.tile_iterator = None;
+            // Ran out. Grab the next child.
+            if let Some((key, root_node)) = self.map_iterator.next() {
+                let origin = key.origin::<CHILD>();
+                let Some((min, max)) = clip_aabb(self.min, self.max, origin, CHILD::EXTENT_MASK) else {
+                    continue;
+                };
                 match root_node {
                     RootNodeEntry::Occupied(ptr) => {
-                        self.child_iterator =
-                            Some(CHILD::iter_in_pool(self.pools, *ptr, self.location_offset));
-                        continue;
+                        self.child_iterator = Some(CHILD::iter_in_aabb_in_pool(
+                            self.pools,
+                            *ptr,
+                            self.location_offset + origin,
+                            min - origin,
+                            max - origin,
+                        ));
                     }
-                    _ => {
-                        return None;
+                    RootNodeEntry::Free(_) => {
+                        self.tile_iterator = Some(TileIterator::new(
+                            self.location_offset + min,
+                            max - min + UVec3::ONE,
+                        ));
                     }
                 }
+                continue;
             } else {
                 // Also ran out. We have nothing left.
                 return None;
@@ -219,9 +1023,12 @@ impl<'a, CHILD: Node> Iterator for RootIterator<'a, CHILD> {
 }
 pub struct RootLeafIterator<'a, CHILD: Node> {
     pools: &'a [Pool],
-    map_iterator: std::collections::hash_map::Iter<'a, RootKey, RootNodeEntry>,
+    map_iterator: std::collections::hash_map::Iter<'a, RootKey, RootNodeEntry<CHILD::Voxel>>,
     child_iterator: Option<CHILD::LeafIterator<'a>>,
     location_offset: UVec3,
+    /// Leaf nodes outside of `min` and `max` (inclusive) are skipped.
+    min: UVec3,
+    max: UVec3,
 }
 
 impl<'a, CHILD: Node> Iterator for RootLeafIterator<'a, CHILD> {
@@ -234,18 +1041,26 @@ impl<'a, CHILD: Node> Iterator for RootLeafIterator<'a, CHILD> {
                 return Some(item);
             }
             // self.child_iterator is None or ran out. Grab the next child.
-            if let Some((_, root_node)) = self.map_iterator.next() {
+            if let Some((key, root_node)) = self.map_iterator.next() {
                 match root_node {
                     RootNodeEntry::Occupied(ptr) => {
-                        self.child_iterator = Some(CHILD::iter_leaf_in_pool(
+                        let origin = key.origin::<CHILD>();
+                        let Some((min, max)) = clip_aabb(self.min, self.max, origin, CHILD::EXTENT_MASK) else {
+                            continue;
+                        };
+                        self.child_iterator = Some(CHILD::iter_leaf_in_aabb_in_pool(
                             self.pools,
                             *ptr,
-                            self.location_offset,
+                            self.location_offset + origin,
+                            min - origin,
+                            max - origin,
                         ));
                         continue;
                     }
-                    _ => {
-                        return None;
+                    RootNodeEntry::Free(_) => {
+                        // Tiles do not contain any leaf nodes.
+                        self.child_iterator = None;
+                        continue;
                     }
                 }
             } else {
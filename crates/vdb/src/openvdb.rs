@@ -0,0 +1,1085 @@
+use std::io::{Read, Write};
+
+use glam::{DVec3, IVec3, UVec3};
+use miniz_oxide::{deflate::compress_to_vec_zlib, inflate::decompress_to_vec_zlib_with_limit};
+
+use crate::{serialize::invalid_data, IsRoot, Node, NodeConst, Pool, SerializableValue, Tree};
+
+/// Magic number at the start of OpenVDB files.
+const MAGIC: i64 = 0x5644_4220;
+/// Oldest supported version of the file format, the first to store compression settings per grid.
+const MIN_FILE_VERSION: u32 = 222;
+/// Version of the file format written.
+const FILE_VERSION: u32 = 224;
+/// Version of the OpenVDB library recorded in written files.
+const LIBRARY_VERSION: [u32; 2] = [10, 0];
+/// Suffix appended to the names of grids sharing the same name to make them unique.
+const UNIQUE_NAME_SEPARATOR: char = '\u{1e}';
+const HALF_FLOAT_SUFFIX: &str = "_HalfFloat";
+/// Level of the zip compression, the default level of zlib used by OpenVDB.
+const ZIP_COMPRESSION_LEVEL: u8 = 6;
+
+/// Compression flags of grids.
+const COMPRESS_ZIP: u32 = 0x1;
+const COMPRESS_ACTIVE_MASK: u32 = 0x2;
+const COMPRESS_BLOSC: u32 = 0x4;
+
+/// Flags preceding the values of each node, describing how inactive values were saved.
+const NO_MASK_OR_INACTIVE_VALS: u8 = 0;
+const NO_MASK_AND_ONE_INACTIVE_VAL: u8 = 2;
+const MASK_AND_NO_INACTIVE_VALS: u8 = 3;
+const MASK_AND_ONE_INACTIVE_VAL: u8 = 4;
+const MASK_AND_TWO_INACTIVE_VALS: u8 = 5;
+const NO_MASK_AND_ALL_VALS: u8 = 6;
+
+#[derive(Debug)]
+pub enum OpenVdbError {
+    Io(std::io::Error),
+    /// The data does not start with the magic number of OpenVDB files.
+    InvalidMagic,
+    /// The file was written with a version of the format older than 222 (OpenVDB 3.0).
+    UnsupportedVersion(u32),
+    /// The file was written as a stream without the offsets of its grids.
+    MissingGridOffsets,
+    /// The grid was compressed with Blosc, which is not supported.
+    UnsupportedCompression(u32),
+    /// The transform of the grid is not an axis-aligned scale and translation.
+    UnsupportedTransform(String),
+    /// The grid does not hold a tree of values, or holds half precision floats.
+    UnsupportedGridType(String),
+    /// The file does not contain any grid with the name.
+    GridNotFound(String),
+    /// The grid stores values of a different type than the tree.
+    ValueTypeMismatch {
+        expected: &'static str,
+        found: String,
+    },
+    /// The nodes of the grid have different sizes than the nodes of the tree.
+    /// Layouts list the log2 of the fanout of each level of nodes along each axis, from the top
+    /// down to the leaves, as in `hierarchy!(#, 5, 4, 3)`.
+    LayoutMismatch {
+        expected: Vec<u32>,
+        found: Vec<u32>,
+    },
+    /// The tree has nodes that OpenVDB can't represent. OpenVDB nodes are cubes with
+    /// at least 4 voxels on each side.
+    UnsupportedLayout,
+}
+
+fn format_layout(layout: &[u32]) -> String {
+    layout
+        .iter()
+        .map(|log2| log2.to_string())
+        .collect::<Vec<_>>()
+        .join("_")
+}
+
+impl std::fmt::Display for OpenVdbError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OpenVdbError::Io(err) => err.fmt(f),
+            OpenVdbError::InvalidMagic => f.write_str("not an OpenVDB file"),
+            OpenVdbError::UnsupportedVersion(version) => {
+                write!(f, "unsupported OpenVDB file version {}", version)
+            }
+            OpenVdbError::MissingGridOffsets => f.write_str("OpenVDB file without grid offsets"),
+            OpenVdbError::UnsupportedCompression(flags) => {
+                write!(f, "unsupported OpenVDB compression {:#x}", flags)
+            }
+            OpenVdbError::UnsupportedTransform(map) => {
+                write!(f, "unsupported OpenVDB transform {}", map)
+            }
+            OpenVdbError::UnsupportedGridType(grid_type) => {
+                write!(f, "unsupported OpenVDB grid type {}", grid_type)
+            }
+            OpenVdbError::GridNotFound(name) => write!(f, "no OpenVDB grid named {:?}", name),
+            OpenVdbError::ValueTypeMismatch { expected, found } => write!(
+                f,
+                "grid value type mismatch: expected {}, found {}",
+                expected, found
+            ),
+            OpenVdbError::LayoutMismatch { expected, found } => write!(
+                f,
+                "grid layout mismatch: expected {}, found {}",
+                format_layout(expected),
+                format_layout(found)
+            ),
+            OpenVdbError::UnsupportedLayout => {
+                f.write_str("tree layout can't be represented by OpenVDB")
+            }
+        }
+    }
+}
+
+impl std::error::Error for OpenVdbError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            OpenVdbError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for OpenVdbError {
+    fn from(err: std::io::Error) -> Self {
+        OpenVdbError::Io(err)
+    }
+}
+
+/// Iterate over the indices of the set bits of a node mask.
+fn set_bits(mask: &[usize]) -> impl Iterator<Item = usize> + '_ {
+    mask.iter().enumerate().flat_map(|(word_index, word)| {
+        let mut word = *word;
+        std::iter::from_fn(move || {
+            if word == 0 {
+                return None;
+            }
+            let bit = word.trailing_zeros() as usize;
+            word &= word - 1;
+            Some(word_index * usize::BITS as usize + bit)
+        })
+    })
+}
+
+fn read_bytes<R: Read>(reader: &mut R, len: usize) -> std::io::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    reader.take(len as u64).read_to_end(&mut bytes)?;
+    if bytes.len() != len {
+        return Err(std::io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(bytes)
+}
+
+fn read_string<R: Read>(reader: &mut R) -> std::io::Result<String> {
+    let len = u32::read_from(reader)?;
+    String::from_utf8(read_bytes(reader, len as usize)?).map_err(|_| invalid_data("invalid string"))
+}
+
+fn write_string<W: Write>(writer: &mut W, string: &str) -> std::io::Result<()> {
+    (string.len() as u32).write_to(writer)?;
+    writer.write_all(string.as_bytes())
+}
+
+/// Read an array of values, zip compressed if requested by `compression`.
+fn read_data<T: SerializableValue, R: Read>(
+    reader: &mut R,
+    count: usize,
+    compression: u32,
+) -> std::io::Result<Vec<T>> {
+    let len = count * T::SIZE;
+    let bytes = if compression & COMPRESS_ZIP != 0 {
+        // Data that didn't shrink when compressed is stored as is, with a negative size.
+        let size = i64::read_from(reader)?;
+        if size <= 0 {
+            if size.unsigned_abs() != len as u64 {
+                return Err(invalid_data("unexpected size of node values"));
+            }
+            read_bytes(reader, len)?
+        } else {
+            let compressed = read_bytes(reader, size as usize)?;
+            decompress_to_vec_zlib_with_limit(&compressed, len)
+                .map_err(|_| invalid_data("invalid zip compressed node values"))?
+        }
+    } else {
+        read_bytes(reader, len)?
+    };
+    if bytes.len() != len {
+        return Err(invalid_data("unexpected size of node values"));
+    }
+    let mut bytes = bytes.as_slice();
+    (0..count).map(|_| T::read_from(&mut bytes)).collect()
+}
+
+fn write_data<T: SerializableValue, W: Write>(
+    writer: &mut W,
+    values: impl Iterator<Item = T>,
+    compression: u32,
+) -> std::io::Result<()> {
+    let mut bytes: Vec<u8> = Vec::new();
+    for value in values {
+        value.write_to(&mut bytes)?;
+    }
+    if compression & COMPRESS_ZIP != 0 {
+        let compressed = compress_to_vec_zlib(&bytes, ZIP_COMPRESSION_LEVEL);
+        if compressed.len() < bytes.len() {
+            (compressed.len() as i64).write_to(writer)?;
+            return writer.write_all(&compressed);
+        }
+        (-(bytes.len() as i64)).write_to(writer)?;
+    }
+    writer.write_all(&bytes)
+}
+
+/// Read the values of a node with `size` entries, and return the values of the entries
+/// set on `mask` in order. Values of the other entries are skipped.
+pub(crate) fn read_active_values<T: VdbValue, R: Read>(
+    reader: &mut R,
+    mask: &[usize],
+    size: usize,
+    compression: u32,
+) -> std::io::Result<Vec<T>> {
+    let metadata = u8::read_from(reader)?;
+    if metadata > NO_MASK_AND_ALL_VALS {
+        return Err(invalid_data("invalid node value metadata"));
+    }
+    if let NO_MASK_AND_ONE_INACTIVE_VAL | MASK_AND_ONE_INACTIVE_VAL | MASK_AND_TWO_INACTIVE_VALS =
+        metadata
+    {
+        T::read_from(reader)?;
+        if metadata == MASK_AND_TWO_INACTIVE_VALS {
+            T::read_from(reader)?;
+        }
+    }
+    if let MASK_AND_NO_INACTIVE_VALS | MASK_AND_ONE_INACTIVE_VAL | MASK_AND_TWO_INACTIVE_VALS =
+        metadata
+    {
+        // Mask selecting between the inactive values.
+        read_bytes(reader, size / 8)?;
+    }
+    if compression & COMPRESS_ACTIVE_MASK != 0 && metadata != NO_MASK_AND_ALL_VALS {
+        let count = set_bits(mask).count();
+        read_data(reader, count, compression)
+    } else {
+        let values: Vec<T> = read_data(reader, size, compression)?;
+        Ok(set_bits(mask).map(|index| values[index]).collect())
+    }
+}
+
+/// Write the values of a node holding `values` on the entries set on its mask.
+/// The inactive entries are all saved with the background value.
+pub(crate) fn write_active_values<T: VdbValue, W: Write>(
+    writer: &mut W,
+    values: impl Iterator<Item = T>,
+    compression: u32,
+) -> std::io::Result<()> {
+    debug_assert!(compression & COMPRESS_ACTIVE_MASK != 0);
+    NO_MASK_OR_INACTIVE_VALS.write_to(writer)?;
+    write_data(writer, values, compression)
+}
+
+/// Voxel values of OpenVDB grids.
+pub trait VdbValue: SerializableValue + Copy + Default + PartialEq + 'static {
+    /// Name of the value type within the type names of OpenVDB trees, like `Tree_float_5_4_3`.
+    const TYPE_NAME: &'static str;
+
+    /// Write the values of a leaf node after its value mask.
+    /// `values` holds all voxels of the leaf node, `mask` is its occupancy and `origin` is the
+    /// coordinates of its first voxel in the grid.
+    fn write_leaf_values<W: Write>(
+        values: &[Self],
+        mask: &[usize],
+        _origin: IVec3,
+        writer: &mut W,
+        compression: u32,
+    ) -> std::io::Result<()> {
+        write_active_values(
+            writer,
+            set_bits(mask).map(|index| values[index]),
+            compression,
+        )
+    }
+
+    /// Read the values of a leaf node with its first voxel at `origin` into the voxels set
+    /// on `mask`.
+    fn read_leaf_values<R: Read>(
+        values: &mut [Self],
+        mask: &[usize],
+        _origin: IVec3,
+        reader: &mut R,
+        compression: u32,
+    ) -> std::io::Result<()> {
+        let active: Vec<Self> = read_active_values(reader, mask, values.len(), compression)?;
+        for (index, value) in set_bits(mask).zip(active) {
+            values[index] = value;
+        }
+        Ok(())
+    }
+}
+
+macro_rules! impl_vdb_value {
+    ($($t: ty => $name: literal),*) => {
+        $(
+            impl VdbValue for $t {
+                const TYPE_NAME: &'static str = $name;
+            }
+        )*
+    };
+}
+impl_vdb_value!(f32 => "float", f64 => "double", i32 => "int32", i64 => "int64");
+
+/// Leaf nodes of bool grids store their origin after their value mask, followed by their values
+/// as a bit mask regardless of compression.
+impl VdbValue for bool {
+    const TYPE_NAME: &'static str = "bool";
+
+    fn write_leaf_values<W: Write>(
+        values: &[Self],
+        _mask: &[usize],
+        origin: IVec3,
+        writer: &mut W,
+        _compression: u32,
+    ) -> std::io::Result<()> {
+        write_coords(writer, origin)?;
+        for chunk in values.chunks(64) {
+            let word = chunk
+                .iter()
+                .enumerate()
+                .fold(0_u64, |word, (bit, value)| word | ((*value as u64) << bit));
+            word.write_to(writer)?;
+        }
+        Ok(())
+    }
+
+    fn read_leaf_values<R: Read>(
+        values: &mut [Self],
+        mask: &[usize],
+        origin: IVec3,
+        reader: &mut R,
+        _compression: u32,
+    ) -> std::io::Result<()> {
+        if read_coords(reader)? != origin {
+            return Err(invalid_data("misplaced leaf node"));
+        }
+        for (chunk, mask) in values.chunks_mut(64).zip(mask.iter()) {
+            // Unoccupied voxels are kept at the default value.
+            let word = u64::read_from(reader)? & *mask as u64;
+            for (bit, value) in chunk.iter_mut().enumerate() {
+                *value = (word >> bit) & 1 != 0;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Nodes that can be written into and read from OpenVDB files.
+/// The topology of the nodes is written depth-first, with the values of the tiles.
+/// The values of the leaf nodes follow, in the same order.
+pub trait NodeOpenVdb: Node {
+    /// Returns the log2 of the fanout of this node and its descendants along each axis, from
+    /// the top down to the leaf nodes, as in the type names of OpenVDB trees.
+    /// Root nodes are not listed.
+    /// Returns None when a node can't be represented by OpenVDB.
+    fn vdb_layout() -> Option<Vec<u32>>;
+
+    /// Write the topology of the node and all of its descendants.
+    /// This is called when the node was owned.
+    fn write_vdb_topology<W: Write>(
+        &self,
+        pools: &[Pool],
+        writer: &mut W,
+        compression: u32,
+    ) -> std::io::Result<()>;
+    /// Write the topology of the node and all of its descendants.
+    /// This is called when the node was located in a node pool.
+    fn write_vdb_topology_in_pools<W: Write>(
+        pools: &[Pool],
+        ptr: u32,
+        writer: &mut W,
+        compression: u32,
+    ) -> std::io::Result<()>;
+    /// Write the values of the leaf nodes below the node, located at `offset`.
+    /// This is called when the node was owned.
+    fn write_vdb_buffers<W: Write>(
+        &self,
+        pools: &[Pool],
+        offset: UVec3,
+        writer: &mut W,
+        compression: u32,
+    ) -> std::io::Result<()>;
+    /// Write the values of the leaf nodes below the node, located at `offset`.
+    /// This is called when the node was located in a node pool.
+    fn write_vdb_buffers_in_pools<W: Write>(
+        pools: &[Pool],
+        ptr: u32,
+        offset: UVec3,
+        writer: &mut W,
+        compression: u32,
+    ) -> std::io::Result<()>;
+
+    /// Read the topology of the node and all of its descendants into an empty node,
+    /// allocating the descendants from the pools.
+    /// This is called when the node was owned.
+    fn read_vdb_topology<R: Read>(
+        &mut self,
+        pools: &mut [Pool],
+        reader: &mut R,
+        compression: u32,
+    ) -> std::io::Result<()>;
+    /// Read the topology of the node and all of its descendants into an empty node,
+    /// allocating the descendants from the pools.
+    /// This is called when the node was located in a node pool.
+    fn read_vdb_topology_in_pools<R: Read>(
+        pools: &mut [Pool],
+        ptr: u32,
+        reader: &mut R,
+        compression: u32,
+    ) -> std::io::Result<()>;
+    /// Read the values of the leaf nodes below the node located at `offset`, after its topology
+    /// was read.
+    /// This is called when the node was owned.
+    fn read_vdb_buffers<R: Read>(
+        &mut self,
+        pools: &mut [Pool],
+        offset: UVec3,
+        reader: &mut R,
+        compression: u32,
+    ) -> std::io::Result<()>;
+    /// Read the values of the leaf nodes below the node located at `offset`, after its topology
+    /// was read.
+    /// This is called when the node was located in a node pool.
+    fn read_vdb_buffers_in_pools<R: Read>(
+        pools: &mut [Pool],
+        ptr: u32,
+        offset: UVec3,
+        reader: &mut R,
+        compression: u32,
+    ) -> std::io::Result<()>;
+}
+
+pub(crate) fn read_coords<R: Read>(reader: &mut R) -> std::io::Result<IVec3> {
+    Ok(IVec3::new(
+        i32::read_from(reader)?,
+        i32::read_from(reader)?,
+        i32::read_from(reader)?,
+    ))
+}
+
+pub(crate) fn write_coords<W: Write>(writer: &mut W, coords: IVec3) -> std::io::Result<()> {
+    coords.x.write_to(writer)?;
+    coords.y.write_to(writer)?;
+    coords.z.write_to(writer)
+}
+
+/// Returns the value type and the layout of an OpenVDB tree type name, like `Tree_float_5_4_3`.
+fn parse_grid_type(grid_type: &str) -> Result<(&str, Vec<u32>), OpenVdbError> {
+    let unsupported = || OpenVdbError::UnsupportedGridType(grid_type.to_string());
+    if grid_type.ends_with(HALF_FLOAT_SUFFIX) {
+        return Err(unsupported());
+    }
+    let mut rest = grid_type.strip_prefix("Tree_").ok_or_else(unsupported)?;
+    let mut layout = Vec::new();
+    while let Some((head, log2)) = rest.rsplit_once('_') {
+        let Ok(log2) = log2.parse::<u32>() else {
+            break;
+        };
+        layout.insert(0, log2);
+        rest = head;
+    }
+    if layout.is_empty() {
+        return Err(unsupported());
+    }
+    Ok((rest, layout))
+}
+
+/// Transform from the coordinates of the voxels to world space.
+/// Only axis-aligned scales and translations are supported.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct VdbTransform {
+    /// Size of a voxel along each axis.
+    pub voxel_size: DVec3,
+    /// World space location of the center of the voxel at the origin.
+    pub translation: DVec3,
+}
+
+impl Default for VdbTransform {
+    fn default() -> Self {
+        Self {
+            voxel_size: DVec3::ONE,
+            translation: DVec3::ZERO,
+        }
+    }
+}
+
+fn read_dvec3<R: Read>(reader: &mut R) -> std::io::Result<DVec3> {
+    Ok(DVec3::new(
+        f64::read_from(reader)?,
+        f64::read_from(reader)?,
+        f64::read_from(reader)?,
+    ))
+}
+
+fn write_dvec3<W: Write>(writer: &mut W, vec: DVec3) -> std::io::Result<()> {
+    vec.x.write_to(writer)?;
+    vec.y.write_to(writer)?;
+    vec.z.write_to(writer)
+}
+
+impl VdbTransform {
+    fn read_from<R: Read>(reader: &mut R) -> Result<Self, OpenVdbError> {
+        let map = read_string(reader)?;
+        let mut transform = Self::default();
+        match map.as_str() {
+            "TranslationMap" => {
+                transform.translation = read_dvec3(reader)?;
+            }
+            "ScaleMap" | "UniformScaleMap" | "ScaleTranslateMap" | "UniformScaleTranslateMap" => {
+                if map.ends_with("TranslateMap") {
+                    transform.translation = read_dvec3(reader)?;
+                }
+                transform.voxel_size = read_dvec3(reader)?;
+                // Voxel size, inverse scale, inverse square scale and inverse twice scale,
+                // which are derived from the scale.
+                read_bytes(reader, 4 * 3 * 8)?;
+            }
+            "AffineMap" => {
+                // Row-major matrix transforming row vectors.
+                let mut matrix = [[0.0; 4]; 4];
+                for row in matrix.iter_mut() {
+                    for value in row.iter_mut() {
+                        *value = f64::read_from(reader)?;
+                    }
+                }
+                let axis_aligned =
+                    (0..3).all(|row| (0..3).all(|col| row == col || matrix[row][col] == 0.0));
+                if !axis_aligned {
+                    return Err(OpenVdbError::UnsupportedTransform(map));
+                }
+                transform.voxel_size = DVec3::new(matrix[0][0], matrix[1][1], matrix[2][2]);
+                transform.translation = DVec3::new(matrix[3][0], matrix[3][1], matrix[3][2]);
+            }
+            _ => return Err(OpenVdbError::UnsupportedTransform(map)),
+        }
+        Ok(transform)
+    }
+
+    fn write_to<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        let scale = self.voxel_size;
+        if scale.x == scale.y && scale.y == scale.z {
+            write_string(writer, "UniformScaleTranslateMap")?;
+        } else {
+            write_string(writer, "ScaleTranslateMap")?;
+        }
+        write_dvec3(writer, self.translation)?;
+        write_dvec3(writer, scale)?;
+        write_dvec3(writer, scale)?;
+        write_dvec3(writer, 1.0 / scale)?;
+        write_dvec3(writer, 1.0 / (scale * scale))?;
+        write_dvec3(writer, 0.5 / scale)
+    }
+}
+
+/// Metadata entries, as their name, their type name and their value.
+fn read_metadata<R: Read>(reader: &mut R) -> std::io::Result<Vec<(String, String, Vec<u8>)>> {
+    let count = u32::read_from(reader)?;
+    (0..count)
+        .map(|_| {
+            let name = read_string(reader)?;
+            let type_name = read_string(reader)?;
+            let len = u32::read_from(reader)?;
+            Ok((name, type_name, read_bytes(reader, len as usize)?))
+        })
+        .collect()
+}
+
+fn write_string_metadata<W: Write>(writer: &mut W, name: &str, value: &str) -> std::io::Result<()> {
+    write_string(writer, name)?;
+    write_string(writer, "string")?;
+    write_string(writer, value)
+}
+
+/// Description of a grid stored in an OpenVDB file.
+#[derive(Clone, Debug)]
+pub struct VdbGridInfo {
+    /// Name of the grid. Several grids of a file may have the same name.
+    pub name: String,
+    /// Type name of the tree of the grid, like `Tree_float_5_4_3`.
+    pub grid_type: String,
+    /// Class of the grid, like `level set` or `fog volume`, when recorded.
+    pub class: Option<String>,
+    pub transform: VdbTransform,
+    unique_name: String,
+    /// Unique name of the grid sharing its tree with this grid, if any.
+    instance_parent: String,
+    compression: u32,
+    /// Offset of the topology of the tree within the file.
+    topology_offset: usize,
+}
+
+/// An OpenVDB file loaded in memory, from which grids may be read into trees.
+/// The reader follows the format of OpenVDB 3.0 and later, uncompressed or zip compressed, but it
+/// has only been tested against files written by [`VdbWriter`] so far.
+/// ```
+/// #![feature(generic_const_exprs)]
+/// use dust_vdb::{hierarchy, Tree, VdbCompression, VdbFile, VdbTransform, VdbWriter};
+/// use glam::{DVec3, IVec3};
+/// let mut density = Tree::<hierarchy!(#, 5, 4, 3; f32)>::new();
+/// density.set_value(IVec3::new(-1, 2, 3), Some(0.5));
+/// let mut mask = Tree::<hierarchy!(#, 5, 4, 3)>::new();
+/// mask.fill(IVec3::ZERO, IVec3::splat(7), Some(true));
+///
+/// let mut writer = VdbWriter::new(VdbCompression::Zip);
+/// let transform = VdbTransform {
+///     voxel_size: DVec3::splat(0.1),
+///     translation: DVec3::ZERO,
+/// };
+/// writer.add_grid("density", &density, transform).unwrap();
+/// writer.add_grid("mask", &mask, VdbTransform::default()).unwrap();
+/// let mut data: Vec<u8> = Vec::new();
+/// writer.write_to(&mut data).unwrap();
+///
+/// let file = VdbFile::read_from(&mut data.as_slice()).unwrap();
+/// assert_eq!(file.grids()[0].name, "density");
+/// assert_eq!(file.grids()[0].grid_type, "Tree_float_5_4_3");
+/// assert_eq!(file.grids()[0].transform, transform);
+/// let density = file.read_grid::<hierarchy!(#, 5, 4, 3; f32)>("density").unwrap();
+/// assert_eq!(density.get_value(IVec3::new(-1, 2, 3)), Some(0.5));
+/// let mask = file.read_grid::<hierarchy!(#, 5, 4, 3)>("mask").unwrap();
+/// assert_eq!(mask.count_active(), 512);
+/// ```
+pub struct VdbFile {
+    data: Vec<u8>,
+    grids: Vec<VdbGridInfo>,
+}
+
+impl VdbFile {
+    /// Read a whole OpenVDB file from `reader`, and the descriptions of its grids.
+    pub fn read_from<R: Read>(reader: &mut R) -> Result<Self, OpenVdbError> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        let mut reader = data.as_slice();
+        if i64::read_from(&mut reader)? != MAGIC {
+            return Err(OpenVdbError::InvalidMagic);
+        }
+        let version = u32::read_from(&mut reader)?;
+        if version < MIN_FILE_VERSION {
+            return Err(OpenVdbError::UnsupportedVersion(version));
+        }
+        // Library version.
+        read_bytes(&mut reader, 8)?;
+        if u8::read_from(&mut reader)? == 0 {
+            return Err(OpenVdbError::MissingGridOffsets);
+        }
+        // UUID of the file, as text.
+        read_bytes(&mut reader, 36)?;
+        read_metadata(&mut reader)?;
+
+        let count = i32::read_from(&mut reader)?;
+        let mut grids = Vec::with_capacity(count.max(0) as usize);
+        for _ in 0..count {
+            let unique_name = read_string(&mut reader)?;
+            let grid_type = read_string(&mut reader)?;
+            let instance_parent = read_string(&mut reader)?;
+            let grid_offset = i64::read_from(&mut reader)?;
+            let _block_offset = i64::read_from(&mut reader)?;
+            let end_offset = i64::read_from(&mut reader)?;
+            let grid_offset = usize::try_from(grid_offset)
+                .ok()
+                .filter(|offset| *offset <= data.len())
+                .ok_or_else(|| invalid_data("grid offset out of bounds"))?;
+            let end_offset = usize::try_from(end_offset)
+                .ok()
+                .filter(|offset| *offset <= data.len())
+                .ok_or_else(|| invalid_data("grid offset out of bounds"))?;
+
+            let mut grid_reader = &data[grid_offset..];
+            let compression = u32::read_from(&mut grid_reader)?;
+            let class = read_metadata(&mut grid_reader)?
+                .into_iter()
+                .find(|(name, type_name, _)| name == "class" && type_name == "string")
+                .and_then(|(_, _, value)| String::from_utf8(value).ok());
+            let transform = VdbTransform::read_from(&mut grid_reader)?;
+            grids.push(VdbGridInfo {
+                name: unique_name
+                    .split(UNIQUE_NAME_SEPARATOR)
+                    .next()
+                    .unwrap_or_default()
+                    .to_string(),
+                grid_type,
+                class,
+                transform,
+                unique_name,
+                instance_parent,
+                compression,
+                topology_offset: data.len() - grid_reader.len(),
+            });
+            reader = &data[end_offset..];
+        }
+        Ok(Self { data, grids })
+    }
+
+    pub fn grids(&self) -> &[VdbGridInfo] {
+        &self.grids
+    }
+
+    /// Read the first grid named `name` into a tree. The grid must store values of the same type
+    /// as the tree, in nodes of the same sizes.
+    /// Only active voxels and tiles are read. Inactive values, like the inside of level sets,
+    /// are dropped.
+    pub fn read_grid<ROOT: IsRoot + NodeOpenVdb>(
+        &self,
+        name: &str,
+    ) -> Result<Tree<ROOT>, OpenVdbError>
+    where
+        ROOT: ~const NodeConst,
+        [(); ROOT::LEVEL as usize + 1]: Sized,
+        ROOT::Voxel: VdbValue,
+    {
+        let grid = self
+            .grids
+            .iter()
+            .find(|grid| grid.name == name)
+            .ok_or_else(|| OpenVdbError::GridNotFound(name.to_string()))?;
+        self.read_grid_tree(grid)
+    }
+
+    fn read_grid_tree<ROOT: IsRoot + NodeOpenVdb>(
+        &self,
+        grid: &VdbGridInfo,
+    ) -> Result<Tree<ROOT>, OpenVdbError>
+    where
+        ROOT: ~const NodeConst,
+        [(); ROOT::LEVEL as usize + 1]: Sized,
+        ROOT::Voxel: VdbValue,
+    {
+        // Instances share the tree of another grid.
+        let grid = if grid.instance_parent.is_empty() {
+            grid
+        } else {
+            self.grids
+                .iter()
+                .find(|parent| parent.unique_name == grid.instance_parent)
+                .ok_or_else(|| OpenVdbError::GridNotFound(grid.instance_parent.clone()))?
+        };
+        let (value_type, layout) = parse_grid_type(&grid.grid_type)?;
+        if value_type != ROOT::Voxel::TYPE_NAME {
+            return Err(OpenVdbError::ValueTypeMismatch {
+                expected: ROOT::Voxel::TYPE_NAME,
+                found: value_type.to_string(),
+            });
+        }
+        let expected = ROOT::vdb_layout().ok_or(OpenVdbError::UnsupportedLayout)?;
+        if layout != expected {
+            return Err(OpenVdbError::LayoutMismatch {
+                expected,
+                found: layout,
+            });
+        }
+        if grid.compression & COMPRESS_BLOSC != 0 {
+            return Err(OpenVdbError::UnsupportedCompression(grid.compression));
+        }
+
+        let mut reader = &self.data[grid.topology_offset..];
+        // Number of buffers per leaf node, which is always 1.
+        i32::read_from(&mut reader)?;
+        let mut tree = Tree::<ROOT>::new();
+        tree.root
+            .read_vdb_topology(&mut tree.pool, &mut reader, grid.compression)?;
+        tree.root
+            .read_vdb_buffers(&mut tree.pool, UVec3::ZERO, &mut reader, grid.compression)?;
+        // Nodes with inactive voxels only are left empty.
+        tree.prune();
+        Ok(tree)
+    }
+}
+
+/// Compression of the values of the grids written into OpenVDB files.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum VdbCompression {
+    /// Only the values of active voxels are written.
+    #[default]
+    None,
+    /// The values of active voxels are zip compressed.
+    Zip,
+}
+
+struct EncodedGrid {
+    unique_name: String,
+    grid_type: String,
+    /// Metadata, transform and topology of the grid.
+    header: Vec<u8>,
+    /// Values of the leaf nodes of the grid.
+    buffers: Vec<u8>,
+}
+
+/// Writer of OpenVDB files, which holds the added grids until the file is written.
+/// See [`VdbFile`].
+pub struct VdbWriter {
+    compression: VdbCompression,
+    grids: Vec<EncodedGrid>,
+}
+
+impl VdbWriter {
+    pub fn new(compression: VdbCompression) -> Self {
+        Self {
+            compression,
+            grids: Vec::new(),
+        }
+    }
+
+    /// Add a grid named `name` holding the voxels of `tree`.
+    pub fn add_grid<ROOT: IsRoot + NodeOpenVdb>(
+        &mut self,
+        name: &str,
+        tree: &Tree<ROOT>,
+        transform: VdbTransform,
+    ) -> Result<(), OpenVdbError>
+    where
+        [(); ROOT::LEVEL as usize + 1]: Sized,
+        ROOT::Voxel: VdbValue,
+    {
+        let layout = ROOT::vdb_layout().ok_or(OpenVdbError::UnsupportedLayout)?;
+        let grid_type = format!("Tree_{}_{}", ROOT::Voxel::TYPE_NAME, format_layout(&layout));
+        let duplicates = self
+            .grids
+            .iter()
+            .filter(|grid| grid.unique_name.split(UNIQUE_NAME_SEPARATOR).next() == Some(name))
+            .count();
+        let unique_name = if duplicates == 0 {
+            name.to_string()
+        } else {
+            format!("{}{}{}", name, UNIQUE_NAME_SEPARATOR, duplicates)
+        };
+
+        let compression = match self.compression {
+            VdbCompression::None => COMPRESS_ACTIVE_MASK,
+            VdbCompression::Zip => COMPRESS_ACTIVE_MASK | COMPRESS_ZIP,
+        };
+        let mut header: Vec<u8> = Vec::new();
+        compression.write_to(&mut header)?;
+        2_u32.write_to(&mut header)?;
+        write_string_metadata(&mut header, "class", "unknown")?;
+        write_string_metadata(&mut header, "name", name)?;
+        transform.write_to(&mut header)?;
+        1_i32.write_to(&mut header)?;
+        tree.root
+            .write_vdb_topology(&tree.pool, &mut header, compression)?;
+        let mut buffers: Vec<u8> = Vec::new();
+        tree.root
+            .write_vdb_buffers(&tree.pool, UVec3::ZERO, &mut buffers, compression)?;
+        self.grids.push(EncodedGrid {
+            unique_name,
+            grid_type,
+            header,
+            buffers,
+        });
+        Ok(())
+    }
+
+    /// Write the file with all added grids into `writer`.
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> Result<(), OpenVdbError> {
+        let mut data: Vec<u8> = Vec::new();
+        MAGIC.write_to(&mut data)?;
+        FILE_VERSION.write_to(&mut data)?;
+        LIBRARY_VERSION[0].write_to(&mut data)?;
+        LIBRARY_VERSION[1].write_to(&mut data)?;
+        // The offsets of the grids are recorded.
+        1_u8.write_to(&mut data)?;
+        data.extend_from_slice(generate_uuid().as_bytes());
+        // File metadata.
+        0_u32.write_to(&mut data)?;
+        (self.grids.len() as i32).write_to(&mut data)?;
+        for grid in self.grids.iter() {
+            write_string(&mut data, &grid.unique_name)?;
+            write_string(&mut data, &grid.grid_type)?;
+            // Grids are never written as instances of other grids.
+            write_string(&mut data, "")?;
+            let grid_offset = data.len() + 3 * 8;
+            let block_offset = grid_offset + grid.header.len();
+            let end_offset = block_offset + grid.buffers.len();
+            (grid_offset as i64).write_to(&mut data)?;
+            (block_offset as i64).write_to(&mut data)?;
+            (end_offset as i64).write_to(&mut data)?;
+            data.extend_from_slice(&grid.header);
+            data.extend_from_slice(&grid.buffers);
+        }
+        writer.write_all(&data)?;
+        Ok(())
+    }
+}
+
+/// Random UUID identifying a written file.
+fn generate_uuid() -> String {
+    use std::hash::{BuildHasher, Hasher};
+    let mut hasher = std::collections::hash_map::RandomState::new().build_hasher();
+    if let Ok(time) = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH) {
+        hasher.write_u128(time.as_nanos());
+    }
+    let high = hasher.finish();
+    hasher.write_u8(0);
+    let low = hasher.finish();
+    format!(
+        "{:08x}-{:04x}-4{:03x}-{:04x}-{:012x}",
+        high >> 32,
+        (high >> 16) & 0xffff,
+        high & 0xfff,
+        0x8000 | ((low >> 48) & 0x3fff),
+        low & 0xffff_ffff_ffff
+    )
+}
+
+/// Reading and writing single grids, for files holding a single tree.
+impl<ROOT: IsRoot + NodeOpenVdb> Tree<ROOT>
+where
+    [(); ROOT::LEVEL as usize + 1]: Sized,
+    ROOT::Voxel: VdbValue,
+{
+    /// Write the tree into `writer` as an OpenVDB file containing a single grid named `name`.
+    /// See [`VdbWriter`] to write several grids into the same file.
+    /// ```
+    /// #![feature(generic_const_exprs)]
+    /// use dust_vdb::{hierarchy, Tree};
+    /// use glam::IVec3;
+    /// let mut tree = Tree::<hierarchy!(#, 5, 4, 3; f32)>::new();
+    /// tree.set_value(IVec3::new(1, -2, 3), Some(1.5));
+    /// tree.fill(IVec3::splat(-4096), IVec3::splat(-1), Some(2.0));
+    ///
+    /// let mut data: Vec<u8> = Vec::new();
+    /// tree.write_vdb(&mut data, "density").unwrap();
+    /// let tree = Tree::<hierarchy!(#, 5, 4, 3; f32)>::read_vdb(&mut data.as_slice()).unwrap();
+    /// assert_eq!(tree.get_value(IVec3::new(1, -2, 3)), Some(1.5));
+    /// assert_eq!(tree.get_value(IVec3::splat(-100)), Some(2.0));
+    /// assert_eq!(tree.count_active(), 1 + 4096 * 4096 * 4096);
+    /// ```
+    pub fn write_vdb<W: Write>(&self, writer: &mut W, name: &str) -> Result<(), OpenVdbError> {
+        let mut vdb_writer = VdbWriter::new(VdbCompression::None);
+        vdb_writer.add_grid(name, self, VdbTransform::default())?;
+        vdb_writer.write_to(writer)
+    }
+
+    /// Read the first grid of an OpenVDB file from `reader`. See [`VdbFile::read_grid`].
+    pub fn read_vdb<R: Read>(reader: &mut R) -> Result<Self, OpenVdbError>
+    where
+        ROOT: ~const NodeConst,
+    {
+        let file = VdbFile::read_from(reader)?;
+        let grid = file
+            .grids
+            .first()
+            .ok_or_else(|| OpenVdbError::GridNotFound(String::new()))?;
+        file.read_grid_tree(grid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::IVec3;
+
+    use super::{
+        read_active_values, OpenVdbError, VdbCompression, VdbFile, VdbTransform, VdbWriter,
+        COMPRESS_ACTIVE_MASK, COMPRESS_ZIP, MASK_AND_TWO_INACTIVE_VALS, NO_MASK_AND_ALL_VALS,
+    };
+    use crate::{hierarchy, SerializableValue, Tree};
+
+    type FloatRoot = hierarchy!(#, 5, 4, 3; f32);
+    type BoolRoot = hierarchy!(#, 5, 4, 3);
+
+    #[test]
+    fn test_round_trip() {
+        use rand::prelude::*;
+        let mut rng = StdRng::seed_from_u64(4);
+        let mut floats = Tree::<FloatRoot>::new();
+        let mut bools = Tree::<BoolRoot>::new();
+        for _ in 0..2000 {
+            let coords = IVec3::new(
+                rng.gen_range(-300..300),
+                rng.gen_range(-300..300),
+                rng.gen_range(-300..300),
+            );
+            floats.set_value(coords, Some(rng.gen()));
+            bools.set_value(coords, Some(rng.gen()));
+        }
+        // Tiles of leaf nodes, internal nodes and root node.
+        floats.fill(IVec3::splat(512), IVec3::splat(527), Some(3.0));
+        bools.fill(IVec3::splat(-4096), IVec3::splat(-1), Some(true));
+
+        for compression in [VdbCompression::None, VdbCompression::Zip] {
+            let mut writer = VdbWriter::new(compression);
+            let transform = VdbTransform {
+                voxel_size: glam::DVec3::new(0.5, 0.5, 2.0),
+                translation: glam::DVec3::new(1.0, 2.0, 3.0),
+            };
+            writer.add_grid("floats", &floats, transform).unwrap();
+            writer
+                .add_grid("bools", &bools, VdbTransform::default())
+                .unwrap();
+            writer
+                .add_grid("floats", &Tree::<FloatRoot>::new(), VdbTransform::default())
+                .unwrap();
+            let mut data: Vec<u8> = Vec::new();
+            writer.write_to(&mut data).unwrap();
+
+            let file = VdbFile::read_from(&mut data.as_slice()).unwrap();
+            let names: Vec<&str> = file.grids().iter().map(|grid| grid.name.as_str()).collect();
+            assert_eq!(names, ["floats", "bools", "floats"]);
+            assert_eq!(file.grids()[0].transform, transform);
+            assert_eq!(file.grids()[1].grid_type, "Tree_bool_5_4_3");
+
+            let read_floats = file.read_grid::<FloatRoot>("floats").unwrap();
+            assert_eq!(read_floats.count_active(), floats.count_active());
+            for coords in floats.iter_signed() {
+                assert_eq!(read_floats.get_value(coords), floats.get_value(coords));
+            }
+            let read_bools = file.read_grid::<BoolRoot>("bools").unwrap();
+            assert_eq!(read_bools.count_active(), bools.count_active());
+            for coords in bools.iter_leaf().flat_map(|(origin, _)| {
+                (0..512).map(move |i| origin + glam::UVec3::new(i >> 6, (i >> 3) & 7, i & 7))
+            }) {
+                assert_eq!(read_bools.get_value(coords), bools.get_value(coords));
+            }
+        }
+    }
+
+    #[test]
+    fn test_mismatches() {
+        let mut tree = Tree::<FloatRoot>::new();
+        tree.set_value(IVec3::new(1, 2, 3), Some(1.0));
+        let mut data: Vec<u8> = Vec::new();
+        tree.write_vdb(&mut data, "density").unwrap();
+
+        assert!(matches!(
+            Tree::<BoolRoot>::read_vdb(&mut data.as_slice()),
+            Err(OpenVdbError::ValueTypeMismatch {
+                expected: "bool",
+                ..
+            })
+        ));
+        match Tree::<hierarchy!(#, 4, 3; f32)>::read_vdb(&mut data.as_slice()) {
+            Err(OpenVdbError::LayoutMismatch { expected, found }) => {
+                assert_eq!(expected, [4, 3]);
+                assert_eq!(found, [5, 4, 3]);
+            }
+            _ => panic!("expected a layout mismatch"),
+        }
+        let file = VdbFile::read_from(&mut data.as_slice()).unwrap();
+        assert!(matches!(
+            file.read_grid::<FloatRoot>("temperature"),
+            Err(OpenVdbError::GridNotFound(_))
+        ));
+        assert!(matches!(
+            Tree::<FloatRoot>::read_vdb(&mut &data[1..]),
+            Err(OpenVdbError::InvalidMagic)
+        ));
+    }
+
+    #[test]
+    fn test_read_inactive_values() {
+        // A node of 64 values, the first 2 being active.
+        let mask = [0b11_usize];
+        let mut data: Vec<u8> = Vec::new();
+        data.push(MASK_AND_TWO_INACTIVE_VALS);
+        5.0_f32.write_to(&mut data).unwrap();
+        (-5.0_f32).write_to(&mut data).unwrap();
+        data.extend_from_slice(&[0xff; 8]);
+        1.0_f32.write_to(&mut data).unwrap();
+        2.0_f32.write_to(&mut data).unwrap();
+        let values: Vec<f32> =
+            read_active_values(&mut data.as_slice(), &mask, 64, COMPRESS_ACTIVE_MASK).unwrap();
+        assert_eq!(values, [1.0, 2.0]);
+
+        let mut data: Vec<u8> = vec![NO_MASK_AND_ALL_VALS];
+        for i in 0..64 {
+            (i as f32).write_to(&mut data).unwrap();
+        }
+        let values: Vec<f32> = read_active_values(&mut data.as_slice(), &[0b101], 64, 0).unwrap();
+        assert_eq!(values, [0.0, 2.0]);
+
+        // Zip compressed values decompressing past the size of the node are rejected.
+        let compressed = miniz_oxide::deflate::compress_to_vec_zlib(&[0; 64 * 4 + 1], 6);
+        let mut data: Vec<u8> = vec![NO_MASK_AND_ALL_VALS];
+        (compressed.len() as i64).write_to(&mut data).unwrap();
+        data.extend_from_slice(&compressed);
+        let result: std::io::Result<Vec<f32>> =
+            read_active_values(&mut data.as_slice(), &[0b101], 64, COMPRESS_ZIP);
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,835 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs::{File, OpenOptions},
+    io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use glam::{IVec3, UVec3};
+
+use crate::{
+    coords::{from_signed, to_signed},
+    serialize::invalid_data,
+    tree::TreeMeta,
+    NodeConst, PagedRoot, SerializableValue, SerializeError, Tree, TreeCoords,
+};
+
+/// Backing store for the tiles evicted from a [`PagedTree`], keyed by the location of each tile
+/// of the root node in signed coordinates divided by the extent of the tiles.
+///
+/// All methods take `&self`, so that a store shared through [`PagedTree::store`] can be read
+/// from loader threads while the tree keeps being edited.
+pub trait TileStore: Send + Sync {
+    /// Returns the data stored for the tile at `key`, or None if the tile was never stored.
+    fn load(&self, key: IVec3) -> io::Result<Option<Vec<u8>>>;
+    /// Store `data` for the tile at `key`, replacing any previous data.
+    fn save(&self, key: IVec3, data: &[u8]) -> io::Result<()>;
+    /// Forget the tile at `key`. Removing a tile that was never stored is not an error.
+    fn remove(&self, key: IVec3) -> io::Result<()>;
+}
+
+/// Keeps the tiles in memory, mostly useful for tests.
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    tiles: Mutex<HashMap<IVec3, Vec<u8>>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the number of stored tiles.
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<IVec3, Vec<u8>>> {
+        self.tiles
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl TileStore for MemoryStore {
+    fn load(&self, key: IVec3) -> io::Result<Option<Vec<u8>>> {
+        Ok(self.lock().get(&key).cloned())
+    }
+    fn save(&self, key: IVec3, data: &[u8]) -> io::Result<()> {
+        self.lock().insert(key, data.to_vec());
+        Ok(())
+    }
+    fn remove(&self, key: IVec3) -> io::Result<()> {
+        self.lock().remove(&key);
+        Ok(())
+    }
+}
+
+/// Stores each tile in its own file within a directory, named after the key of the tile.
+/// Files are written under a temporary name first, so that an interrupted write never leaves
+/// a truncated tile behind.
+#[derive(Debug, Clone)]
+pub struct DirectoryStore {
+    path: PathBuf,
+}
+
+impl DirectoryStore {
+    /// Open the store in the directory at `path`, creating it if needed.
+    pub fn open(path: impl Into<PathBuf>) -> io::Result<Self> {
+        let path = path.into();
+        std::fs::create_dir_all(&path)?;
+        Ok(Self { path })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn tile_path(&self, key: IVec3) -> PathBuf {
+        self.path
+            .join(format!("{}_{}_{}.tile", key.x, key.y, key.z))
+    }
+}
+
+impl TileStore for DirectoryStore {
+    fn load(&self, key: IVec3) -> io::Result<Option<Vec<u8>>> {
+        match std::fs::read(self.tile_path(key)) {
+            Ok(data) => Ok(Some(data)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+    fn save(&self, key: IVec3, data: &[u8]) -> io::Result<()> {
+        let path = self.tile_path(key);
+        let temp_path = path.with_extension("tile.tmp");
+        std::fs::write(&temp_path, data)?;
+        std::fs::rename(temp_path, path)
+    }
+    fn remove(&self, key: IVec3) -> io::Result<()> {
+        match std::fs::remove_file(self.tile_path(key)) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Magic number at the start of pack files.
+const PACK_MAGIC: [u8; 4] = *b"DPAK";
+/// Version of the pack file format.
+const PACK_VERSION: u32 = 1;
+/// Length recorded in place of the data length for removed tiles.
+const PACK_REMOVED: u32 = u32::MAX;
+/// Size of the key and length preceding the data of each record.
+const PACK_RECORD_HEADER: u64 = 16;
+
+/// Stores all tiles in a single append-only file.
+///
+/// The file starts with a header, followed by records made of the key of a tile, the length of
+/// its data and the data itself. Saving or removing a tile appends a record, and the last record
+/// of each tile wins when the file is opened. Records cut short by an interrupted write are
+/// dropped when opening the file. Superseded records are only reclaimed by
+/// [`PackFileStore::compact`].
+pub struct PackFileStore {
+    path: PathBuf,
+    inner: Mutex<PackFile>,
+}
+
+struct PackFile {
+    file: File,
+    /// Offset and length of the data of each tile.
+    index: HashMap<IVec3, (u64, u32)>,
+    /// Length of the file.
+    end: u64,
+    /// Bytes taken by superseded records.
+    garbage: u64,
+}
+
+impl PackFile {
+    fn open(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .open(path)?;
+        let len = file.metadata()?.len();
+        let mut pack = PackFile {
+            file,
+            index: HashMap::new(),
+            end: 0,
+            garbage: 0,
+        };
+        if len == 0 {
+            pack.file.write_all(&PACK_MAGIC)?;
+            PACK_VERSION.write_to(&mut pack.file)?;
+            pack.end = 8;
+            return Ok(pack);
+        }
+
+        let mut reader = BufReader::new(&mut pack.file);
+        let mut magic = [0_u8; 4];
+        reader.read_exact(&mut magic)?;
+        if magic != PACK_MAGIC {
+            return Err(invalid_data("not a pack file"));
+        }
+        if u32::read_from(&mut reader)? != PACK_VERSION {
+            return Err(invalid_data("unsupported pack file version"));
+        }
+        let mut offset = 8;
+        while offset + PACK_RECORD_HEADER <= len {
+            let key = IVec3 {
+                x: i32::read_from(&mut reader)?,
+                y: i32::read_from(&mut reader)?,
+                z: i32::read_from(&mut reader)?,
+            };
+            let data_len = u32::read_from(&mut reader)?;
+            let stored_len = if data_len == PACK_REMOVED {
+                0
+            } else {
+                data_len as u64
+            };
+            let data_offset = offset + PACK_RECORD_HEADER;
+            if data_offset + stored_len > len {
+                break;
+            }
+            reader.seek_relative(stored_len as i64)?;
+            let previous = if data_len == PACK_REMOVED {
+                pack.garbage += PACK_RECORD_HEADER;
+                pack.index.remove(&key)
+            } else {
+                pack.index.insert(key, (data_offset, data_len))
+            };
+            if let Some((_, previous_len)) = previous {
+                pack.garbage += PACK_RECORD_HEADER + previous_len as u64;
+            }
+            offset = data_offset + stored_len;
+        }
+        drop(reader);
+        if offset < len {
+            pack.file.set_len(offset)?;
+        }
+        pack.end = offset;
+        Ok(pack)
+    }
+
+    fn append(&mut self, key: IVec3, data_len: u32, data: &[u8]) -> io::Result<u64> {
+        let offset = self.end;
+        self.file.seek(SeekFrom::Start(offset))?;
+        let mut writer = BufWriter::new(&mut self.file);
+        key.x.write_to(&mut writer)?;
+        key.y.write_to(&mut writer)?;
+        key.z.write_to(&mut writer)?;
+        data_len.write_to(&mut writer)?;
+        writer.write_all(data)?;
+        writer.flush()?;
+        self.end = offset + PACK_RECORD_HEADER + data.len() as u64;
+        Ok(offset + PACK_RECORD_HEADER)
+    }
+}
+
+impl PackFileStore {
+    /// Open the pack file at `path`, creating it if needed.
+    pub fn open(path: impl Into<PathBuf>) -> io::Result<Self> {
+        let path = path.into();
+        let pack = PackFile::open(&path)?;
+        Ok(Self {
+            path,
+            inner: Mutex::new(pack),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the number of bytes taken by superseded records.
+    pub fn wasted_bytes(&self) -> u64 {
+        self.lock().garbage
+    }
+
+    /// Rewrite the file with only the latest data of each tile.
+    /// The new file is written next to the current one before replacing it.
+    pub fn compact(&self) -> io::Result<()> {
+        let mut pack = self.lock();
+        let temp_path = self.path.with_extension("tmp");
+        let _ = std::fs::remove_file(&temp_path);
+        let mut compacted = PackFile::open(&temp_path)?;
+        let mut entries: Vec<(IVec3, (u64, u32))> = pack
+            .index
+            .iter()
+            .map(|(key, entry)| (*key, *entry))
+            .collect();
+        entries.sort_unstable_by_key(|(_, (offset, _))| *offset);
+        for (key, (offset, len)) in entries {
+            let mut data = vec![0; len as usize];
+            pack.file.seek(SeekFrom::Start(offset))?;
+            pack.file.read_exact(&mut data)?;
+            let offset = compacted.append(key, len, &data)?;
+            compacted.index.insert(key, (offset, len));
+        }
+        compacted.file.sync_all()?;
+        std::fs::rename(&temp_path, &self.path)?;
+        *pack = compacted;
+        Ok(())
+    }
+
+    fn lock(&self) -> MutexGuard<'_, PackFile> {
+        self.inner
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl TileStore for PackFileStore {
+    fn load(&self, key: IVec3) -> io::Result<Option<Vec<u8>>> {
+        let mut pack = self.lock();
+        let Some((offset, len)) = pack.index.get(&key).copied() else {
+            return Ok(None);
+        };
+        let mut data = vec![0; len as usize];
+        pack.file.seek(SeekFrom::Start(offset))?;
+        pack.file.read_exact(&mut data)?;
+        Ok(Some(data))
+    }
+    fn save(&self, key: IVec3, data: &[u8]) -> io::Result<()> {
+        let len: u32 = data
+            .len()
+            .try_into()
+            .ok()
+            .filter(|len| *len != PACK_REMOVED)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "tile data too large"))?;
+        let mut pack = self.lock();
+        let offset = pack.append(key, len, data)?;
+        if let Some((_, previous_len)) = pack.index.insert(key, (offset, len)) {
+            pack.garbage += PACK_RECORD_HEADER + previous_len as u64;
+        }
+        Ok(())
+    }
+    fn remove(&self, key: IVec3) -> io::Result<()> {
+        let mut pack = self.lock();
+        if let Some((_, previous_len)) = pack.index.remove(&key) {
+            pack.append(key, PACK_REMOVED, &[])?;
+            pack.garbage += 2 * PACK_RECORD_HEADER + previous_len as u64;
+        }
+        Ok(())
+    }
+}
+
+/// A tree whose tiles of the root node are paged in and out of a [`TileStore`].
+///
+/// Tiles are loaded on first access, and the least recently used ones are evicted once more
+/// than `capacity` tiles are in memory. Evicted tiles are only written back when modified since
+/// they were loaded, and tiles left empty are removed from the store. Regions can also be loaded
+/// and unloaded explicitly with [`PagedTree::load_region`] and [`PagedTree::unload_region`].
+///
+/// Modified tiles still in memory are not written back when the tree is dropped.
+/// Call [`PagedTree::flush`] to save them.
+/// ```
+/// #![feature(generic_const_exprs)]
+/// use dust_vdb::{MemoryStore, PagedTree, hierarchy};
+/// use glam::IVec3;
+/// let mut tree = PagedTree::<hierarchy!(#, 3, 2; u8), _>::new(MemoryStore::new(), 2);
+/// for x in 0..4 {
+///     tree.set_value(IVec3::new(x * 32, 0, 0), Some(x as u8)).unwrap();
+/// }
+/// // Only the two tiles used last are still in memory.
+/// assert_eq!(tree.resident_tiles().count(), 2);
+/// assert_eq!(tree.store().len(), 2);
+/// assert_eq!(tree.get_value(IVec3::new(0, 0, 0)).unwrap(), Some(0));
+/// ```
+pub struct PagedTree<ROOT: PagedRoot, S: TileStore>
+where
+    [(); ROOT::LEVEL as usize]: Sized,
+{
+    tree: Tree<ROOT>,
+    store: Arc<S>,
+    /// Keys of the tiles in memory, with the time of their last use.
+    resident: HashMap<IVec3, u64>,
+    /// Keys of the tiles modified since they were loaded.
+    modified: HashSet<IVec3>,
+    clock: u64,
+    capacity: usize,
+}
+
+impl<ROOT: PagedRoot, S: TileStore> PagedTree<ROOT, S>
+where
+    [(); ROOT::LEVEL as usize + 1]: Sized,
+    ROOT::Voxel: SerializableValue,
+{
+    /// Create an empty tree keeping up to `capacity` tiles in memory, backed by `store`.
+    /// Tiles already in `store` are loaded when accessed.
+    pub fn new(store: S, capacity: usize) -> Self
+    where
+        ROOT: ~const NodeConst,
+    {
+        Self::with_shared_store(Arc::new(store), capacity)
+    }
+
+    pub fn with_shared_store(store: Arc<S>, capacity: usize) -> Self
+    where
+        ROOT: ~const NodeConst,
+    {
+        assert!(capacity > 0, "A paged tree needs to keep at least one tile");
+        Self {
+            tree: Tree::new(),
+            store,
+            resident: HashMap::new(),
+            modified: HashSet::new(),
+            clock: 0,
+            capacity,
+        }
+    }
+
+    pub fn store(&self) -> &Arc<S> {
+        &self.store
+    }
+
+    /// The tiles currently in memory. Voxels of evicted tiles are missing from it.
+    pub fn tree(&self) -> &Tree<ROOT> {
+        &self.tree
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Change the number of tiles kept in memory, evicting tiles if needed.
+    pub fn set_capacity(&mut self, capacity: usize) -> Result<(), SerializeError>
+    where
+        ROOT: ~const NodeConst,
+    {
+        assert!(capacity > 0, "A paged tree needs to keep at least one tile");
+        self.capacity = capacity;
+        self.evict_over_capacity()
+    }
+
+    /// Returns the keys of the tiles in memory.
+    pub fn resident_tiles(&self) -> impl Iterator<Item = IVec3> + '_ {
+        self.resident.keys().copied()
+    }
+
+    /// Returns the key of the tile containing `coords`, as used by the [`TileStore`].
+    pub fn tile_key(coords: impl TreeCoords<ROOT>) -> IVec3 {
+        to_signed(coords.to_tree_coords()) >> ROOT::TILE_EXTENT_LOG2.as_ivec3()
+    }
+
+    /// Returns the minimum and maximum coordinates (inclusive) of the tile at `key`.
+    fn tile_bounds(key: IVec3) -> (UVec3, UVec3) {
+        let origin = from_signed(key << ROOT::TILE_EXTENT_LOG2.as_ivec3());
+        (
+            origin,
+            origin + ((UVec3::ONE << ROOT::TILE_EXTENT_LOG2) - 1),
+        )
+    }
+
+    /// Returns the keys of all tiles intersecting the box within `min` and `max` (inclusive).
+    fn region_keys<C: TreeCoords<ROOT>>(min: C, max: C) -> Vec<IVec3> {
+        let (min, max) = (Self::tile_key(min), Self::tile_key(max));
+        let mut keys = Vec::new();
+        for x in min.x..=max.x {
+            for y in min.y..=max.y {
+                for z in min.z..=max.z {
+                    keys.push(IVec3 { x, y, z });
+                }
+            }
+        }
+        keys
+    }
+
+    pub fn get_value(
+        &mut self,
+        coords: impl TreeCoords<ROOT>,
+    ) -> Result<Option<ROOT::Voxel>, SerializeError>
+    where
+        ROOT: ~const NodeConst,
+    {
+        self.fault(Self::tile_key(coords))?;
+        let value = self.tree.get_value(coords);
+        self.evict_over_capacity()?;
+        Ok(value)
+    }
+
+    /// Set the value of a voxel, loading its tile first. See [`Tree::set_value`].
+    pub fn set_value(
+        &mut self,
+        coords: impl TreeCoords<ROOT>,
+        value: Option<ROOT::Voxel>,
+    ) -> Result<(), SerializeError>
+    where
+        ROOT: ~const NodeConst,
+    {
+        let key = Self::tile_key(coords);
+        self.fault(key)?;
+        self.modified.insert(key);
+        self.tree.set_value(coords, value);
+        self.evict_over_capacity()
+    }
+
+    /// Set all voxels within `min` and `max` (inclusive) to `value`, loading all tiles
+    /// intersecting the box first. See [`Tree::fill`].
+    pub fn fill<C: TreeCoords<ROOT>>(
+        &mut self,
+        min: C,
+        max: C,
+        value: Option<ROOT::Voxel>,
+    ) -> Result<(), SerializeError>
+    where
+        ROOT: ~const NodeConst,
+    {
+        for key in Self::region_keys(min, max) {
+            self.fault(key)?;
+            self.modified.insert(key);
+        }
+        self.tree.fill(min, max, value);
+        self.evict_over_capacity()
+    }
+
+    /// Load all tiles intersecting the box within `min` and `max` (inclusive).
+    /// Tiles beyond the capacity are evicted right away, least recently used first,
+    /// so the region should fit within the capacity.
+    pub fn load_region<C: TreeCoords<ROOT>>(&mut self, min: C, max: C) -> Result<(), SerializeError>
+    where
+        ROOT: ~const NodeConst,
+    {
+        for key in Self::region_keys(min, max) {
+            self.fault(key)?;
+        }
+        self.evict_over_capacity()
+    }
+
+    /// Evict all tiles intersecting the box within `min` and `max` (inclusive),
+    /// writing back the modified ones.
+    pub fn unload_region<C: TreeCoords<ROOT>>(
+        &mut self,
+        min: C,
+        max: C,
+    ) -> Result<(), SerializeError>
+    where
+        ROOT: ~const NodeConst,
+    {
+        for key in Self::region_keys(min, max) {
+            if self.resident.contains_key(&key) {
+                self.evict(key)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Write back all modified tiles, keeping them in memory.
+    pub fn flush(&mut self) -> Result<(), SerializeError>
+    where
+        ROOT: ~const NodeConst,
+    {
+        let mut modified: Vec<IVec3> = self.modified.iter().copied().collect();
+        modified.sort_unstable_by_key(|key| key.to_array());
+        for key in modified {
+            self.write_back(key)?;
+            self.modified.remove(&key);
+        }
+        Ok(())
+    }
+
+    /// Returns the keys of the tiles intersecting the box within `min` and `max` (inclusive)
+    /// which are not in memory.
+    ///
+    /// This is the first half of loading tiles asynchronously: the data of the returned tiles
+    /// can be read from a clone of [`PagedTree::store`] on another thread, and handed back
+    /// with [`PagedTree::insert_loaded_tile`].
+    /// ```
+    /// #![feature(generic_const_exprs)]
+    /// use dust_vdb::{MemoryStore, PagedTree, TileStore, hierarchy};
+    /// use glam::IVec3;
+    /// let mut tree = PagedTree::<hierarchy!(#, 3, 2; u8), _>::new(MemoryStore::new(), 8);
+    /// tree.set_value(IVec3::new(40, 0, 0), Some(4)).unwrap();
+    /// tree.unload_region(IVec3::new(0, 0, 0), IVec3::new(63, 0, 0)).unwrap();
+    ///
+    /// let missing = tree.missing_tiles(IVec3::new(0, 0, 0), IVec3::new(63, 0, 0));
+    /// let store = tree.store().clone();
+    /// let loaded = std::thread::spawn(move || {
+    ///     missing
+    ///         .into_iter()
+    ///         .map(|key| (key, store.load(key).unwrap()))
+    ///         .collect::<Vec<_>>()
+    /// })
+    /// .join()
+    /// .unwrap();
+    /// for (key, data) in loaded {
+    ///     tree.insert_loaded_tile(key, data.as_deref()).unwrap();
+    /// }
+    /// assert_eq!(tree.tree().get_value(IVec3::new(40, 0, 0)), Some(4));
+    /// ```
+    pub fn missing_tiles<C: TreeCoords<ROOT>>(&self, min: C, max: C) -> Vec<IVec3> {
+        Self::region_keys(min, max)
+            .into_iter()
+            .filter(|key| !self.resident.contains_key(key))
+            .collect()
+    }
+
+    /// Insert the data of the tile at `key` as returned by [`TileStore::load`].
+    /// Tiles already in memory are kept as they are, since they may have been modified after
+    /// the data was loaded.
+    pub fn insert_loaded_tile(
+        &mut self,
+        key: IVec3,
+        data: Option<&[u8]>,
+    ) -> Result<(), SerializeError>
+    where
+        ROOT: ~const NodeConst,
+    {
+        if !self.resident.contains_key(&key) {
+            self.install(key, data)?;
+        }
+        self.evict_over_capacity()
+    }
+
+    /// Start or stop recording the regions modified in the tree. See [`Tree::track_dirty`].
+    /// Loading and evicting a tile records the whole tile.
+    pub fn track_dirty(&mut self, enabled: bool) {
+        self.tree.track_dirty(enabled);
+    }
+
+    /// Returns the boxes modified since the last call. See [`Tree::drain_dirty`].
+    pub fn drain_dirty(&mut self) -> impl Iterator<Item = (UVec3, UVec3)> {
+        self.tree.drain_dirty()
+    }
+
+    #[inline]
+    fn touch(&mut self, key: IVec3) {
+        self.clock += 1;
+        self.resident.insert(key, self.clock);
+    }
+
+    /// Make sure the tile at `key` is in memory and record its use.
+    fn fault(&mut self, key: IVec3) -> Result<(), SerializeError>
+    where
+        ROOT: ~const NodeConst,
+    {
+        if self.resident.contains_key(&key) {
+            self.touch(key);
+            return Ok(());
+        }
+        let data = self.store.load(key)?;
+        self.install(key, data.as_deref())
+    }
+
+    fn install(&mut self, key: IVec3, data: Option<&[u8]>) -> Result<(), SerializeError>
+    where
+        ROOT: ~const NodeConst,
+    {
+        let (min, max) = Self::tile_bounds(key);
+        if let Some(mut data) = data {
+            let layout = u64::read_from(&mut data)?;
+            if layout != <Tree<ROOT> as TreeMeta<ROOT>>::ID {
+                return Err(SerializeError::LayoutMismatch {
+                    expected: <Tree<ROOT> as TreeMeta<ROOT>>::ID,
+                    found: layout,
+                });
+            }
+            let value_size = u32::read_from(&mut data)?;
+            if value_size != ROOT::Voxel::SIZE as u32 {
+                return Err(SerializeError::ValueSizeMismatch {
+                    expected: ROOT::Voxel::SIZE as u32,
+                    found: value_size,
+                });
+            }
+            let result = self
+                .tree
+                .root
+                .read_tile(&mut self.tree.pool, min, &mut data);
+            if let Err(err) = result {
+                self.tree.root.remove_tile(&mut self.tree.pool, min);
+                return Err(err.into());
+            }
+            self.tree.mark_dirty_box(min, max);
+        }
+        self.touch(key);
+        Ok(())
+    }
+
+    /// Save the tile at `key` into the store, or remove it from the store if empty.
+    fn write_back(&mut self, key: IVec3) -> Result<(), SerializeError>
+    where
+        ROOT: ~const NodeConst,
+    {
+        let (origin, _) = Self::tile_bounds(key);
+        if self.tree.root.is_tile_empty(&self.tree.pool, origin) {
+            self.store.remove(key)?;
+            return Ok(());
+        }
+        let mut data = Vec::new();
+        <Tree<ROOT> as TreeMeta<ROOT>>::ID.write_to(&mut data)?;
+        (ROOT::Voxel::SIZE as u32).write_to(&mut data)?;
+        self.tree
+            .root
+            .write_tile(&self.tree.pool, origin, &mut data)?;
+        self.store.save(key, &data)?;
+        Ok(())
+    }
+
+    /// Remove the tile at `key` from memory, writing it back first if modified.
+    fn evict(&mut self, key: IVec3) -> Result<(), SerializeError>
+    where
+        ROOT: ~const NodeConst,
+    {
+        if self.modified.contains(&key) {
+            self.write_back(key)?;
+            self.modified.remove(&key);
+        }
+        let (min, max) = Self::tile_bounds(key);
+        if !self.tree.root.is_tile_empty(&self.tree.pool, min) {
+            self.tree.mark_dirty_box(min, max);
+        }
+        self.tree.root.remove_tile(&mut self.tree.pool, min);
+        self.resident.remove(&key);
+        Ok(())
+    }
+
+    fn evict_over_capacity(&mut self) -> Result<(), SerializeError>
+    where
+        ROOT: ~const NodeConst,
+    {
+        while self.resident.len() > self.capacity {
+            let (key, _) = self
+                .resident
+                .iter()
+                .min_by_key(|(_, last_use)| **last_use)
+                .map(|(key, last_use)| (*key, *last_use))
+                .unwrap();
+            self.evict(key)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use glam::IVec3;
+
+    use super::{DirectoryStore, MemoryStore, PackFileStore, PagedTree, TileStore};
+    use crate::hierarchy;
+
+    type MyRoot = hierarchy!(#, 3, 2; u16);
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("dust_vdb_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    /// Random edits through a tree keeping few tiles in memory, compared with a plain map.
+    fn check_paging<S: TileStore>(store: S) -> PagedTree<MyRoot, S> {
+        use rand::prelude::*;
+        let mut rng = StdRng::seed_from_u64(3);
+        let mut tree = PagedTree::<MyRoot, S>::new(store, 4);
+        let mut expected: HashMap<IVec3, u16> = HashMap::new();
+        for _ in 0..3000 {
+            let coords = IVec3::new(
+                rng.gen_range(-100..100),
+                rng.gen_range(-40..40),
+                rng.gen_range(-100..100),
+            );
+            if rng.gen_bool(0.1) {
+                tree.set_value(coords, None).unwrap();
+                expected.remove(&coords);
+            } else {
+                let value = rng.gen();
+                tree.set_value(coords, Some(value)).unwrap();
+                expected.insert(coords, value);
+            }
+            assert!(tree.resident_tiles().count() <= 4);
+        }
+        tree.fill(IVec3::new(-20, -20, -20), IVec3::new(20, 20, 20), Some(9))
+            .unwrap();
+        for x in -20..=20 {
+            for y in -20..=20 {
+                for z in -20..=20 {
+                    expected.insert(IVec3::new(x, y, z), 9);
+                }
+            }
+        }
+        for (coords, value) in expected.iter() {
+            assert_eq!(tree.get_value(*coords).unwrap(), Some(*value));
+        }
+        assert_eq!(tree.get_value(IVec3::new(500, 0, 0)).unwrap(), None);
+        tree.flush().unwrap();
+        tree
+    }
+
+    #[test]
+    fn test_paging() {
+        let tree = check_paging(MemoryStore::new());
+        let store = tree.store().clone();
+        drop(tree);
+        let mut reloaded = PagedTree::<MyRoot, _>::with_shared_store(store.clone(), 2);
+        reloaded
+            .load_region(IVec3::new(-20, -20, -20), IVec3::new(-1, -1, -1))
+            .unwrap();
+        assert_eq!(reloaded.tree().get_value(IVec3::new(-1, -1, -1)), Some(9));
+
+        // Tiles left empty are removed from the store.
+        let num_tiles = store.len();
+        reloaded
+            .fill(IVec3::new(-32, -32, -32), IVec3::new(-1, -1, -1), None)
+            .unwrap();
+        reloaded
+            .unload_region(IVec3::new(-32, -32, -32), IVec3::new(-1, -1, -1))
+            .unwrap();
+        assert_eq!(store.len(), num_tiles - 1);
+        assert_eq!(reloaded.resident_tiles().count(), 0);
+    }
+
+    #[test]
+    fn test_directory_store() {
+        let path = temp_path("tiles");
+        let tree = check_paging(DirectoryStore::open(&path).unwrap());
+        drop(tree);
+        let mut reloaded = PagedTree::<MyRoot, _>::new(DirectoryStore::open(&path).unwrap(), 2);
+        assert_eq!(reloaded.get_value(IVec3::new(20, 20, 20)).unwrap(), Some(9));
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn test_pack_file_store() {
+        let path = temp_path("tiles.pack");
+        let tree = check_paging(PackFileStore::open(&path).unwrap());
+        let store = tree.store().clone();
+        drop(tree);
+        assert!(store.wasted_bytes() > 0);
+        let key = IVec3::new(0, 0, 0);
+        let data = store.load(key).unwrap().unwrap();
+        drop(store);
+
+        // Records cut short are dropped when opening the file.
+        let len = std::fs::metadata(&path).unwrap().len();
+        let mut file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap();
+        std::io::Write::write_all(&mut file, &[1, 2, 3]).unwrap();
+        drop(file);
+        let store = PackFileStore::open(&path).unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), len);
+
+        store.compact().unwrap();
+        assert_eq!(store.wasted_bytes(), 0);
+        assert!(std::fs::metadata(&path).unwrap().len() < len);
+        assert_eq!(store.load(key).unwrap(), Some(data));
+        store.remove(key).unwrap();
+        drop(store);
+        let store = PackFileStore::open(&path).unwrap();
+        assert_eq!(store.load(key).unwrap(), None);
+        let mut reloaded = PagedTree::<MyRoot, _>::new(store, 2);
+        assert_eq!(
+            reloaded.get_value(IVec3::new(-20, -20, -20)).unwrap(),
+            Some(9)
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,423 @@
+use std::collections::{HashMap, HashSet};
+
+use glam::UVec3;
+use rayon::prelude::*;
+
+use crate::{IsLeaf, Node, NodeConst, Pool, Tree};
+
+impl<ROOT: Node> Tree<ROOT>
+where
+    [(); ROOT::LEVEL as usize + 1]: Sized,
+{
+    /// Iterate over all leaf nodes in the tree in parallel.
+    /// The leaf node pool does not record where its leaf nodes are located in the tree, so the
+    /// leaf nodes are first gathered on the calling thread by walking the tree. They are then
+    /// ordered by address, so that nodes close in memory tend to be visited by the same thread,
+    /// and split across threads in runs of at least one pool chunk worth of nodes. The runs are
+    /// not aligned to the chunks of the pool.
+    /// ```
+    /// #![feature(generic_const_exprs)]
+    /// use dust_vdb::{Tree, hierarchy};
+    /// use glam::UVec3;
+    /// use rayon::prelude::*;
+    /// let mut tree = Tree::<hierarchy!(4, 2)>::new();
+    /// tree.set_value(UVec3::new(0, 1, 2), Some(true));
+    /// tree.set_value(UVec3::new(63, 1, 3), Some(true));
+    /// let voxels: u32 = tree
+    ///     .par_iter_leaf()
+    ///     .map(|(_, leaf)| leaf.occupancy.count_ones() as u32)
+    ///     .sum();
+    /// assert_eq!(voxels, 2);
+    /// ```
+    pub fn par_iter_leaf(&self) -> impl IndexedParallelIterator<Item = (UVec3, &ROOT::LeafType)>
+    where
+        ROOT::LeafType: Sync,
+    {
+        let chunk_len = self.pool.first().map_or(1, Pool::chunk_len);
+        let mut leaves: Vec<(UVec3, &ROOT::LeafType)> = self.iter_leaf().collect();
+        leaves.sort_unstable_by_key(|(_, leaf)| *leaf as *const ROOT::LeafType as usize);
+        leaves.into_par_iter().with_min_len(chunk_len)
+    }
+
+    /// Iterate over all leaf nodes in the tree in parallel, allowing them to be edited.
+    /// Each leaf node is visited by exactly one thread. See [`Tree::par_iter_leaf`].
+    ///
+    /// Leaf nodes emptied this way are not released. Call [`Tree::prune`] afterwards if needed.
+    pub fn par_iter_leaf_mut(
+        &mut self,
+    ) -> impl IndexedParallelIterator<Item = (UVec3, &mut ROOT::LeafType)>
+    where
+        ROOT::LeafType: Send,
+    {
+        let chunk_len = self.pool.first().map_or(1, Pool::chunk_len);
+        let mut leaves: Vec<(UVec3, &mut ROOT::LeafType)> = self.iter_leaf_mut().collect();
+        leaves.sort_unstable_by_key(|(_, leaf)| *leaf as *const ROOT::LeafType as usize);
+        leaves.into_par_iter().with_min_len(chunk_len)
+    }
+
+    /// Replace the value of every voxel within `min` and `max` (inclusive) with `f(coords, value)`.
+    /// `f` is evaluated in parallel, one leaf-sized region at a time.
+    /// Leaf nodes already in the tree are then edited in parallel, while regions stored as tiles or
+    /// left empty are written through the tree so that leaf nodes are allocated where needed.
+    /// Leaf nodes left empty by the edits are released, along with the nodes above them left empty.
+    ///
+    /// `f` is called for all voxels in the box, so prefer [`Tree::fill`] when setting every voxel
+    /// to the same value.
+    /// ```
+    /// #![feature(generic_const_exprs)]
+    /// use dust_vdb::{Tree, hierarchy};
+    /// use glam::UVec3;
+    /// let mut tree = Tree::<hierarchy!(4, 2; u8)>::new();
+    /// tree.fill(UVec3::new(0, 0, 0), UVec3::new(63, 7, 63), Some(1));
+    /// // Carve a checkerboard into the top layer and paint the rest.
+    /// tree.par_apply(UVec3::new(0, 7, 0), UVec3::new(63, 8, 63), |coords, value| {
+    ///     if (coords.x + coords.z) % 2 == 0 {
+    ///         None
+    ///     } else {
+    ///         value.map(|_| 2)
+    ///     }
+    /// });
+    /// assert_eq!(tree.get_value(UVec3::new(0, 7, 0)), None);
+    /// assert_eq!(tree.get_value(UVec3::new(1, 7, 0)), Some(2));
+    /// assert_eq!(tree.get_value(UVec3::new(1, 8, 0)), None);
+    /// assert_eq!(tree.get_value(UVec3::new(1, 6, 0)), Some(1));
+    /// ```
+    pub fn par_apply<F>(&mut self, min: UVec3, max: UVec3, f: F)
+    where
+        ROOT: ~const NodeConst + Sync,
+        ROOT::LeafType: Node<Voxel = ROOT::Voxel> + Send,
+        ROOT::Voxel: Send + Sync,
+        F: Fn(UVec3, Option<ROOT::Voxel>) -> Option<ROOT::Voxel> + Sync,
+    {
+        let max = max.min(ROOT::EXTENT_MASK);
+        if min.cmpgt(max).any() {
+            return;
+        }
+        let leaf_log2 = ROOT::LeafType::EXTENT_LOG2;
+        let cell_min = min >> leaf_log2;
+        let cell_count = (max >> leaf_log2) - cell_min + UVec3::ONE;
+        let num_cells = cell_count.x as usize * cell_count.y as usize * cell_count.z as usize;
+
+        // Changes are keyed by the origin of the leaf node they belong to,
+        // with the coordinates relative to that origin.
+        let tree: &Self = self;
+        let mut changes = (0..num_cells)
+            .into_par_iter()
+            .map_init(
+                || tree.accessor(),
+                |accessor, i| {
+                    let cell = UVec3 {
+                        x: (i / (cell_count.y as usize * cell_count.z as usize)) as u32,
+                        y: ((i / cell_count.z as usize) % cell_count.y as usize) as u32,
+                        z: (i % cell_count.z as usize) as u32,
+                    };
+                    let origin = (cell_min + cell) << leaf_log2;
+                    let local_min = origin.max(min);
+                    let local_max = (origin + ROOT::LeafType::EXTENT_MASK).min(max);
+                    let mut cell_changes = Vec::new();
+                    for x in local_min.x..=local_max.x {
+                        for y in local_min.y..=local_max.y {
+                            for z in local_min.z..=local_max.z {
+                                let coords = UVec3 { x, y, z };
+                                let value = accessor.get(coords);
+                                let new_value = f(coords, value);
+                                if new_value != value {
+                                    cell_changes.push((coords - origin, new_value));
+                                }
+                            }
+                        }
+                    }
+                    (origin, cell_changes)
+                },
+            )
+            .filter(|(_, cell_changes)| !cell_changes.is_empty())
+            .collect::<HashMap<_, _>>();
+        if changes.is_empty() {
+            return;
+        }
+
+        let leaf_origins: HashSet<UVec3> = self
+            .iter_leaf_in_aabb(min, max)
+            .map(|(origin, _)| origin)
+            .collect();
+        let mut accessor = self.accessor_mut();
+        changes.retain(|origin, cell_changes| {
+            if leaf_origins.contains(origin) {
+                return true;
+            }
+            for (local, value) in cell_changes.iter() {
+                accessor.set(*origin + *local, *value);
+            }
+            false
+        });
+
+        let mut leaves: Vec<(UVec3, &mut ROOT::LeafType)> = Vec::with_capacity(changes.len());
+        for origin in changes.keys() {
+            self.mark_dirty_voxel(*origin);
+            // Safety: the leaf nodes are distinct and stay in place until the edits are done.
+            leaves.push((*origin, unsafe { &mut *self.ensure_leaf(*origin) }));
+        }
+        let emptied: Vec<UVec3> = leaves
+            .into_par_iter()
+            .filter_map(|(origin, leaf)| {
+                for (local, value) in changes[&origin].iter() {
+                    leaf.set(&mut [], *local, *value, &mut []);
+                }
+                leaf.is_empty().then_some(origin)
+            })
+            .collect();
+        for origin in emptied {
+            // Clearing a voxel releases the leaf node if it is empty, and so on up the tree.
+            self.root.set(&mut self.pool, origin, None, &mut []);
+        }
+    }
+
+    /// Regenerate every leaf-sized region intersecting the box within `min` and `max` (inclusive)
+    /// with `f(origin, values)`. `values` holds all voxels of the region, indexed like the
+    /// occupancy mask of leaf nodes, and starts out empty.
+    ///
+    /// `f` is evaluated in parallel. Regions left empty are cleared and regions holding a single
+    /// value are stored as tiles, while the others are written as whole leaf nodes in parallel.
+    /// ```
+    /// #![feature(generic_const_exprs)]
+    /// use dust_vdb::{Tree, hierarchy};
+    /// use glam::UVec3;
+    /// let mut tree = Tree::<hierarchy!(4, 2; u8)>::new();
+    /// tree.set_value(UVec3::new(40, 0, 0), Some(3));
+    /// // The region containing (1, 1, 1) is written as a whole, discarding its previous voxels.
+    /// tree.par_fill_leaves(UVec3::new(0, 0, 0), UVec3::new(1, 1, 1), |origin, values| {
+    ///     values[0] = Some(origin.x as u8 + 1);
+    /// });
+    /// assert_eq!(tree.get_value(UVec3::new(0, 0, 0)), Some(1));
+    /// assert_eq!(tree.get_value(UVec3::new(1, 1, 1)), None);
+    /// assert_eq!(tree.get_value(UVec3::new(40, 0, 0)), Some(3));
+    /// ```
+    pub fn par_fill_leaves<F>(&mut self, min: UVec3, max: UVec3, f: F)
+    where
+        ROOT: ~const NodeConst + Sync,
+        ROOT::LeafType: Node<Voxel = ROOT::Voxel> + Send,
+        ROOT::Voxel: Send + Sync,
+        F: Fn(UVec3, &mut [Option<ROOT::Voxel>]) + Sync,
+    {
+        /// Number of regions generated before writing them into the tree, bounding memory use.
+        const BATCH_SIZE: usize = 4096;
+
+        let max = max.min(ROOT::EXTENT_MASK);
+        if min.cmpgt(max).any() {
+            return;
+        }
+        let leaf_log2 = ROOT::LeafType::EXTENT_LOG2;
+        let cell_min = min >> leaf_log2;
+        let cell_count = (max >> leaf_log2) - cell_min + UVec3::ONE;
+        let num_cells = cell_count.x as usize * cell_count.y as usize * cell_count.z as usize;
+
+        for batch_start in (0..num_cells).step_by(BATCH_SIZE) {
+            let regions: Vec<(UVec3, Vec<Option<ROOT::Voxel>>)> = (batch_start
+                ..num_cells.min(batch_start + BATCH_SIZE))
+                .into_par_iter()
+                .map(|i| {
+                    let cell = UVec3 {
+                        x: (i / (cell_count.y as usize * cell_count.z as usize)) as u32,
+                        y: ((i / cell_count.z as usize) % cell_count.y as usize) as u32,
+                        z: (i % cell_count.z as usize) as u32,
+                    };
+                    let origin = (cell_min + cell) << leaf_log2;
+                    let mut values = vec![None; ROOT::LeafType::SIZE];
+                    f(origin, &mut values);
+                    (origin, values)
+                })
+                .collect();
+
+            // Uniform regions become tiles first: filling with None releases the nodes it empties,
+            // which must not include the leaf nodes gathered below before they are written.
+            let mut leaves = Vec::with_capacity(regions.len());
+            for (origin, values) in regions {
+                let first = values[0];
+                if values.iter().all(|value| *value == first) {
+                    self.fill(origin, origin + ROOT::LeafType::EXTENT_MASK, first);
+                } else {
+                    leaves.push((origin, values));
+                }
+            }
+            let leaves: Vec<_> = leaves
+                .into_iter()
+                .map(|(origin, values)| {
+                    self.mark_dirty_voxel(origin);
+                    // Safety: the leaf nodes are distinct and stay in place until they are written.
+                    (unsafe { &mut *self.ensure_leaf(origin) }, values)
+                })
+                .collect();
+            leaves
+                .into_par_iter()
+                .for_each(|(leaf, values)| leaf.set_voxels(&values));
+        }
+    }
+}
+
+/// Coordinates of the voxel at `index` within a leaf node, relative to its origin.
+pub(crate) fn leaf_local_coords<ROOT: Node>(index: usize) -> UVec3 {
+    let log2 = ROOT::LeafType::EXTENT_LOG2;
+    let index = index as u32;
+    UVec3 {
+        x: index >> (log2.y + log2.z),
+        y: (index >> log2.z) & ((1 << log2.y) - 1),
+        z: index & ((1 << log2.z) - 1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use glam::UVec3;
+    use rayon::prelude::*;
+
+    use crate::{hierarchy, IsLeaf, Tree};
+
+    #[test]
+    fn test_par_iter_leaf() {
+        use rand::prelude::*;
+        let mut rng = rand::thread_rng();
+        let mut tree = Tree::<hierarchy!(3, 2, 2; u8)>::new();
+        for _i in 0..2000 {
+            let location = UVec3::new(rng.gen(), rng.gen(), rng.gen()) >> 25;
+            tree.set_value(location, Some(rng.gen()));
+        }
+        let mut expected: Vec<UVec3> = tree.iter_leaf().map(|(origin, _)| origin).collect();
+        let mut origins: Vec<UVec3> = tree.par_iter_leaf().map(|(origin, _)| origin).collect();
+        expected.sort_by_key(|o| (o.x, o.y, o.z));
+        origins.sort_by_key(|o| (o.x, o.y, o.z));
+        assert_eq!(origins, expected);
+
+        let before: Vec<(UVec3, Option<u8>)> = tree
+            .iter()
+            .map(|coords| (coords, tree.get_value(coords)))
+            .collect();
+        tree.par_iter_leaf_mut().for_each(|(_, leaf)| {
+            for (i, value) in leaf.values.iter_mut().enumerate() {
+                if leaf.occupancy.get(i) {
+                    *value = value.wrapping_add(1);
+                }
+            }
+        });
+        for (coords, value) in before {
+            assert_eq!(tree.get_value(coords), value.map(|v| v.wrapping_add(1)));
+        }
+    }
+
+    #[test]
+    fn test_par_apply() {
+        use rand::prelude::*;
+        let mut rng = rand::thread_rng();
+        let mut tree = Tree::<hierarchy!(3, 2, 2; u8)>::new();
+        let mut model: HashMap<UVec3, u8> = HashMap::new();
+        for _i in 0..2000 {
+            let location = UVec3::new(rng.gen(), rng.gen(), rng.gen()) >> 25;
+            let value: u8 = rng.gen();
+            tree.set_value(location, Some(value));
+            model.insert(location, value);
+        }
+        tree.fill(UVec3::new(32, 32, 32), UVec3::new(63, 63, 63), Some(7));
+        for x in 32..64 {
+            for y in 32..64 {
+                for z in 32..64 {
+                    model.insert(UVec3::new(x, y, z), 7);
+                }
+            }
+        }
+        // A uniform leaf node outside of the edited box, which pruning would collapse into a tile.
+        let outside = UVec3::new(100, 100, 100);
+        for x in 0..4 {
+            for y in 0..4 {
+                for z in 0..4 {
+                    let coords = outside + UVec3::new(x, y, z);
+                    tree.set_value(coords, Some(9));
+                    model.insert(coords, 9);
+                }
+            }
+        }
+
+        let f = |coords: UVec3, value: Option<u8>| match (coords.x + coords.y * 3 + coords.z) % 3 {
+            0 => None,
+            1 => Some(value.unwrap_or(0).wrapping_add(coords.y as u8)),
+            _ => value,
+        };
+        let min = UVec3::new(5, 30, 17);
+        let max = UVec3::new(60, 90, 40);
+        tree.par_apply(min, max, f);
+        for x in min.x..=max.x {
+            for y in min.y..=max.y {
+                for z in min.z..=max.z {
+                    let coords = UVec3::new(x, y, z);
+                    match f(coords, model.get(&coords).copied()) {
+                        Some(value) => model.insert(coords, value),
+                        None => model.remove(&coords),
+                    };
+                }
+            }
+        }
+
+        assert_eq!(tree.count_active(), model.len() as u64);
+        for (location, value) in model.iter() {
+            assert_eq!(tree.get_value(*location), Some(*value));
+        }
+        // Emptied leaf nodes were released, while nodes outside of the box were left as they were.
+        for (_, leaf) in tree.iter_leaf() {
+            let mut occupancy = [0_u64; 1];
+            leaf.get_occupancy(&mut occupancy);
+            assert_ne!(occupancy[0], 0);
+        }
+        assert!(tree.iter_leaf().any(|(origin, _)| origin == outside));
+    }
+
+    #[test]
+    fn test_par_fill_leaves() {
+        let mut tree = Tree::<hierarchy!(3, 2, 2; u8)>::new();
+        tree.fill(UVec3::new(0, 0, 0), UVec3::new(31, 31, 31), Some(1));
+        // A uniform leaf node outside of the filled box, which pruning would collapse into a tile.
+        let outside = UVec3::new(100, 100, 100);
+        for x in 0..4 {
+            for y in 0..4 {
+                for z in 0..4 {
+                    tree.set_value(outside + UVec3::new(x, y, z), Some(9));
+                }
+            }
+        }
+
+        let f = |origin: UVec3, values: &mut [Option<u8>]| match (origin.x / 4) % 3 {
+            0 => {}
+            1 => values.fill(Some(2)),
+            _ => values[5] = Some(origin.y as u8),
+        };
+        let min = UVec3::new(2, 3, 4);
+        let max = UVec3::new(45, 20, 9);
+        tree.par_fill_leaves(min, max, f);
+        let region_min = min & !3;
+        let region_max = max | 3;
+        for x in 0..64 {
+            for y in 0..64 {
+                for z in 0..64 {
+                    let coords = UVec3::new(x, y, z);
+                    let expected =
+                        if coords.cmpge(region_min).all() && coords.cmple(region_max).all() {
+                            let origin = coords & !3;
+                            let mut values = [None; 64];
+                            f(origin, &mut values);
+                            values[((x & 3) << 4 | (y & 3) << 2 | (z & 3)) as usize]
+                        } else if coords.cmple(UVec3::splat(31)).all() {
+                            Some(1)
+                        } else {
+                            None
+                        };
+                    assert_eq!(tree.get_value(coords), expected);
+                }
+            }
+        }
+        // Emptied regions released their nodes, while nodes outside of the box were left as they
+        // were.
+        let live = tree.memory_stats().map(|stats| stats.live);
+        assert!(tree.iter_leaf().any(|(origin, _)| origin == outside));
+        tree.prune();
+        assert_eq!(tree.memory_stats()[1].live, live[1]);
+    }
+}
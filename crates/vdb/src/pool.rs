@@ -1,4 +1,4 @@
-use std::{alloc::Layout, marker::PhantomData, mem::MaybeUninit};
+use std::{alloc::Layout, marker::PhantomData, sync::Arc};
 
 pub struct Pool {
     /// Size of one individual allocation
@@ -12,10 +12,99 @@ pub struct Pool {
     /// When running out of space, request (1 << chunk_size_log2) * size bytes.
     chunk_size_log2: usize,
     chunks: Vec<*mut u8>,
+    /// Owners of the memory behind `chunks`. These are shared with the snapshots of the pool.
+    chunk_owners: Vec<Arc<Chunk>>,
+    /// Items copied out of shared chunks before writing to them, one entry for each chunk.
+    overlays: Vec<Option<Box<Overlay>>>,
+    /// One bit for each item below `top`, set if the item is in use.
+    live: Vec<u64>,
 
     count: u32,
 }
 
+/// A block of memory holding `1 << chunk_size_log2` items.
+/// The memory is never written while the chunk is shared between multiple pools.
+struct Chunk {
+    ptr: *mut u8,
+    layout: Layout,
+}
+
+unsafe impl Send for Chunk {}
+unsafe impl Sync for Chunk {}
+
+impl Chunk {
+    fn new(layout: Layout) -> Self {
+        let ptr = unsafe { std::alloc::alloc_zeroed(layout) };
+        if ptr.is_null() {
+            std::alloc::handle_alloc_error(layout);
+        }
+        Self { ptr, layout }
+    }
+    fn new_uninit(layout: Layout) -> Self {
+        let ptr = unsafe { std::alloc::alloc(layout) };
+        if ptr.is_null() {
+            std::alloc::handle_alloc_error(layout);
+        }
+        Self { ptr, layout }
+    }
+}
+
+/// Private copies of the items of a chunk shared with snapshots, so that writing to an item copies
+/// that item alone rather than the whole chunk. Copied items keep their offset within the chunk,
+/// so pointers to the items don't change. Items that were not copied are read from the shared
+/// chunk. The memory of the overlay is left uninitialized, so only the pages holding copied items
+/// are ever touched.
+struct Overlay {
+    chunk: Chunk,
+    /// One bit for each item in the chunk, set if the item was copied into the overlay.
+    copied: Box<[u64]>,
+    count: usize,
+}
+
+impl Overlay {
+    fn new(layout: Layout, chunk_len: usize) -> Self {
+        Self {
+            chunk: Chunk::new_uninit(layout),
+            copied: vec![0; chunk_len.div_ceil(64)].into_boxed_slice(),
+            count: 0,
+        }
+    }
+
+    #[inline]
+    fn is_copied(&self, item_index: usize) -> bool {
+        self.copied[item_index / 64] & (1 << (item_index % 64)) != 0
+    }
+
+    /// Copy the item at `item_index` from `src` into the overlay.
+    unsafe fn copy_from(&mut self, src: *const u8, item_index: usize, item_size: usize) {
+        let offset = item_index * item_size;
+        std::ptr::copy_nonoverlapping(src.add(offset), self.chunk.ptr.add(offset), item_size);
+        self.copied[item_index / 64] |= 1 << (item_index % 64);
+        self.count += 1;
+    }
+
+    /// Returns a copy of the overlay. Only the copied items are copied.
+    fn duplicate(&self, item_size: usize) -> Self {
+        let mut overlay = Self::new(self.chunk.layout, self.copied.len() * 64);
+        for item_index in 0..self.copied.len() * 64 {
+            if self.is_copied(item_index) {
+                unsafe {
+                    overlay.copy_from(self.chunk.ptr, item_index, item_size);
+                }
+            }
+        }
+        overlay
+    }
+}
+
+impl Drop for Chunk {
+    fn drop(&mut self) {
+        unsafe {
+            std::alloc::dealloc(self.ptr, self.layout);
+        }
+    }
+}
+
 unsafe impl Send for Pool {}
 unsafe impl Sync for Pool {}
 
@@ -48,6 +137,9 @@ impl Pool {
             top: 0,
             chunk_size_log2,
             chunks: Vec::new(),
+            chunk_owners: Vec::new(),
+            overlays: Vec::new(),
+            live: Vec::new(),
             count: 0,
         }
     }
@@ -70,10 +162,16 @@ impl Pool {
             if chunk_index >= self.chunks.len() {
                 // allocate new block
                 let (layout, _) = self.layout.repeat(1 << self.chunk_size_log2).unwrap();
-                let block = std::alloc::alloc_zeroed(layout);
-                self.chunks.push(block);
+                let chunk = Chunk::new(layout);
+                self.chunks.push(chunk.ptr);
+                self.chunk_owners.push(Arc::new(chunk));
+                self.overlays.push(None);
             }
             self.top += 1;
+            if top as usize / 64 >= self.live.len() {
+                self.live.push(0);
+            }
+            self.set_live(top, true);
             top
         } else {
             // take from freelist
@@ -81,6 +179,7 @@ impl Pool {
             let next_available_location = *(item_location as *const u32);
             let head = self.head;
             self.head = next_available_location;
+            self.set_live(head, true);
             return head;
         }
     }
@@ -99,25 +198,359 @@ impl Pool {
             // push to freelist
             self.head = index;
         }
+        self.set_live(index, false);
     }
 
     pub fn num_chunks(&self) -> usize {
         self.chunks.len()
     }
 
+    /// Number of items in each chunk.
+    #[inline]
+    pub fn chunk_len(&self) -> usize {
+        1 << self.chunk_size_log2
+    }
+
+    /// Returns true if the item at `ptr` was allocated and not freed since.
+    #[inline]
+    pub fn is_live(&self, ptr: u32) -> bool {
+        ptr < self.top && self.live[ptr as usize / 64] & (1 << (ptr % 64)) != 0
+    }
+
+    #[inline]
+    fn set_live(&mut self, ptr: u32, live: bool) {
+        let word = &mut self.live[ptr as usize / 64];
+        if live {
+            *word |= 1 << (ptr % 64);
+        } else {
+            *word &= !(1 << (ptr % 64));
+        }
+    }
+
+    /// Move all items in use to the front of the pool, filling the slots freed before them,
+    /// and release the chunks left unused at the end.
+    ///
+    /// Returns where each item went: the item previously at `ptr` is now at `remap[ptr]`.
+    /// Free slots map to `u32::MAX`. All pointers to the items in this pool become invalid
+    /// and must be rewritten with the returned table. Trees do this in [`crate::Tree::compact`].
+    /// ```
+    /// use std::alloc::Layout;
+    /// use dust_vdb::Pool;
+    /// unsafe {
+    ///   let mut pool = Pool::new(Layout::new::<u64>(), 1);
+    ///   for i in 0..6 {
+    ///     let ptr = pool.alloc::<u64>();
+    ///     *pool.get_item_mut::<u64>(ptr) = i;
+    ///   }
+    ///   pool.free(0);
+    ///   pool.free(2);
+    ///   pool.free(3);
+    ///   assert_eq!(pool.num_chunks(), 3);
+    ///
+    ///   let remap = pool.compact();
+    ///   assert_eq!(remap, vec![u32::MAX, 1, u32::MAX, u32::MAX, 2, 0]);
+    ///   assert_eq!(pool.num_chunks(), 2);
+    ///   assert_eq!(*pool.get_item::<u64>(remap[4]), 4);
+    ///   assert_eq!(*pool.get_item::<u64>(remap[5]), 5);
+    ///   // The freelist is gone, so new items are appended.
+    ///   assert_eq!(pool.alloc::<u64>(), 3);
+    /// }
+    /// ```
+    pub fn compact(&mut self) -> Vec<u32> {
+        let mut remap: Vec<u32> = (0..self.top)
+            .map(|ptr| if self.is_live(ptr) { ptr } else { u32::MAX })
+            .collect();
+        // Fill the free slots from the front with the items in use from the back.
+        let mut front = 0;
+        let mut back = self.top;
+        loop {
+            while front < back && self.is_live(front) {
+                front += 1;
+            }
+            while back > front && !self.is_live(back - 1) {
+                back -= 1;
+            }
+            if back <= front + 1 {
+                break;
+            }
+            back -= 1;
+            unsafe {
+                let src = self.get(back);
+                let dst = self.get_mut(front);
+                std::ptr::copy_nonoverlapping(src, dst, self.layout.size());
+            }
+            self.set_live(front, true);
+            self.set_live(back, false);
+            remap[back as usize] = front;
+        }
+        debug_assert_eq!(back, self.count);
+
+        self.top = self.count;
+        self.head = u32::MAX;
+        self.live.truncate((self.top as usize).div_ceil(64));
+        let num_chunks = (self.top as usize).div_ceil(1 << self.chunk_size_log2);
+        self.chunks.truncate(num_chunks);
+        self.chunk_owners.truncate(num_chunks);
+        self.overlays.truncate(num_chunks);
+        // Slots above the top are expected to be zeroed for new allocations.
+        let chunk_len = 1 << self.chunk_size_log2;
+        if self.top as usize % chunk_len != 0 {
+            self.own_chunk(self.top as usize >> self.chunk_size_log2);
+            unsafe {
+                let start = self.get_mut(self.top);
+                let len = (chunk_len - self.top as usize % chunk_len) * self.layout.size();
+                std::ptr::write_bytes(start, 0, len);
+            }
+        }
+        remap
+    }
+
+    /// Move all items of `other` into this pool, after the chunks of this pool.
+    /// The chunks of `other` are taken over as they are, so no item is copied.
+    ///
+    /// Returns the offset added to the pointers of the items of `other`: the item previously at
+    /// `ptr` in `other` is now at `ptr + offset`. Slots left unused at the end of the last chunk
+    /// of this pool are added to the freelist.
+    /// ```
+    /// use std::alloc::Layout;
+    /// use dust_vdb::Pool;
+    /// unsafe {
+    ///   let mut pool = Pool::new(Layout::new::<u64>(), 2);
+    ///   let mut other = Pool::new(Layout::new::<u64>(), 2);
+    ///   pool.alloc::<u64>();
+    ///   for i in 0..2 {
+    ///     let ptr = other.alloc::<u64>();
+    ///     *other.get_item_mut::<u64>(ptr) = i + 10;
+    ///   }
+    ///   let offset = pool.append(other);
+    ///   assert_eq!(offset, 4);
+    ///   assert_eq!(*pool.get_item::<u64>(offset + 1), 11);
+    ///   assert_eq!(pool.count(), 3);
+    ///   // The rest of the first chunk is reused first.
+    ///   assert_eq!(pool.alloc::<u64>(), 1);
+    /// }
+    /// ```
+    pub fn append(&mut self, mut other: Pool) -> u32 {
+        assert_eq!(self.layout, other.layout);
+        assert_eq!(self.chunk_size_log2, other.chunk_size_log2);
+        let offset = (self.chunks.len() << self.chunk_size_log2) as u32;
+        self.live.resize((offset as usize).div_ceil(64), 0);
+        for ptr in (self.top..offset).rev() {
+            unsafe {
+                *(self.get_mut(ptr) as *mut u32) = self.head;
+            }
+            self.head = ptr;
+        }
+
+        // Rebase the freelist of `other` and chain it in front of the freelist of this pool.
+        let mut free = other.head;
+        while free != u32::MAX {
+            unsafe {
+                let next = other.get_mut(free) as *mut u32;
+                free = *next;
+                *next = if free == u32::MAX {
+                    self.head
+                } else {
+                    free + offset
+                };
+            }
+        }
+        if other.head != u32::MAX {
+            self.head = other.head + offset;
+        }
+
+        let top = offset + other.top;
+        self.live.resize((top as usize).div_ceil(64), 0);
+        for ptr in 0..other.top {
+            if other.is_live(ptr) {
+                self.set_live(ptr + offset, true);
+            }
+        }
+        self.top = top;
+        self.count += other.count;
+        self.chunks.append(&mut other.chunks);
+        self.chunk_owners.append(&mut other.chunk_owners);
+        self.overlays.append(&mut other.overlays);
+        offset
+    }
+
+    /// Returns the memory usage of the pool.
+    pub fn stats(&self) -> PoolStats {
+        PoolStats {
+            item_size: self.layout.size(),
+            live: self.count,
+            free: self.top - self.count,
+            capacity: (self.chunks.len() << self.chunk_size_log2) as u32,
+            copied: self
+                .overlays
+                .iter()
+                .flatten()
+                .map(|overlay| overlay.count as u32)
+                .sum(),
+            chunks: self.chunks.len() as u32,
+            shared_chunks: self
+                .chunk_owners
+                .iter()
+                .filter(|chunk| Arc::strong_count(chunk) > 1)
+                .count() as u32,
+        }
+    }
+
     #[inline]
     pub unsafe fn get(&self, ptr: u32) -> *const u8 {
         let chunk_index = (ptr as usize) >> self.chunk_size_log2;
         let item_index = (ptr as usize) & ((1 << self.chunk_size_log2) - 1);
-        return self
-            .chunks
-            .get_unchecked(chunk_index)
-            .add(item_index * self.layout.size());
+        let chunk = match self.overlays.get_unchecked(chunk_index) {
+            Some(overlay) if overlay.is_copied(item_index) => overlay.chunk.ptr,
+            _ => *self.chunks.get_unchecked(chunk_index),
+        };
+        chunk.add(item_index * self.layout.size())
     }
+    /// Returns a pointer to the item for writing.
+    /// If the chunk containing the item is shared with a snapshot, the item is copied first.
+    /// Only the item itself is copied, and it keeps its pointer.
+    ///
+    /// # Safety
+    /// `ptr` must have been allocated from this pool.
     #[inline]
     pub unsafe fn get_mut(&mut self, ptr: u32) -> *mut u8 {
-        let ptr = self.get(ptr);
-        ptr as *mut u8
+        let chunk_index = (ptr as usize) >> self.chunk_size_log2;
+        let item_index = (ptr as usize) & ((1 << self.chunk_size_log2) - 1);
+        let offset = item_index * self.layout.size();
+        if let Some(overlay) = self.overlays.get_unchecked(chunk_index) {
+            if overlay.is_copied(item_index) {
+                return overlay.chunk.ptr.add(offset);
+            }
+        }
+        if Arc::strong_count(self.chunk_owners.get_unchecked(chunk_index)) > 1 {
+            return self.copy_item(chunk_index, item_index);
+        }
+        // Items that were not copied are written in place once the snapshots are gone.
+        self.chunks.get_unchecked(chunk_index).add(offset)
+    }
+
+    #[cold]
+    fn copy_item(&mut self, chunk_index: usize, item_index: usize) -> *mut u8 {
+        let chunk_len = 1 << self.chunk_size_log2;
+        let layout = self.chunk_owners[chunk_index].layout;
+        let overlay = self.overlays[chunk_index]
+            .get_or_insert_with(|| Box::new(Overlay::new(layout, chunk_len)));
+        let item = unsafe {
+            overlay.copy_from(self.chunks[chunk_index], item_index, self.layout.size());
+            overlay.chunk.ptr.add(item_index * self.layout.size())
+        };
+        if overlay.count == chunk_len {
+            // Every item was copied, so the overlay replaces the shared chunk.
+            let overlay = self.overlays[chunk_index].take().unwrap();
+            self.chunks[chunk_index] = overlay.chunk.ptr;
+            self.chunk_owners[chunk_index] = Arc::new(overlay.chunk);
+        }
+        item
+    }
+
+    /// Gather the items of the chunk into a single block of memory owned by this pool alone.
+    #[cold]
+    fn own_chunk(&mut self, chunk_index: usize) {
+        let item_size = self.layout.size();
+        let overlay = self.overlays[chunk_index].take();
+        let shared = &self.chunk_owners[chunk_index];
+        if Arc::strong_count(shared) == 1 {
+            // The snapshots are gone, so the copied items are moved back into the chunk.
+            if let Some(overlay) = overlay {
+                for item_index in 0..1 << self.chunk_size_log2 {
+                    if overlay.is_copied(item_index) {
+                        let offset = item_index * item_size;
+                        unsafe {
+                            std::ptr::copy_nonoverlapping(
+                                overlay.chunk.ptr.add(offset),
+                                shared.ptr.add(offset),
+                                item_size,
+                            );
+                        }
+                    }
+                }
+            }
+            return;
+        }
+        let chunk = match overlay {
+            Some(mut overlay) => {
+                for item_index in 0..1 << self.chunk_size_log2 {
+                    if !overlay.is_copied(item_index) {
+                        unsafe {
+                            overlay.copy_from(shared.ptr, item_index, item_size);
+                        }
+                    }
+                }
+                overlay.chunk
+            }
+            None => {
+                let chunk = Chunk::new_uninit(shared.layout);
+                unsafe {
+                    std::ptr::copy_nonoverlapping(shared.ptr, chunk.ptr, shared.layout.size());
+                }
+                chunk
+            }
+        };
+        self.chunks[chunk_index] = chunk.ptr;
+        self.chunk_owners[chunk_index] = Arc::new(chunk);
+    }
+
+    /// Copy all items shared with snapshots of the pool into chunks owned by this pool alone,
+    /// so that the items may be written through pointers obtained with [`Pool::get`].
+    /// Items copied before keep their memory as far as possible, but pointers to the items
+    /// obtained before this call may be invalidated.
+    pub fn unshare(&mut self) {
+        for chunk_index in 0..self.chunks.len() {
+            if self.overlays[chunk_index].is_some()
+                || Arc::strong_count(&self.chunk_owners[chunk_index]) > 1
+            {
+                self.own_chunk(chunk_index);
+            }
+        }
+    }
+
+    /// Returns a copy of the pool sharing all of its chunks with this pool.
+    /// Items are copied lazily, one at a time, when either pool writes into them.
+    /// Items this pool already copied out of chunks shared with earlier snapshots are copied
+    /// right away.
+    /// ```
+    /// use std::alloc::Layout;
+    /// use dust_vdb::Pool;
+    /// unsafe {
+    ///   let mut pool = Pool::new(Layout::new::<u64>(), 4);
+    ///   let ptr = pool.alloc::<u64>();
+    ///   *pool.get_item_mut::<u64>(ptr) = 1;
+    ///   pool.alloc::<u64>();
+    ///
+    ///   let snapshot = pool.snapshot();
+    ///   *pool.get_item_mut::<u64>(ptr) = 2;
+    ///   assert_eq!(*snapshot.get_item::<u64>(ptr), 1);
+    ///   assert_eq!(*pool.get_item::<u64>(ptr), 2);
+    ///   // Only the item written to was copied, not the chunk holding it.
+    ///   assert_eq!(pool.stats().copied, 1);
+    ///   assert_eq!(pool.stats().shared_chunks, 1);
+    /// }
+    /// ```
+    pub fn snapshot(&self) -> Self {
+        Self {
+            layout: self.layout,
+            head: self.head,
+            top: self.top,
+            chunk_size_log2: self.chunk_size_log2,
+            chunks: self.chunks.clone(),
+            chunk_owners: self.chunk_owners.clone(),
+            overlays: self
+                .overlays
+                .iter()
+                .map(|overlay| {
+                    overlay
+                        .as_ref()
+                        .map(|overlay| Box::new(overlay.duplicate(self.layout.size())))
+                })
+                .collect(),
+            live: self.live.clone(),
+            count: self.count,
+        }
     }
 
     #[inline]
@@ -131,7 +564,11 @@ impl Pool {
         &mut *(self.get_mut(ptr) as *mut T)
     }
 
-    pub fn iter_entries<T>(&self) -> PoolIterator<T> {
+    /// Iterate over the items in use along with their pointers. Free slots are skipped.
+    ///
+    /// # Safety
+    /// All items in use must have been initialized as `T`.
+    pub unsafe fn iter_entries<T>(&self) -> PoolIterator<T> {
         debug_assert_eq!(Layout::new::<T>().pad_to_align(), self.layout);
         PoolIterator {
             pool: self,
@@ -141,6 +578,42 @@ impl Pool {
     }
 }
 
+/// Memory usage of a [`Pool`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PoolStats {
+    /// Size of one item in bytes, including padding.
+    pub item_size: usize,
+    /// Number of items in use.
+    pub live: u32,
+    /// Number of slots freed and not reused yet.
+    pub free: u32,
+    /// Number of slots in the allocated chunks, whether they were ever used or not.
+    pub capacity: u32,
+    /// Number of allocated chunks.
+    pub chunks: u32,
+    /// Number of chunks shared with snapshots of the pool.
+    pub shared_chunks: u32,
+    /// Number of items copied out of chunks shared with snapshots before writing to them.
+    /// These are held in addition to the shared chunks.
+    pub copied: u32,
+}
+
+impl PoolStats {
+    /// Bytes occupied by the items in use.
+    pub fn live_bytes(&self) -> usize {
+        self.live as usize * self.item_size
+    }
+    /// Bytes allocated by the pool, including the items copied out of shared chunks.
+    pub fn allocated_bytes(&self) -> usize {
+        (self.capacity + self.copied) as usize * self.item_size
+    }
+    /// Bytes allocated by the pool but held by free slots, which [`Pool::compact`] may release.
+    /// Slots at the end of the last chunk that were never used are not included.
+    pub fn wasted_bytes(&self) -> usize {
+        self.free as usize * self.item_size
+    }
+}
+
 pub struct PoolIterator<'a, T> {
     pool: &'a Pool,
     cur: u32,
@@ -148,29 +621,17 @@ pub struct PoolIterator<'a, T> {
 }
 
 impl<'a, T: 'a> Iterator for PoolIterator<'a, T> {
-    type Item = &'a MaybeUninit<T>;
+    type Item = (u32, &'a T);
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.cur >= self.pool.top {
-            return None;
-        }
-        let item: &'a MaybeUninit<T> = unsafe {
-            let item = self.pool.get(self.cur);
-            std::mem::transmute(item)
-        };
-        self.cur += 1;
-        Some(item)
-    }
-}
-
-impl Drop for Pool {
-    fn drop(&mut self) {
-        unsafe {
-            let (layout, _) = self.layout.repeat(1 << self.chunk_size_log2).unwrap();
-            for chunk in self.chunks.iter() {
-                let chunk = *chunk;
-                std::alloc::dealloc(chunk, layout);
+        while self.cur < self.pool.top {
+            let ptr = self.cur;
+            self.cur += 1;
+            if self.pool.is_live(ptr) {
+                let item: &'a T = unsafe { &*(self.pool.get(ptr) as *const T) };
+                return Some((ptr, item));
             }
         }
+        None
     }
 }
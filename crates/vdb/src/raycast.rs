@@ -0,0 +1,491 @@
+use glam::{IVec3, UVec3, Vec3};
+
+use crate::{
+    accessor::first_valid_level, tree::TreeMeta, Accessor, Node, NodeConst, Tree, TreeCoords,
+};
+
+/// A voxel hit by a ray.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RayHit<V, C = UVec3> {
+    /// Coordinates of the voxel hit by the ray.
+    pub coords: C,
+    /// Value of the voxel hit by the ray.
+    pub value: V,
+    /// Normal of the voxel face through which the ray entered the voxel.
+    /// This is zero when the ray starts inside the voxel.
+    pub normal: IVec3,
+    /// Distance along the ray to the hit, in multiples of `dir`.
+    pub t: f32,
+}
+
+impl<'a, ROOT: Node> Accessor<'a, ROOT>
+where
+    [(); ROOT::LEVEL as usize + 1]: Sized,
+{
+    /// Cast a ray from `origin` along `dir` and return the first voxel hit within `max_t`.
+    ///
+    /// This is a hierarchical version of
+    /// Amanatides, John & Woo, Andrew. (1987). A Fast Voxel Traversal Algorithm for Ray Tracing.
+    /// Whenever a lookup misses, the cached path of the accessor tells us the lowest node that
+    /// exists at the current location, and the ray skips the entire empty child of that node at once.
+    /// Rays through large empty regions of trees with a [`crate::RootNode`] are only bounded by
+    /// `max_t`, so keep it finite.
+    ///
+    /// `origin` is in the coordinates of the tree, where `f32` loses precision quickly away from
+    /// zero. Use [`Accessor::raycast_from`] for rays far from zero, such as rays in signed
+    /// coordinates around the origin of trees with a [`crate::RootNode`].
+    pub fn raycast(&mut self, origin: Vec3, dir: Vec3, max_t: f32) -> Option<RayHit<ROOT::Voxel>>
+    where
+        ROOT: ~const NodeConst,
+    {
+        self.raycast_from(UVec3::ZERO, origin, dir, max_t)
+    }
+
+    /// Cast a ray from `offset` relative to the minimum corner of the voxel at `origin` along `dir`
+    /// and return the first voxel hit within `max_t`. See [`Accessor::raycast`].
+    ///
+    /// All floating point math is done relative to `origin`, so the ray keeps full precision
+    /// anywhere in the tree. `origin` and the coordinates of the hit may be given in signed
+    /// coordinates for trees with a [`crate::RootNode`]:
+    /// ```
+    /// #![feature(generic_const_exprs)]
+    /// use dust_vdb::{Tree, hierarchy};
+    /// use glam::{IVec3, Vec3};
+    /// let mut tree = Tree::<hierarchy!(#, 4, 2; u8)>::new();
+    /// tree.set_value(IVec3::new(-3, 0, 0), Some(1));
+    /// let hit = tree
+    ///     .raycast_from(IVec3::new(5, 0, 0), Vec3::new(0.5, 0.5, 0.5), Vec3::new(-1.0, 0.0, 0.0), 100.0)
+    ///     .unwrap();
+    /// assert_eq!(hit.coords, IVec3::new(-3, 0, 0));
+    /// assert_eq!(hit.t, 7.5);
+    /// ```
+    pub fn raycast_from<C: TreeCoords<ROOT>>(
+        &mut self,
+        origin: C,
+        offset: Vec3,
+        dir: Vec3,
+        max_t: f32,
+    ) -> Option<RayHit<ROOT::Voxel, C>>
+    where
+        ROOT: ~const NodeConst,
+    {
+        let base = origin.to_tree_coords();
+        let origin = offset;
+        // Position of the minimum corner of voxel `coords` relative to the base voxel.
+        let relative = |coords: u64, axis: usize| (coords as i64 - base[axis] as i64) as f32;
+        // Coordinates of the voxel containing a position relative to the base voxel.
+        let voxel = |position: f32, axis: usize| {
+            (base[axis] as i64 + position.floor() as i64).clamp(0, ROOT::EXTENT_MASK[axis] as i64)
+                as u32
+        };
+
+        // Clip the ray against the bounding box of the tree.
+        let mut t_enter = 0.0_f32;
+        let mut t_exit = max_t;
+        let mut entry_axis = None;
+        for axis in 0..3 {
+            let min = relative(0, axis);
+            let max = relative(ROOT::EXTENT_MASK[axis] as u64 + 1, axis);
+            if dir[axis] == 0.0 {
+                if origin[axis] < min || origin[axis] >= max {
+                    return None;
+                }
+                continue;
+            }
+            let t0 = (min - origin[axis]) / dir[axis];
+            let t1 = (max - origin[axis]) / dir[axis];
+            let (near, far) = if dir[axis] > 0.0 { (t0, t1) } else { (t1, t0) };
+            if near > t_enter {
+                t_enter = near;
+                entry_axis = Some(axis);
+            }
+            t_exit = t_exit.min(far);
+        }
+        if t_enter >= t_exit {
+            return None;
+        }
+
+        let entry_point = origin + dir * t_enter;
+        let mut coords = UVec3::ZERO;
+        let mut normal = IVec3::ZERO;
+        for axis in 0..3 {
+            coords[axis] = voxel(entry_point[axis], axis);
+        }
+        if let Some(axis) = entry_axis {
+            // Avoid rounding errors on the face through which the ray entered the tree.
+            if dir[axis] > 0.0 {
+                coords[axis] = 0;
+                normal[axis] = -1;
+            } else {
+                coords[axis] = ROOT::EXTENT_MASK[axis];
+                normal[axis] = 1;
+            }
+        }
+
+        let mut t = t_enter;
+        loop {
+            if let Some(value) = self.get(coords) {
+                return Some(RayHit {
+                    coords: C::from_tree_coords(coords),
+                    value,
+                    normal,
+                    t,
+                });
+            }
+            // The lowest node on the path to `coords` that exists. Its child containing `coords`
+            // is empty, and so is the entire region spanned by that child.
+            let level = first_valid_level(&self.ptrs, 0) as usize;
+            let cell_log2 = if level == 0 {
+                UVec3::ZERO
+            } else {
+                <Tree<ROOT> as TreeMeta<ROOT>>::METAS[level - 1].extent_log2
+            };
+            let cell_min = (coords >> cell_log2) << cell_log2;
+            let cell_max = cell_min + ((UVec3::ONE << cell_log2) - UVec3::ONE);
+
+            // Find the face through which the ray leaves the empty region.
+            let mut t_next = f32::INFINITY;
+            let mut exit_axis = 0;
+            for axis in 0..3 {
+                let boundary = if dir[axis] > 0.0 {
+                    relative(cell_max[axis] as u64 + 1, axis)
+                } else if dir[axis] < 0.0 {
+                    relative(cell_min[axis] as u64, axis)
+                } else {
+                    continue;
+                };
+                let t_axis = (boundary - origin[axis]) / dir[axis];
+                if t_axis < t_next {
+                    t_next = t_axis;
+                    exit_axis = axis;
+                }
+            }
+            if t_next >= t_exit {
+                return None;
+            }
+            t = t.max(t_next);
+
+            let point = origin + dir * t;
+            for axis in 0..3 {
+                if axis != exit_axis {
+                    coords[axis] = voxel(point[axis], axis).clamp(cell_min[axis], cell_max[axis]);
+                }
+            }
+            normal = IVec3::ZERO;
+            if dir[exit_axis] > 0.0 {
+                if cell_max[exit_axis] >= ROOT::EXTENT_MASK[exit_axis] {
+                    return None;
+                }
+                coords[exit_axis] = cell_max[exit_axis] + 1;
+                normal[exit_axis] = -1;
+            } else {
+                if cell_min[exit_axis] == 0 {
+                    return None;
+                }
+                coords[exit_axis] = cell_min[exit_axis] - 1;
+                normal[exit_axis] = 1;
+            }
+        }
+    }
+}
+
+impl<ROOT: Node> Tree<ROOT>
+where
+    [(); ROOT::LEVEL as usize + 1]: Sized,
+{
+    /// Cast a ray from `origin` along `dir` and return the first voxel hit within `max_t`.
+    /// See [`Accessor::raycast`].
+    /// ```
+    /// #![feature(generic_const_exprs)]
+    /// use dust_vdb::{Tree, hierarchy};
+    /// use glam::{IVec3, UVec3, Vec3};
+    /// let mut tree = Tree::<hierarchy!(4, 2; u8)>::new();
+    /// tree.set_value(UVec3::new(40, 3, 5), Some(7));
+    /// let hit = tree
+    ///     .raycast(Vec3::new(0.5, 3.5, 5.5), Vec3::new(1.0, 0.0, 0.0), f32::INFINITY)
+    ///     .unwrap();
+    /// assert_eq!(hit.coords, UVec3::new(40, 3, 5));
+    /// assert_eq!(hit.value, 7);
+    /// assert_eq!(hit.normal, IVec3::new(-1, 0, 0));
+    /// assert_eq!(hit.t, 39.5);
+    /// assert!(tree
+    ///     .raycast(Vec3::new(0.5, 3.5, 5.5), Vec3::new(1.0, 0.0, 0.0), 30.0)
+    ///     .is_none());
+    /// ```
+    pub fn raycast(&self, origin: Vec3, dir: Vec3, max_t: f32) -> Option<RayHit<ROOT::Voxel>>
+    where
+        ROOT: ~const NodeConst,
+    {
+        self.accessor().raycast(origin, dir, max_t)
+    }
+
+    /// Cast a ray from `offset` relative to the minimum corner of the voxel at `origin` along `dir`
+    /// and return the first voxel hit within `max_t`. See [`Accessor::raycast_from`].
+    pub fn raycast_from<C: TreeCoords<ROOT>>(
+        &self,
+        origin: C,
+        offset: Vec3,
+        dir: Vec3,
+        max_t: f32,
+    ) -> Option<RayHit<ROOT::Voxel, C>>
+    where
+        ROOT: ~const NodeConst,
+    {
+        self.accessor().raycast_from(origin, offset, dir, max_t)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{IVec3, UVec3, Vec3};
+
+    use super::RayHit;
+    use crate::{
+        coords::{from_signed, to_signed},
+        hierarchy, Node, NodeConst, Tree,
+    };
+
+    /// Step through the voxels along the ray one at a time.
+    /// `origin` is relative to the minimum corner of the voxel at `base`.
+    fn raycast_brute_force<ROOT: Node + NodeConst>(
+        tree: &Tree<ROOT>,
+        base: UVec3,
+        origin: Vec3,
+        dir: Vec3,
+        max_t: f32,
+    ) -> Option<RayHit<ROOT::Voxel>>
+    where
+        [(); ROOT::LEVEL as usize + 1]: Sized,
+    {
+        let relative = |coords: i64, axis: usize| (coords - base[axis] as i64) as f32;
+        let mut t = 0.0_f32;
+        let mut t_exit = max_t;
+        let mut normal = IVec3::ZERO;
+        for axis in 0..3 {
+            if dir[axis] == 0.0 {
+                if origin[axis] < relative(0, axis)
+                    || origin[axis] >= relative(ROOT::EXTENT_MASK[axis] as i64 + 1, axis)
+                {
+                    return None;
+                }
+                continue;
+            }
+            let t0 = (relative(0, axis) - origin[axis]) / dir[axis];
+            let t1 =
+                (relative(ROOT::EXTENT_MASK[axis] as i64 + 1, axis) - origin[axis]) / dir[axis];
+            let (near, far) = if dir[axis] > 0.0 { (t0, t1) } else { (t1, t0) };
+            if near > t {
+                t = near;
+                normal = IVec3::ZERO;
+                normal[axis] = if dir[axis] > 0.0 { -1 } else { 1 };
+            }
+            t_exit = t_exit.min(far);
+        }
+        if t >= t_exit {
+            return None;
+        }
+        // Coordinates are tracked in i64 so that stepping outside of the tree can be detected.
+        let point = origin + dir * t;
+        let mut coords = [0_i64; 3];
+        for axis in 0..3 {
+            let extent_mask = ROOT::EXTENT_MASK[axis] as i64;
+            coords[axis] = match normal[axis] {
+                -1 => 0,
+                1 => extent_mask,
+                _ => (base[axis] as i64 + point[axis].floor() as i64).clamp(0, extent_mask),
+            };
+        }
+        loop {
+            let location = UVec3::new(coords[0] as u32, coords[1] as u32, coords[2] as u32);
+            if let Some(value) = tree.get_value(location) {
+                return Some(RayHit {
+                    coords: location,
+                    value,
+                    normal,
+                    t,
+                });
+            }
+            let mut t_next = f32::INFINITY;
+            let mut exit_axis = 0;
+            for axis in 0..3 {
+                let boundary = if dir[axis] > 0.0 {
+                    relative(coords[axis] + 1, axis)
+                } else if dir[axis] < 0.0 {
+                    relative(coords[axis], axis)
+                } else {
+                    continue;
+                };
+                let t_axis = (boundary - origin[axis]) / dir[axis];
+                if t_axis < t_next {
+                    t_next = t_axis;
+                    exit_axis = axis;
+                }
+            }
+            if t_next >= t_exit {
+                return None;
+            }
+            t = t.max(t_next);
+            let step = if dir[exit_axis] > 0.0 { 1 } else { -1 };
+            coords[exit_axis] += step as i64;
+            if coords[exit_axis] < 0 || coords[exit_axis] > ROOT::EXTENT_MASK[exit_axis] as i64 {
+                return None;
+            }
+            normal = IVec3::ZERO;
+            normal[exit_axis] = -step;
+        }
+    }
+
+    /// Compare the hits of two ray casts, allowing for rounding differences in the distance.
+    fn assert_hit_eq<V: PartialEq + std::fmt::Debug, C: PartialEq + std::fmt::Debug>(
+        hit: Option<RayHit<V, C>>,
+        expected: Option<RayHit<V, C>>,
+        context: std::fmt::Arguments,
+    ) {
+        match (hit, expected) {
+            (Some(hit), Some(expected)) => {
+                assert_eq!(hit.coords, expected.coords, "{}", context);
+                assert_eq!(hit.value, expected.value, "{}", context);
+                assert_eq!(hit.normal, expected.normal, "{}", context);
+                let tolerance = 1e-4 * expected.t.abs().max(1.0);
+                assert!(
+                    (hit.t - expected.t).abs() <= tolerance,
+                    "t: {} != {}, {}",
+                    hit.t,
+                    expected.t,
+                    context
+                );
+            }
+            (hit, expected) => assert_eq!(hit, expected, "{}", context),
+        }
+    }
+
+    fn random_dir(rng: &mut impl rand::Rng) -> Vec3 {
+        let mut dir = Vec3::new(
+            rng.gen_range(-1.0..1.0),
+            rng.gen_range(-1.0..1.0),
+            rng.gen_range(-1.0..1.0),
+        );
+        // Exercise axis aligned rays as well.
+        for axis in 0..3 {
+            if rng.gen_bool(0.1) {
+                dir[axis] = 0.0;
+            }
+        }
+        dir
+    }
+
+    #[test]
+    fn test_raycast() {
+        use rand::prelude::*;
+        let mut rng = StdRng::seed_from_u64(11);
+
+        type MyTree = Tree<hierarchy!(3, 2, 2; u16)>;
+        let mut tree = MyTree::new();
+        for _i in 0..400 {
+            let location = UVec3::new(rng.gen(), rng.gen(), rng.gen()) >> 25;
+            tree.set_value(location, Some(rng.gen()));
+        }
+        tree.fill(UVec3::new(96, 96, 96), UVec3::new(111, 127, 103), Some(1));
+
+        for _i in 0..2000 {
+            let origin = Vec3::new(
+                rng.gen_range(-32.0..160.0),
+                rng.gen_range(-32.0..160.0),
+                rng.gen_range(-32.0..160.0),
+            );
+            let dir = random_dir(&mut rng);
+            let max_t = if rng.gen_bool(0.5) {
+                f32::INFINITY
+            } else {
+                rng.gen_range(0.0..200.0)
+            };
+            let hit = tree.raycast(origin, dir, max_t);
+            let expected = raycast_brute_force(&tree, UVec3::ZERO, origin, dir, max_t);
+            assert_hit_eq(
+                hit,
+                expected,
+                format_args!("origin: {:?}, dir: {:?}, max_t: {}", origin, dir, max_t),
+            );
+        }
+    }
+
+    #[test]
+    fn test_raycast_root() {
+        use rand::prelude::*;
+        let mut rng = StdRng::seed_from_u64(12);
+
+        type MyTree = Tree<hierarchy!(#, 2, 2)>;
+        let mut tree = MyTree::new();
+        for _i in 0..200 {
+            let location = UVec3::new(rng.gen(), rng.gen(), rng.gen()) >> 26;
+            tree.set_value(location, Some(true));
+        }
+        tree.fill(UVec3::new(32, 0, 16), UVec3::new(47, 15, 31), Some(false));
+
+        let mut accessor = tree.accessor();
+        for _i in 0..1000 {
+            let origin = Vec3::new(
+                rng.gen_range(0.0..64.0),
+                rng.gen_range(0.0..64.0),
+                rng.gen_range(0.0..64.0),
+            );
+            let dir = random_dir(&mut rng);
+            let max_t = rng.gen_range(0.0..100.0);
+            let hit = accessor.raycast(origin, dir, max_t);
+            let expected = raycast_brute_force(&tree, UVec3::ZERO, origin, dir, max_t);
+            assert_hit_eq(
+                hit,
+                expected,
+                format_args!("origin: {:?}, dir: {:?}, max_t: {}", origin, dir, max_t),
+            );
+        }
+    }
+
+    #[test]
+    fn test_raycast_signed() {
+        use rand::prelude::*;
+        let mut rng = StdRng::seed_from_u64(13);
+
+        type MyTree = Tree<hierarchy!(#, 2, 2)>;
+        let mut tree = MyTree::new();
+        for _i in 0..400 {
+            let location = IVec3::new(
+                rng.gen_range(-48..48),
+                rng.gen_range(-48..48),
+                rng.gen_range(-48..48),
+            );
+            tree.set_value(location, Some(true));
+        }
+        tree.fill(IVec3::new(-8, -16, 0), IVec3::new(7, -1, 15), Some(false));
+
+        let mut accessor = tree.accessor();
+        for _i in 0..1000 {
+            let origin = IVec3::new(
+                rng.gen_range(-64..64),
+                rng.gen_range(-64..64),
+                rng.gen_range(-64..64),
+            );
+            let offset = Vec3::new(rng.gen(), rng.gen(), rng.gen());
+            let dir = random_dir(&mut rng);
+            let max_t = rng.gen_range(0.0..100.0);
+            let hit = accessor.raycast_from(origin, offset, dir, max_t);
+            let expected =
+                raycast_brute_force(&tree, from_signed(origin), offset, dir, max_t).map(|hit| {
+                    RayHit {
+                        coords: to_signed(hit.coords),
+                        value: hit.value,
+                        normal: hit.normal,
+                        t: hit.t,
+                    }
+                });
+            assert_hit_eq(
+                hit,
+                expected,
+                format_args!(
+                    "origin: {:?}, offset: {:?}, dir: {:?}, max_t: {}",
+                    origin, offset, dir, max_t
+                ),
+            );
+        }
+    }
+}
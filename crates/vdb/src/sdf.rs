@@ -0,0 +1,610 @@
+use glam::{IVec3, UVec3, Vec3};
+
+use crate::{
+    coords::position,
+    stencil::{offset_coords, FACE_NEIGHBORS},
+    tree::TreeMeta,
+    Node, NodeConst, Tree, TreeCoords,
+};
+
+/// Values stored in trees holding signed distances.
+/// [`Half`] stores them in half precision, which halves the size of the distance trees.
+pub trait SdfValue: Copy + PartialEq + Default + 'static {
+    fn from_distance(distance: f32) -> Self;
+    fn to_distance(self) -> f32;
+}
+
+impl SdfValue for f32 {
+    #[inline]
+    fn from_distance(distance: f32) -> Self {
+        distance
+    }
+    #[inline]
+    fn to_distance(self) -> f32 {
+        self
+    }
+}
+
+/// A half precision (IEEE 754 binary16) floating point number, for storing signed distances
+/// in two bytes. Distances up to 2048 voxels keep a precision of at least one voxel, and
+/// distances within a narrow band of a few voxels are accurate to a few thousandths of a voxel.
+/// ```
+/// #![feature(generic_const_exprs)]
+/// use dust_vdb::{Half, Tree, hierarchy};
+/// use glam::UVec3;
+/// let mut tree = Tree::<hierarchy!(4, 2)>::new();
+/// tree.fill(UVec3::new(10, 10, 10), UVec3::new(20, 20, 20), Some(true));
+/// let sdf: Tree<hierarchy!(4, 2; Half)> = tree.to_sdf(3.0);
+/// assert_eq!(sdf.get_value(UVec3::new(8, 15, 15)), Some(Half::from_f32(1.5)));
+/// assert_eq!(Half::from_f32(1.5).to_f32(), 1.5);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Hash)]
+pub struct Half(pub u16);
+
+impl Half {
+    /// Round `value` to the nearest half precision number, ties to even.
+    /// Values beyond the range of half precision numbers become infinite.
+    pub fn from_f32(value: f32) -> Self {
+        let bits = value.to_bits();
+        let sign = ((bits >> 16) & 0x8000) as u16;
+        let exponent = ((bits >> 23) & 0xFF) as i32;
+        let mantissa = bits & 0x7F_FFFF;
+        if exponent == 0xFF {
+            // Infinity, or NaN kept quiet.
+            let nan = if mantissa != 0 { 0x200 } else { 0 };
+            return Self(sign | 0x7C00 | nan);
+        }
+        let exponent = exponent - 127 + 15;
+        if exponent >= 0x1F {
+            return Self(sign | 0x7C00);
+        }
+        if exponent <= 0 {
+            // Subnormal numbers, counting multiples of 2^-24.
+            if exponent < -10 {
+                return Self(sign);
+            }
+            let mantissa = mantissa | 0x80_0000;
+            let shift = (14 - exponent) as u32;
+            let rounded = (mantissa + (1 << (shift - 1)) - 1 + ((mantissa >> shift) & 1)) >> shift;
+            return Self(sign | rounded as u16);
+        }
+        // Rounding may carry into the exponent, up to infinity.
+        let rounded = mantissa + 0xFFF + ((mantissa >> 13) & 1);
+        let bits = ((exponent as u32) << 10) + (rounded >> 13);
+        Self(sign | bits.min(0x7C00) as u16)
+    }
+
+    /// Returns the value as a single precision number, which represents it exactly.
+    pub fn to_f32(self) -> f32 {
+        let sign = ((self.0 & 0x8000) as u32) << 16;
+        let exponent = ((self.0 >> 10) & 0x1F) as u32;
+        let mantissa = (self.0 & 0x3FF) as u32;
+        let bits = match exponent {
+            0 => {
+                let value = mantissa as f32 / (1 << 24) as f32;
+                return if sign != 0 { -value } else { value };
+            }
+            0x1F => sign | 0x7F80_0000 | (mantissa << 13),
+            _ => sign | ((exponent + 127 - 15) << 23) | (mantissa << 13),
+        };
+        f32::from_bits(bits)
+    }
+}
+
+impl SdfValue for Half {
+    #[inline]
+    fn from_distance(distance: f32) -> Self {
+        Half::from_f32(distance)
+    }
+    #[inline]
+    fn to_distance(self) -> f32 {
+        self.to_f32()
+    }
+}
+
+impl<ROOT: Node> Tree<ROOT>
+where
+    [(); ROOT::LEVEL as usize + 1]: Sized,
+{
+    /// Compute a narrow-band signed distance field from the occupancy of the tree.
+    /// The surface lies on the faces between occupied and unoccupied voxels, so voxels next to
+    /// the surface are 0.5 away from it. Distances are in voxels, negative within the occupied
+    /// region and positive outside of it.
+    ///
+    /// Only voxels within `band` of the surface are written into the returned tree. The surface is
+    /// found by visiting the voxels of the leaf nodes and the faces of the tiles, so the interiors
+    /// of tiles are never visited. Each voxel on the surface then writes its distance to the voxels
+    /// on the other side of the surface within `band` of it, so the work grows with the area of
+    /// the surface rather than the volume of the tree. Voxels outside of the tree are not
+    /// considered, so occupied voxels on the boundary of the tree are on the surface, but their
+    /// distance to it is only measured within the tree.
+    /// ```
+    /// #![feature(generic_const_exprs)]
+    /// use dust_vdb::{Tree, hierarchy};
+    /// use glam::UVec3;
+    /// let mut tree = Tree::<hierarchy!(4, 2)>::new();
+    /// tree.fill(UVec3::new(10, 10, 10), UVec3::new(20, 20, 20), Some(true));
+    /// let sdf: Tree<hierarchy!(4, 2; f32)> = tree.to_sdf(3.0);
+    /// assert_eq!(sdf.get_value(UVec3::new(10, 15, 15)), Some(-0.5));
+    /// assert_eq!(sdf.get_value(UVec3::new(8, 15, 15)), Some(1.5));
+    /// assert_eq!(sdf.get_value(UVec3::new(12, 15, 15)), Some(-2.5));
+    /// // Too far from the surface.
+    /// assert_eq!(sdf.get_value(UVec3::new(14, 15, 15)), None);
+    /// ```
+    pub fn to_sdf<SDF: Node + NodeConst>(&self, band: f32) -> Tree<SDF>
+    where
+        ROOT: ~const NodeConst,
+        SDF::Voxel: SdfValue,
+        [(); SDF::LEVEL as usize + 1]: Sized,
+    {
+        // Voxels on either side of the surface. Distances are measured from their centers,
+        // then moved by half a voxel onto the surface.
+        let mut inner: Vec<UVec3> = Vec::new();
+        let mut outer: Vec<UVec3> = Vec::new();
+        let mut accessor = self.accessor();
+        let mut visit = |coords: UVec3| {
+            let mut on_surface = false;
+            for dir in FACE_NEIGHBORS {
+                match offset_coords::<ROOT>(coords, dir) {
+                    Some(neighbor) if accessor.get(neighbor).is_none() => {
+                        on_surface = true;
+                        outer.push(neighbor);
+                    }
+                    Some(_) => {}
+                    None => on_surface = true,
+                }
+            }
+            if on_surface {
+                inner.push(coords);
+            }
+        };
+        for (origin, _) in self.iter_leaf() {
+            for coords in self.iter_in_aabb(origin, origin + ROOT::LeafType::EXTENT_MASK) {
+                visit(coords);
+            }
+        }
+        // Only the faces of a tile may be on the surface.
+        self.for_each_tile_in_aabb(UVec3::ZERO, ROOT::EXTENT_MASK, |min, max, _| {
+            for axis in 0..3 {
+                let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+                for side in [min[axis], max[axis]] {
+                    for a in min[u]..=max[u] {
+                        for b in min[v]..=max[v] {
+                            let mut coords = UVec3::ZERO;
+                            coords[axis] = side;
+                            coords[u] = a;
+                            coords[v] = b;
+                            visit(coords);
+                        }
+                    }
+                }
+            }
+        });
+        for voxels in [&mut inner, &mut outer] {
+            voxels.sort_unstable_by_key(|coords| coords.to_array());
+            voxels.dedup();
+        }
+
+        // Offsets within the band, along with their distance to the surface.
+        let reach = (band + 0.5).floor() as i32;
+        let mut ball: Vec<(IVec3, f32)> = Vec::new();
+        for x in -reach..=reach {
+            for y in -reach..=reach {
+                for z in -reach..=reach {
+                    let offset = IVec3 { x, y, z };
+                    let distance = offset.as_vec3().length() - 0.5;
+                    if offset != IVec3::ZERO && distance <= band {
+                        ball.push((offset, distance));
+                    }
+                }
+            }
+        }
+
+        let mut sdf = Tree::<SDF>::new();
+        let mut sdf_accessor = sdf.accessor_mut();
+        // Unoccupied voxels measure their distance to the occupied voxels on the surface,
+        // and occupied voxels measure theirs to the unoccupied voxels on the surface.
+        for (sources, sign) in [(&inner, 1.0_f32), (&outer, -1.0_f32)] {
+            for source in sources.iter() {
+                for (offset, distance) in ball.iter() {
+                    let Some(coords) = offset_coords::<ROOT>(*source, *offset) else {
+                        continue;
+                    };
+                    if accessor.get(coords).is_some() == (sign > 0.0) {
+                        continue;
+                    }
+                    let closer = match sdf_accessor.get(coords) {
+                        Some(current) => current.to_distance().abs() > *distance,
+                        None => true,
+                    };
+                    if closer {
+                        sdf_accessor.set(coords, Some(SDF::Voxel::from_distance(sign * distance)));
+                    }
+                }
+            }
+        }
+        sdf
+    }
+
+    /// Set all voxels within `min` and `max` (inclusive) inside the shape described by `sdf` to
+    /// `value`, leaving the voxels outside of the shape untouched. `sdf` returns the signed distance
+    /// from a point to the surface of the shape, negative inside. Voxels are inside the shape
+    /// when the distance at their center, `coords + 0.5`, is at most 0.
+    ///
+    /// Points are given in the space of the coordinates of `min` and `max`, see
+    /// [`TreeCoords::ORIGIN`]. For trees with a [`crate::RootNode`], use signed coordinates to
+    /// keep the points precise around the origin.
+    ///
+    /// The box is visited one node at a time. `sdf` is evaluated at the center of each node first,
+    /// and nodes entirely inside the shape are filled as a whole while nodes entirely outside of it
+    /// are skipped. `sdf` must therefore never overestimate the distance to the surface.
+    /// ```
+    /// #![feature(generic_const_exprs)]
+    /// use dust_vdb::{Tree, hierarchy};
+    /// use glam::{IVec3, UVec3, Vec3};
+    /// let mut tree = Tree::<hierarchy!(4, 2; u8)>::new();
+    /// let center = Vec3::splat(32.0);
+    /// tree.fill_sdf(UVec3::ZERO, UVec3::splat(63), 1, |p| (p - center).length() - 20.0);
+    /// assert_eq!(tree.get_value(UVec3::splat(32)), Some(1));
+    /// assert_eq!(tree.get_value(UVec3::new(32, 32, 51)), Some(1));
+    /// assert_eq!(tree.get_value(UVec3::new(32, 32, 52)), None);
+    /// // The interior of the sphere is stored as tiles.
+    /// assert!(tree.iter_leaf().count() < 24 * 24 * 6);
+    ///
+    /// let mut tree = Tree::<hierarchy!(#, 4, 2; u8)>::new();
+    /// tree.fill_sdf(IVec3::splat(-16), IVec3::splat(16), 1, |p| p.length() - 10.0);
+    /// assert_eq!(tree.get_value(IVec3::new(-10, 0, 0)), Some(1));
+    /// assert_eq!(tree.get_value(IVec3::new(-11, 0, 0)), None);
+    /// ```
+    pub fn fill_sdf<C: TreeCoords<ROOT>>(
+        &mut self,
+        min: C,
+        max: C,
+        value: ROOT::Voxel,
+        sdf: impl Fn(Vec3) -> f32,
+    ) where
+        ROOT: ~const NodeConst,
+    {
+        let min = min.to_tree_coords();
+        let max = max.to_tree_coords().min(ROOT::EXTENT_MASK);
+        if min.cmpgt(max).any() {
+            return;
+        }
+        if ROOT::LEVEL == 0 {
+            self.fill_sdf_voxels::<C>(min, max, value, &sdf);
+            return;
+        }
+        // Visit the children of the root node that intersect the box.
+        let level = ROOT::LEVEL - 1;
+        let extent_log2 = <Self as TreeMeta<ROOT>>::METAS[level].extent_log2;
+        let block_min = min >> extent_log2;
+        let block_max = max >> extent_log2;
+        for x in block_min.x..=block_max.x {
+            for y in block_min.y..=block_max.y {
+                for z in block_min.z..=block_max.z {
+                    let origin = UVec3 { x, y, z } << extent_log2;
+                    self.fill_sdf_node::<C>(level, origin, min, max, value, &sdf);
+                }
+            }
+        }
+    }
+
+    /// Fill the voxels within `min` and `max` (inclusive) inside the node at `level` located at `origin`.
+    fn fill_sdf_node<C: TreeCoords<ROOT>>(
+        &mut self,
+        level: usize,
+        origin: UVec3,
+        min: UVec3,
+        max: UVec3,
+        value: ROOT::Voxel,
+        sdf: &impl Fn(Vec3) -> f32,
+    ) where
+        ROOT: ~const NodeConst,
+    {
+        let meta = &<Self as TreeMeta<ROOT>>::METAS[level];
+        let node_min = min.max(origin);
+        let node_max = max.min(origin + meta.extent_mask);
+        // Distances are measured to the voxel centers, so the corner voxels are half a voxel
+        // closer to the center of the box than its corners.
+        let size = (node_max - node_min).as_vec3();
+        let center = position::<ROOT, C>(node_min) + (size + Vec3::ONE) * 0.5;
+        let half_diagonal = size.length() * 0.5;
+        let distance = sdf(center);
+        if distance > half_diagonal {
+            return;
+        }
+        if distance <= -half_diagonal {
+            self.fill(node_min, node_max, Some(value));
+            return;
+        }
+        if level == 0 {
+            self.fill_sdf_voxels::<C>(node_min, node_max, value, sdf);
+            return;
+        }
+        let child_extent_log2 = <Self as TreeMeta<ROOT>>::METAS[level - 1].extent_log2;
+        let child_min = node_min >> child_extent_log2;
+        let child_max = node_max >> child_extent_log2;
+        for x in child_min.x..=child_max.x {
+            for y in child_min.y..=child_max.y {
+                for z in child_min.z..=child_max.z {
+                    let child_origin = UVec3 { x, y, z } << child_extent_log2;
+                    self.fill_sdf_node::<C>(
+                        level - 1,
+                        child_origin,
+                        node_min,
+                        node_max,
+                        value,
+                        sdf,
+                    );
+                }
+            }
+        }
+    }
+
+    fn fill_sdf_voxels<C: TreeCoords<ROOT>>(
+        &mut self,
+        min: UVec3,
+        max: UVec3,
+        value: ROOT::Voxel,
+        sdf: &impl Fn(Vec3) -> f32,
+    ) where
+        ROOT: ~const NodeConst,
+    {
+        let min_position = position::<ROOT, C>(min) + Vec3::splat(0.5);
+        let mut accessor = self.accessor_mut();
+        for x in min.x..=max.x {
+            for y in min.y..=max.y {
+                for z in min.z..=max.z {
+                    let coords = UVec3 { x, y, z };
+                    if sdf(min_position + (coords - min).as_vec3()) <= 0.0 {
+                        accessor.set(coords, Some(value));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Sample the signed distance field stored in the tree at `position`, interpolating between
+    /// the centers of the eight closest voxels. Voxels without a value are left out of the
+    /// interpolation, and None is returned if all of them are missing.
+    ///
+    /// `position` is in the coordinates of the tree, where `f32` loses precision quickly away from
+    /// zero. Use [`Tree::sample_sdf_from`] for positions far from zero, such as positions in signed
+    /// coordinates around the origin of trees with a [`crate::RootNode`].
+    /// ```
+    /// #![feature(generic_const_exprs)]
+    /// use dust_vdb::{Tree, hierarchy};
+    /// use glam::{UVec3, Vec3};
+    /// let mut tree = Tree::<hierarchy!(4, 2)>::new();
+    /// tree.fill(UVec3::new(0, 0, 0), UVec3::new(9, 63, 63), Some(true));
+    /// let sdf: Tree<hierarchy!(4, 2; f32)> = tree.to_sdf(4.0);
+    /// // The surface lies on the face between x = 9 and x = 10.
+    /// assert_eq!(sdf.sample_sdf(Vec3::new(10.0, 20.5, 20.5)), Some(0.0));
+    /// assert_eq!(sdf.sample_sdf(Vec3::new(11.0, 20.0, 20.0)), Some(1.0));
+    /// assert_eq!(sdf.sample_sdf(Vec3::new(40.0, 20.0, 20.0)), None);
+    /// ```
+    pub fn sample_sdf(&self, position: Vec3) -> Option<f32>
+    where
+        ROOT: ~const NodeConst,
+        ROOT::Voxel: SdfValue,
+    {
+        // Positions below zero still interpolate with the voxels at zero.
+        let origin = position.floor().max(Vec3::ZERO);
+        self.sample_sdf_from(origin.as_uvec3(), position - origin)
+    }
+
+    /// Sample the signed distance field stored in the tree at `offset` relative to the minimum
+    /// corner of the voxel at `origin`. See [`Tree::sample_sdf`].
+    ///
+    /// All floating point math is done relative to `origin`, so the samples keep full precision
+    /// anywhere in the tree.
+    /// ```
+    /// #![feature(generic_const_exprs)]
+    /// use dust_vdb::{Tree, hierarchy};
+    /// use glam::{IVec3, Vec3};
+    /// let mut tree = Tree::<hierarchy!(#, 4, 2)>::new();
+    /// tree.fill(IVec3::new(-32, -32, -32), IVec3::new(-1, 31, 31), Some(true));
+    /// let sdf: Tree<hierarchy!(#, 4, 2; f32)> = tree.to_sdf(4.0);
+    /// // The surface lies on the face between x = -1 and x = 0.
+    /// let sample = sdf.sample_sdf_from(IVec3::ZERO, Vec3::new(0.25, 0.5, 0.5));
+    /// assert_eq!(sample, Some(0.25));
+    /// ```
+    pub fn sample_sdf_from<C: TreeCoords<ROOT>>(&self, origin: C, offset: Vec3) -> Option<f32>
+    where
+        ROOT: ~const NodeConst,
+        ROOT::Voxel: SdfValue,
+    {
+        let origin = origin.to_tree_coords();
+        let base = offset - Vec3::splat(0.5);
+        let base_floor = base.floor();
+        let t = base - base_floor;
+        let base_floor = base_floor.as_ivec3();
+        let mut accessor = self.accessor();
+        let mut sum = 0.0;
+        let mut weight_sum = 0.0;
+        for corner in 0..8 {
+            let offset = IVec3::new(corner >> 2, (corner >> 1) & 1, corner & 1);
+            let Some(coords) = offset_coords::<ROOT>(origin, base_floor + offset) else {
+                continue;
+            };
+            let Some(value) = accessor.get(coords) else {
+                continue;
+            };
+            let weights = Vec3::select(offset.cmpeq(IVec3::ONE), t, Vec3::ONE - t);
+            let weight = weights.x * weights.y * weights.z;
+            sum += value.to_distance() * weight;
+            weight_sum += weight;
+        }
+        if weight_sum == 0.0 {
+            return None;
+        }
+        Some(sum / weight_sum)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use glam::{IVec3, UVec3, Vec3};
+
+    use super::Half;
+    use crate::{hierarchy, Tree};
+
+    #[test]
+    fn test_to_sdf() {
+        use rand::prelude::*;
+        let mut rng = rand::thread_rng();
+        let mut tree = Tree::<hierarchy!(3, 2, 2)>::new();
+        let mut occupied: HashSet<UVec3> = HashSet::new();
+        for _i in 0..300 {
+            // Keep away from the boundary of the tree.
+            let location = (UVec3::new(rng.gen(), rng.gen(), rng.gen()) >> 26) + UVec3::splat(8);
+            tree.set_value(location, Some(true));
+            occupied.insert(location);
+        }
+        // Large enough to be stored as tiles, whose interiors are skipped.
+        tree.fill(UVec3::new(14, 16, 16), UVec3::new(33, 31, 39), Some(true));
+        let mut tiles = 0;
+        tree.for_each_tile_in_aabb(UVec3::ZERO, UVec3::splat(127), |_, _, _| tiles += 1);
+        assert!(tiles > 0);
+        for x in 14..=33 {
+            for y in 16..=31 {
+                for z in 16..=39 {
+                    occupied.insert(UVec3::new(x, y, z));
+                }
+            }
+        }
+
+        let band = 2.5;
+        let sdf: Tree<hierarchy!(3, 2, 2; f32)> = tree.to_sdf(band);
+        let brute_force = |coords: UVec3| -> f32 {
+            let inside = occupied.contains(&coords);
+            let mut best = f32::MAX;
+            for x in coords.x.saturating_sub(3)..=coords.x + 3 {
+                for y in coords.y.saturating_sub(3)..=coords.y + 3 {
+                    for z in coords.z.saturating_sub(3)..=coords.z + 3 {
+                        let other = UVec3::new(x, y, z);
+                        if occupied.contains(&other) != inside {
+                            let distance = (other.as_vec3() - coords.as_vec3()).length() - 0.5;
+                            best = best.min(distance);
+                        }
+                    }
+                }
+            }
+            if inside {
+                -best
+            } else {
+                best
+            }
+        };
+        for coords in sdf.iter() {
+            assert_eq!(
+                sdf.get_value(coords),
+                Some(brute_force(coords)),
+                "{:?}",
+                coords
+            );
+        }
+        for _i in 0..20000 {
+            let coords = UVec3::new(
+                rng.gen_range(0..80),
+                rng.gen_range(0..80),
+                rng.gen_range(0..80),
+            );
+            let expected = brute_force(coords);
+            if expected.abs() <= band {
+                assert_eq!(sdf.get_value(coords), Some(expected), "{:?}", coords);
+            } else {
+                assert_eq!(sdf.get_value(coords), None, "{:?}", coords);
+            }
+        }
+    }
+
+    #[test]
+    fn test_fill_sdf() {
+        let mut tree = Tree::<hierarchy!(3, 2, 2; u8)>::new();
+        let center = Vec3::new(60.0, 50.0, 70.0);
+        let sphere = |p: Vec3| (p - center).length() - 30.5;
+        let min = UVec3::new(20, 10, 30);
+        let max = UVec3::new(90, 90, 80);
+        tree.fill_sdf(min, max, 7, sphere);
+
+        let mut count = 0;
+        for x in min.x..=max.x {
+            for y in min.y..=max.y {
+                for z in min.z..=max.z {
+                    let coords = UVec3::new(x, y, z);
+                    let inside = sphere(coords.as_vec3() + Vec3::splat(0.5)) <= 0.0;
+                    assert_eq!(tree.get_value(coords).is_some(), inside, "{:?}", coords);
+                    count += inside as u64;
+                }
+            }
+        }
+        assert_eq!(tree.count_active(), count);
+    }
+
+    #[test]
+    fn test_signed() {
+        let mut tree = Tree::<hierarchy!(#, 3, 2; u8)>::new();
+        let center = Vec3::new(0.5, -2.0, 1.0);
+        let sphere = |p: Vec3| (p - center).length() - 12.5;
+        let min = IVec3::new(-20, -20, -20);
+        let max = IVec3::new(20, 10, 20);
+        tree.fill_sdf(min, max, 7, sphere);
+
+        let mut count = 0;
+        for x in min.x..=max.x {
+            for y in min.y..=max.y {
+                for z in min.z..=max.z {
+                    let coords = IVec3::new(x, y, z);
+                    let inside = sphere(coords.as_vec3() + Vec3::splat(0.5)) <= 0.0;
+                    assert_eq!(tree.get_value(coords).is_some(), inside, "{:?}", coords);
+                    count += inside as u64;
+                }
+            }
+        }
+        assert_eq!(tree.count_active(), count);
+
+        // The distances follow the sphere, on both sides of the signed origin.
+        let sdf: Tree<hierarchy!(#, 3, 2; f32)> = tree.to_sdf(3.0);
+        for offset in [
+            IVec3::new(-13, -2, 1),
+            IVec3::new(12, -2, 1),
+            IVec3::new(0, -2, -12),
+        ] {
+            let point = center + offset.as_vec3().normalize() * 12.5;
+            let origin = point.floor().as_ivec3();
+            let sample = sdf
+                .sample_sdf_from(origin, point - origin.as_vec3())
+                .unwrap();
+            assert!(sample.abs() < 0.75, "{:?}: {}", point, sample);
+        }
+        assert!(sdf.sample_sdf_from(IVec3::ZERO, Vec3::splat(0.5)).is_none());
+    }
+
+    #[test]
+    fn test_half() {
+        // Every half precision number survives a round trip.
+        for bits in 0..=u16::MAX {
+            let half = Half(bits);
+            let value = half.to_f32();
+            if value.is_nan() {
+                assert!(Half::from_f32(value).to_f32().is_nan());
+            } else {
+                assert_eq!(Half::from_f32(value), half, "{:#x}", bits);
+            }
+        }
+        assert_eq!(Half::from_f32(1.0), Half(0x3C00));
+        assert_eq!(Half::from_f32(-2.5), Half(0xC100));
+        assert_eq!(Half::from_f32(0.1), Half(0x2E66));
+        assert_eq!(Half::from_f32(65504.0), Half(0x7BFF));
+        assert_eq!(Half::from_f32(65520.0), Half(0x7C00));
+        assert_eq!(Half::from_f32(1e-8), Half(0));
+        assert_eq!(Half::from_f32(2.0_f32.powi(-24)), Half(1));
+        // Ties go to even.
+        assert_eq!(Half::from_f32(1.0 + 2.0_f32.powi(-11)), Half(0x3C00));
+        assert_eq!(Half::from_f32(1.0 + 3.0 * 2.0_f32.powi(-11)), Half(0x3C02));
+        assert_eq!(Half::from_f32(3.0 * 2.0_f32.powi(-25)), Half(2));
+    }
+}
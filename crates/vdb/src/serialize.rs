@@ -0,0 +1,311 @@
+use std::{
+    io::{Read, Write},
+    mem::size_of,
+};
+
+use crate::{tree::TreeMeta, BitMask, Node, NodeConst, Pool, Tree};
+
+/// Magic number at the start of all serialized trees.
+const MAGIC: [u8; 4] = *b"DVDB";
+/// Version of the serialization format.
+/// Version 2 stores the keys of root node entries as signed integers.
+const VERSION: u32 = 2;
+
+#[derive(Debug)]
+pub enum SerializeError {
+    Io(std::io::Error),
+    /// The data does not start with the expected magic number.
+    InvalidMagic,
+    /// The data was written with an unsupported version of the format.
+    UnsupportedVersion(u32),
+    /// The data was written for a tree with a different hierarchy.
+    LayoutMismatch {
+        expected: u64,
+        found: u64,
+    },
+    /// The data was written for a tree with a different voxel type.
+    ValueSizeMismatch {
+        expected: u32,
+        found: u32,
+    },
+}
+
+impl std::fmt::Display for SerializeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SerializeError::Io(err) => err.fmt(f),
+            SerializeError::InvalidMagic => f.write_str("not a serialized tree"),
+            SerializeError::UnsupportedVersion(version) => {
+                write!(f, "unsupported format version {}", version)
+            }
+            SerializeError::LayoutMismatch { expected, found } => write!(
+                f,
+                "tree layout mismatch: expected {:#x}, found {:#x}",
+                expected, found
+            ),
+            SerializeError::ValueSizeMismatch { expected, found } => write!(
+                f,
+                "voxel value size mismatch: expected {} bytes, found {} bytes",
+                expected, found
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SerializeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SerializeError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for SerializeError {
+    fn from(err: std::io::Error) -> Self {
+        SerializeError::Io(err)
+    }
+}
+
+/// Voxel values that can be written into serialized trees.
+/// All values are stored in little endian.
+pub trait SerializableValue: Sized {
+    /// Size of the serialized value in bytes.
+    const SIZE: usize;
+    fn write_to<W: Write>(&self, writer: &mut W) -> std::io::Result<()>;
+    fn read_from<R: Read>(reader: &mut R) -> std::io::Result<Self>;
+}
+
+macro_rules! impl_serializable_value {
+    ($($t: ty),*) => {
+        $(
+            impl SerializableValue for $t {
+                const SIZE: usize = size_of::<$t>();
+                #[inline]
+                fn write_to<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+                    writer.write_all(&self.to_le_bytes())
+                }
+                #[inline]
+                fn read_from<R: Read>(reader: &mut R) -> std::io::Result<Self> {
+                    let mut bytes = [0_u8; size_of::<$t>()];
+                    reader.read_exact(&mut bytes)?;
+                    Ok(<$t>::from_le_bytes(bytes))
+                }
+            }
+        )*
+    };
+}
+impl_serializable_value!(u8, u16, u32, u64, i8, i16, i32, i64, f32, f64);
+
+impl SerializableValue for bool {
+    const SIZE: usize = 1;
+    #[inline]
+    fn write_to<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        writer.write_all(&[*self as u8])
+    }
+    #[inline]
+    fn read_from<R: Read>(reader: &mut R) -> std::io::Result<Self> {
+        match u8::read_from(reader)? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(invalid_data("invalid bool value")),
+        }
+    }
+}
+
+impl SerializableValue for () {
+    const SIZE: usize = 0;
+    #[inline]
+    fn write_to<W: Write>(&self, _writer: &mut W) -> std::io::Result<()> {
+        Ok(())
+    }
+    #[inline]
+    fn read_from<R: Read>(_reader: &mut R) -> std::io::Result<Self> {
+        Ok(())
+    }
+}
+
+pub(crate) fn invalid_data(message: &'static str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+}
+
+/// Nodes that can be written into serialized trees.
+/// Nodes are written depth-first, with the children of each node following the node itself.
+pub trait NodeSerialize: Node {
+    /// Write the node and all of its descendants.
+    /// This is called when the node was owned.
+    fn write<W: Write>(&self, pools: &[Pool], writer: &mut W) -> std::io::Result<()>;
+    /// Write the node and all of its descendants.
+    /// This is called when the node was located in a node pool.
+    fn write_in_pools<W: Write>(pools: &[Pool], ptr: u32, writer: &mut W) -> std::io::Result<()>;
+    /// Read the node and all of its descendants into an empty node,
+    /// allocating the descendants from the pools.
+    /// This is called when the node was owned.
+    fn read<R: Read>(&mut self, pools: &mut [Pool], reader: &mut R) -> std::io::Result<()>;
+    /// Read the node and all of its descendants into an empty node,
+    /// allocating the descendants from the pools.
+    /// This is called when the node was located in a node pool.
+    fn read_in_pools<R: Read>(pools: &mut [Pool], ptr: u32, reader: &mut R) -> std::io::Result<()>;
+}
+
+impl<const SIZE: usize> BitMask<SIZE>
+where
+    [(); SIZE / size_of::<usize>() / 8]: Sized,
+{
+    pub(crate) fn write_to<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        for word in self.data.iter() {
+            (*word as u64).write_to(writer)?;
+        }
+        Ok(())
+    }
+    pub(crate) fn read_from<R: Read>(&mut self, reader: &mut R) -> std::io::Result<()> {
+        for word in self.data.iter_mut() {
+            *word = u64::read_from(reader)? as usize;
+        }
+        Ok(())
+    }
+}
+
+/// Serialized trees start with a header containing the magic number, the format version,
+/// the layout of the tree hierarchy and the size of the voxel values.
+/// The nodes reachable from the root node follow in depth-first order.
+/// Nodes released back into the pools are not written, so the pools are compacted when read back.
+/// ```
+/// #![feature(generic_const_exprs)]
+/// use dust_vdb::{Tree, hierarchy};
+/// use glam::UVec3;
+/// let mut tree = Tree::<hierarchy!(4, 2; u16)>::new();
+/// tree.set_value(UVec3::new(0, 1, 2), Some(12));
+/// tree.fill(UVec3::new(16, 16, 16), UVec3::new(31, 31, 31), Some(3));
+///
+/// let mut data: Vec<u8> = Vec::new();
+/// tree.write_to(&mut data).unwrap();
+/// let tree = Tree::<hierarchy!(4, 2; u16)>::read_from(&mut data.as_slice()).unwrap();
+/// assert_eq!(tree.get_value(UVec3::new(0, 1, 2)), Some(12));
+/// assert_eq!(tree.get_value(UVec3::new(20, 20, 20)), Some(3));
+/// assert_eq!(tree.get_value(UVec3::new(0, 0, 0)), None);
+/// ```
+impl<ROOT: NodeSerialize> Tree<ROOT>
+where
+    [(); ROOT::LEVEL as usize + 1]: Sized,
+    ROOT::Voxel: SerializableValue,
+{
+    /// Write the tree into `writer`.
+    /// Consider wrapping `writer` in a [`std::io::BufWriter`] as the nodes are written in small pieces.
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> Result<(), SerializeError>
+    where
+        ROOT: ~const NodeConst,
+    {
+        writer.write_all(&MAGIC)?;
+        VERSION.write_to(writer)?;
+        <Self as TreeMeta<ROOT>>::ID.write_to(writer)?;
+        (ROOT::Voxel::SIZE as u32).write_to(writer)?;
+        self.root.write(&self.pool, writer)?;
+        Ok(())
+    }
+
+    /// Read a tree previously written with [`Tree::write_to`] from `reader`.
+    /// Consider wrapping `reader` in a [`std::io::BufReader`] as the nodes are read in small pieces.
+    pub fn read_from<R: Read>(reader: &mut R) -> Result<Self, SerializeError>
+    where
+        ROOT: ~const NodeConst,
+    {
+        let mut magic = [0_u8; 4];
+        reader.read_exact(&mut magic)?;
+        if magic != MAGIC {
+            return Err(SerializeError::InvalidMagic);
+        }
+        let version = u32::read_from(reader)?;
+        if version != VERSION {
+            return Err(SerializeError::UnsupportedVersion(version));
+        }
+        let layout = u64::read_from(reader)?;
+        if layout != <Self as TreeMeta<ROOT>>::ID {
+            return Err(SerializeError::LayoutMismatch {
+                expected: <Self as TreeMeta<ROOT>>::ID,
+                found: layout,
+            });
+        }
+        let value_size = u32::read_from(reader)?;
+        if value_size != ROOT::Voxel::SIZE as u32 {
+            return Err(SerializeError::ValueSizeMismatch {
+                expected: ROOT::Voxel::SIZE as u32,
+                found: value_size,
+            });
+        }
+        let mut tree = Self::new();
+        tree.root.read(&mut tree.pool, reader)?;
+        Ok(tree)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::UVec3;
+
+    use super::SerializeError;
+    use crate::{hierarchy, Tree};
+
+    #[test]
+    fn test_round_trip() {
+        use rand::prelude::*;
+        let mut rng = rand::thread_rng();
+
+        type MyTree = Tree<hierarchy!(#, 3, 2; u32)>;
+        let mut tree = MyTree::new();
+        let mut set_locations: Vec<(UVec3, u32)> = Vec::with_capacity(1000);
+        for _i in 0..1000 {
+            let location = UVec3::new(rng.gen(), rng.gen(), rng.gen()) >> 20;
+            let value: u32 = rng.gen();
+            tree.set_value(location, Some(value));
+            set_locations.push((location, value));
+        }
+        tree.fill(UVec3::new(64, 0, 0), UVec3::new(127, 31, 31), Some(7));
+        for (location, _) in set_locations.iter().step_by(3) {
+            tree.set_value(*location, None);
+        }
+
+        let mut data: Vec<u8> = Vec::new();
+        tree.write_to(&mut data).unwrap();
+        let read_tree = MyTree::read_from(&mut data.as_slice()).unwrap();
+        for (location, _) in set_locations.iter() {
+            assert_eq!(read_tree.get_value(*location), tree.get_value(*location));
+        }
+        assert_eq!(read_tree.get_value(UVec3::new(100, 20, 10)), Some(7));
+        assert_eq!(read_tree.iter().count(), tree.iter().count());
+        assert_eq!(read_tree.iter_leaf().count(), tree.iter_leaf().count());
+
+        // Writing the tree back produces the same amount of data.
+        let mut data2: Vec<u8> = Vec::new();
+        read_tree.write_to(&mut data2).unwrap();
+        assert_eq!(data.len(), data2.len());
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut tree = Tree::<hierarchy!(4, 2)>::new();
+        tree.set_value(UVec3::new(1, 2, 3), Some(true));
+        let mut data: Vec<u8> = Vec::new();
+        tree.write_to(&mut data).unwrap();
+
+        assert!(matches!(
+            Tree::<hierarchy!(3, 3)>::read_from(&mut data.as_slice()),
+            Err(SerializeError::LayoutMismatch { .. })
+        ));
+        assert!(matches!(
+            Tree::<hierarchy!(4, 2; u16)>::read_from(&mut data.as_slice()),
+            Err(SerializeError::ValueSizeMismatch {
+                expected: 2,
+                found: 1
+            })
+        ));
+        assert!(matches!(
+            Tree::<hierarchy!(4, 2)>::read_from(&mut &data[1..]),
+            Err(SerializeError::InvalidMagic)
+        ));
+        assert!(matches!(
+            Tree::<hierarchy!(4, 2)>::read_from(&mut &data[..data.len() - 1]),
+            Err(SerializeError::Io(_))
+        ));
+    }
+}
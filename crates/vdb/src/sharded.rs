@@ -0,0 +1,285 @@
+use std::{
+    hash::{Hash, Hasher},
+    sync::{Mutex, MutexGuard},
+};
+
+use glam::UVec3;
+
+use crate::{IsRoot, Node, NodeConst, Tree, TreeCoords};
+
+impl<ROOT: IsRoot> Tree<ROOT>
+where
+    [(); ROOT::LEVEL as usize + 1]: Sized,
+{
+    /// Move all voxels of `other` into this tree. The two trees must not have any tile of the
+    /// root node in common, which is checked.
+    ///
+    /// No node is copied: the chunks of memory holding the nodes of `other` are moved into the
+    /// pools of this tree, and only the pointers to the child nodes are rewritten.
+    /// ```
+    /// #![feature(generic_const_exprs)]
+    /// use dust_vdb::{Tree, hierarchy};
+    /// use glam::IVec3;
+    /// let mut tree = Tree::<hierarchy!(#, 4, 2; u8)>::new();
+    /// tree.set_value(IVec3::new(1, 2, 3), Some(1));
+    /// let mut other = Tree::<hierarchy!(#, 4, 2; u8)>::new();
+    /// other.set_value(IVec3::new(-1, 2, 3), Some(2));
+    /// tree.merge_disjoint(other);
+    /// assert_eq!(tree.get_value(IVec3::new(1, 2, 3)), Some(1));
+    /// assert_eq!(tree.get_value(IVec3::new(-1, 2, 3)), Some(2));
+    /// ```
+    pub fn merge_disjoint(&mut self, other: Self) {
+        if self.dirty.is_some() {
+            if let Some((min, max)) = other.bounding_box() {
+                self.mark_dirty_box(min, max);
+            }
+        }
+        let Tree { mut root, pool, .. } = other;
+        let remaps: Vec<Vec<u32>> = pool
+            .into_iter()
+            .zip(self.pool.iter_mut())
+            .map(|(other_pool, pool)| {
+                let stats = other_pool.stats();
+                let offset = pool.append(other_pool);
+                (0..stats.live + stats.free)
+                    .map(|ptr| ptr + offset)
+                    .collect()
+            })
+            .collect();
+        root.remap_children(&mut self.pool, &remaps);
+        self.root.merge_tiles(root);
+    }
+}
+
+/// A tree split into shards that can be written from multiple threads at once.
+///
+/// Each tile of the root node belongs to exactly one shard, picked by hashing the location of
+/// the tile, and each shard is a [`Tree`] behind its own lock. Threads writing into tiles of
+/// different shards never wait on each other. Once populated, the shards are merged into a
+/// single tree with [`ShardedTree::into_tree`] without copying any node.
+/// ```
+/// #![feature(generic_const_exprs)]
+/// use dust_vdb::{ShardedTree, hierarchy};
+/// use glam::IVec3;
+/// let sharded = ShardedTree::<hierarchy!(#, 4, 2; u8)>::new(8);
+/// std::thread::scope(|scope| {
+///     for i in 0..4 {
+///         let sharded = &sharded;
+///         scope.spawn(move || {
+///             for x in 0..100 {
+///                 sharded.set_value(IVec3::new(x * 4 + i, 0, 0), Some(i as u8));
+///             }
+///         });
+///     }
+/// });
+/// let tree = sharded.into_tree();
+/// assert_eq!(tree.count_active(), 400);
+/// assert_eq!(tree.get_value(IVec3::new(398, 0, 0)), Some(2));
+/// ```
+pub struct ShardedTree<ROOT: Node>
+where
+    [(); ROOT::LEVEL as usize]: Sized,
+{
+    shards: Box<[Mutex<Tree<ROOT>>]>,
+}
+
+impl<ROOT: IsRoot> ShardedTree<ROOT>
+where
+    [(); ROOT::LEVEL as usize + 1]: Sized,
+{
+    pub fn new(num_shards: usize) -> Self
+    where
+        ROOT: ~const NodeConst,
+    {
+        assert!(num_shards > 0, "A sharded tree needs at least one shard");
+        Self {
+            shards: (0..num_shards).map(|_| Mutex::new(Tree::new())).collect(),
+        }
+    }
+
+    pub fn num_shards(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// Returns the index of the shard owning the tile of the root node containing `coords`.
+    #[inline]
+    pub fn shard_index(&self, coords: impl TreeCoords<ROOT>) -> usize {
+        let tile = coords.to_tree_coords() >> ROOT::TILE_EXTENT_LOG2;
+        let mut hasher = fxhash::FxHasher::default();
+        tile.to_array().hash(&mut hasher);
+        (hasher.finish() % self.shards.len() as u64) as usize
+    }
+
+    /// Lock the shard owning the tile of the root node containing `coords`, to read or write
+    /// many voxels without locking for each of them.
+    ///
+    /// Only the tiles owned by the shard may be written through the returned tree, that is
+    /// the tiles containing coordinates for which [`ShardedTree::shard_index`] is the index of
+    /// the shard. Otherwise, [`ShardedTree::into_tree`] panics.
+    pub fn lock(&self, coords: impl TreeCoords<ROOT>) -> MutexGuard<'_, Tree<ROOT>> {
+        self.lock_shard(self.shard_index(coords))
+    }
+
+    /// Lock the shard at `index`. See [`ShardedTree::lock`].
+    pub fn lock_shard(&self, index: usize) -> MutexGuard<'_, Tree<ROOT>> {
+        // A panic while holding the lock leaves the shard in a consistent state,
+        // since trees are only edited through safe methods.
+        self.shards[index]
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    pub fn get_value(&self, coords: impl TreeCoords<ROOT>) -> Option<ROOT::Voxel> {
+        self.lock(coords).get_value(coords)
+    }
+
+    /// Set the value of a voxel. See [`Tree::set_value`].
+    pub fn set_value(&self, coords: impl TreeCoords<ROOT>, value: Option<ROOT::Voxel>) {
+        self.lock(coords).set_value(coords, value)
+    }
+
+    /// Set all voxels within `min` and `max` (inclusive) to `value`. The box is split along the
+    /// tiles of the root node, and each part is filled while holding the lock of its shard only.
+    /// See [`Tree::fill`].
+    pub fn fill<C: TreeCoords<ROOT>>(&self, min: C, max: C, value: Option<ROOT::Voxel>) {
+        let min = min.to_tree_coords();
+        let max = max.to_tree_coords();
+        if min.cmpgt(max).any() {
+            return;
+        }
+        let log2 = ROOT::TILE_EXTENT_LOG2;
+        let (tile_min, tile_max) = (min >> log2, max >> log2);
+        for x in tile_min.x..=tile_max.x {
+            for y in tile_min.y..=tile_max.y {
+                for z in tile_min.z..=tile_max.z {
+                    let origin = UVec3::new(x, y, z) << log2;
+                    let end = origin + ((UVec3::ONE << log2) - 1);
+                    self.lock(origin).fill(origin.max(min), end.min(max), value);
+                }
+            }
+        }
+    }
+
+    /// Merge all shards into a single tree, moving their nodes without copying them.
+    /// See [`Tree::merge_disjoint`].
+    pub fn into_tree(self) -> Tree<ROOT>
+    where
+        ROOT: ~const NodeConst,
+    {
+        let mut shards = self.shards.into_vec().into_iter().map(|shard| {
+            shard
+                .into_inner()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+        });
+        let mut tree = shards.next().unwrap_or_else(Tree::new);
+        for shard in shards {
+            tree.merge_disjoint(shard);
+        }
+        tree
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use glam::IVec3;
+
+    use super::ShardedTree;
+    use crate::{hierarchy, Tree};
+
+    type MyRoot = hierarchy!(#, 3, 2; u16);
+
+    #[test]
+    fn test_sharded_contention() {
+        use rand::prelude::*;
+        const THREADS: usize = 16;
+        let sharded = ShardedTree::<MyRoot>::new(4);
+        // Every thread writes into the same few tiles, but never into the same voxel,
+        // so the result does not depend on the order of the writes.
+        let writes: Vec<Vec<(IVec3, u16)>> = (0..THREADS)
+            .map(|thread| {
+                let mut rng = StdRng::seed_from_u64(thread as u64);
+                (0..5000)
+                    .map(|_| {
+                        let coords = IVec3::new(
+                            rng.gen_range(-64..64),
+                            rng.gen_range(-64..64),
+                            rng.gen_range(-64..64),
+                        );
+                        let coords = coords - coords % THREADS as i32 + thread as i32;
+                        (coords, rng.gen())
+                    })
+                    .collect()
+            })
+            .collect();
+        std::thread::scope(|scope| {
+            for (thread, writes) in writes.iter().enumerate() {
+                let sharded = &sharded;
+                scope.spawn(move || {
+                    for (i, (coords, value)) in writes.iter().enumerate() {
+                        if i % 100 == 0 {
+                            // Batch of writes under a single lock.
+                            let mut shard = sharded.lock(*coords);
+                            shard.set_value(*coords, Some(*value));
+                        } else {
+                            sharded.set_value(*coords, Some(*value));
+                        }
+                    }
+                    // Disjoint boxes crossing many tiles.
+                    let z = 100 + thread as i32 * 3;
+                    sharded.fill(IVec3::new(-40, -40, z), IVec3::new(40, 40, z + 1), Some(7));
+                });
+            }
+        });
+
+        let mut expected: HashMap<IVec3, u16> = HashMap::new();
+        for writes in writes.iter() {
+            for (coords, value) in writes.iter() {
+                expected.insert(*coords, *value);
+            }
+        }
+        for thread in 0..THREADS as i32 {
+            for x in -40..=40 {
+                for y in -40..=40 {
+                    for z in 0..2 {
+                        expected.insert(IVec3::new(x, y, 100 + thread * 3 + z), 7);
+                    }
+                }
+            }
+        }
+
+        let num_nodes: u32 = (0..sharded.num_shards())
+            .flat_map(|i| sharded.lock_shard(i).memory_stats())
+            .map(|stats| stats.live)
+            .sum();
+        let tree: Tree<MyRoot> = sharded.into_tree();
+        assert_eq!(tree.count_active(), expected.len() as u64);
+        for (coords, value) in expected.iter() {
+            assert_eq!(tree.get_value(*coords), Some(*value));
+        }
+        assert_eq!(
+            tree.memory_stats()
+                .iter()
+                .map(|stats| stats.live)
+                .sum::<u32>(),
+            num_nodes
+        );
+
+        // The merged tree keeps working as a regular tree.
+        let mut tree = tree;
+        tree.fill(IVec3::new(-64, -64, -64), IVec3::new(63, 63, 63), None);
+        tree.prune();
+        assert_eq!(tree.count_active(), (THREADS * 81 * 81 * 2) as u64);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_merge_overlapping() {
+        let mut tree = Tree::<MyRoot>::new();
+        tree.set_value(IVec3::new(1, 2, 3), Some(1));
+        let mut other = Tree::<MyRoot>::new();
+        other.set_value(IVec3::new(2, 2, 3), Some(2));
+        tree.merge_disjoint(other);
+    }
+}
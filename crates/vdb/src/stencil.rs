@@ -0,0 +1,258 @@
+use glam::{IVec3, UVec3};
+
+use crate::{
+    journal::iter_set_bits, parallel::leaf_local_coords, Accessor, IsLeaf, Node, NodeConst, Tree,
+    TreeCoords,
+};
+
+/// Offsets to the six face neighbors of a voxel.
+pub const FACE_NEIGHBORS: [IVec3; 6] = [
+    IVec3::new(-1, 0, 0),
+    IVec3::new(1, 0, 0),
+    IVec3::new(0, -1, 0),
+    IVec3::new(0, 1, 0),
+    IVec3::new(0, 0, -1),
+    IVec3::new(0, 0, 1),
+];
+
+/// Offsets to the 18 neighbors of a voxel sharing a face or an edge with it,
+/// ordered by x, then y, then z.
+pub const EDGE_NEIGHBORS: [IVec3; 18] = {
+    let mut offsets = [IVec3::ZERO; 18];
+    let mut i = 0;
+    let mut index = 0;
+    while i < 26 {
+        let offset = ALL_NEIGHBORS[i];
+        if offset.x.abs() + offset.y.abs() + offset.z.abs() <= 2 {
+            offsets[index] = offset;
+            index += 1;
+        }
+        i += 1;
+    }
+    offsets
+};
+
+/// Offsets to all 26 neighbors of a voxel sharing a face, an edge or a corner with it,
+/// ordered by x, then y, then z.
+pub const ALL_NEIGHBORS: [IVec3; 26] = {
+    let mut offsets = [IVec3::ZERO; 26];
+    let mut i = 0;
+    let mut index = 0;
+    while i < 27 {
+        if i != 13 {
+            offsets[index] = IVec3::new(i / 9 - 1, (i / 3) % 3 - 1, i % 3 - 1);
+            index += 1;
+        }
+        i += 1;
+    }
+    offsets
+};
+
+/// Reads a voxel together with the voxels at a fixed set of offsets around it.
+///
+/// Only the path to the center voxel is cached. Neighbors within the same leaf node are read from
+/// the cached leaf node, and the others are looked up starting from the lowest node they share
+/// with the center voxel, so the path is walked again only where a neighbor crosses into
+/// another node.
+/// ```
+/// #![feature(generic_const_exprs)]
+/// use dust_vdb::{Tree, hierarchy, FACE_NEIGHBORS};
+/// use glam::UVec3;
+/// let mut tree = Tree::<hierarchy!(4, 2; u8)>::new();
+/// tree.set_value(UVec3::new(3, 3, 3), Some(1));
+/// tree.set_value(UVec3::new(4, 3, 3), Some(2));
+/// let mut stencil = tree.stencil(FACE_NEIGHBORS);
+/// let (center, neighbors) = stencil.get(UVec3::new(3, 3, 3));
+/// assert_eq!(center, Some(1));
+/// assert_eq!(neighbors, [None, Some(2), None, None, None, None]);
+///
+/// // Neighbors outside of the tree are None.
+/// let (center, neighbors) = stencil.get(UVec3::new(0, 0, 0));
+/// assert_eq!(center, None);
+/// assert_eq!(neighbors[0], None);
+/// ```
+pub struct Stencil<'a, ROOT: Node, const N: usize>
+where
+    [(); ROOT::LEVEL as usize]: Sized,
+{
+    offsets: [IVec3; N],
+    center: Accessor<'a, ROOT>,
+}
+
+impl<'a, ROOT: Node, const N: usize> Stencil<'a, ROOT, N>
+where
+    [(); ROOT::LEVEL as usize + 1]: Sized,
+{
+    pub fn new(tree: &'a Tree<ROOT>, offsets: [IVec3; N]) -> Self {
+        Self {
+            offsets,
+            center: tree.accessor(),
+        }
+    }
+
+    pub fn offsets(&self) -> &[IVec3; N] {
+        &self.offsets
+    }
+
+    /// Returns the value of the voxel at `coords`, and the values of the voxels at each offset from it.
+    /// Neighbors located outside of the tree are None.
+    #[inline]
+    pub fn get(
+        &mut self,
+        coords: impl TreeCoords<ROOT>,
+    ) -> (Option<ROOT::Voxel>, [Option<ROOT::Voxel>; N])
+    where
+        ROOT: ~const NodeConst,
+    {
+        let coords = coords.to_tree_coords();
+        let center = self.center.get(coords);
+        (center, self.neighbors(coords))
+    }
+
+    /// Returns the values of the voxels at each offset from `coords`, which must be in the same
+    /// leaf node as the voxel last passed to [`Stencil::get`].
+    #[inline]
+    fn neighbors(&self, coords: UVec3) -> [Option<ROOT::Voxel>; N]
+    where
+        ROOT: ~const NodeConst,
+    {
+        let mut values = [None; N];
+        for (value, offset) in values.iter_mut().zip(self.offsets.iter()) {
+            if let Some(neighbor) = offset_coords::<ROOT>(coords, *offset) {
+                *value = self.center.get_nearby(neighbor);
+            }
+        }
+        values
+    }
+}
+
+/// Returns `coords + offset`, or None if it falls outside of the tree.
+#[inline]
+pub(crate) fn offset_coords<ROOT: Node>(coords: UVec3, offset: IVec3) -> Option<UVec3> {
+    let result = UVec3 {
+        x: coords.x.checked_add_signed(offset.x)?,
+        y: coords.y.checked_add_signed(offset.y)?,
+        z: coords.z.checked_add_signed(offset.z)?,
+    };
+    if result.cmpgt(ROOT::EXTENT_MASK).any() {
+        return None;
+    }
+    Some(result)
+}
+
+impl<ROOT: Node> Tree<ROOT>
+where
+    [(); ROOT::LEVEL as usize + 1]: Sized,
+{
+    /// Create a [`Stencil`] reading the voxels at `offsets` around each visited voxel.
+    pub fn stencil<const N: usize>(&self, offsets: [IVec3; N]) -> Stencil<ROOT, N> {
+        Stencil::new(self, offsets)
+    }
+
+    /// Iterate over all voxels in the tree along with their value and the values of the voxels at
+    /// `offsets` around them. Voxels are visited one leaf node at a time, reading the voxels from
+    /// the leaf node and looking up the neighbors from the path cached for the leaf node.
+    /// The voxels within tiles are visited afterwards, one tile at a time.
+    /// ```
+    /// #![feature(generic_const_exprs)]
+    /// use dust_vdb::{Tree, hierarchy, ALL_NEIGHBORS};
+    /// use glam::UVec3;
+    /// let mut tree = Tree::<hierarchy!(4, 2)>::new();
+    /// tree.fill(UVec3::new(2, 2, 2), UVec3::new(4, 4, 4), Some(true));
+    /// // Only the center of the cube is fully surrounded.
+    /// let enclosed: Vec<UVec3> = tree
+    ///     .iter_stencil(ALL_NEIGHBORS)
+    ///     .filter(|(_, _, neighbors)| neighbors.iter().all(Option::is_some))
+    ///     .map(|(coords, _, _)| coords)
+    ///     .collect();
+    /// assert_eq!(enclosed, vec![UVec3::new(3, 3, 3)]);
+    /// ```
+    pub fn iter_stencil<const N: usize>(
+        &self,
+        offsets: [IVec3; N],
+    ) -> impl Iterator<Item = (UVec3, ROOT::Voxel, [Option<ROOT::Voxel>; N])> + '_
+    where
+        ROOT: ~const NodeConst,
+        ROOT::LeafType: IsLeaf + Node<Voxel = ROOT::Voxel>,
+    {
+        let mut stencil = self.stencil(offsets);
+        let leaf_voxels = self.iter_leaf().flat_map(move |(origin, leaf)| {
+            // Cache the path to the leaf node for the lookups of all its voxels.
+            stencil.center.get(origin);
+            let mut occupancy = vec![0; (ROOT::LeafType::SIZE + 63) / 64];
+            leaf.get_occupancy(&mut occupancy);
+            iter_set_bits(&occupancy)
+                .map(|index| {
+                    let local = leaf_local_coords::<ROOT>(index);
+                    let coords = origin + local;
+                    let value = leaf
+                        .get(&[], local, &mut [])
+                        .expect("Voxels of the occupancy mask are always occupied");
+                    (coords, value, stencil.neighbors(coords))
+                })
+                .collect::<Vec<_>>()
+        });
+
+        let mut tiles = Vec::new();
+        self.for_each_tile_in_aabb(UVec3::ZERO, ROOT::EXTENT_MASK, |min, max, value| {
+            tiles.push((min, max, value));
+        });
+        let mut stencil = self.stencil(offsets);
+        let tile_voxels = tiles
+            .into_iter()
+            .flat_map(|(min, max, value)| {
+                (min.x..=max.x).flat_map(move |x| {
+                    (min.y..=max.y)
+                        .flat_map(move |y| (min.z..=max.z).map(move |z| (UVec3 { x, y, z }, value)))
+                })
+            })
+            .map(move |(coords, value)| (coords, value, stencil.get(coords).1));
+        leaf_voxels.chain(tile_voxels)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::UVec3;
+
+    use super::{offset_coords, ALL_NEIGHBORS};
+    use crate::{hierarchy, Tree};
+
+    #[test]
+    fn test_stencil() {
+        use rand::prelude::*;
+        let mut rng = rand::thread_rng();
+        type MyRoot = hierarchy!(3, 2, 2; u8);
+        let mut tree = Tree::<MyRoot>::new();
+        for _i in 0..3000 {
+            let location = UVec3::new(rng.gen(), rng.gen(), rng.gen()) >> 25;
+            tree.set_value(location, Some(rng.gen()));
+        }
+        tree.fill(UVec3::new(32, 96, 32), UVec3::new(63, 127, 63), Some(9));
+
+        let mut count = 0;
+        for (coords, value, neighbors) in tree.iter_stencil(ALL_NEIGHBORS) {
+            assert_eq!(tree.get_value(coords), Some(value));
+            for (offset, neighbor) in ALL_NEIGHBORS.iter().zip(neighbors.iter()) {
+                let expected =
+                    offset_coords::<MyRoot>(coords, *offset).and_then(|n| tree.get_value(n));
+                assert_eq!(*neighbor, expected);
+            }
+            count += 1;
+        }
+        assert_eq!(count, tree.count_active());
+
+        // Voxels visited in any order, within tiles, leaf nodes or empty space.
+        let mut stencil = tree.stencil(ALL_NEIGHBORS);
+        for _i in 0..3000 {
+            let coords = UVec3::new(rng.gen(), rng.gen(), rng.gen()) >> 25;
+            let (value, neighbors) = stencil.get(coords);
+            assert_eq!(value, tree.get_value(coords));
+            for (offset, neighbor) in ALL_NEIGHBORS.iter().zip(neighbors.iter()) {
+                let expected =
+                    offset_coords::<MyRoot>(coords, *offset).and_then(|n| tree.get_value(n));
+                assert_eq!(*neighbor, expected);
+            }
+        }
+    }
+}
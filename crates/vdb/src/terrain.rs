@@ -0,0 +1,511 @@
+use glam::{IVec3, UVec3, Vec2, Vec3};
+
+use crate::{Node, NodeConst, Tree, TreeCoords};
+
+/// Height of the terrain surface above each column of voxels, in voxels.
+/// Implemented for closures, so that sources can be scaled and combined freely.
+pub trait HeightSource: Sync {
+    fn height(&self, x: f32, z: f32) -> f32;
+}
+
+impl<F: Fn(f32, f32) -> f32 + Sync> HeightSource for F {
+    #[inline]
+    fn height(&self, x: f32, z: f32) -> f32 {
+        self(x, z)
+    }
+}
+
+/// Scalar field sampled in 3D, used to carve caves into the terrain.
+/// Implemented for closures, so that sources can be scaled and combined freely.
+pub trait DensitySource: Sync {
+    fn density(&self, point: Vec3) -> f32;
+}
+
+impl<F: Fn(Vec3) -> f32 + Sync> DensitySource for F {
+    #[inline]
+    fn density(&self, point: Vec3) -> f32 {
+        self(point)
+    }
+}
+
+/// Mixes the seed and the coordinates of a lattice point into well distributed bits.
+#[inline]
+fn hash(seed: u32, x: i32, y: i32, z: i32) -> u32 {
+    let mut h = seed
+        ^ (x as u32).wrapping_mul(0x8da6_b343)
+        ^ (y as u32).wrapping_mul(0xd816_3841)
+        ^ (z as u32).wrapping_mul(0xcb1a_b31f);
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x7feb_352d);
+    h ^= h >> 15;
+    h = h.wrapping_mul(0x846c_a68b);
+    h ^= h >> 16;
+    h
+}
+
+#[inline]
+fn fade(t: f32) -> f32 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+#[inline]
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+const GRADIENTS_2D: [Vec2; 8] = [
+    Vec2::new(1.0, 0.0),
+    Vec2::new(-1.0, 0.0),
+    Vec2::new(0.0, 1.0),
+    Vec2::new(0.0, -1.0),
+    Vec2::new(
+        std::f32::consts::FRAC_1_SQRT_2,
+        std::f32::consts::FRAC_1_SQRT_2,
+    ),
+    Vec2::new(
+        -std::f32::consts::FRAC_1_SQRT_2,
+        std::f32::consts::FRAC_1_SQRT_2,
+    ),
+    Vec2::new(
+        std::f32::consts::FRAC_1_SQRT_2,
+        -std::f32::consts::FRAC_1_SQRT_2,
+    ),
+    Vec2::new(
+        -std::f32::consts::FRAC_1_SQRT_2,
+        -std::f32::consts::FRAC_1_SQRT_2,
+    ),
+];
+
+const GRADIENTS_3D: [Vec3; 12] = [
+    Vec3::new(1.0, 1.0, 0.0),
+    Vec3::new(-1.0, 1.0, 0.0),
+    Vec3::new(1.0, -1.0, 0.0),
+    Vec3::new(-1.0, -1.0, 0.0),
+    Vec3::new(1.0, 0.0, 1.0),
+    Vec3::new(-1.0, 0.0, 1.0),
+    Vec3::new(1.0, 0.0, -1.0),
+    Vec3::new(-1.0, 0.0, -1.0),
+    Vec3::new(0.0, 1.0, 1.0),
+    Vec3::new(0.0, -1.0, 1.0),
+    Vec3::new(0.0, 1.0, -1.0),
+    Vec3::new(0.0, -1.0, -1.0),
+];
+
+/// Gradient noise in 2D, roughly within [-1, 1].
+/// The gradients are hashed from the seed rather than looked up in a permutation table,
+/// so the noise does not repeat and is identical on all platforms.
+fn gradient_noise_2d(seed: u32, point: Vec2) -> f32 {
+    let cell = point.floor();
+    let (x, y) = (cell.x as i32, cell.y as i32);
+    let offset = point - cell;
+    let corner = |dx: i32, dy: i32| {
+        let gradient = GRADIENTS_2D[(hash(seed, x + dx, y + dy, 0) % 8) as usize];
+        gradient.dot(offset - Vec2::new(dx as f32, dy as f32))
+    };
+    let (u, v) = (fade(offset.x), fade(offset.y));
+    let value = lerp(
+        lerp(corner(0, 0), corner(1, 0), u),
+        lerp(corner(0, 1), corner(1, 1), u),
+        v,
+    );
+    value * std::f32::consts::SQRT_2
+}
+
+/// Gradient noise in 3D, roughly within [-1, 1]. See [`gradient_noise_2d`].
+fn gradient_noise_3d(seed: u32, point: Vec3) -> f32 {
+    let cell = point.floor();
+    let (x, y, z) = (cell.x as i32, cell.y as i32, cell.z as i32);
+    let offset = point - cell;
+    let corner = |dx: i32, dy: i32, dz: i32| {
+        let gradient = GRADIENTS_3D[(hash(seed, x + dx, y + dy, z + dz) % 12) as usize];
+        gradient.dot(offset - Vec3::new(dx as f32, dy as f32, dz as f32))
+    };
+    let (u, v, w) = (fade(offset.x), fade(offset.y), fade(offset.z));
+    lerp(
+        lerp(
+            lerp(corner(0, 0, 0), corner(1, 0, 0), u),
+            lerp(corner(0, 1, 0), corner(1, 1, 0), u),
+            v,
+        ),
+        lerp(
+            lerp(corner(0, 0, 1), corner(1, 0, 1), u),
+            lerp(corner(0, 1, 1), corner(1, 1, 1), u),
+            v,
+        ),
+        w,
+    )
+}
+
+/// Fractal Brownian motion: octaves of gradient noise at increasing frequencies and decreasing
+/// amplitudes, normalized to stay roughly within [-1, 1]. The same seed always gives the same noise.
+/// ```
+/// use dust_vdb::{Fbm, HeightSource};
+/// let hills = Fbm::new(7, 4, 1.0 / 64.0);
+/// let height = |x: f32, z: f32| 32.0 + 16.0 * hills.height(x, z);
+/// assert_eq!(height(10.0, 20.0), height(10.0, 20.0));
+/// assert!((16.0..=48.0).contains(&height(10.0, 20.0)));
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Fbm {
+    pub seed: u32,
+    pub octaves: u32,
+    /// Frequency of the first octave, in cycles per voxel.
+    pub frequency: f32,
+    /// Factor between the frequencies of successive octaves.
+    pub lacunarity: f32,
+    /// Factor between the amplitudes of successive octaves.
+    pub gain: f32,
+}
+
+impl Fbm {
+    pub fn new(seed: u32, octaves: u32, frequency: f32) -> Self {
+        Self {
+            seed,
+            octaves,
+            frequency,
+            lacunarity: 2.0,
+            gain: 0.5,
+        }
+    }
+
+    /// Sum the octaves of `noise`, each octave using its own seed.
+    #[inline]
+    fn sum<P: std::ops::Mul<f32, Output = P> + Copy>(
+        &self,
+        point: P,
+        noise: impl Fn(u32, P) -> f32,
+    ) -> f32 {
+        let mut frequency = self.frequency;
+        let mut amplitude = 1.0;
+        let mut sum = 0.0;
+        let mut total = 0.0;
+        for octave in 0..self.octaves {
+            sum += amplitude * noise(self.seed.wrapping_add(octave), point * frequency);
+            total += amplitude;
+            frequency *= self.lacunarity;
+            amplitude *= self.gain;
+        }
+        if total > 0.0 {
+            sum / total
+        } else {
+            0.0
+        }
+    }
+}
+
+impl HeightSource for Fbm {
+    fn height(&self, x: f32, z: f32) -> f32 {
+        self.sum(Vec2::new(x, z), gradient_noise_2d)
+    }
+}
+
+impl DensitySource for Fbm {
+    fn density(&self, point: Vec3) -> f32 {
+        self.sum(point, gradient_noise_3d)
+    }
+}
+
+/// Ridged multifractal noise: like [`Fbm`], but with each octave folded into sharp crests,
+/// giving mountain ranges and winding tunnels. Values are within [0, 1], with 1 along the crests.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Ridged(pub Fbm);
+
+impl Ridged {
+    pub fn new(seed: u32, octaves: u32, frequency: f32) -> Self {
+        Self(Fbm::new(seed, octaves, frequency))
+    }
+}
+
+#[inline]
+fn ridge(value: f32) -> f32 {
+    let value = 1.0 - value.abs().min(1.0);
+    value * value
+}
+
+impl HeightSource for Ridged {
+    fn height(&self, x: f32, z: f32) -> f32 {
+        self.0.sum(Vec2::new(x, z), |seed, point| {
+            ridge(gradient_noise_2d(seed, point))
+        })
+    }
+}
+
+impl DensitySource for Ridged {
+    fn density(&self, point: Vec3) -> f32 {
+        self.0
+            .sum(point, |seed, point| ridge(gradient_noise_3d(seed, point)))
+    }
+}
+
+/// Heights read from an image, one pixel per column of voxels, interpolated bilinearly in
+/// between. Pixels are stored row by row along `x`, with one row per `z`. Columns outside of the
+/// image take the height of the nearest edge.
+/// ```
+/// use dust_vdb::{Heightmap, HeightSource};
+/// let heightmap = Heightmap::from_luma8(2, 1, &[0, 255], 10.0);
+/// assert_eq!(heightmap.height(0.5, 0.0), 5.0);
+/// assert_eq!(heightmap.height(-3.0, 4.0), 0.0);
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct Heightmap {
+    width: u32,
+    depth: u32,
+    heights: Vec<f32>,
+}
+
+impl Heightmap {
+    pub fn new(width: u32, depth: u32, heights: Vec<f32>) -> Self {
+        assert!(
+            width > 0 && depth > 0,
+            "A heightmap needs at least one pixel"
+        );
+        assert_eq!(heights.len(), width as usize * depth as usize);
+        Self {
+            width,
+            depth,
+            heights,
+        }
+    }
+
+    /// Build a heightmap from 8 bit grayscale pixels, mapping 255 to `max_height`.
+    pub fn from_luma8(width: u32, depth: u32, pixels: &[u8], max_height: f32) -> Self {
+        let scale = max_height / u8::MAX as f32;
+        Self::new(
+            width,
+            depth,
+            pixels.iter().map(|pixel| *pixel as f32 * scale).collect(),
+        )
+    }
+
+    /// Build a heightmap from 16 bit grayscale pixels, mapping 65535 to `max_height`.
+    pub fn from_luma16(width: u32, depth: u32, pixels: &[u16], max_height: f32) -> Self {
+        let scale = max_height / u16::MAX as f32;
+        Self::new(
+            width,
+            depth,
+            pixels.iter().map(|pixel| *pixel as f32 * scale).collect(),
+        )
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn depth(&self) -> u32 {
+        self.depth
+    }
+
+    #[inline]
+    fn pixel(&self, x: u32, z: u32) -> f32 {
+        self.heights[z as usize * self.width as usize + x as usize]
+    }
+}
+
+impl HeightSource for Heightmap {
+    fn height(&self, x: f32, z: f32) -> f32 {
+        let x = x.clamp(0.0, (self.width - 1) as f32);
+        let z = z.clamp(0.0, (self.depth - 1) as f32);
+        let (x0, z0) = (x.floor() as u32, z.floor() as u32);
+        let (x1, z1) = ((x0 + 1).min(self.width - 1), (z0 + 1).min(self.depth - 1));
+        let (u, v) = (x - x0 as f32, z - z0 as f32);
+        lerp(
+            lerp(self.pixel(x0, z0), self.pixel(x1, z0), u),
+            lerp(self.pixel(x0, z1), self.pixel(x1, z1), u),
+            v,
+        )
+    }
+}
+
+/// Description of a terrain: voxels lie below the surface given by a [`HeightSource`], minus the
+/// caves carved where a [`DensitySource`] exceeds a threshold. The value of each voxel is picked
+/// by a material function from its coordinates and its depth below the surface.
+///
+/// Voxels only depend on their own coordinates, so any part of the terrain can be generated,
+/// dropped and generated again later with identical results.
+/// ```
+/// #![feature(generic_const_exprs)]
+/// use dust_vdb::{Fbm, HeightSource, Terrain, Tree, hierarchy};
+/// use glam::IVec3;
+/// let hills = Fbm::new(1, 4, 1.0 / 32.0);
+/// let terrain = Terrain::new(
+///     move |x, z| 8.0 + 4.0 * hills.height(x, z),
+///     |_, depth| if depth < 1.0 { 1u8 } else { 2 },
+/// )
+/// .with_caves(Fbm::new(2, 2, 1.0 / 16.0), 0.3);
+/// let mut tree = Tree::<hierarchy!(#, 4, 2; u8)>::new();
+/// tree.generate_terrain(&terrain, IVec3::new(-32, -8, -32), IVec3::new(31, 23, 31));
+/// assert_eq!(tree.get_value(IVec3::new(5, 30, 5)), None);
+/// assert_eq!(tree.get_value(IVec3::new(5, 0, 5)), terrain.sample(IVec3::new(5, 0, 5)));
+/// ```
+pub struct Terrain<T> {
+    height: Box<dyn HeightSource>,
+    caves: Option<(Box<dyn DensitySource>, f32)>,
+    material: Box<dyn Fn(IVec3, f32) -> T + Sync>,
+}
+
+impl<T> Terrain<T> {
+    /// The voxel at `(x, y, z)` is solid when `y` is below `height.height(x, z)`. `material` is
+    /// called with the coordinates of each solid voxel and its depth below the surface, which is
+    /// within (0, 1] for the topmost voxel of each column.
+    pub fn new(
+        height: impl HeightSource + 'static,
+        material: impl Fn(IVec3, f32) -> T + Sync + 'static,
+    ) -> Self {
+        Self {
+            height: Box::new(height),
+            caves: None,
+            material: Box::new(material),
+        }
+    }
+
+    /// Carve caves out of the voxels where `density` is above `threshold`.
+    pub fn with_caves(mut self, density: impl DensitySource + 'static, threshold: f32) -> Self {
+        self.caves = Some((Box::new(density), threshold));
+        self
+    }
+
+    /// Value of the voxel at `coords`, or None when it is empty.
+    pub fn sample(&self, coords: IVec3) -> Option<T> {
+        let height = self.height.height(coords.x as f32, coords.z as f32);
+        self.sample_column(coords, height)
+    }
+
+    #[inline]
+    fn sample_column(&self, coords: IVec3, height: f32) -> Option<T> {
+        let y = coords.y as f32;
+        if y >= height {
+            return None;
+        }
+        if let Some((density, threshold)) = &self.caves {
+            if density.density(coords.as_vec3()) > *threshold {
+                return None;
+            }
+        }
+        Some((self.material)(coords, height - y))
+    }
+
+    /// Write the voxels of the leaf-sized region at `origin` into `values`, indexed like the
+    /// occupancy mask of leaf nodes. The height is evaluated once per column.
+    fn fill_leaf(&self, origin: IVec3, log2: UVec3, values: &mut [Option<T>]) {
+        let size = UVec3::ONE << log2;
+        for x in 0..size.x {
+            for z in 0..size.z {
+                let height = self
+                    .height
+                    .height((origin.x + x as i32) as f32, (origin.z + z as i32) as f32);
+                for y in 0..size.y {
+                    let index = ((x as usize) << (log2.y + log2.z))
+                        | ((y as usize) << log2.z)
+                        | (z as usize);
+                    let coords = origin + UVec3 { x, y, z }.as_ivec3();
+                    values[index] = self.sample_column(coords, height);
+                }
+            }
+        }
+    }
+}
+
+impl<ROOT: Node> Tree<ROOT>
+where
+    [(); ROOT::LEVEL as usize + 1]: Sized,
+    IVec3: TreeCoords<ROOT>,
+{
+    /// Generate `terrain` over every leaf-sized region intersecting the box within `min` and `max`
+    /// (inclusive), overwriting whole regions even where they extend past the box.
+    ///
+    /// The regions are generated in parallel, replacing their previous voxels. Regions that are
+    /// entirely empty or made of a single material are stored as tiles. See [`Tree::par_fill_leaves`].
+    pub fn generate_terrain(&mut self, terrain: &Terrain<ROOT::Voxel>, min: IVec3, max: IVec3)
+    where
+        ROOT: ~const NodeConst + Sync,
+        ROOT::LeafType: Node<Voxel = ROOT::Voxel> + Send,
+        ROOT::Voxel: Send + Sync,
+    {
+        let log2 = ROOT::LeafType::EXTENT_LOG2;
+        self.par_fill_leaves(
+            min.to_tree_coords(),
+            max.to_tree_coords(),
+            |origin, values| terrain.fill_leaf(IVec3::from_tree_coords(origin), log2, values),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::IVec3;
+
+    use super::{Fbm, HeightSource, Heightmap, Ridged, Terrain};
+    use crate::{hierarchy, Tree};
+
+    type MyRoot = hierarchy!(#, 3, 2; u8);
+
+    fn terrain(seed: u32) -> Terrain<u8> {
+        let hills = Fbm::new(seed, 5, 1.0 / 48.0);
+        let mountains = Ridged::new(seed + 1, 3, 1.0 / 96.0);
+        Terrain::new(
+            move |x, z| 4.0 + 12.0 * hills.height(x, z) + 24.0 * mountains.height(x, z),
+            |coords: IVec3, depth| match depth {
+                d if d <= 1.0 => 1,
+                d if d <= 4.0 => 2,
+                _ => 3 + (coords.y < -10) as u8,
+            },
+        )
+        .with_caves(Fbm::new(seed + 2, 3, 1.0 / 24.0), 0.25)
+    }
+
+    #[test]
+    fn test_terrain_deterministic() {
+        let (min, max) = (IVec3::new(-70, -30, -50), IVec3::new(60, 50, 45));
+        let mut whole = Tree::<MyRoot>::new();
+        whole.generate_terrain(&terrain(5), min, max);
+        assert!(whole.count_active() > 0);
+        for coords in whole.iter_signed().step_by(97) {
+            assert_eq!(whole.get_value(coords), terrain(5).sample(coords));
+        }
+
+        // Chunks generated separately, in any order and more than once, give the same tree.
+        let mut chunked = Tree::<MyRoot>::new();
+        chunked.set_value(IVec3::new(0, 0, 0), Some(200));
+        let chunk = 32;
+        let mut chunks = Vec::new();
+        for x in (min.x.div_euclid(chunk)..=max.x.div_euclid(chunk)).rev() {
+            for y in min.y.div_euclid(chunk)..=max.y.div_euclid(chunk) {
+                for z in min.z.div_euclid(chunk)..=max.z.div_euclid(chunk) {
+                    chunks.push(IVec3::new(x, y, z) * chunk);
+                }
+            }
+        }
+        let terrain = terrain(5);
+        for origin in chunks.iter().chain(chunks.iter().step_by(3)) {
+            let chunk_min = origin.max(min);
+            let chunk_max = (*origin + chunk - 1).min(max);
+            chunked.generate_terrain(&terrain, chunk_min, chunk_max);
+        }
+        assert_eq!(chunked.count_active(), whole.count_active());
+        for coords in whole.iter_signed() {
+            assert_eq!(chunked.get_value(coords), whole.get_value(coords));
+        }
+
+        let mut other_seed = Tree::<MyRoot>::new();
+        other_seed.generate_terrain(&self::terrain(6), min, max);
+        assert!(whole
+            .iter_signed()
+            .any(|coords| other_seed.get_value(coords) != whole.get_value(coords)));
+    }
+
+    #[test]
+    fn test_heightmap() {
+        let heightmap = Heightmap::from_luma16(3, 2, &[0, 65535, 0, 65535, 65535, 0], 8.0);
+        assert_eq!(heightmap.height(1.0, 0.0), 8.0);
+        assert_eq!(heightmap.height(0.5, 0.5), 6.0);
+        assert_eq!(heightmap.height(2.0, 5.0), 0.0);
+
+        let terrain = Terrain::new(heightmap, |_, _| 1u8);
+        let mut tree = Tree::<MyRoot>::new();
+        tree.generate_terrain(&terrain, IVec3::new(0, -4, 0), IVec3::new(2, 10, 1));
+        assert_eq!(tree.get_value(IVec3::new(1, 7, 0)), Some(1));
+        assert_eq!(tree.get_value(IVec3::new(1, 8, 0)), None);
+        assert_eq!(tree.get_value(IVec3::new(2, 0, 1)), None);
+        assert_eq!(tree.get_value(IVec3::new(2, -1, 1)), Some(1));
+    }
+}
@@ -2,7 +2,11 @@ use std::mem::MaybeUninit;
 
 use glam::UVec3;
 
-use crate::{Node, NodeConst, NodeMeta, Pool};
+use crate::{dirty::DirtySet, Node, NodeConst, NodeMeta, Pool, PoolStats, TreeCoords};
+
+/// Target size of the chunks allocated by the node pools, in bytes.
+/// Large nodes get small chunks, down to a single node per chunk.
+const CHUNK_SIZE_TARGET: usize = 64 * 1024;
 
 pub struct Tree<ROOT: Node>
 where
@@ -10,6 +14,8 @@ where
 {
     pub(crate) root: ROOT,
     pub(crate) pool: [Pool; ROOT::LEVEL as usize],
+    /// Regions modified since the last drain. None if change tracking is disabled.
+    pub(crate) dirty: Option<DirtySet>,
 }
 
 /// ```
@@ -33,7 +39,11 @@ where
     {
         let mut pools: [MaybeUninit<Pool>; ROOT::LEVEL as usize] = MaybeUninit::uninit_array();
         for (i, meta) in Self::METAS.iter().take(ROOT::LEVEL).enumerate() {
-            let pool = Pool::new(meta.layout, 10);
+            let chunk_size_log2 = (CHUNK_SIZE_TARGET / meta.layout.size())
+                .max(1)
+                .ilog2()
+                .min(10);
+            let pool = Pool::new(meta.layout, chunk_size_log2 as usize);
             pools[i].write(pool);
         }
 
@@ -44,8 +54,42 @@ where
         Self {
             root: ROOT::default(),
             pool: pools,
+            dirty: None,
         }
     }
+    /// Returns a snapshot of the tree in its current state.
+    /// This is cheap: the snapshot shares all nodes with the tree, and the nodes are only copied
+    /// once either of them writes into the shared nodes. Writes therefore copy only the nodes along
+    /// the edited paths, one node at a time. The copies keep the pointers of the nodes they replace,
+    /// so the parents of a copied node don't need to be copied for the sake of pointing to it.
+    ///
+    /// Snapshots are regular trees. They can be sent to other threads, read and edited
+    /// independently of the tree they were taken from.
+    /// ```
+    /// #![feature(generic_const_exprs)]
+    /// use dust_vdb::{Tree, hierarchy};
+    /// use glam::UVec3;
+    /// let mut tree = Tree::<hierarchy!(4, 2; u8)>::new();
+    /// tree.set_value(UVec3::new(1, 2, 3), Some(1));
+    /// let snapshot = tree.snapshot();
+    /// tree.set_value(UVec3::new(1, 2, 3), Some(2));
+    /// tree.set_value(UVec3::new(40, 2, 3), Some(2));
+    ///
+    /// let reader = std::thread::spawn(move || {
+    ///     assert_eq!(snapshot.get_value(UVec3::new(1, 2, 3)), Some(1));
+    ///     assert_eq!(snapshot.get_value(UVec3::new(40, 2, 3)), None);
+    /// });
+    /// assert_eq!(tree.get_value(UVec3::new(1, 2, 3)), Some(2));
+    /// reader.join().unwrap();
+    /// ```
+    pub fn snapshot(&self) -> Self {
+        Self {
+            root: self.root.clone(),
+            pool: std::array::from_fn(|i| self.pool[i].snapshot()),
+            dirty: self.dirty.clone(),
+        }
+    }
+
     pub unsafe fn alloc_node<CHILD: Node>(&mut self) -> u32 {
         if ROOT::LEVEL <= CHILD::LEVEL {
             panic!("Can not allocate root node");
@@ -75,15 +119,124 @@ where
     }
 
     #[inline]
-    pub fn get_value(&self, coords: UVec3) -> Option<ROOT::Voxel> {
-        self.root.get(&self.pool, coords, &mut [])
+    pub fn get_value(&self, coords: impl TreeCoords<ROOT>) -> Option<ROOT::Voxel> {
+        self.root.get(&self.pool, coords.to_tree_coords(), &mut [])
     }
 
+    /// Set the value of a voxel. Setting a voxel to `None` removes it from the tree,
+    /// and nodes left empty by the removal are released back to their pools.
     #[inline]
-    pub fn set_value(&mut self, coords: UVec3, value: Option<ROOT::Voxel>) {
+    pub fn set_value(&mut self, coords: impl TreeCoords<ROOT>, value: Option<ROOT::Voxel>) {
+        let coords = coords.to_tree_coords();
+        self.mark_dirty_voxel(coords);
         self.root.set(&mut self.pool, coords, value, &mut [])
     }
 
+    /// Set the value of all voxels within `min` and `max` (inclusive).
+    /// Regions entirely covered by the box are stored as a single tile value in their parent
+    /// instead of being populated voxel by voxel.
+    /// ```
+    /// #![feature(generic_const_exprs)]
+    /// use dust_vdb::{Tree, hierarchy};
+    /// use glam::UVec3;
+    /// let mut tree = Tree::<hierarchy!(4, 2; u8)>::new();
+    /// tree.fill(UVec3::new(0, 0, 0), UVec3::new(63, 3, 63), Some(1));
+    /// assert_eq!(tree.get_value(UVec3::new(10, 2, 40)), Some(1));
+    /// assert_eq!(tree.get_value(UVec3::new(10, 4, 40)), None);
+    /// // The region is stored as tiles, so no leaf nodes were allocated.
+    /// assert_eq!(tree.iter_leaf().count(), 0);
+    /// assert_eq!(tree.iter().count(), 64 * 4 * 64);
+    ///
+    /// // Writing into a tile densifies it on demand.
+    /// tree.set_value(UVec3::new(10, 2, 40), Some(2));
+    /// assert_eq!(tree.get_value(UVec3::new(10, 2, 40)), Some(2));
+    /// assert_eq!(tree.get_value(UVec3::new(10, 2, 41)), Some(1));
+    /// assert_eq!(tree.iter_leaf().count(), 1);
+    /// ```
+    pub fn fill<C: TreeCoords<ROOT>>(&mut self, min: C, max: C, value: Option<ROOT::Voxel>) {
+        let min = min.to_tree_coords();
+        let max = max.to_tree_coords().min(ROOT::EXTENT_MASK);
+        if min.cmpgt(max).any() {
+            return;
+        }
+        self.mark_dirty_box(min, max);
+        self.root.fill(&mut self.pool, min, max, value)
+    }
+
+    /// Release all nodes that no longer contain any voxels back to their pools,
+    /// and collapse nodes completely filled with the same value into tiles. Leaf nodes with
+    /// surface voxels marked by [`Tree::update_surface`] are kept, so the marks are not lost.
+    /// This is useful after editing the leaf nodes directly with [`Tree::iter_leaf_mut`].
+    /// ```
+    /// #![feature(generic_const_exprs)]
+    /// use dust_vdb::{Tree, hierarchy};
+    /// use glam::UVec3;
+    /// let mut tree = Tree::<hierarchy!(4, 2)>::new();
+    /// tree.set_value(UVec3::new(0, 1, 2), Some(true));
+    /// tree.set_value(UVec3::new(63, 1, 3), Some(true));
+    /// assert_eq!(tree.iter_leaf().count(), 2);
+    ///
+    /// tree.set_value(UVec3::new(0, 1, 2), None);
+    /// assert_eq!(tree.get_value(UVec3::new(0, 1, 2)), None);
+    /// assert_eq!(tree.iter_leaf().count(), 1);
+    ///
+    /// for (_, leaf) in tree.iter_leaf_mut() {
+    ///     leaf.occupancy.set(0b110111, false);
+    /// }
+    /// assert_eq!(tree.iter_leaf().count(), 1);
+    /// tree.prune();
+    /// assert_eq!(tree.iter_leaf().count(), 0);
+    /// ```
+    pub fn prune(&mut self) {
+        if self.dirty.is_none() {
+            self.root.prune(&mut self.pool);
+            return;
+        }
+        // Voxel values are unchanged, but leaf nodes collapsed into tiles or released disappear.
+        let leaf_origins: Vec<UVec3> = self.iter_leaf().map(|(origin, _)| origin).collect();
+        self.root.prune(&mut self.pool);
+        let remaining: fxhash::FxHashSet<UVec3> =
+            self.iter_leaf().map(|(origin, _)| origin).collect();
+        for origin in leaf_origins {
+            if !remaining.contains(&origin) {
+                self.mark_dirty_voxel(origin);
+            }
+        }
+    }
+
+    /// Move the nodes in each pool to the front of the pool, rewriting the pointers to them
+    /// through the tree, and release the chunks left unused. Node pools never shrink otherwise,
+    /// so this is useful after removing large parts of the tree.
+    /// ```
+    /// #![feature(generic_const_exprs)]
+    /// use dust_vdb::{Tree, hierarchy};
+    /// use glam::UVec3;
+    /// let mut tree = Tree::<hierarchy!(4, 2; u8)>::new();
+    /// for x in 0..16 {
+    ///     tree.set_value(UVec3::new(x * 4, 0, 0), Some(x as u8));
+    /// }
+    /// for x in 0..15 {
+    ///     tree.set_value(UVec3::new(x * 4, 0, 0), None);
+    /// }
+    /// assert_eq!(tree.memory_stats()[0].live, 1);
+    /// assert_eq!(tree.memory_stats()[0].free, 15);
+    ///
+    /// tree.compact();
+    /// assert_eq!(tree.memory_stats()[0].live, 1);
+    /// assert_eq!(tree.memory_stats()[0].free, 0);
+    /// assert_eq!(tree.get_value(UVec3::new(60, 0, 0)), Some(15));
+    /// ```
+    pub fn compact(&mut self) {
+        let remaps: Vec<Vec<u32>> = self.pool.iter_mut().map(Pool::compact).collect();
+        self.root.remap_children(&mut self.pool, &remaps);
+    }
+
+    /// Returns the memory usage of the node pool of each level, starting from the leaf nodes.
+    /// The root node is owned by the tree and not included.
+    pub fn memory_stats(&self) -> [PoolStats; ROOT::LEVEL as usize] {
+        std::array::from_fn(|i| self.pool[i].stats())
+    }
+
     /// ```
     /// #![feature(generic_const_exprs)]
     /// use dust_vdb::{Tree, hierarchy};
@@ -103,6 +256,8 @@ where
         self.root.iter(&self.pool, UVec3 { x: 0, y: 0, z: 0 })
     }
 
+    /// Iterate over all leaf nodes in the tree.
+    /// Regions stored as tiles do not contain any leaf nodes and are not visited.
     pub fn iter_leaf<'a>(&'a self) -> impl Iterator<Item = (UVec3, &'a ROOT::LeafType)> {
         self.root
             .iter_leaf(&self.pool, UVec3 { x: 0, y: 0, z: 0 })
@@ -112,9 +267,28 @@ where
             })
     }
 
+    /// Iterate over all leaf nodes in the tree, allowing them to be edited.
+    /// When tracking changes, all visited leaf nodes are recorded as modified.
+    /// See [`Tree::drain_dirty`].
     pub fn iter_leaf_mut<'a>(
         &'a mut self,
     ) -> impl Iterator<Item = (UVec3, &'a mut ROOT::LeafType)> {
+        if let Some(dirty) = self.dirty.as_mut() {
+            for (origin, _) in self.root.iter_leaf(&self.pool, UVec3::ZERO) {
+                dirty.mark_leaf(origin);
+            }
+        }
+        self.iter_leaf_mut_untracked()
+    }
+
+    /// Same as [`Tree::iter_leaf_mut`], for callers recording their own changes.
+    pub(crate) fn iter_leaf_mut_untracked<'a>(
+        &'a mut self,
+    ) -> impl Iterator<Item = (UVec3, &'a mut ROOT::LeafType)> {
+        // The leaf nodes are written through pointers obtained from a shared borrow of the pool.
+        if let Some(leaf_pool) = self.pool.first_mut() {
+            leaf_pool.unshare();
+        }
         self.root
             .iter_leaf(&mut self.pool, UVec3 { x: 0, y: 0, z: 0 })
             .map(|(position, leaf)| unsafe {
@@ -122,6 +296,145 @@ where
                 (position, leaf)
             })
     }
+
+    /// Iterate over all voxels within `min` and `max` (inclusive).
+    /// Nodes outside of the box are skipped without visiting their voxels.
+    /// ```
+    /// #![feature(generic_const_exprs)]
+    /// use dust_vdb::{Tree, hierarchy};
+    /// use glam::UVec3;
+    /// let mut tree = Tree::<hierarchy!(4, 2)>::new();
+    /// tree.set_value(UVec3::new(0, 1, 2), Some(true));
+    /// tree.set_value(UVec3::new(5, 5, 5), Some(true));
+    /// tree.set_value(UVec3::new(63, 1, 3), Some(true));
+    /// tree.fill(UVec3::new(16, 16, 16), UVec3::new(31, 31, 31), Some(false));
+    /// let voxels: Vec<UVec3> = tree
+    ///     .iter_in_aabb(UVec3::new(0, 0, 0), UVec3::new(16, 16, 16))
+    ///     .collect();
+    /// assert_eq!(
+    ///     voxels,
+    ///     vec![UVec3::new(0, 1, 2), UVec3::new(5, 5, 5), UVec3::new(16, 16, 16)]
+    /// );
+    /// ```
+    pub fn iter_in_aabb(&self, min: UVec3, max: UVec3) -> ROOT::Iterator<'_> {
+        self.root.iter_in_aabb(
+            &self.pool,
+            UVec3 { x: 0, y: 0, z: 0 },
+            min,
+            max.min(ROOT::EXTENT_MASK),
+        )
+    }
+
+    /// Iterate over all leaf nodes intersecting the box between `min` and `max` (inclusive).
+    /// Leaf nodes are yielded in their entirety, so they may contain voxels outside of the box.
+    pub fn iter_leaf_in_aabb(
+        &self,
+        min: UVec3,
+        max: UVec3,
+    ) -> impl Iterator<Item = (UVec3, &ROOT::LeafType)> {
+        self.root
+            .iter_leaf_in_aabb(
+                &self.pool,
+                UVec3 { x: 0, y: 0, z: 0 },
+                min,
+                max.min(ROOT::EXTENT_MASK),
+            )
+            .map(|(position, leaf)| unsafe {
+                let leaf: &ROOT::LeafType = &*leaf.get();
+                (position, leaf)
+            })
+    }
+
+    /// Call `f` with the box (inclusive) and the value of each tile intersecting the box between
+    /// `min` and `max` (inclusive). Tiles are yielded in their entirety, so they may extend outside
+    /// of the box. Together with [`Tree::iter_leaf_in_aabb`], this covers every voxel in the box
+    /// without visiting the voxels within tiles one by one.
+    /// ```
+    /// #![feature(generic_const_exprs)]
+    /// use dust_vdb::{Tree, hierarchy};
+    /// use glam::UVec3;
+    /// let mut tree = Tree::<hierarchy!(4, 2; u8)>::new();
+    /// tree.fill(UVec3::new(0, 0, 0), UVec3::new(7, 3, 3), Some(1));
+    /// tree.set_value(UVec3::new(0, 0, 0), Some(2));
+    /// let mut tiles = Vec::new();
+    /// tree.for_each_tile_in_aabb(UVec3::ZERO, UVec3::splat(63), |min, max, value| {
+    ///     tiles.push((min, max, value));
+    /// });
+    /// assert_eq!(tiles, vec![(UVec3::new(4, 0, 0), UVec3::new(7, 3, 3), 1)]);
+    /// ```
+    pub fn for_each_tile_in_aabb(
+        &self,
+        min: UVec3,
+        max: UVec3,
+        mut f: impl FnMut(UVec3, UVec3, ROOT::Voxel),
+    ) {
+        if min.cmpgt(max).any() {
+            return;
+        }
+        self.root.for_each_tile_in_aabb(
+            &self.pool,
+            UVec3::ZERO,
+            min,
+            max.min(ROOT::EXTENT_MASK),
+            &mut f,
+        )
+    }
+
+    /// Returns the leaf node containing `coords`, allocating it and the nodes above it as needed.
+    /// Tiles containing `coords` are densified, so the voxels are left unchanged. Newly allocated
+    /// leaf nodes are empty, and must be written to or released by the caller.
+    ///
+    /// The pointer stays valid until nodes are released, the pools are compacted or a snapshot
+    /// is taken, so callers may gather the leaf nodes of a region first and write them in parallel.
+    /// Changes are not recorded, see [`Tree::drain_dirty`].
+    pub(crate) fn ensure_leaf(&mut self, coords: UVec3) -> *mut ROOT::LeafType {
+        self.root.ensure_leaf(&mut self.pool, coords)
+    }
+
+    /// Returns the smallest box (inclusive) containing all voxels in the tree,
+    /// or None if the tree is empty.
+    /// ```
+    /// #![feature(generic_const_exprs)]
+    /// use dust_vdb::{Tree, hierarchy};
+    /// use glam::UVec3;
+    /// let mut tree = Tree::<hierarchy!(4, 2)>::new();
+    /// assert_eq!(tree.bounding_box(), None);
+    /// tree.set_value(UVec3::new(3, 40, 2), Some(true));
+    /// tree.set_value(UVec3::new(9, 1, 7), Some(true));
+    /// assert_eq!(
+    ///     tree.bounding_box(),
+    ///     Some((UVec3::new(3, 1, 2), UVec3::new(9, 40, 7)))
+    /// );
+    /// ```
+    pub fn bounding_box(&self) -> Option<(UVec3, UVec3)> {
+        self.root.bounding_box(&self.pool)
+    }
+
+    /// Returns the number of voxels with a value in the tree, including those covered by tiles.
+    /// This is the same as `tree.iter().count()`, but computed from the node masks directly.
+    /// Note that this is unrelated to the surface voxels marked on [`crate::LeafNode::active`].
+    /// ```
+    /// #![feature(generic_const_exprs)]
+    /// use dust_vdb::{Tree, hierarchy};
+    /// use glam::UVec3;
+    /// let mut tree = Tree::<hierarchy!(4, 2)>::new();
+    /// tree.set_value(UVec3::new(3, 40, 2), Some(true));
+    /// tree.fill(UVec3::new(16, 16, 16), UVec3::new(31, 31, 31), Some(false));
+    /// assert_eq!(tree.count_active(), 1 + 16 * 16 * 16);
+    /// ```
+    pub fn count_active(&self) -> u64 {
+        self.root.count_active(&self.pool)
+    }
+}
+
+impl<ROOT: Node> Clone for Tree<ROOT>
+where
+    [(); ROOT::LEVEL as usize + 1]: Sized,
+{
+    /// See [`Tree::snapshot`].
+    fn clone(&self) -> Self {
+        self.snapshot()
+    }
 }
 
 /// Workaround for https://github.com/rust-lang/rust/issues/88424#issuecomment-911158795
@@ -178,3 +491,243 @@ where
         id
     };
 }
+
+#[cfg(test)]
+mod tests {
+    use glam::UVec3;
+
+    use crate::{hierarchy, Tree};
+
+    #[test]
+    fn test_aabb_queries() {
+        use rand::prelude::*;
+        let mut rng = rand::thread_rng();
+
+        type MyTree = Tree<hierarchy!(#, 2, 2; u8)>;
+        let mut tree = MyTree::new();
+        for _i in 0..500 {
+            let location = UVec3::new(rng.gen(), rng.gen(), rng.gen()) >> 25;
+            tree.set_value(location, Some(rng.gen()));
+        }
+        tree.fill(UVec3::new(16, 0, 4), UVec3::new(47, 15, 19), Some(1));
+
+        let voxels: Vec<UVec3> = tree.iter().collect();
+        assert_eq!(tree.count_active(), voxels.len() as u64);
+        let expected_bounds = voxels
+            .iter()
+            .fold((UVec3::MAX, UVec3::ZERO), |(min, max), v| {
+                (min.min(*v), max.max(*v))
+            });
+        assert_eq!(tree.bounding_box(), Some(expected_bounds));
+
+        for _i in 0..100 {
+            let a: UVec3 = UVec3::new(rng.gen(), rng.gen(), rng.gen()) >> 25;
+            let b: UVec3 = UVec3::new(rng.gen(), rng.gen(), rng.gen()) >> 25;
+            let (min, max) = (a.min(b), a.max(b));
+            let inside = |v: &UVec3| v.cmpge(min).all() && v.cmple(max).all();
+
+            let mut expected: Vec<UVec3> = voxels.iter().copied().filter(inside).collect();
+            let mut actual: Vec<UVec3> = tree.iter_in_aabb(min, max).collect();
+            expected.sort_by_key(|v| v.to_array());
+            actual.sort_by_key(|v| v.to_array());
+            assert_eq!(actual, expected);
+
+            for (position, leaf) in tree.iter_leaf_in_aabb(min, max) {
+                assert!(position.cmple(max).all());
+                assert!((position + UVec3::splat(3)).cmpge(min).all());
+                assert!(!leaf.occupancy.is_zeroed());
+            }
+            let leaf_count = tree
+                .iter_leaf()
+                .filter(|(position, _)| {
+                    position.cmple(max).all() && (*position + UVec3::splat(3)).cmpge(min).all()
+                })
+                .count();
+            assert_eq!(tree.iter_leaf_in_aabb(min, max).count(), leaf_count);
+        }
+        // Empty boxes yield nothing.
+        assert_eq!(
+            tree.iter_in_aabb(UVec3::new(5, 0, 0), UVec3::new(4, 127, 127))
+                .count(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_snapshot() {
+        use rand::prelude::*;
+        use std::collections::HashMap;
+        let mut rng = rand::thread_rng();
+
+        type MyTree = Tree<hierarchy!(3, 2, 2; u8)>;
+        let mut tree = MyTree::new();
+        let mut model: HashMap<UVec3, u8> = HashMap::new();
+        for _i in 0..1000 {
+            let location = UVec3::new(rng.gen(), rng.gen(), rng.gen()) >> 25;
+            let value: u8 = rng.gen();
+            tree.set_value(location, Some(value));
+            model.insert(location, value);
+        }
+        let snapshot = tree.snapshot();
+        let snapshot_model = model.clone();
+
+        std::thread::scope(|scope| {
+            let reader = scope.spawn(|| {
+                for (location, value) in snapshot_model.iter() {
+                    assert_eq!(snapshot.get_value(*location), Some(*value));
+                }
+            });
+            for _i in 0..1000 {
+                let location = UVec3::new(rng.gen(), rng.gen(), rng.gen()) >> 25;
+                let value: Option<u8> = if rng.gen() { Some(rng.gen()) } else { None };
+                tree.set_value(location, value);
+                match value {
+                    Some(value) => model.insert(location, value),
+                    None => model.remove(&location),
+                };
+            }
+            tree.fill(UVec3::new(64, 64, 0), UVec3::new(127, 127, 63), Some(3));
+            for (_, leaf) in tree.iter_leaf_mut() {
+                leaf.active.set(0, true);
+            }
+            reader.join().unwrap();
+        });
+
+        assert_eq!(snapshot.count_active(), snapshot_model.len() as u64);
+        for (location, value) in snapshot_model.iter() {
+            assert_eq!(snapshot.get_value(*location), Some(*value));
+        }
+        for (_, leaf) in snapshot.iter_leaf() {
+            assert!(leaf.active.is_zeroed());
+        }
+        for (location, value) in model.iter() {
+            if location.cmplt(UVec3::new(64, 64, 0)).any() || location.z > 63 {
+                assert_eq!(tree.get_value(*location), Some(*value));
+            }
+        }
+        assert_eq!(tree.get_value(UVec3::new(100, 100, 10)), Some(3));
+    }
+
+    #[test]
+    fn test_snapshot_copies_nodes() {
+        use std::collections::HashMap;
+
+        type MyTree = Tree<hierarchy!(3, 2, 2; u8)>;
+        let mut tree = MyTree::new();
+        let mut model: HashMap<UVec3, u8> = HashMap::new();
+        for x in 0..16 {
+            for y in 0..16 {
+                let location = UVec3::new(x * 8, y * 8, 0);
+                tree.set_value(location, Some(1));
+                model.insert(location, 1);
+            }
+        }
+        let first = tree.snapshot();
+        let first_model = model.clone();
+        tree.set_value(UVec3::new(8, 8, 0), Some(2));
+        model.insert(UVec3::new(8, 8, 0), 2);
+        // Only the leaf written to is copied, not the whole chunk of leaves holding it.
+        let stats = tree.memory_stats();
+        assert_eq!(stats[0].copied, 1);
+        assert!(stats[0].live > 1);
+
+        let second = tree.snapshot();
+        let second_model = model.clone();
+        for x in 0..16 {
+            let location = UVec3::new(x * 8, 0, 1);
+            tree.set_value(location, Some(3));
+            model.insert(location, 3);
+        }
+        tree.set_value(UVec3::new(0, 0, 0), None);
+        model.remove(&UVec3::new(0, 0, 0));
+
+        for (tree, model) in [
+            (&tree, &model),
+            (&first, &first_model),
+            (&second, &second_model),
+        ] {
+            assert_eq!(tree.count_active(), model.len() as u64);
+            for (location, value) in model.iter() {
+                assert_eq!(tree.get_value(*location), Some(*value));
+            }
+        }
+
+        // Once the snapshots are gone, the copied leaves are gathered back into the chunks.
+        drop(first);
+        drop(second);
+        for (_, leaf) in tree.iter_leaf_mut() {
+            leaf.active.set(1, true);
+        }
+        assert_eq!(tree.memory_stats()[0].copied, 0);
+        assert_eq!(tree.memory_stats()[0].shared_chunks, 0);
+        for (location, value) in model.iter() {
+            assert_eq!(tree.get_value(*location), Some(*value));
+        }
+    }
+
+    #[test]
+    fn test_compact() {
+        use rand::prelude::*;
+        use std::collections::HashMap;
+        let mut rng = rand::thread_rng();
+
+        type MyRoot = hierarchy!(#, 3, 2, 2; u8);
+        let mut tree = Tree::<MyRoot>::new();
+        let mut model: HashMap<UVec3, u8> = HashMap::new();
+        for _i in 0..3000 {
+            let location = UVec3::new(rng.gen(), rng.gen(), rng.gen()) >> 24;
+            let value: u8 = rng.gen();
+            tree.set_value(location, Some(value));
+            model.insert(location, value);
+        }
+        let snapshot = tree.snapshot();
+        let snapshot_model = model.clone();
+        let removed: Vec<UVec3> = model
+            .keys()
+            .filter(|location| location.x < 200)
+            .copied()
+            .collect();
+        for location in removed {
+            tree.set_value(location, None);
+            model.remove(&location);
+        }
+        let before = tree.memory_stats();
+        assert!(before[0].free > 0);
+
+        tree.compact();
+        let after = tree.memory_stats();
+        for (before, after) in before.iter().zip(after.iter()) {
+            assert_eq!(after.live, before.live);
+            assert_eq!(after.free, 0);
+            assert!(after.chunks <= before.chunks);
+        }
+        assert!(after[0].chunks < before[0].chunks);
+        let leaf_entries = unsafe {
+            tree.pool[0]
+                .iter_entries::<<MyRoot as crate::Node>::LeafType>()
+                .count()
+        };
+        assert_eq!(leaf_entries as u32, after[0].live);
+        assert_eq!(tree.iter_leaf().count() as u32, after[0].live);
+
+        assert_eq!(tree.count_active(), model.len() as u64);
+        for (location, value) in model.iter() {
+            assert_eq!(tree.get_value(*location), Some(*value));
+        }
+        for (location, value) in snapshot_model.iter() {
+            assert_eq!(snapshot.get_value(*location), Some(*value));
+        }
+
+        // The tree keeps working after compaction.
+        for _i in 0..1000 {
+            let location = UVec3::new(rng.gen(), rng.gen(), rng.gen()) >> 24;
+            let value: u8 = rng.gen();
+            tree.set_value(location, Some(value));
+            model.insert(location, value);
+        }
+        assert_eq!(tree.count_active(), model.len() as u64);
+        for (location, value) in model.iter() {
+            assert_eq!(tree.get_value(*location), Some(*value));
+        }
+    }
+}
@@ -53,7 +53,7 @@ impl VoxGeometry {
         &self.geometry_buffer
     }
     pub fn from_tree(
-        tree: Tree,
+        mut tree: Tree,
         size: [u8; 3],
         unit_size: f32,
         allocator: &Allocator,
@@ -62,6 +62,8 @@ impl VoxGeometry {
         let leaf_extent: Vec3A = leaf_extent_int.as_vec3a();
         let leaf_extent: Vec3A = unit_size * leaf_extent;
 
+        // Materials of each leaf are packed in the material buffer in iteration order.
+        let mut material_ptr: u32 = 0;
         let (aabbs, nodes): (Vec<vk::AabbPositionsKHR>, Vec<GPUVoxNode>) = tree
             .iter_leaf()
             .map(|(position, d)| {
@@ -87,10 +89,11 @@ impl VoxGeometry {
                         z: position.z as u16,
                         w: 0,
                         mask,
-                        material_ptr: d.material_ptr,
+                        material_ptr,
                         reserved: 0,
                     }
                 };
+                material_ptr += d.occupancy.count_ones() as u32;
                 (aabb, node)
             })
             .unzip();
@@ -130,6 +133,8 @@ impl VoxGeometry {
                 })
         };
         let num_blocks = aabbs.len() as u32;
+        // The buffers reflect the tree as it is now, so earlier edits need no update.
+        tree.drain_dirty().for_each(drop);
         let future =
             aabb_buffer
                 .join(geometry_buffer)
@@ -143,10 +148,21 @@ impl VoxGeometry {
                 });
         future
     }
-    pub fn set(&mut self, coords: UVec3, value: Option<bool>) {
+    pub fn set(&mut self, coords: UVec3, value: Option<u8>) {
         self.tree.set_value(coords, value)
     }
-    pub fn get(&mut self, coords: UVec3) -> Option<bool> {
+    pub fn get(&mut self, coords: UVec3) -> Option<u8> {
         self.tree.get_value(coords)
     }
+    /// Start or stop recording the boxes of the tree modified by edits, so that the buffers can
+    /// be updated incrementally. Tracking is disabled by default, as the boxes are kept until
+    /// drained. See [`dust_vdb::Tree::track_dirty`].
+    pub fn track_dirty(&mut self, enabled: bool) {
+        self.tree.track_dirty(enabled)
+    }
+    /// Returns the boxes (inclusive) of the tree modified since the last call.
+    /// See [`dust_vdb::Tree::drain_dirty`].
+    pub fn drain_dirty(&mut self) -> impl Iterator<Item = (UVec3, UVec3)> {
+        self.tree.drain_dirty()
+    }
 }
@@ -1,8 +1,8 @@
 #![feature(generic_const_exprs)]
 #![feature(generators)]
 
-mod collector;
 mod loader;
+mod mesh;
 mod palette;
 
 use bevy_asset::{AddAsset, Handle};
@@ -15,9 +15,11 @@ pub use geometry::VoxGeometry;
 pub use loader::*;
 use material::DiffuseMaterial;
 pub use material::PaletteMaterial;
+pub use mesh::voxelize_obj;
 pub use palette::VoxPalette;
 
-pub type TreeRoot = hierarchy!(4, 2, 2);
+/// Each voxel stores its index into the palette.
+pub type TreeRoot = hierarchy!(4, 2, 2; u8);
 pub type Tree = dust_vdb::Tree<TreeRoot>;
 
 #[derive(Default)]
@@ -230,45 +230,34 @@ impl VoxLoader {
         model: &Model,
         palette: Handle<VoxPalette>,
     ) -> impl GPUCommandFuture<Output = (VoxGeometry, PaletteMaterial)> + Send {
-        let mut palette_index_collector = crate::collector::ModelIndexCollector::new();
-
         let mut tree = Tree::new();
         for voxel in model.voxels.iter() {
-            let voxel = dot_vox::Voxel {
-                x: voxel.x,
-                y: voxel.z,
-                z: model.size.y as u8 - voxel.y,
-                i: voxel.i,
-            };
             let coords: UVec3 = UVec3 {
                 x: voxel.x as u32,
-                y: voxel.y as u32,
-                z: voxel.z as u32,
+                y: voxel.z as u32,
+                z: (model.size.y as u8 - voxel.y) as u32,
             };
-            tree.set_value(coords, Some(true));
-            palette_index_collector.set(voxel);
+            tree.set_value(coords, Some(voxel.i));
         }
 
-        let palette_indexes = palette_index_collector.into_iter();
-        // TODO: use iter_leaf_mut here, and insert indices
-        for (location, leaf) in tree.iter_leaf_mut() {
-            let block_index = (location.x >> 2, location.y >> 2, location.z >> 2);
-            let block_index = block_index.0 as usize
-                + block_index.1 as usize * 64
-                + block_index.2 as usize * 64 * 64;
-
-            leaf.material_ptr = palette_indexes.running_sum()[block_index];
-        }
+        // Palette indexes of all occupied voxels, packed leaf by leaf in the same order as
+        // the geometry buffer so that each leaf can locate its materials with `material_ptr`.
+        let palette_indexes: Vec<u8> = tree
+            .iter_leaf()
+            .flat_map(|(_, leaf)| {
+                leaf.occupancy
+                    .iter_set_bits()
+                    .map(move |index| leaf.values[index])
+            })
+            .collect();
 
         let material_buffer = self
             .allocator
             .create_dynamic_asset_buffer_with_writer(
                 palette_indexes.len() as u64,
                 vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
-                |slice| {
-                    for (src, dst) in palette_indexes.zip(slice.iter_mut()) {
-                        *dst = src;
-                    }
+                move |slice| {
+                    slice.copy_from_slice(&palette_indexes);
                 },
             )
             .unwrap()
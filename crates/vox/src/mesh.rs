@@ -0,0 +1,35 @@
+use dust_vdb::TriangleMesh;
+use glam::UVec3;
+
+use crate::Tree;
+
+/// Voxelize the geometry of a Wavefront OBJ file into a tree of palette indexes, so that props
+/// modeled as triangle meshes can be used in place of MagicaVoxel models.
+///
+/// The mesh is moved so that its bounding box starts at the origin of the tree, and each voxel
+/// covers a cube of `voxel_size` in the units of the file. Surface voxels take the palette index
+/// returned by `palette_index` for the name of their material. When `fill` is given, the inside
+/// of the mesh is filled with that palette index, and the mesh must be closed.
+pub fn voxelize_obj(
+    source: &str,
+    voxel_size: f32,
+    fill: Option<u8>,
+    palette_index: impl Fn(&str) -> u8,
+) -> anyhow::Result<Tree> {
+    let (mesh, materials) = TriangleMesh::from_obj(source)?;
+    let indexes: Vec<u8> = materials.iter().map(|name| palette_index(name)).collect();
+    let mut mesh = TriangleMesh::new(
+        mesh.positions,
+        mesh.triangles,
+        mesh.values
+            .into_iter()
+            .map(|material| indexes[material as usize])
+            .collect(),
+    );
+    let mut tree = Tree::new();
+    if let Some((min, _)) = mesh.bounding_box() {
+        mesh.translate(-min);
+        tree.voxelize(&mesh, UVec3::ZERO, voxel_size, fill);
+    }
+    Ok(tree)
+}